@@ -0,0 +1,415 @@
+use std::io::Write;
+
+use proc_exit::prelude::*;
+
+/// Squash staged changes into the commits they modify
+///
+/// Each staged hunk is blamed to find the commit in the stack that last touched the lines it
+/// changes.  The hunks are committed as fixups of those commits, which are then squashed in,
+/// rebasing anything built on top.  Hunks that can't be traced to a single commit in the stack
+/// are left staged.
+#[derive(clap::Args)]
+pub struct AbsorbArgs {
+    /// Don't ask for confirmation before rewriting more than `stack.max-stack-size` commits
+    #[arg(short, long)]
+    yes: bool,
+
+    /// Only rewrite commits whose author name or email contains `PATTERN`
+    ///
+    /// Other commits are protected, along with everything they are built on, and what's built on
+    /// them stays on them.
+    #[arg(long, value_name = "PATTERN")]
+    author: Option<String>,
+
+    /// Don't actually switch
+    #[arg(short = 'n', long)]
+    dry_run: bool,
+
+    /// How `--dry-run` reports what it would do
+    #[arg(long, value_enum, default_value_t, requires = "dry_run")]
+    format: crate::ops::PlanFormat,
+}
+
+impl AbsorbArgs {
+    pub fn exec(&self, global: &git_stack::config::RepoConfig) -> proc_exit::ExitResult {
+        let stderr_palette = crate::ops::Palette::colored();
+
+        let cwd = std::env::current_dir().with_code(proc_exit::sysexits::USAGE_ERR)?;
+        let (mut repo, repo_config) = crate::ops::open_configured_repo(global)?;
+        crate::ops::configure_signing(&mut repo)?;
+
+        let branches = crate::ops::load_branches(&repo, &repo_config)?;
+
+        let head_id = repo.head_commit().id;
+        let base = crate::ops::resolve_implicit_base(
+            &repo,
+            head_id,
+            &branches,
+            repo_config.auto_base_commit_count(),
+        );
+        let merge_base_oid = crate::ops::resolve_merge_base(&repo, &base, head_id, "HEAD")
+            .with_code(proc_exit::sysexits::USAGE_ERR)?;
+        let stack_branches = branches.descendants(&repo, merge_base_oid);
+        let mut graph = git_stack::graph::Graph::from_branches(&repo, stack_branches)
+            .with_code(proc_exit::Code::FAILURE)?;
+        git_stack::graph::protect_branches(&mut graph);
+        git_stack::graph::mark_fixup(&mut graph, &repo);
+        git_stack::graph::mark_wip(&mut graph, &repo);
+        crate::ops::protect_other_authors(&mut graph, &repo, self.author.as_deref());
+
+        if !self.dry_run {
+            crate::ops::confirm_stack_size(
+                git_stack::graph::pickable_count(&graph),
+                repo_config.max_stack_size(),
+                self.yes,
+            )
+            .with_code(proc_exit::sysexits::USAGE_ERR)?;
+        }
+
+        crate::ops::ensure_clean_state(repo.raw(), "walk commits", self.dry_run)?;
+
+        // Each fixup builds on the last, so its tree has the hunks for every target so far
+        let index_tree_id = repo
+            .raw()
+            .index()
+            .and_then(|mut index| index.write_tree())
+            .with_code(proc_exit::Code::FAILURE)?;
+        let (targets, fixups) = {
+            let head_tree = repo
+                .raw()
+                .find_commit(head_id)
+                .and_then(|commit| commit.tree())
+                .with_code(proc_exit::Code::FAILURE)?;
+            let index_tree = repo
+                .raw()
+                .find_tree(index_tree_id)
+                .with_code(proc_exit::Code::FAILURE)?;
+            let mut diff = repo
+                .raw()
+                .diff_tree_to_tree(
+                    Some(&head_tree),
+                    Some(&index_tree),
+                    Some(git2::DiffOptions::new().context_lines(0)),
+                )
+                .with_code(proc_exit::Code::FAILURE)?;
+            let ignore_case = repo.ignore_case();
+            if ignore_case {
+                // Pair up a file staged under a differently-cased name with its tracked self
+                diff.find_similar(Some(git2::DiffFindOptions::new().renames(true)))
+                    .with_code(proc_exit::Code::FAILURE)?;
+            }
+            if diff.deltas().len() == 0 {
+                return Err(proc_exit::sysexits::USAGE_ERR.with_message("nothing staged"));
+            }
+
+            let (targets, skipped) = assign_hunks(&repo, &graph, head_id, &diff, ignore_case)
+                .with_code(proc_exit::Code::FAILURE)?;
+            for (hunk, reason) in &skipped {
+                let _ = writeln!(
+                    anstream::stderr(),
+                    "{}: left {} staged, {}",
+                    stderr_palette.info("note"),
+                    stderr_palette.highlight(hunk),
+                    reason
+                );
+            }
+            if targets.is_empty() {
+                return Err(proc_exit::sysexits::USAGE_ERR
+                    .with_message("no staged hunk belongs to a commit in the stack"));
+            }
+
+            let mut fixups = Vec::new();
+            let mut absorbed = Vec::new();
+            for (target_id, hunks) in &targets {
+                absorbed.extend(hunks.iter().cloned());
+                let tree_id = apply_hunks(repo.raw(), &diff, &head_tree, &absorbed)
+                    .with_code(proc_exit::Code::FAILURE)?;
+                fixups.push((*target_id, tree_id));
+            }
+            (targets, fixups)
+        };
+        let fixup_ids =
+            crate::ops::commit_fixups(&mut repo, &graph.branches, &fixups, self.dry_run)
+                .with_code(proc_exit::Code::FAILURE)?;
+        let mut parent_id = head_id;
+        for fixup_id in fixup_ids.iter().copied() {
+            graph.insert(git_stack::graph::Node::new(fixup_id), parent_id);
+            graph.commit_set(fixup_id, git_stack::graph::Fixup);
+            parent_id = fixup_id;
+        }
+        if !self.dry_run {
+            graph.branches.move_local(head_id, parent_id);
+        }
+
+        let mut stash_id = None;
+        if !self.dry_run {
+            stash_id = git_stack::git::stash_push(&mut repo, "absorb");
+        }
+
+        let mut backed_up = false;
+        {
+            let stash_repo =
+                crate::ops::open_repo(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
+            let stash_repo = git_branch_stash::GitRepo::new(stash_repo);
+            let mut snapshots =
+                git_branch_stash::Stack::new(crate::ops::STASH_STACK_NAME, &stash_repo);
+            let snapshot_capacity = repo_config.capacity();
+            snapshots.capacity(snapshot_capacity);
+            let snapshot = git_branch_stash::Snapshot::from_repo(&stash_repo)
+                .with_code(proc_exit::Code::FAILURE)?;
+            if !self.dry_run {
+                snapshots.push(snapshot).to_sysexits()?;
+                backed_up = true;
+            }
+        }
+
+        let target_ids = targets.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        git_stack::graph::fixup_within(
+            &mut graph,
+            &repo,
+            git_stack::config::Fixup::Squash,
+            &target_ids,
+        );
+
+        let mut summary = crate::ops::RestackSummary::default();
+        let scripts = git_stack::graph::to_scripts(&graph, vec![]);
+        if let Err(err) = crate::ops::ensure_no_merges(
+            repo.raw(),
+            scripts.iter().flat_map(|s| s.stats().rewritten),
+            repo_config.rebase_merges(),
+        ) {
+            git_stack::git::stash_pop(&mut repo, stash_id);
+            return Err(proc_exit::sysexits::USAGE_ERR.with_message(err));
+        }
+        let head_branch = repo.head_branch();
+        if self.format == crate::ops::PlanFormat::Json {
+            crate::ops::print_plan_json(&mut repo, &scripts, &[])
+                .with_code(proc_exit::Code::FAILURE)?;
+            return Ok(());
+        }
+        let mut executor = git_stack::rewrite::Executor::new(self.dry_run);
+        for script in scripts {
+            let results = executor.run(&mut repo, &script);
+            let branches = script.stats().branches;
+            summary.record(branches.iter().map(String::as_str), &results);
+        }
+        executor
+            .close(&mut repo, head_branch.as_ref().and_then(|b| b.local_name()))
+            .with_code(proc_exit::Code::FAILURE)?;
+        let success = summary.is_success();
+
+        git_stack::git::stash_pop(&mut repo, stash_id);
+        if success && !self.dry_run {
+            let absorbed_tree_id = fixups.last().map(|(_, tree_id)| *tree_id);
+            restore_index(repo.raw(), absorbed_tree_id, index_tree_id)
+                .with_code(proc_exit::Code::FAILURE)?;
+        }
+        if success {
+            for (target_id, hunks) in &targets {
+                let target = repo.find_commit(*target_id).expect("graph commits exist");
+                let _ = writeln!(
+                    anstream::stderr(),
+                    "{} {} {} into {}: {}",
+                    stderr_palette.good(if self.dry_run {
+                        "Would absorb"
+                    } else {
+                        "Absorbed"
+                    }),
+                    hunks.len(),
+                    if hunks.len() == 1 { "hunk" } else { "hunks" },
+                    stderr_palette.highlight(repo.abbrev_id(*target_id)),
+                    stderr_palette.hint(&target.summary)
+                );
+            }
+        }
+        if backed_up {
+            anstream::eprintln!(
+                "{}: to undo, run {}",
+                stderr_palette.info("note"),
+                stderr_palette.highlight(format_args!(
+                    "`git branch-stash pop {}`",
+                    crate::ops::STASH_STACK_NAME
+                ))
+            );
+        }
+
+        summary.report();
+        if success {
+            Ok(())
+        } else {
+            Err(crate::ops::CONFLICT_ERR.as_exit())
+        }
+    }
+}
+
+/// A staged hunk, as it appears in the diff of `HEAD` against the index
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Hunk {
+    path: std::path::PathBuf,
+    old_start: u32,
+    old_lines: u32,
+    new_start: u32,
+    new_lines: u32,
+}
+
+impl Hunk {
+    fn new(path: &std::path::Path, hunk: &git2::DiffHunk<'_>) -> Self {
+        Self {
+            path: path.to_owned(),
+            old_start: hunk.old_start(),
+            old_lines: hunk.old_lines(),
+            new_start: hunk.new_start(),
+            new_lines: hunk.new_lines(),
+        }
+    }
+}
+
+impl std::fmt::Display for Hunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.path.display(), self.new_start.max(1))
+    }
+}
+
+fn delta_path<'d>(delta: &git2::DiffDelta<'d>) -> Option<&'d std::path::Path> {
+    delta.new_file().path().or_else(|| delta.old_file().path())
+}
+
+/// Group the hunks of `diff` by the commit in the stack below `head_id` that they modify
+///
+/// Targets are ordered from the base up.  Hunks without a single target are returned with the
+/// reason they were skipped.  With `ignore_case`, a file whose name only changed case still counts
+/// as modified.
+#[allow(clippy::type_complexity)]
+fn assign_hunks(
+    repo: &git_stack::git::GitRepo,
+    graph: &git_stack::graph::Graph,
+    head_id: git2::Oid,
+    diff: &git2::Diff<'_>,
+    ignore_case: bool,
+) -> Result<(Vec<(git2::Oid, Vec<Hunk>)>, Vec<(Hunk, &'static str)>), eyre::Error> {
+    let mut candidates = Vec::new();
+    let mut current_id = Some(head_id);
+    while let Some(id) = current_id {
+        let action = graph
+            .commit_get::<git_stack::graph::Action>(id)
+            .copied()
+            .unwrap_or_default();
+        if action.is_protected() {
+            break;
+        }
+        candidates.push(id);
+        current_id = graph.primary_parent_of(id);
+    }
+
+    let mut targets: Vec<(git2::Oid, Vec<Hunk>)> = Vec::new();
+    let mut skipped = Vec::new();
+    for (delta_index, delta) in diff.deltas().enumerate() {
+        let Some(path) = delta_path(&delta) else {
+            continue;
+        };
+        let Some(patch) = git2::Patch::from_diff(diff, delta_index)? else {
+            continue;
+        };
+        let hunks = (0..patch.num_hunks())
+            .map(|i| patch.hunk(i).map(|(hunk, _)| Hunk::new(path, &hunk)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let old_path = delta.old_file().path().unwrap_or(path);
+        let modified = match delta.status() {
+            git2::Delta::Modified => true,
+            git2::Delta::Renamed => git_stack::git::same_path(old_path, path, ignore_case),
+            _ => false,
+        };
+        if !modified {
+            skipped.extend(
+                hunks
+                    .into_iter()
+                    .map(|h| (h, "the file is not just modified")),
+            );
+            continue;
+        }
+
+        let mut options = git2::BlameOptions::new();
+        options
+            .newest_commit(head_id)
+            .oldest_commit(graph.root_id());
+        let blame = repo.raw().blame_file(old_path, Some(&mut options))?;
+        for hunk in hunks {
+            if hunk.old_lines == 0 {
+                skipped.push((hunk, "it only adds lines"));
+                continue;
+            }
+            let mut blamed = (hunk.old_start..hunk.old_start + hunk.old_lines)
+                .map(|line| {
+                    blame
+                        .get_line(line as usize)
+                        .map(|blame_hunk| blame_hunk.final_commit_id())
+                })
+                .collect::<Vec<_>>();
+            blamed.sort();
+            blamed.dedup();
+            match blamed.as_slice() {
+                [Some(id)] if candidates.contains(id) => {
+                    match targets.iter_mut().find(|(target_id, _)| target_id == id) {
+                        Some((_, hunks)) => hunks.push(hunk),
+                        None => targets.push((*id, vec![hunk])),
+                    }
+                }
+                _ if !blamed.iter().flatten().any(|id| candidates.contains(id)) => {
+                    skipped.push((hunk, "no commit in the stack touched it"));
+                }
+                _ => {
+                    skipped.push((hunk, "its lines come from several commits"));
+                }
+            }
+        }
+    }
+
+    targets.sort_by_key(|(id, _)| std::cmp::Reverse(candidates.iter().position(|c| c == id)));
+    Ok((targets, skipped))
+}
+
+/// Apply only `hunks` of `diff` to `base`
+fn apply_hunks(
+    repo: &git2::Repository,
+    diff: &git2::Diff<'_>,
+    base: &git2::Tree<'_>,
+    hunks: &[Hunk],
+) -> Result<git2::Oid, eyre::Error> {
+    let current_path = std::cell::RefCell::new(None);
+    let mut options = git2::ApplyOptions::new();
+    options.delta_callback(|delta| {
+        let path = delta.as_ref().and_then(delta_path).map(|p| p.to_owned());
+        let wanted = hunks.iter().any(|h| Some(&h.path) == path.as_ref());
+        *current_path.borrow_mut() = path;
+        wanted
+    });
+    options.hunk_callback(|hunk| {
+        let current_path = current_path.borrow();
+        match (hunk, current_path.as_deref()) {
+            (Some(hunk), Some(path)) => hunks.contains(&Hunk::new(path, &hunk)),
+            _ => false,
+        }
+    });
+    let mut index = repo.apply_to_tree(base, diff, Some(&mut options))?;
+    let tree_id = index.write_tree_to(repo)?;
+    Ok(tree_id)
+}
+
+/// Re-stage the hunks that weren't absorbed, which popping the stash leaves unstaged
+///
+/// Once the fixups are squashed, `HEAD` has every absorbed hunk, so what is left to stage is the
+/// difference to the originally staged tree.
+fn restore_index(
+    repo: &git2::Repository,
+    absorbed_tree_id: Option<git2::Oid>,
+    index_tree_id: git2::Oid,
+) -> Result<(), git2::Error> {
+    let head_tree_id = repo.head()?.peel_to_tree()?.id();
+    if Some(head_tree_id) != absorbed_tree_id {
+        log::warn!("could not restore the staged changes that were not absorbed");
+        return Ok(());
+    }
+    let mut index = repo.index()?;
+    index.read_tree(&repo.find_tree(index_tree_id)?)?;
+    index.write()
+}
@@ -251,7 +251,7 @@ const ALIASES: &[Alias] = &[
 
 fn open_repo_config() -> Result<git2::Config, eyre::Error> {
     let cwd = std::env::current_dir()?;
-    let repo = git2::Repository::discover(cwd)?;
+    let repo = crate::ops::open_repo(&cwd)?;
     let config = repo.config()?;
     Ok(config)
 }
@@ -1,6 +1,5 @@
 use std::io::Write;
 
-use itertools::Itertools;
 use proc_exit::prelude::*;
 
 use git_stack::git::Repo;
@@ -12,15 +11,24 @@ use git_stack::git::Repo;
 ///
 /// When you amend a commit that has descendants, those descendants are rebased on top of the
 /// amended version of the commit, unless doing so would result in merge conflicts.
+///
+/// On failure, the repo is restored to its starting point: branches are moved back and the
+/// changes being amended are left staged.
 #[derive(clap::Args)]
 pub struct AmendArgs {
     /// Commit to rewrite
     #[arg(default_value = "HEAD")]
     rev: String,
 
-    /// Commit all changed files
-    #[arg(short, long)]
+    /// Amend the commit the local branch points to, rather than `REV`
+    #[arg(long, value_name = "BRANCH", conflicts_with = "rev")]
+    to_branch: Option<String>,
+
+    /// Commit all changed files, the default with `stack.amend.stage=all`
+    #[arg(short, long, overrides_with("no_all"))]
     all: bool,
+    #[arg(long, overrides_with("all"), hide = true)]
+    no_all: bool,
 
     /// Interactively add changes
     #[arg(
@@ -40,9 +48,74 @@ pub struct AmendArgs {
     #[arg(short, long)]
     message: Option<String>,
 
+    /// Keep the existing message without opening the editor
+    ///
+    /// With `--reedit-message`, the other commit's message is taken as-is, like
+    /// `--reuse-message`.
+    #[arg(long, conflicts_with_all = ["edit", "message"])]
+    no_edit: bool,
+
+    /// Show the diff below the message when editing it, like `git commit --verbose`
+    ///
+    /// Defaults to `commit.verbose`.
+    #[arg(long)]
+    diff: bool,
+
+    /// Take the commit message from another commit
+    #[arg(
+        short = 'C',
+        long,
+        value_name = "COMMIT",
+        conflicts_with_all = ["message", "edit", "reedit_message"]
+    )]
+    reuse_message: Option<String>,
+
+    /// Like `--reuse-message`, but edit the message first
+    #[arg(
+        short = 'c',
+        long,
+        value_name = "COMMIT",
+        conflicts_with_all = ["message", "edit"]
+    )]
+    reedit_message: Option<String>,
+
+    /// Make the configured user the author, with a fresh author date
+    #[arg(long)]
+    reset_author: bool,
+
+    /// Add a `Co-authored-by: NAME <EMAIL>` trailer, unless the message already has it
+    #[arg(long, value_name = "IDENT", value_parser = crate::args::parse_ident)]
+    co_author: Vec<crate::args::Ident>,
+
+    /// Amend even if the commit is protected, after confirming
+    #[arg(long)]
+    force: bool,
+
+    /// Don't ask for confirmation with `--force` or before rewriting more than
+    /// `stack.max-stack-size` commits
+    #[arg(short, long)]
+    yes: bool,
+
+    /// Only rewrite commits whose author name or email contains `PATTERN`
+    ///
+    /// Other commits are protected, along with everything they are built on, and what's built on
+    /// them stays on them.
+    #[arg(long, value_name = "PATTERN")]
+    author: Option<String>,
+
+    /// Skip the `git branch-stash` backup, losing the ability to undo
+    #[arg(long)]
+    no_backup: bool,
+
     /// Don't actually switch
     #[arg(short = 'n', long)]
     dry_run: bool,
+
+    /// Stage changes to tracked files matching these, like `--all` limited to them
+    ///
+    /// Paths can be left out with git's `:(exclude)` / `:!` magic, e.g. `-- . ':!generated/'`.
+    #[arg(last = true, value_name = "PATHSPEC", conflicts_with_all = ["all", "interactive"])]
+    pathspecs: Vec<String>,
 }
 
 impl AmendArgs {
@@ -56,55 +129,49 @@ impl AmendArgs {
         }
     }
 
-    pub fn exec(&self) -> proc_exit::ExitResult {
+    pub fn exec(&self, global: &git_stack::config::RepoConfig) -> proc_exit::ExitResult {
         let stderr_palette = crate::ops::Palette::colored();
 
         let cwd = std::env::current_dir().with_code(proc_exit::sysexits::USAGE_ERR)?;
-        let repo = git2::Repository::discover(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
-        let mut repo = git_stack::git::GitRepo::new(repo);
-
-        let repo_config = git_stack::config::RepoConfig::from_all(repo.raw())
-            .with_code(proc_exit::sysexits::CONFIG_ERR)?;
-        repo.set_push_remote(repo_config.push_remote());
-        repo.set_pull_remote(repo_config.pull_remote());
-        let config = repo
-            .raw()
-            .config()
-            .with_code(proc_exit::sysexits::CONFIG_ERR)?;
-        repo.set_sign(
-            config
-                .get_bool("stack.gpgSign")
-                .or_else(|_| config.get_bool("commit.gpgSign"))
-                .unwrap_or_default(),
-        )
-        .with_code(proc_exit::Code::FAILURE)?;
+        let (mut repo, repo_config) = crate::ops::open_configured_repo(global)?;
+        if self.message.is_none() && self.should_edit() {
+            crate::ops::ensure_editor(&repo_config).with_code(proc_exit::sysexits::USAGE_ERR)?;
+        }
+        crate::ops::configure_signing(&mut repo)?;
 
-        let protected = git_stack::git::ProtectedBranches::new(
-            repo_config.protected_branches().iter().map(|s| s.as_str()),
-        )
-        .with_code(proc_exit::sysexits::CONFIG_ERR)?;
-        let branches = git_stack::graph::BranchSet::from_repo(&repo, &protected)
-            .with_code(proc_exit::Code::FAILURE)?;
+        let branches = crate::ops::load_branches(&repo, &repo_config)?;
 
-        let head_id = crate::ops::resolve_explicit_base(&repo, &self.rev)
-            .with_code(proc_exit::Code::FAILURE)?
-            .id;
+        let head_id = if let Some(name) = self.to_branch.as_deref() {
+            repo.find_local_branch(name)
+                .ok_or_else(|| {
+                    proc_exit::sysexits::USAGE_ERR
+                        .with_message(format!("could not find local branch `{name}`"))
+                })?
+                .id
+        } else {
+            crate::ops::resolve_explicit_base(&repo, &self.rev)
+                .with_code(proc_exit::Code::FAILURE)?
+                .id
+        };
         let head = repo.find_commit(head_id).expect("explicit bases exist");
+        let message_source = self
+            .reuse_message
+            .as_deref()
+            .or(self.reedit_message.as_deref())
+            .map(|rev| {
+                repo.resolve(rev).ok_or_else(|| {
+                    proc_exit::sysexits::USAGE_ERR
+                        .with_message(format!("could not find commit `{rev}`"))
+                })
+            })
+            .transpose()?;
         let base = crate::ops::resolve_implicit_base(
             &repo,
             head_id,
             &branches,
             repo_config.auto_base_commit_count(),
         );
-        let merge_base_oid = repo
-            .merge_base(base.id, head_id)
-            .ok_or_else(|| {
-                git2::Error::new(
-                    git2::ErrorCode::NotFound,
-                    git2::ErrorClass::Reference,
-                    format!("could not find base between {base} and HEAD"),
-                )
-            })
+        let merge_base_oid = crate::ops::resolve_merge_base(&repo, &base, head_id, "HEAD")
             .with_code(proc_exit::sysexits::USAGE_ERR)?;
         let stack_branches = branches.descendants(&repo, merge_base_oid);
         let mut graph = git_stack::graph::Graph::from_branches(&repo, stack_branches)
@@ -112,20 +179,18 @@ impl AmendArgs {
         git_stack::graph::protect_branches(&mut graph);
         git_stack::graph::mark_fixup(&mut graph, &repo);
         git_stack::graph::mark_wip(&mut graph, &repo);
+        crate::ops::protect_other_authors(&mut graph, &repo, self.author.as_deref());
 
-        if repo.raw().state() != git2::RepositoryState::Clean {
-            let message = format!("cannot walk commits, {:?} in progress", repo.raw().state());
-            if self.dry_run {
-                let _ = writeln!(
-                    anstream::stderr(),
-                    "{}: {}",
-                    stderr_palette.error("error"),
-                    message
-                );
-            } else {
-                return Err(proc_exit::sysexits::USAGE_ERR.with_message(message));
-            }
+        if !self.dry_run {
+            crate::ops::confirm_stack_size(
+                git_stack::graph::pickable_count(&graph),
+                repo_config.max_stack_size(),
+                self.yes,
+            )
+            .with_code(proc_exit::sysexits::USAGE_ERR)?;
         }
+
+        crate::ops::ensure_clean_state(repo.raw(), "walk commits", self.dry_run)?;
         let action = graph
             .commit_get::<git_stack::graph::Action>(head_id)
             .copied()
@@ -133,42 +198,85 @@ impl AmendArgs {
         match action {
             git_stack::graph::Action::Pick => {}
             git_stack::graph::Action::Fixup => {}
+            git_stack::graph::Action::Protected if self.force => {
+                let abbrev_id = repo.abbrev_id(head_id);
+                let prompt = format!("commit {} is protected; amend anyway?", abbrev_id.as_str());
+                if !self.yes && !crate::ops::confirm(&prompt).with_code(proc_exit::Code::FAILURE)? {
+                    return Err(proc_exit::Code::FAILURE.with_message("aborted"));
+                }
+                git_stack::graph::unprotect_descendants(&mut graph, head_id);
+            }
             git_stack::graph::Action::Protected => {
-                return Err(proc_exit::Code::FAILURE.with_message("cannot amend protected commits"));
+                return Err(
+                    proc_exit::sysexits::USAGE_ERR.with_message("cannot amend protected commits")
+                );
             }
         }
 
-        let index_tree = stage_fixup(
+        // Staging flags take precedence over `stack.amend.stage`
+        let all = if self.interactive || !self.pathspecs.is_empty() {
+            false
+        } else {
+            crate::args::resolve_bool_arg(self.all, self.no_all)
+                .unwrap_or(repo_config.amend_stage() == git_stack::config::AmendStage::All)
+        };
+        // Checking out the fixup commit resets the index, so keep a copy to put back on failure
+        let original_index = repo.scratch_index().with_code(proc_exit::Code::FAILURE)?;
+        let (index_tree, scratch_index) = stage_fixup(
             &repo,
-            self.all,
+            all,
             self.interactive,
+            &self.pathspecs,
             stderr_palette,
             self.dry_run,
         )
         .with_code(proc_exit::Code::FAILURE)?;
-        let fixup_id = commit_fixup(
+        // Nothing needs rebasing when amending the tip, so skip stashing and checking out the
+        // worktree and just move the refs
+        let is_tip =
+            head_id == repo.head_commit().id && graph.children_of(head_id).next().is_none();
+        let original_head_id = repo.head_commit().id;
+        let original_branches = repo.local_branches().collect::<Vec<_>>();
+        let fixup_id = crate::ops::commit_fixup(
             &mut repo,
             &graph.branches,
             head_id,
             index_tree,
-            self.dry_run,
+            self.dry_run || is_tip,
         )
         .with_code(proc_exit::Code::FAILURE)?;
-        if let Some(fixup_id) = fixup_id {
-            if let Some(parent_id) = repo.parent_ids(fixup_id).expect("commit exists").first() {
-                graph.insert(git_stack::graph::Node::new(fixup_id), *parent_id);
+        if let Some(fixup_id) = fixup_id.filter(|_| !is_tip) {
+            let parent_id = repo
+                .parent_ids(fixup_id)
+                .expect("commit exists")
+                .first()
+                .copied();
+            match parent_id {
+                Some(parent_id) if graph.contains_id(parent_id) => {
+                    // Patch the graph in place rather than re-reading every branch
+                    graph.insert(git_stack::graph::Node::new(fixup_id), parent_id);
+                    graph.commit_set(fixup_id, git_stack::graph::Fixup);
+                    if !self.dry_run {
+                        graph.branches.move_local(parent_id, fixup_id);
+                    }
+                }
+                _ => {
+                    let branches = crate::ops::load_branches(&repo, &repo_config)?;
+                    let stack_branches = branches.descendants(&repo, merge_base_oid);
+                    graph = git_stack::graph::Graph::from_branches(&repo, stack_branches)
+                        .with_code(proc_exit::Code::FAILURE)?;
+                    git_stack::graph::protect_branches(&mut graph);
+                    git_stack::graph::mark_fixup(&mut graph, &repo);
+                    git_stack::graph::mark_wip(&mut graph, &repo);
+                    crate::ops::protect_other_authors(&mut graph, &repo, self.author.as_deref());
+                }
             }
-            graph.commit_set(fixup_id, git_stack::graph::Fixup);
         }
-        graph
-            .branches
-            .update(&repo)
-            .with_code(proc_exit::Code::FAILURE)?;
 
         let mut backed_up = false;
-        {
+        if !self.no_backup && repo_config.backup() {
             let stash_repo =
-                git2::Repository::discover(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
+                crate::ops::open_repo(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
             let stash_repo = git_branch_stash::GitRepo::new(stash_repo);
             let mut snapshots =
                 git_branch_stash::Stack::new(crate::ops::STASH_STACK_NAME, &stash_repo);
@@ -184,108 +292,173 @@ impl AmendArgs {
 
         let new_message = if let Some(message) = self.message.as_deref() {
             Some(message.trim().to_owned())
-        } else if self.edit {
+        } else if self.should_edit() {
             use std::fmt::Write;
 
-            let raw_commit = repo
-                .raw()
-                .find_commit(head_id)
-                .expect("head_commit is always valid");
-            let existing = String::from_utf8_lossy(raw_commit.message_bytes());
+            let existing = std::borrow::Cow::Borrowed(
+                message_source.as_ref().unwrap_or(&head).message.as_str(),
+            );
+            let existing = if existing.trim().is_empty() {
+                crate::ops::commit_template(repo.raw())
+                    .map(std::borrow::Cow::Owned)
+                    .unwrap_or(existing)
+            } else {
+                existing
+            };
             let mut template = String::new();
             writeln!(&mut template, "{existing}").unwrap();
             writeln!(&mut template).unwrap();
-            writeln!(
-                &mut template,
-                "# Please enter the commit message for your changes. Lines starting"
-            )
-            .unwrap();
-            writeln!(
-                &mut template,
-                "# with '#' will be ignored, and an empty message aborts the commit."
-            )
-            .unwrap();
+            crate::ops::write_cleanup_hint(&mut template, repo_config.cleanup());
             let head_branch = repo.head_branch();
             if let Some(head_branch) = &head_branch {
                 writeln!(&mut template, "#").unwrap();
                 writeln!(&mut template, "# On branch {head_branch}").unwrap();
             }
+            if self.diff || repo.config_bool("commit.verbose", false) {
+                // Amending `HEAD` also takes in what was just staged
+                let tree_id = if head_id == original_head_id {
+                    index_tree
+                } else {
+                    repo.raw()
+                        .find_commit(head_id)
+                        .with_code(proc_exit::Code::FAILURE)?
+                        .tree_id()
+                };
+                let parent_id = repo
+                    .parent_ids(head_id)
+                    .with_code(proc_exit::Code::FAILURE)?
+                    .first()
+                    .copied();
+                writeln!(&mut template, "#").unwrap();
+                crate::ops::append_verbose_diff(&mut template, repo.raw(), parent_id, tree_id)
+                    .with_code(proc_exit::Code::FAILURE)?;
+            }
             let message = crate::ops::edit_commit(
                 repo.path()
                     .ok_or_else(|| eyre::format_err!("no `.git` path found"))
                     .with_code(proc_exit::Code::FAILURE)?,
                 repo_config.editor(),
+                repo_config.cleanup(),
                 &template,
             )
             .with_code(proc_exit::Code::FAILURE)?;
             message
         } else {
-            None
+            message_source.map(|source| source.message.trim().to_owned())
         };
 
-        if fixup_id.is_none() && new_message.is_none() {
-            let abbrev_id = repo
-                .raw()
-                .find_object(head_id, None)
-                .unwrap_or_else(|e| panic!("Unexpected git2 error: {e}"))
-                .short_id()
-                .unwrap_or_else(|e| panic!("Unexpected git2 error: {e}"));
+        let new_message = if self.co_author.is_empty() {
+            new_message
+        } else {
+            let co_authors = self
+                .co_author
+                .iter()
+                .map(|ident| ident.to_string())
+                .collect::<Vec<_>>();
+            let message = crate::ops::add_trailers(
+                new_message.as_deref().unwrap_or(&head.message),
+                "Co-authored-by",
+                &co_authors,
+            );
+            (new_message.is_some() || message != head.message.trim_end()).then_some(message)
+        };
+
+        if fixup_id.is_none() && new_message.is_none() && !self.reset_author {
+            let abbrev_id = repo.abbrev_id(head_id);
             let _ = writeln!(
                 anstream::stderr(),
                 "{} nothing to amend to {}: {}",
                 stderr_palette.error("error:"),
-                stderr_palette.highlight(abbrev_id.as_str().unwrap()),
+                stderr_palette.highlight(abbrev_id.as_str()),
                 stderr_palette.hint(&head.summary)
             );
-            return Err(proc_exit::Code::FAILURE.as_exit());
+            return Err(proc_exit::sysexits::USAGE_ERR.as_exit());
         }
 
         let mut stash_id = None;
-        if !self.dry_run {
-            stash_id = git_stack::git::stash_push(&mut repo, "amend");
-        }
-
-        git_stack::graph::fixup(&mut graph, &repo, git_stack::config::Fixup::Squash);
-        if let Some(new_message) = new_message {
-            git_stack::graph::reword_commit(&mut graph, &repo, head_id, new_message)
-                .with_code(proc_exit::Code::FAILURE)?;
-        }
-
         let mut success = true;
-        let scripts = git_stack::graph::to_scripts(&graph, vec![]);
-        let head_branch = repo.head_branch();
-        let mut executor = git_stack::rewrite::Executor::new(self.dry_run);
-        for script in scripts {
-            let results = executor.run(&mut repo, &script);
-            for (err, name, dependents) in results.iter() {
-                success = false;
-                log::error!("Failed to re-stack branch `{}`: {}", name, err);
-                if !dependents.is_empty() {
-                    log::error!("  Blocked dependents: {}", dependents.iter().join(", "));
-                }
+        let mut summary = crate::ops::RestackSummary::default();
+        if is_tip {
+            amend_tip(
+                &mut repo,
+                &graph.branches,
+                head_id,
+                fixup_id,
+                new_message.as_deref(),
+                self.reset_author,
+                self.dry_run,
+            )
+            .with_code(proc_exit::Code::FAILURE)?;
+            // Nothing gets checked out, so the index has to catch up with the amended commit
+            if !self.dry_run {
+                scratch_index
+                    .persist()
+                    .with_code(proc_exit::Code::FAILURE)?;
             }
+        } else {
+            if !self.dry_run {
+                stash_id = git_stack::git::stash_push(&mut repo, "amend");
+            }
+
+            git_stack::graph::fixup(&mut graph, &repo, git_stack::config::Fixup::Squash);
+            success = match rewrite_stack(
+                &mut repo,
+                &mut graph,
+                head_id,
+                new_message,
+                self.reset_author,
+                repo_config.rebase_merges(),
+                self.dry_run,
+            ) {
+                Ok(restacked) => {
+                    summary = restacked;
+                    summary.is_success()
+                }
+                Err(err) => {
+                    log::error!("{}", err);
+                    false
+                }
+            };
         }
-        executor
-            .close(&mut repo, head_branch.as_ref().and_then(|b| b.local_name()))
-            .with_code(proc_exit::Code::FAILURE)?;
 
         if success {
-            let abbrev_id = repo
-                .raw()
-                .find_object(head_id, None)
-                .unwrap_or_else(|e| panic!("Unexpected git2 error: {e}"))
-                .short_id()
-                .unwrap_or_else(|e| panic!("Unexpected git2 error: {e}"));
+            let abbrev_id = repo.abbrev_id(head_id);
             let _ = writeln!(
                 anstream::stderr(),
                 "{} to {}: {}",
                 stderr_palette.good("Amended"),
-                stderr_palette.highlight(abbrev_id.as_str().unwrap()),
+                stderr_palette.highlight(abbrev_id.as_str()),
                 stderr_palette.hint(&head.summary)
             );
         }
 
         git_stack::git::stash_pop(&mut repo, stash_id);
+        // Unstaged changes were stashed on top of the fixup commit, so restore after popping them
+        if !success && !self.dry_run {
+            match restore_branches(
+                &mut repo,
+                &original_branches,
+                original_head_id,
+                original_index,
+            ) {
+                Ok(()) => {
+                    let restored = if all {
+                        "restored branches and the index"
+                    } else {
+                        "restored branches, leaving the changes staged"
+                    };
+                    let _ = writeln!(
+                        anstream::stderr(),
+                        "{}: {}",
+                        stderr_palette.info("note"),
+                        restored
+                    );
+                }
+                Err(err) => {
+                    log::error!("Failed to restore branches: {}", err);
+                }
+            }
+        }
         if backed_up {
             anstream::eprintln!(
                 "{}: to undo, run {}",
@@ -297,26 +470,41 @@ impl AmendArgs {
             );
         }
 
+        summary.report();
         if success {
             Ok(())
         } else {
-            Err(proc_exit::Code::FAILURE.as_exit())
+            Err(crate::ops::CONFLICT_ERR.as_exit())
         }
     }
+
+    fn should_edit(&self) -> bool {
+        (self.edit || self.reedit_message.is_some()) && !self.no_edit
+    }
 }
 
 fn stage_fixup(
     repo: &git_stack::git::GitRepo,
     all: bool,
     interactive: bool,
+    pathspecs: &[String],
     stderr_palette: crate::ops::Palette,
     dry_run: bool,
-) -> Result<git2::Oid, eyre::Error> {
-    let mut index = repo.raw().index()?;
-    if all {
+) -> Result<(git2::Oid, git_stack::git::ScratchIndex), eyre::Error> {
+    // Stage into a copy so a failed amend leaves what the user had staged alone
+    let mut scratch_index = repo.scratch_index()?;
+    let index = scratch_index.index_mut();
+    if all || !pathspecs.is_empty() {
+        let filter = PathFilter::new(repo.raw(), pathspecs, repo.ignore_case())?;
+        // Like `git add`, this runs the worktree contents through the `core.autocrlf` /
+        // `core.eol` / `.gitattributes` filters, so the blobs match what `git commit -a` would
+        // write.  Without `--all`, git already normalized whatever was staged.
         index.update_all(
             ["*"].iter(),
             Some(&mut |path, _| {
+                if !filter.matches(path) {
+                    return 1;
+                }
                 let _ = writeln!(
                     anstream::stderr(),
                     "{} {}",
@@ -324,10 +512,8 @@ fn stage_fixup(
                     path.display()
                 );
                 if dry_run {
-                    // skip
                     1
                 } else {
-                    // confirm
                     0
                 }
             }),
@@ -338,86 +524,229 @@ fn stage_fixup(
         // - https://github.com/arxanas/git-branchless/tree/master/git-record
         todo!("interactive support")
     }
-    let tree_id = index.write_tree()?;
-    Ok(tree_id)
+    let tree_id = scratch_index.write_tree()?;
+    Ok((tree_id, scratch_index))
 }
 
-fn commit_fixup(
-    repo: &mut git_stack::git::GitRepo,
-    branches: &git_stack::graph::BranchSet,
-    target_id: git2::Oid,
-    tree_id: git2::Oid,
-    dry_run: bool,
-) -> Result<Option<git2::Oid>, eyre::Error> {
-    let parent_id = repo.head_commit().id;
-
-    let id = {
-        let parent_raw_commit = repo
-            .raw()
-            .find_commit(parent_id)
-            .expect("head_commit is always valid");
-        if parent_raw_commit.tree_id() == tree_id {
-            return Ok(None);
-        }
+/// Which paths to stage, following git's rules for combining pathspecs
+///
+/// A path is picked when it matches any of the plain pathspecs (or there are none) and none of
+/// the `:(exclude)` ones.  With `core.ignorecase`, case is ignored like git does.
+struct PathFilter {
+    include: Option<git2::Pathspec>,
+    exclude: Option<git2::Pathspec>,
+    flags: git2::PathspecFlags,
+}
 
-        let target_commit = repo.find_commit(target_id).unwrap();
+impl PathFilter {
+    fn new(
+        repo: &git2::Repository,
+        pathspecs: &[String],
+        ignore_case: bool,
+    ) -> Result<Self, eyre::Error> {
+        // Like git, pathspecs are relative to the current directory unless they use `:(top)`
+        let prefix = match repo.workdir() {
+            Some(workdir) => {
+                let cwd = std::env::current_dir()?.canonicalize()?;
+                let workdir = workdir.canonicalize()?;
+                cwd.strip_prefix(&workdir)
+                    .map(|p| p.to_string_lossy().replace('\\', "/"))
+                    .unwrap_or_default()
+            }
+            None => String::new(),
+        };
 
-        let tree = repo.raw().find_tree(tree_id)?;
-        let message = format!(
-            "fixup! {}",
-            target_commit
-                .fixup_summary()
-                .unwrap_or_else(|| target_commit.summary.as_ref())
-        );
-        let id = git2_ext::ops::commit(
-            repo.raw(),
-            &parent_raw_commit.author(),
-            &parent_raw_commit.committer(),
-            &message,
-            &tree,
-            &[&parent_raw_commit],
-            None,
-        )?;
-        log::debug!("committed {} {}", id, message);
-        id
-    };
-    if !dry_run {
-        let mut stash_id = None;
-        if repo.is_dirty() {
-            stash_id = repo.stash_push(None).ok();
+        let mut include = Vec::new();
+        let mut exclude = Vec::new();
+        for pathspec in pathspecs {
+            let magic = parse_pathspec_magic(pathspec)?;
+            let pattern = magic.pattern.trim_start_matches("./").trim_end_matches('/');
+            let pattern = if pattern == "." { "" } else { pattern };
+            let pattern = match (magic.top || prefix.is_empty(), pattern.is_empty()) {
+                (true, true) => "*".to_owned(),
+                (true, false) => pattern.to_owned(),
+                (false, true) => prefix.clone(),
+                (false, false) => format!("{prefix}/{pattern}"),
+            };
+            if magic.exclude {
+                exclude.push(pattern);
+            } else {
+                include.push(pattern);
+            }
         }
 
-        let head_branch = repo.head_branch();
-        if head_branch.is_some() {
-            repo.detach()?;
-        }
-        for branch in branches.get(parent_id).into_iter().flatten() {
-            if let Some(name) = branch.local_name() {
-                repo.branch(name, id)?;
+        let to_pathspec = |patterns: Vec<String>| {
+            (!patterns.is_empty())
+                .then(|| git2::Pathspec::new(patterns))
+                .transpose()
+        };
+        Ok(Self {
+            include: to_pathspec(include)?,
+            exclude: to_pathspec(exclude)?,
+            flags: if ignore_case {
+                git2::PathspecFlags::IGNORE_CASE
+            } else {
+                git2::PathspecFlags::DEFAULT
+            },
+        })
+    }
+
+    fn matches(&self, path: &std::path::Path) -> bool {
+        let flags = self.flags;
+        let included = self
+            .include
+            .as_ref()
+            .map(|include| include.matches_path(path, flags))
+            .unwrap_or(true);
+        let excluded = self
+            .exclude
+            .as_ref()
+            .map(|exclude| exclude.matches_path(path, flags))
+            .unwrap_or(false);
+        included && !excluded
+    }
+}
+
+struct PathspecMagic<'p> {
+    pattern: &'p str,
+    exclude: bool,
+    top: bool,
+}
+
+/// Split off the `:(exclude,top)` or short `:!/` magic git allows in front of a pathspec
+fn parse_pathspec_magic(pathspec: &str) -> Result<PathspecMagic<'_>, eyre::Error> {
+    let mut magic = PathspecMagic {
+        pattern: pathspec,
+        exclude: false,
+        top: false,
+    };
+    if let Some(rest) = pathspec.strip_prefix(":(") {
+        let (words, pattern) = rest
+            .split_once(')')
+            .ok_or_else(|| eyre::eyre!("missing `)` in pathspec `{pathspec}`"))?;
+        for word in words.split(',') {
+            match word {
+                "exclude" => magic.exclude = true,
+                "top" => magic.top = true,
+                _ => eyre::bail!("unsupported pathspec magic `{word}` in `{pathspec}`"),
             }
         }
-        if let Some(head_branch) = head_branch {
-            log::debug!("switching to {} {}", head_branch, id);
-            repo.switch_branch(
-                head_branch
-                    .local_name()
-                    .expect("HEAD branch is always local"),
+        magic.pattern = pattern;
+    } else if let Some(rest) = pathspec.strip_prefix(':') {
+        let end = rest
+            .find(|c| !matches!(c, '!' | '^' | '/'))
+            .unwrap_or(rest.len());
+        let (short, pattern) = rest.split_at(end);
+        magic.exclude = short.contains(['!', '^']);
+        magic.top = short.contains('/');
+        magic.pattern = pattern.strip_prefix(':').unwrap_or(pattern);
+    }
+    Ok(magic)
+}
+
+/// Squash the fixup into its target and re-stack the descendants
+fn rewrite_stack(
+    repo: &mut git_stack::git::GitRepo,
+    graph: &mut git_stack::graph::Graph,
+    head_id: git2::Oid,
+    new_message: Option<String>,
+    reset_author: bool,
+    rebase_merges: bool,
+    dry_run: bool,
+) -> Result<crate::ops::RestackSummary, eyre::Error> {
+    if let Some(new_message) = new_message {
+        git_stack::graph::reword_commit(graph, repo, head_id, new_message)?;
+    }
+    if reset_author {
+        git_stack::graph::reset_author(graph, head_id)?;
+    }
+
+    let mut summary = crate::ops::RestackSummary::default();
+    let scripts = git_stack::graph::to_scripts(graph, vec![]);
+    crate::ops::ensure_no_merges(
+        repo.raw(),
+        scripts.iter().flat_map(|s| s.stats().rewritten),
+        rebase_merges,
+    )?;
+    let head_branch = repo.head_branch();
+    let mut executor = git_stack::rewrite::Executor::new(dry_run);
+    for script in scripts {
+        let results = executor.run(repo, &script);
+        let branches = script.stats().branches;
+        summary.record(branches.iter().map(String::as_str), &results);
+    }
+    executor.close(repo, head_branch.as_ref().and_then(|b| b.local_name()))?;
+    Ok(summary)
+}
+
+/// Move every branch back to where it was before the amend, like `git reset --soft`
+///
+/// The index goes back to what the user had staged and the worktree is left alone, so the
+/// changes being amended are not lost in the abandoned fixup commit.
+fn restore_branches(
+    repo: &mut git_stack::git::GitRepo,
+    branches: &[git_stack::git::Branch],
+    head_id: git2::Oid,
+    index: git_stack::git::ScratchIndex,
+) -> Result<(), eyre::Error> {
+    for branch in branches {
+        let current_id = repo.find_local_branch(&branch.name).map(|b| b.id);
+        if current_id != Some(branch.id) {
+            log::debug!("restoring {} to {}", branch.name, branch.id);
+            repo.raw().reference(
+                &format!("refs/heads/{}", branch.name),
+                branch.id,
+                true,
+                "git-stack: amend aborted",
             )?;
-        } else {
-            log::debug!("switching to {}", id);
-            repo.switch_commit(id)?;
         }
+    }
+    if repo.head_branch().is_none() {
+        repo.raw().set_head_detached(head_id)?;
+    }
+    index.persist()?;
+    Ok(())
+}
 
-        if let Some(stash_id) = stash_id {
-            match repo.stash_pop(stash_id) {
-                Ok(()) => {
-                    log::debug!("Dropped refs/stash {}", stash_id);
-                }
-                Err(err) => {
-                    log::error!("Failed to pop {} from stash: {}", stash_id, err);
-                }
+/// Amend a commit without descendants by updating its refs in place
+///
+/// The worktree is left untouched; the caller writes the staged index so it matches the amended
+/// tree.
+fn amend_tip(
+    repo: &mut git_stack::git::GitRepo,
+    branches: &git_stack::graph::BranchSet,
+    head_id: git2::Oid,
+    fixup_id: Option<git2::Oid>,
+    new_message: Option<&str>,
+    reset_author: bool,
+    dry_run: bool,
+) -> Result<git2::Oid, eyre::Error> {
+    let mut amended_id = head_id;
+    if let Some(fixup_id) = fixup_id {
+        amended_id = repo.squash(fixup_id, amended_id)?;
+    }
+    if let Some(new_message) = new_message {
+        amended_id = repo.reword(amended_id, new_message)?;
+    }
+    if reset_author {
+        amended_id = repo.reset_author(amended_id)?;
+    }
+    log::debug!("amended {} to {}", head_id, amended_id);
+
+    if !dry_run {
+        for branch in branches.get(head_id).into_iter().flatten() {
+            if let Some(name) = branch.local_name() {
+                repo.raw().reference(
+                    &format!("refs/heads/{name}"),
+                    amended_id,
+                    true,
+                    "git-stack: amend",
+                )?;
             }
         }
+        if repo.head_branch().is_none() {
+            repo.raw().set_head_detached(amended_id)?;
+        }
     }
-    Ok(Some(id))
+    Ok(amended_id)
 }
@@ -11,6 +11,10 @@ pub struct Args {
     #[arg(long)]
     pub pull: bool,
 
+    /// Rebase onto the already-fetched remote base branch, leaving the local one as-is
+    #[arg(long, conflicts_with_all = ["onto", "pull"])]
+    pub onto_remote: bool,
+
     /// Push all ready branches
     #[arg(long)]
     pub push: bool,
@@ -40,12 +44,104 @@ pub struct Args {
     #[arg(short = 'n', long)]
     pub dry_run: bool,
 
+    /// Don't ask for confirmation before rewriting more than `stack.max-stack-size` commits
+    #[arg(short, long)]
+    pub yes: bool,
+
+    /// Branch to end on after rebasing (default: the current branch or, if it gets deleted, the
+    /// closest branch on top of it)
+    #[arg(long, value_name = "BRANCH")]
+    pub switch: Option<String>,
+
     #[arg(long, value_enum)]
     pub format: Option<git_stack::config::Format>,
 
+    /// Short for `--format porcelain`
+    #[arg(long, conflicts_with = "format")]
+    pub porcelain: bool,
+
+    /// Write the stacks as a Graphviz DOT graph to stdout, for debugging
+    #[arg(long, conflicts_with_all = ["format", "porcelain"])]
+    pub dump_graph: bool,
+
+    /// Report how long each phase took to stderr, for performance tuning
+    #[arg(long)]
+    pub timings: bool,
+
     #[arg(long, value_enum)]
     pub show_commits: Option<git_stack::config::ShowCommits>,
 
+    /// How to order sibling stacks
+    #[arg(long, value_enum)]
+    pub order: Option<git_stack::config::LogOrder>,
+
+    /// Truncate commit summaries to fit in `N` columns (default: terminal width)
+    #[arg(long, value_name = "N")]
+    pub width: Option<usize>,
+
+    /// Show full commit summaries, even if they wrap
+    #[arg(long, conflicts_with = "width")]
+    pub no_truncate: bool,
+
+    /// Show up to `N` commits below the base of each stack, or `all`
+    ///
+    /// Deeper commits are collapsed into a marker; pass a larger `N` to expand them.
+    #[arg(long, value_name = "N", value_parser = parse_depth)]
+    pub depth: Option<usize>,
+
+    /// Show every protected commit rather than collapsing long runs of them
+    #[arg(long)]
+    pub show_protected: bool,
+
+    /// Show the stacks hidden with `git stack hide` too
+    #[arg(long)]
+    pub show_all: bool,
+
+    /// Only show commits that haven't been pushed yet
+    #[arg(long)]
+    pub since_pushed: bool,
+
+    /// Only follow first parents, like `git log --first-parent`, leaving out merged-in commits
+    ///
+    /// Same as `stack.first-parent`, for every command.
+    #[arg(long, global = true)]
+    pub first_parent: bool,
+
+    /// Recreate merge commits in the stack rather than refusing to rewrite them
+    ///
+    /// The merged-in side is left as-is.  Same as `stack.rebase-merges`, for every command.
+    #[arg(long, global = true)]
+    pub rebase_merges: bool,
+
+    /// How commits are replayed when moving them
+    ///
+    /// Same as `stack.rebase.strategy`, for every command.
+    #[arg(long, global = true, value_enum, value_name = "STRATEGY")]
+    pub rebase_strategy: Option<git_stack::config::RebaseStrategy>,
+
+    /// List the files each commit touches, under it
+    #[arg(long)]
+    pub stat_by_file: bool,
+
+    /// With `--stat-by-file`, list every file rather than only the first few
+    #[arg(long, requires = "stat_by_file")]
+    pub all_files: bool,
+
+    /// Mark each commit as signed or unsigned
+    #[arg(long)]
+    pub show_signature: bool,
+
+    /// With `--show-signature`, check signatures with `git verify-commit`
+    #[arg(long, requires = "show_signature")]
+    pub verify: bool,
+
+    /// Only rewrite commits whose author name or email contains `PATTERN`
+    ///
+    /// Other commits are protected, along with everything they are built on, and what's built on
+    /// them stays on them.
+    #[arg(long, value_name = "PATTERN")]
+    pub author: Option<String>,
+
     /// See what branches are protected
     #[arg(long, group = "mode")]
     pub protected: bool,
@@ -54,6 +150,33 @@ pub struct Args {
     #[arg(long, group = "mode")]
     pub protect: Option<String>,
 
+    #[command(flatten)]
+    pub git: GitOptions,
+
+    /// Write the current configuration to file with `-` for stdout
+    #[arg(long, group = "mode")]
+    pub dump_config: Option<std::path::PathBuf>,
+
+    /// Use `NAME <EMAIL>` as the committer of every commit that gets rewritten
+    ///
+    /// This is the same as setting `GIT_COMMITTER_NAME` and `GIT_COMMITTER_EMAIL`.  The author is
+    /// kept, unless also resetting it.
+    #[arg(long, global = true, value_name = "IDENT", value_parser = parse_ident)]
+    pub committer: Option<Ident>,
+
+    #[command(flatten)]
+    pub(crate) color: colorchoice_clap::Color,
+
+    #[command(flatten)]
+    pub verbose: clap_verbosity_flag::Verbosity<clap_verbosity_flag::InfoLevel>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Options for finding the repository, which like with `git` can come before a subcommand
+#[derive(clap::Args, Default)]
+pub struct GitOptions {
     /// Run as if git was started in `PATH` instead of the current working directory.
     ///
     /// When multiple -C options are given, each subsequent
@@ -65,21 +188,69 @@ pub struct Args {
     ///
     ///     git --git-dir=a.git --work-tree=b -C c status
     ///     git --git-dir=c/a.git --work-tree=c/b status
-    #[arg(short = 'C', hide = true, value_name = "PATH")]
+    #[arg(short = 'C', value_name = "PATH")]
     pub current_dir: Option<Vec<std::path::PathBuf>>,
 
-    /// Write the current configuration to file with `-` for stdout
-    #[arg(long, group = "mode")]
-    pub dump_config: Option<std::path::PathBuf>,
+    /// Set the path to the repository (".git" directory), like `GIT_DIR`
+    #[arg(long, value_name = "PATH")]
+    pub git_dir: Option<std::path::PathBuf>,
 
-    #[command(flatten)]
-    pub(crate) color: colorchoice_clap::Color,
+    /// Set the path to the working tree, like `GIT_WORK_TREE`
+    #[arg(long, value_name = "PATH")]
+    pub work_tree: Option<std::path::PathBuf>,
+}
 
-    #[command(flatten)]
-    pub verbose: clap_verbosity_flag::Verbosity<clap_verbosity_flag::InfoLevel>,
+impl GitOptions {
+    /// Pull these options off the front of `argv`, returning them and what's left
+    ///
+    /// [`Args`] doesn't allow its own options alongside a subcommand, so `git-stack -C <path>
+    /// reword` would otherwise be rejected.
+    pub fn split_leading(
+        argv: Vec<std::ffi::OsString>,
+    ) -> Result<(Self, Vec<std::ffi::OsString>), clap::Error> {
+        #[derive(clap::Parser)]
+        #[command(no_binary_name = true)]
+        struct Leading {
+            #[command(flatten)]
+            git: GitOptions,
+        }
 
-    #[command(subcommand)]
-    command: Option<Command>,
+        let Some((bin, args)) = argv.split_first() else {
+            return Ok((Self::default(), argv));
+        };
+        let mut end = 0;
+        while let Some(arg) = args.get(end).and_then(|a| a.to_str()) {
+            if matches!(arg, "-C" | "--git-dir" | "--work-tree") {
+                end += 2;
+            } else if arg.starts_with("-C")
+                || arg.starts_with("--git-dir=")
+                || arg.starts_with("--work-tree=")
+            {
+                end += 1;
+            } else {
+                break;
+            }
+        }
+        let end = end.min(args.len());
+        let leading = <Leading as clap::Parser>::try_parse_from(&args[..end])?;
+        let rest = std::iter::once(bin).chain(&args[end..]).cloned().collect();
+        Ok((leading.git, rest))
+    }
+
+    /// Add `other`, given later on the command line
+    pub fn extend(&mut self, other: Self) {
+        if let Some(current_dir) = other.current_dir {
+            self.current_dir
+                .get_or_insert_with(Vec::new)
+                .extend(current_dir);
+        }
+        if other.git_dir.is_some() {
+            self.git_dir = other.git_dir;
+        }
+        if other.work_tree.is_some() {
+            self.work_tree = other.work_tree;
+        }
+    }
 }
 
 #[derive(clap::Subcommand)]
@@ -89,21 +260,53 @@ pub enum Command {
     Next(crate::next::NextArgs),
     Reword(crate::reword::RewordArgs),
     Amend(crate::amend::AmendArgs),
+    Fixup(crate::fixup::FixupArgs),
+    Commit(crate::commit::CommitArgs),
+    Absorb(crate::absorb::AbsorbArgs),
+    SquashBranch(crate::squash_branch::SquashBranchArgs),
+    Drop(crate::drop::DropArgs),
+    #[command(alias = "cp")]
+    CherryPick(crate::cherry_pick::CherryPickArgs),
+    Duplicate(crate::duplicate::DuplicateArgs),
+    Explain(crate::explain::ExplainArgs),
+    Log(crate::graph_log::LogArgs),
+    Pick(crate::pick::PickArgs),
+    Hide(crate::hide::HideArgs),
+    Show(crate::hide::ShowArgs),
+    PrBody(crate::pr_body::PrBodyArgs),
     Sync(crate::sync::SyncArgs),
     Run(crate::run::RunArgs),
     Alias(crate::alias::AliasArgs),
+    Init(crate::init::InitArgs),
+    Gc(crate::gc::GcArgs),
 }
 
 impl Args {
     pub fn exec(&self) -> proc_exit::ExitResult {
+        let global = self.global_config();
         match &self.command {
-            Some(Command::Previous(c)) => c.exec(),
-            Some(Command::Next(c)) => c.exec(),
-            Some(Command::Reword(c)) => c.exec(),
-            Some(Command::Amend(c)) => c.exec(),
-            Some(Command::Sync(c)) => c.exec(),
-            Some(Command::Run(c)) => c.exec(),
+            Some(Command::Previous(c)) => c.exec(&global),
+            Some(Command::Next(c)) => c.exec(&global),
+            Some(Command::Reword(c)) => c.exec(&global),
+            Some(Command::Amend(c)) => c.exec(&global),
+            Some(Command::Fixup(c)) => c.exec(&global),
+            Some(Command::Commit(c)) => c.exec(&global),
+            Some(Command::Absorb(c)) => c.exec(&global),
+            Some(Command::SquashBranch(c)) => c.exec(&global),
+            Some(Command::Drop(c)) => c.exec(&global),
+            Some(Command::CherryPick(c)) => c.exec(&global),
+            Some(Command::Duplicate(c)) => c.exec(&global),
+            Some(Command::Explain(c)) => c.exec(&global),
+            Some(Command::Log(c)) => c.exec(self),
+            Some(Command::Pick(c)) => c.exec(),
+            Some(Command::Hide(c)) => c.exec(),
+            Some(Command::Show(c)) => c.exec(),
+            Some(Command::PrBody(c)) => c.exec(&global),
+            Some(Command::Sync(c)) => c.exec(&global),
+            Some(Command::Run(c)) => c.exec(&global),
             Some(Command::Alias(c)) => c.exec(),
+            Some(Command::Init(c)) => c.exec(),
+            Some(Command::Gc(c)) => c.exec(),
             None => {
                 if let Some(output_path) = self.dump_config.as_deref() {
                     crate::config::dump_config(self, output_path)
@@ -118,21 +321,60 @@ impl Args {
         }
     }
 
+    /// Whether the command works on the working tree, rather than only on the config
+    pub fn needs_worktree(&self) -> bool {
+        match &self.command {
+            Some(Command::Alias(_)) => false,
+            Some(_) => true,
+            None => self.dump_config.is_none() && self.protect.is_none() && !self.protected,
+        }
+    }
+
+    /// The config set by flags that apply to every subcommand, like `--rebase-merges`
+    pub fn global_config(&self) -> git_stack::config::RepoConfig {
+        git_stack::config::RepoConfig {
+            first_parent: self.first_parent.then_some(true),
+            rebase_merges: self.rebase_merges.then_some(true),
+            rebase_strategy: self.rebase_strategy,
+            ..Default::default()
+        }
+    }
+
     pub fn to_config(&self) -> git_stack::config::RepoConfig {
         git_stack::config::RepoConfig {
             editor: None,
+            sequence_editor: None,
+            cleanup: None,
             protected_branches: None,
             protect_commit_count: None,
             protect_commit_age: None,
             auto_base_commit_count: None,
+            max_stack_size: None,
             stack: self.stack,
             push_remote: None,
             pull_remote: None,
-            show_format: self.format,
+            show_format: if self.porcelain {
+                Some(git_stack::config::Format::Porcelain)
+            } else {
+                self.format
+            },
             show_commits: self.show_commits,
             show_stacked: None,
+            log_order: self.order,
             auto_fixup: None,
             auto_repair: None,
+            rebase_strategy: self.rebase_strategy,
+            amend_stage: None,
+            backup: None,
+            patch_id_skip_binary: None,
+            abbrev: None,
+            first_parent: self.first_parent.then_some(true),
+            rebase_merges: self.rebase_merges.then_some(true),
+            set_upstream_on_create: None,
+            default_action: None,
+            picked: None,
+            hidden: None,
+            pr_body_template: None,
 
             capacity: None,
         }
@@ -143,7 +385,48 @@ impl Args {
     }
 }
 
-fn resolve_bool_arg(yes: bool, no: bool) -> Option<bool> {
+/// A `Name <email>` identity, like git's `--author`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ident {
+    pub name: String,
+    pub email: String,
+}
+
+pub(crate) fn parse_ident(value: &str) -> Result<Ident, String> {
+    let invalid = || format!("expected `NAME <EMAIL>`, got `{value}`");
+    let (name, email) = value
+        .trim()
+        .strip_suffix('>')
+        .and_then(|value| value.split_once('<'))
+        .ok_or_else(invalid)?;
+    let name = name.trim();
+    let email = email.trim();
+    let is_valid = |s: &str| !s.is_empty() && !s.contains(['<', '>', '\n']);
+    if !is_valid(name) || !is_valid(email) {
+        return Err(invalid());
+    }
+    Ok(Ident {
+        name: name.to_owned(),
+        email: email.to_owned(),
+    })
+}
+
+impl std::fmt::Display for Ident {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} <{}>", self.name, self.email)
+    }
+}
+
+pub fn parse_depth(value: &str) -> Result<usize, String> {
+    if value == "all" {
+        return Ok(usize::MAX);
+    }
+    value
+        .parse()
+        .map_err(|_| format!("expected a number or `all`, got `{value}`"))
+}
+
+pub(crate) fn resolve_bool_arg(yes: bool, no: bool) -> Option<bool> {
     match (yes, no) {
         (true, false) => Some(true),
         (false, true) => Some(false),
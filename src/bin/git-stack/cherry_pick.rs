@@ -0,0 +1,194 @@
+use std::io::Write;
+
+use proc_exit::prelude::*;
+
+/// Copy a commit from another branch into the current stack
+///
+/// The copy goes on top of `HEAD` (or `--onto`), taking its branch along and rebasing anything
+/// built on top of it.
+#[derive(clap::Args)]
+pub struct CherryPickArgs {
+    /// Commit to copy
+    #[arg(value_name = "REV")]
+    rev: String,
+
+    /// Commit in the current stack to copy it on top of
+    #[arg(long, value_name = "REV", default_value = "HEAD")]
+    onto: String,
+
+    /// Don't ask for confirmation before rewriting more than `stack.max-stack-size` commits
+    #[arg(short, long)]
+    yes: bool,
+
+    /// Only rewrite commits whose author name or email contains `PATTERN`
+    ///
+    /// Other commits are protected, along with everything they are built on, and what's built on
+    /// them stays on them.
+    #[arg(long, value_name = "PATTERN")]
+    author: Option<String>,
+
+    /// Don't actually switch
+    #[arg(short = 'n', long)]
+    dry_run: bool,
+
+    /// How `--dry-run` reports what it would do
+    #[arg(long, value_enum, default_value_t, requires = "dry_run")]
+    format: crate::ops::PlanFormat,
+}
+
+impl CherryPickArgs {
+    pub fn exec(&self, global: &git_stack::config::RepoConfig) -> proc_exit::ExitResult {
+        let stderr_palette = crate::ops::Palette::colored();
+
+        let cwd = std::env::current_dir().with_code(proc_exit::sysexits::USAGE_ERR)?;
+        let (mut repo, repo_config) = crate::ops::open_configured_repo(global)?;
+        crate::ops::configure_signing(&mut repo)?;
+
+        let branches = crate::ops::load_branches(&repo, &repo_config)?;
+
+        let head_branch = repo.head_branch();
+        let resolve = |rev: &str| {
+            repo.raw()
+                .revparse_single(rev)
+                .and_then(|obj| obj.peel_to_commit())
+                .map(|commit| commit.id())
+                .map_err(|_| {
+                    proc_exit::sysexits::USAGE_ERR.with_message(format!("unknown commit `{rev}`"))
+                })
+        };
+        let cherry_id = resolve(&self.rev)?;
+        let onto_id = resolve(&self.onto)?;
+        let cherry = repo.find_commit(cherry_id).expect("resolved commits exist");
+        let parent_count = repo
+            .raw()
+            .find_commit(cherry_id)
+            .with_code(proc_exit::Code::FAILURE)?
+            .parent_count();
+        if 1 < parent_count {
+            return Err(proc_exit::sysexits::USAGE_ERR
+                .with_message(format!("cannot cherry-pick merge commit `{}`", self.rev)));
+        }
+        if repo.merge_base(cherry_id, onto_id) == Some(cherry_id) {
+            return Err(proc_exit::sysexits::USAGE_ERR.with_message(format!(
+                "commit `{}` is already in `{}`",
+                self.rev, self.onto
+            )));
+        }
+
+        let base = crate::ops::resolve_implicit_base(
+            &repo,
+            onto_id,
+            &branches,
+            repo_config.auto_base_commit_count(),
+        );
+        let merge_base_oid = crate::ops::resolve_merge_base(&repo, &base, onto_id, &self.onto)
+            .with_code(proc_exit::sysexits::USAGE_ERR)?;
+        // Leave out sibling stacks, which is likely where the commit comes from
+        let stack_branches = branches.dependents(&repo, merge_base_oid, onto_id);
+        let mut graph =
+            git_stack::graph::Graph::from_branches_with_root(&repo, merge_base_oid, stack_branches)
+                .with_code(proc_exit::Code::FAILURE)?;
+        git_stack::graph::protect_branches(&mut graph);
+        git_stack::graph::mark_fixup(&mut graph, &repo);
+        git_stack::graph::mark_wip(&mut graph, &repo);
+        crate::ops::protect_other_authors(&mut graph, &repo, self.author.as_deref());
+
+        if !self.dry_run {
+            crate::ops::confirm_stack_size(
+                git_stack::graph::pickable_count(&graph),
+                repo_config.max_stack_size(),
+                self.yes,
+            )
+            .with_code(proc_exit::sysexits::USAGE_ERR)?;
+        }
+
+        crate::ops::ensure_clean_state(repo.raw(), "walk commits", self.dry_run)?;
+
+        if !graph.contains_id(onto_id) {
+            return Err(proc_exit::sysexits::USAGE_ERR.with_message(format!(
+                "commit `{}` is not in the current stack",
+                self.onto
+            )));
+        }
+        git_stack::graph::cherry_pick(&mut graph, cherry_id, onto_id)
+            .with_code(proc_exit::sysexits::USAGE_ERR)?;
+
+        let mut stash_id = None;
+        if !self.dry_run {
+            stash_id = git_stack::git::stash_push(&mut repo, "cherry-pick");
+        }
+
+        let mut backed_up = false;
+        {
+            let stash_repo =
+                crate::ops::open_repo(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
+            let stash_repo = git_branch_stash::GitRepo::new(stash_repo);
+            let mut snapshots =
+                git_branch_stash::Stack::new(crate::ops::STASH_STACK_NAME, &stash_repo);
+            let snapshot_capacity = repo_config.capacity();
+            snapshots.capacity(snapshot_capacity);
+            let snapshot = git_branch_stash::Snapshot::from_repo(&stash_repo)
+                .with_code(proc_exit::Code::FAILURE)?;
+            if !self.dry_run {
+                snapshots.push(snapshot).to_sysexits()?;
+                backed_up = true;
+            }
+        }
+
+        let mut summary = crate::ops::RestackSummary::default();
+        let scripts = git_stack::graph::to_scripts(&graph, vec![]);
+        if let Err(err) = crate::ops::ensure_no_merges(
+            repo.raw(),
+            scripts.iter().flat_map(|s| s.stats().rewritten),
+            repo_config.rebase_merges(),
+        ) {
+            git_stack::git::stash_pop(&mut repo, stash_id);
+            return Err(proc_exit::sysexits::USAGE_ERR.with_message(err));
+        }
+        if self.format == crate::ops::PlanFormat::Json {
+            crate::ops::print_plan_json(&mut repo, &scripts, &[])
+                .with_code(proc_exit::Code::FAILURE)?;
+            return Ok(());
+        }
+        let mut executor = git_stack::rewrite::Executor::new(self.dry_run);
+        for script in scripts {
+            let results = executor.run(&mut repo, &script);
+            let branches = script.stats().branches;
+            summary.record(branches.iter().map(String::as_str), &results);
+        }
+        executor
+            .close(&mut repo, head_branch.as_ref().and_then(|b| b.local_name()))
+            .with_code(proc_exit::Code::FAILURE)?;
+        let success = summary.is_success();
+
+        if success {
+            let abbrev_id = repo.abbrev_id(cherry_id);
+            let _ = writeln!(
+                anstream::stderr(),
+                "{} {}: {}",
+                stderr_palette.good("Cherry-picked"),
+                stderr_palette.highlight(abbrev_id.as_str()),
+                stderr_palette.hint(&cherry.summary)
+            );
+        }
+
+        git_stack::git::stash_pop(&mut repo, stash_id);
+        if backed_up {
+            anstream::eprintln!(
+                "{}: to undo, run {}",
+                stderr_palette.info("note"),
+                stderr_palette.highlight(format_args!(
+                    "`git branch-stash pop {}`",
+                    crate::ops::STASH_STACK_NAME
+                ))
+            );
+        }
+
+        summary.report();
+        if success {
+            Ok(())
+        } else {
+            Err(crate::ops::CONFLICT_ERR.as_exit())
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::io::Write;
+
+use proc_exit::prelude::*;
+
+/// Commit the staged changes as a fixup of a commit in the stack
+///
+/// Like `git commit --fixup`, the commit is titled `fixup! <summary>` so `git stack fixup` (or
+/// `git stack --fixup squash`) later folds it into its target.  Branches at `HEAD` move to the new
+/// commit.
+#[derive(clap::Args)]
+pub struct CommitArgs {
+    /// Commit in the current stack to fix up
+    #[arg(long, required = true, value_name = "REV")]
+    fixup: String,
+
+    /// Don't actually switch
+    #[arg(short = 'n', long)]
+    dry_run: bool,
+}
+
+impl CommitArgs {
+    pub fn exec(&self, global: &git_stack::config::RepoConfig) -> proc_exit::ExitResult {
+        let stderr_palette = crate::ops::Palette::colored();
+
+        let (mut repo, repo_config) = crate::ops::open_configured_repo(global)?;
+        crate::ops::configure_signing(&mut repo)?;
+
+        let branches = crate::ops::load_branches(&repo, &repo_config)?;
+
+        let head_id = repo.head_commit().id;
+        let base = crate::ops::resolve_implicit_base(
+            &repo,
+            head_id,
+            &branches,
+            repo_config.auto_base_commit_count(),
+        );
+        let merge_base_oid = crate::ops::resolve_merge_base(&repo, &base, head_id, "HEAD")
+            .with_code(proc_exit::sysexits::USAGE_ERR)?;
+        let stack_branches = branches.descendants(&repo, merge_base_oid);
+        let mut graph = git_stack::graph::Graph::from_branches(&repo, stack_branches)
+            .with_code(proc_exit::Code::FAILURE)?;
+        git_stack::graph::protect_branches(&mut graph);
+
+        crate::ops::ensure_clean_state(repo.raw(), "walk commits", self.dry_run)?;
+
+        let rev = self.fixup.as_str();
+        let target_id = repo
+            .raw()
+            .revparse_single(rev)
+            .and_then(|obj| obj.peel_to_commit())
+            .map(|commit| commit.id())
+            .map_err(|_| {
+                proc_exit::sysexits::USAGE_ERR.with_message(format!("unknown commit `{rev}`"))
+            })?;
+        if !graph.contains_id(target_id) || repo.merge_base(target_id, head_id) != Some(target_id) {
+            return Err(proc_exit::sysexits::USAGE_ERR
+                .with_message(format!("commit `{rev}` is not in the current stack")));
+        }
+        let action = graph
+            .commit_get::<git_stack::graph::Action>(target_id)
+            .copied()
+            .unwrap_or_default();
+        if action.is_protected() {
+            return Err(proc_exit::sysexits::USAGE_ERR
+                .with_message(format!("cannot fix up protected commit `{rev}`")));
+        }
+
+        let tree_id = repo
+            .raw()
+            .index()
+            .and_then(|mut index| index.write_tree())
+            .with_code(proc_exit::Code::FAILURE)?;
+        let fixup_id =
+            crate::ops::commit_fixup(&mut repo, &graph.branches, target_id, tree_id, self.dry_run)
+                .with_code(proc_exit::Code::FAILURE)?
+                .ok_or_else(|| proc_exit::sysexits::USAGE_ERR.with_message("nothing staged"))?;
+
+        let fixup = repo.find_commit(fixup_id).expect("just committed");
+        let abbrev_id = repo.abbrev_id(fixup_id);
+        let _ = writeln!(
+            anstream::stderr(),
+            "{} {}: {}",
+            stderr_palette.good("Committed"),
+            stderr_palette.highlight(abbrev_id.as_str()),
+            stderr_palette.hint(&fixup.summary)
+        );
+
+        Ok(())
+    }
+}
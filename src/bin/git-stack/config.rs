@@ -8,7 +8,7 @@ pub fn dump_config(
 ) -> proc_exit::ExitResult {
     log::trace!("Initializing");
     let cwd = std::env::current_dir().with_code(proc_exit::sysexits::USAGE_ERR)?;
-    let repo = git2::Repository::discover(cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
+    let repo = crate::ops::open_repo(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
 
     let repo_config = git_stack::config::RepoConfig::from_all(&repo)
         .with_code(proc_exit::sysexits::CONFIG_ERR)?
@@ -30,16 +30,19 @@ pub fn dump_config(
 pub fn protect(args: &crate::args::Args, ignore: &str) -> proc_exit::ExitResult {
     log::trace!("Initializing");
     let cwd = std::env::current_dir().with_code(proc_exit::sysexits::USAGE_ERR)?;
-    let repo = git2::Repository::discover(cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
+    let repo = crate::ops::open_repo(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
 
-    let mut repo_config = git_stack::config::RepoConfig::from_repo(&repo)
+    let repo_config = git_stack::config::RepoConfig::from_repo(&repo)
         .with_code(proc_exit::sysexits::CONFIG_ERR)?
         .update(args.to_config());
-    repo_config
-        .protected_branches
-        .get_or_insert_with(Vec::new)
-        .push(ignore.to_owned());
+    let mut protected_branches = repo_config.protected_branches.unwrap_or_default();
+    protected_branches.push(ignore.to_owned());
 
+    // Only touch the protected branches, leaving the rest of the config as-is
+    let repo_config = git_stack::config::RepoConfig {
+        protected_branches: Some(protected_branches),
+        ..Default::default()
+    };
     repo_config
         .write_repo(&repo)
         .with_code(proc_exit::Code::FAILURE)?;
@@ -50,7 +53,7 @@ pub fn protect(args: &crate::args::Args, ignore: &str) -> proc_exit::ExitResult
 pub fn protected(args: &crate::args::Args) -> proc_exit::ExitResult {
     log::trace!("Initializing");
     let cwd = std::env::current_dir().with_code(proc_exit::sysexits::USAGE_ERR)?;
-    let repo = git2::Repository::discover(cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
+    let repo = crate::ops::open_repo(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
 
     let repo_config = git_stack::config::RepoConfig::from_all(&repo)
         .with_code(proc_exit::sysexits::CONFIG_ERR)?
@@ -0,0 +1,236 @@
+use std::io::Write;
+
+use proc_exit::prelude::*;
+
+/// Delete commits from the stack
+///
+/// Descendants of a dropped commit are rebased onto its parent and branches pointing at it move to
+/// its parent.
+#[derive(clap::Args)]
+pub struct DropArgs {
+    /// Commits to drop
+    #[arg(required_unless_present = "select", value_name = "REV")]
+    revs: Vec<String>,
+
+    /// Limit dropping to a contiguous range of commits, dropping all of them when no `REV` is given
+    #[arg(long, value_name = "REV..REV")]
+    select: Option<String>,
+
+    /// Don't ask for confirmation before rewriting more than `stack.max-stack-size` commits
+    #[arg(short, long)]
+    yes: bool,
+
+    /// Only rewrite commits whose author name or email contains `PATTERN`
+    ///
+    /// Other commits are protected, along with everything they are built on, and what's built on
+    /// them stays on them.
+    #[arg(long, value_name = "PATTERN")]
+    author: Option<String>,
+
+    /// Don't actually switch
+    #[arg(short = 'n', long)]
+    dry_run: bool,
+
+    /// How `--dry-run` reports what it would do
+    #[arg(long, value_enum, default_value_t, requires = "dry_run")]
+    format: crate::ops::PlanFormat,
+}
+
+impl DropArgs {
+    pub fn exec(&self, global: &git_stack::config::RepoConfig) -> proc_exit::ExitResult {
+        let stderr_palette = crate::ops::Palette::colored();
+
+        let cwd = std::env::current_dir().with_code(proc_exit::sysexits::USAGE_ERR)?;
+        let (mut repo, repo_config) = crate::ops::open_configured_repo(global)?;
+        crate::ops::configure_signing(&mut repo)?;
+
+        let branches = crate::ops::load_branches(&repo, &repo_config)?;
+
+        let head_branch = repo.head_branch();
+        let head_id = repo.head_commit().id;
+        let base = crate::ops::resolve_implicit_base(
+            &repo,
+            head_id,
+            &branches,
+            repo_config.auto_base_commit_count(),
+        );
+        let merge_base_oid = crate::ops::resolve_merge_base(&repo, &base, head_id, "HEAD")
+            .with_code(proc_exit::sysexits::USAGE_ERR)?;
+        let stack_branches = branches.descendants(&repo, merge_base_oid);
+        let mut graph = git_stack::graph::Graph::from_branches(&repo, stack_branches)
+            .with_code(proc_exit::Code::FAILURE)?;
+        git_stack::graph::protect_branches(&mut graph);
+        git_stack::graph::mark_fixup(&mut graph, &repo);
+        git_stack::graph::mark_wip(&mut graph, &repo);
+        crate::ops::protect_other_authors(&mut graph, &repo, self.author.as_deref());
+
+        if !self.dry_run {
+            crate::ops::confirm_stack_size(
+                git_stack::graph::pickable_count(&graph),
+                repo_config.max_stack_size(),
+                self.yes,
+            )
+            .with_code(proc_exit::sysexits::USAGE_ERR)?;
+        }
+
+        crate::ops::ensure_clean_state(repo.raw(), "walk commits", self.dry_run)?;
+
+        let selection = self
+            .select
+            .as_deref()
+            .map(|range| crate::ops::resolve_selection(&repo, &graph, range))
+            .transpose()
+            .with_code(proc_exit::sysexits::USAGE_ERR)?;
+
+        // Check every commit before dropping any
+        let mut drop_ids = Vec::new();
+        if self.revs.is_empty() {
+            drop_ids.extend(selection.iter().flatten().copied());
+        }
+        for rev in &self.revs {
+            let id = repo
+                .raw()
+                .revparse_single(rev)
+                .and_then(|obj| obj.peel_to_commit())
+                .map(|commit| commit.id())
+                .map_err(|_| {
+                    proc_exit::sysexits::USAGE_ERR.with_message(format!("unknown commit `{rev}`"))
+                })?;
+            if !graph.contains_id(id) {
+                return Err(proc_exit::sysexits::USAGE_ERR
+                    .with_message(format!("commit `{rev}` is not in the current stack")));
+            }
+            if matches!(&selection, Some(selection) if !selection.contains(&id)) {
+                let _ = writeln!(
+                    anstream::stderr(),
+                    "{}: skipping {}, it is outside of `--select`",
+                    stderr_palette.warn("warning"),
+                    stderr_palette.highlight(rev),
+                );
+                continue;
+            }
+            if !drop_ids.contains(&id) {
+                drop_ids.push(id);
+            }
+        }
+        for id in drop_ids.iter().copied() {
+            let action = graph
+                .commit_get::<git_stack::graph::Action>(id)
+                .copied()
+                .unwrap_or_default();
+            if action.is_protected() {
+                return Err(
+                    proc_exit::sysexits::USAGE_ERR.with_message("cannot drop protected commits")
+                );
+            }
+        }
+
+        let mut moved_branches = Vec::new();
+        for id in drop_ids.iter().copied() {
+            let moved = git_stack::graph::drop_commit(&mut graph, id)
+                .with_code(proc_exit::sysexits::USAGE_ERR)?;
+            moved_branches
+                .retain(|b: &git_stack::graph::Branch| !moved.iter().any(|m| m.name() == b.name()));
+            moved_branches.extend(moved);
+        }
+        for branch in moved_branches.iter() {
+            let _ = writeln!(
+                anstream::stderr(),
+                "{}: branch {} moves to {}",
+                stderr_palette.warn("warning"),
+                stderr_palette.highlight(branch.display_name()),
+                stderr_palette.highlight(repo.abbrev_id(branch.id())),
+            );
+        }
+
+        let mut stash_id = None;
+        if !self.dry_run {
+            stash_id = git_stack::git::stash_push(&mut repo, "drop");
+        }
+
+        let mut backed_up = false;
+        {
+            let stash_repo =
+                crate::ops::open_repo(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
+            let stash_repo = git_branch_stash::GitRepo::new(stash_repo);
+            let mut snapshots =
+                git_branch_stash::Stack::new(crate::ops::STASH_STACK_NAME, &stash_repo);
+            let snapshot_capacity = repo_config.capacity();
+            snapshots.capacity(snapshot_capacity);
+            let snapshot = git_branch_stash::Snapshot::from_repo(&stash_repo)
+                .with_code(proc_exit::Code::FAILURE)?;
+            if !self.dry_run {
+                snapshots.push(snapshot).to_sysexits()?;
+                backed_up = true;
+            }
+        }
+
+        let mut summary = crate::ops::RestackSummary::default();
+        let mut scripts = git_stack::graph::to_scripts(&graph, vec![]);
+        // `to_scripts` only moves branches onto commits it walks to, which excludes the root
+        let root_id = graph.root_id();
+        let mut root_batch = git_stack::rewrite::Batch::new(root_id);
+        for branch in moved_branches.iter().filter(|b| b.id() == root_id) {
+            if let Some(local_name) = branch.local_name() {
+                root_batch.push(
+                    root_id,
+                    git_stack::rewrite::Command::CreateBranch(local_name.to_owned()),
+                );
+            }
+        }
+        if !root_batch.is_empty() {
+            scripts.insert(0, vec![root_batch].into());
+        }
+        if let Err(err) = crate::ops::ensure_no_merges(
+            repo.raw(),
+            scripts.iter().flat_map(|s| s.stats().rewritten),
+            repo_config.rebase_merges(),
+        ) {
+            git_stack::git::stash_pop(&mut repo, stash_id);
+            return Err(proc_exit::sysexits::USAGE_ERR.with_message(err));
+        }
+        if self.format == crate::ops::PlanFormat::Json {
+            crate::ops::print_plan_json(&mut repo, &scripts, &drop_ids)
+                .with_code(proc_exit::Code::FAILURE)?;
+            return Ok(());
+        }
+        let mut executor = git_stack::rewrite::Executor::new(self.dry_run);
+        for script in scripts {
+            let results = executor.run(&mut repo, &script);
+            let branches = script.stats().branches;
+            summary.record(branches.iter().map(String::as_str), &results);
+        }
+        executor
+            .close(&mut repo, head_branch.as_ref().and_then(|b| b.local_name()))
+            .with_code(proc_exit::Code::FAILURE)?;
+        let success = summary.is_success();
+
+        if success {
+            let _ = writeln!(
+                anstream::stderr(),
+                "{} {} commits",
+                stderr_palette.good("Dropped"),
+                drop_ids.len(),
+            );
+        }
+
+        git_stack::git::stash_pop(&mut repo, stash_id);
+        if backed_up {
+            anstream::eprintln!(
+                "{}: to undo, run {}",
+                stderr_palette.info("note"),
+                stderr_palette.highlight(format_args!(
+                    "`git branch-stash pop {}`",
+                    crate::ops::STASH_STACK_NAME
+                ))
+            );
+        }
+
+        summary.report();
+        if success {
+            Ok(())
+        } else {
+            Err(crate::ops::CONFLICT_ERR.as_exit())
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::io::Write;
+
+use itertools::Itertools;
+use proc_exit::prelude::*;
+
+/// Copy the current stack's branches under new names
+///
+/// Each development branch gets a parallel branch pointing at the same commit, e.g. `feature` ->
+/// `feature-backup` with `--suffix -backup`.  No commits are rewritten.
+#[derive(clap::Args)]
+pub struct DuplicateArgs {
+    /// Append to each branch name
+    #[arg(long, required_unless_present = "prefix")]
+    suffix: Option<String>,
+
+    /// Prepend to each branch name
+    #[arg(long)]
+    prefix: Option<String>,
+
+    /// Don't actually switch
+    #[arg(short = 'n', long)]
+    dry_run: bool,
+}
+
+impl DuplicateArgs {
+    pub fn exec(&self, global: &git_stack::config::RepoConfig) -> proc_exit::ExitResult {
+        let stderr_palette = crate::ops::Palette::colored();
+
+        let (mut repo, repo_config) = crate::ops::open_configured_repo(global)?;
+
+        let branches = crate::ops::load_branches(&repo, &repo_config)?;
+
+        let head_id = repo.head_commit().id;
+        let base = crate::ops::resolve_implicit_base(
+            &repo,
+            head_id,
+            &branches,
+            repo_config.auto_base_commit_count(),
+        );
+        let merge_base_oid = crate::ops::resolve_merge_base(&repo, &base, head_id, "HEAD")
+            .with_code(proc_exit::sysexits::USAGE_ERR)?;
+        let stack_branches = branches.dependents(&repo, merge_base_oid, head_id);
+        let graph = git_stack::graph::Graph::from_branches(&repo, stack_branches)
+            .with_code(proc_exit::Code::FAILURE)?;
+
+        let prefix = self.prefix.as_deref().unwrap_or("");
+        let suffix = self.suffix.as_deref().unwrap_or("");
+        let mut names = Vec::new();
+        let script = git_stack::graph::to_duplicate_script(&graph, |name| {
+            let new_name = format!("{prefix}{name}{suffix}");
+            names.push(new_name.clone());
+            new_name
+        });
+        if names.is_empty() {
+            return Err(proc_exit::Code::SUCCESS.with_message("Nothing to do."));
+        }
+        script
+            .validate()
+            .with_code(proc_exit::sysexits::USAGE_ERR)?;
+        crate::ops::ensure_no_merges(
+            repo.raw(),
+            script.stats().rewritten,
+            repo_config.rebase_merges(),
+        )
+        .with_code(proc_exit::sysexits::USAGE_ERR)?;
+        let existing = names
+            .iter()
+            .filter(|name| repo.find_local_branch(name).is_some())
+            .collect::<Vec<_>>();
+        if !existing.is_empty() {
+            return Err(proc_exit::sysexits::USAGE_ERR.with_message(format!(
+                "branches already exist: {}",
+                existing.iter().join(", ")
+            )));
+        }
+
+        let mut success = true;
+        let head_branch = repo.head_branch();
+        let mut executor = git_stack::rewrite::Executor::new(self.dry_run);
+        let results = executor.run(&mut repo, &script);
+        for (err, name, dependents) in results.iter() {
+            success = false;
+            log::error!("Failed to duplicate branch `{}`: {}", name, err);
+            if !dependents.is_empty() {
+                log::error!("  Blocked dependents: {}", dependents.iter().join(", "));
+            }
+        }
+        executor
+            .close(&mut repo, head_branch.as_ref().and_then(|b| b.local_name()))
+            .with_code(proc_exit::Code::FAILURE)?;
+
+        if success {
+            names.sort();
+            for name in &names {
+                let _ = writeln!(
+                    anstream::stderr(),
+                    "{} {}",
+                    stderr_palette.good("Created"),
+                    stderr_palette.highlight(name),
+                );
+            }
+            Ok(())
+        } else {
+            Err(proc_exit::Code::FAILURE.as_exit())
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use std::io::Write;
+
+use proc_exit::prelude::*;
+
+/// Show why a commit is treated the way it is
+///
+/// Walks from the stack's base to the commit, listing how each commit would be handled.
+#[derive(clap::Args)]
+pub struct ExplainArgs {
+    /// Commit to explain
+    #[arg(default_value = "HEAD")]
+    rev: String,
+}
+
+impl ExplainArgs {
+    pub fn exec(&self, global: &git_stack::config::RepoConfig) -> proc_exit::ExitResult {
+        let stdout_palette = crate::ops::Palette::colored();
+
+        let (repo, repo_config) = crate::ops::open_configured_repo(global)?;
+
+        let branches = crate::ops::load_branches(&repo, &repo_config)?;
+
+        let target_id = crate::ops::resolve_explicit_base(&repo, &self.rev)
+            .with_code(proc_exit::sysexits::USAGE_ERR)?
+            .id;
+        let base = crate::ops::resolve_implicit_base(
+            &repo,
+            target_id,
+            &branches,
+            repo_config.auto_base_commit_count(),
+        );
+        let merge_base_oid = crate::ops::resolve_merge_base(&repo, &base, target_id, &self.rev)
+            .with_code(proc_exit::sysexits::USAGE_ERR)?;
+        let stack_branches = branches.descendants(&repo, merge_base_oid);
+        // Root the graph at the merge-base so already-merged commits are still included
+        let mut graph =
+            git_stack::graph::Graph::from_branches_with_root(&repo, merge_base_oid, stack_branches)
+                .with_code(proc_exit::Code::FAILURE)?;
+        git_stack::graph::protect_branches(&mut graph);
+        git_stack::graph::mark_fixup(&mut graph, &repo);
+        git_stack::graph::mark_wip(&mut graph, &repo);
+
+        let path = graph.path_to(target_id).ok_or_else(|| {
+            proc_exit::sysexits::USAGE_ERR.with_message(format!(
+                "{} is not part of a stack on {}",
+                self.rev,
+                base.display(&repo)
+            ))
+        })?;
+
+        let mut stdout = anstream::stdout().lock();
+        for id in path.iter().copied() {
+            let commit = repo.find_commit(id).expect("graph commits exist");
+            let action = graph
+                .commit_get::<git_stack::graph::Action>(id)
+                .copied()
+                .unwrap_or_default();
+            let _ = writeln!(
+                stdout,
+                "{} {} {}",
+                stdout_palette.highlight(repo.abbrev_id(id)),
+                stdout_palette.info(action_name(action)),
+                stdout_palette.hint(&commit.summary),
+            );
+        }
+
+        let action = graph
+            .commit_get::<git_stack::graph::Action>(target_id)
+            .copied()
+            .unwrap_or_default();
+        let reason = match action {
+            git_stack::graph::Action::Pick => "is a development commit".to_owned(),
+            git_stack::graph::Action::Fixup => {
+                "is a fixup, to be squashed into its target".to_owned()
+            }
+            git_stack::graph::Action::Protected => protected_reason(&repo, &graph, target_id),
+        };
+        let _ = writeln!(
+            stdout,
+            "{} {}",
+            stdout_palette.highlight(repo.abbrev_id(target_id)),
+            reason
+        );
+
+        Ok(())
+    }
+}
+
+fn protected_reason(
+    repo: &git_stack::git::GitRepo,
+    graph: &git_stack::graph::Graph,
+    id: git2::Oid,
+) -> String {
+    match graph.commit_get::<git_stack::graph::ProtectedReason>(id) {
+        Some(git_stack::graph::ProtectedReason::OnProtectedBranch(name)) => {
+            format!("is protected because it is on protected branch `{name}`")
+        }
+        Some(git_stack::graph::ProtectedReason::DescendantProtected(descendant_id)) => {
+            let branch = graph
+                .branches
+                .get(*descendant_id)
+                .into_iter()
+                .flatten()
+                .find(|b| b.kind() == git_stack::graph::BranchKind::Protected)
+                .map(|b| b.name())
+                .unwrap_or_default();
+            format!(
+                "is protected because descendant {} is on protected branch `{branch}`",
+                repo.abbrev_id(*descendant_id)
+            )
+        }
+        Some(git_stack::graph::ProtectedReason::StackProtected) => {
+            "is protected by the stack's protection settings".to_owned()
+        }
+        Some(git_stack::graph::ProtectedReason::Pinned(pinned_id)) => {
+            if *pinned_id == id {
+                "is protected because it is by another author".to_owned()
+            } else {
+                format!(
+                    "is protected because descendant {} is by another author",
+                    repo.abbrev_id(*pinned_id)
+                )
+            }
+        }
+        None => "is protected".to_owned(),
+    }
+}
+
+fn action_name(action: git_stack::graph::Action) -> &'static str {
+    match action {
+        git_stack::graph::Action::Pick => "pick",
+        git_stack::graph::Action::Fixup => "fixup",
+        git_stack::graph::Action::Protected => "protected",
+    }
+}
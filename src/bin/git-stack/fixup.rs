@@ -0,0 +1,267 @@
+use itertools::Itertools;
+use proc_exit::prelude::*;
+
+/// Squash pending fixup commits into their targets
+///
+/// Unlike `git stack --rebase`, the stack is not moved onto a new base.  Only the fixup commits
+/// and the commits that build on their targets are rewritten, leaving unrelated commits
+/// untouched.
+///
+/// With `--auto`, the staged changes are first committed as a fixup of the commit in the stack
+/// that last touched the staged files.
+#[derive(clap::Args)]
+pub struct FixupArgs {
+    /// Commit the staged changes as a fixup of the commit that last touched those files
+    #[arg(long)]
+    auto: bool,
+
+    /// Only squash fixups of commits in this contiguous range, leaving the rest pending
+    #[arg(long, value_name = "REV..REV", conflicts_with = "auto")]
+    select: Option<String>,
+
+    /// Don't ask for confirmation before rewriting more than `stack.max-stack-size` commits
+    #[arg(short, long)]
+    yes: bool,
+
+    /// Only rewrite commits whose author name or email contains `PATTERN`
+    ///
+    /// Other commits are protected, along with everything they are built on, and what's built on
+    /// them stays on them.
+    #[arg(long, value_name = "PATTERN")]
+    author: Option<String>,
+
+    /// Don't actually switch
+    #[arg(short = 'n', long)]
+    dry_run: bool,
+
+    /// How `--dry-run` reports what it would do
+    #[arg(long, value_enum, default_value_t, requires = "dry_run")]
+    format: crate::ops::PlanFormat,
+}
+
+impl FixupArgs {
+    pub fn exec(&self, global: &git_stack::config::RepoConfig) -> proc_exit::ExitResult {
+        let stderr_palette = crate::ops::Palette::colored();
+
+        let cwd = std::env::current_dir().with_code(proc_exit::sysexits::USAGE_ERR)?;
+        let (mut repo, repo_config) = crate::ops::open_configured_repo(global)?;
+        crate::ops::configure_signing(&mut repo)?;
+
+        let branches = crate::ops::load_branches(&repo, &repo_config)?;
+
+        let head_id = repo.head_commit().id;
+        let base = crate::ops::resolve_implicit_base(
+            &repo,
+            head_id,
+            &branches,
+            repo_config.auto_base_commit_count(),
+        );
+        let merge_base_oid = crate::ops::resolve_merge_base(&repo, &base, head_id, "HEAD")
+            .with_code(proc_exit::sysexits::USAGE_ERR)?;
+        let stack_branches = branches.descendants(&repo, merge_base_oid);
+        let mut graph = git_stack::graph::Graph::from_branches(&repo, stack_branches)
+            .with_code(proc_exit::Code::FAILURE)?;
+        git_stack::graph::protect_branches(&mut graph);
+        git_stack::graph::mark_fixup(&mut graph, &repo);
+        git_stack::graph::mark_wip(&mut graph, &repo);
+        crate::ops::protect_other_authors(&mut graph, &repo, self.author.as_deref());
+
+        if !self.dry_run {
+            crate::ops::confirm_stack_size(
+                git_stack::graph::pickable_count(&graph),
+                repo_config.max_stack_size(),
+                self.yes,
+            )
+            .with_code(proc_exit::sysexits::USAGE_ERR)?;
+        }
+
+        crate::ops::ensure_clean_state(repo.raw(), "walk commits", self.dry_run)?;
+
+        let selection = self
+            .select
+            .as_deref()
+            .map(|range| crate::ops::resolve_selection(&repo, &graph, range))
+            .transpose()
+            .with_code(proc_exit::sysexits::USAGE_ERR)?;
+
+        let mut auto_fixup = None;
+        if self.auto {
+            let head_id = repo.head_commit().id;
+            let tree_id = repo
+                .raw()
+                .index()
+                .and_then(|mut index| index.write_tree())
+                .with_code(proc_exit::Code::FAILURE)?;
+            let staged = staged_paths(&repo, tree_id).with_code(proc_exit::Code::FAILURE)?;
+            if staged.is_empty() {
+                return Err(proc_exit::sysexits::USAGE_ERR.with_message("nothing staged"));
+            }
+            let target_id = find_fixup_target(&repo, &graph, head_id, &staged)?;
+            // Only write the commit, its branches move once every check has passed
+            let fixup_id =
+                crate::ops::commit_fixup(&mut repo, &graph.branches, target_id, tree_id, true)
+                    .with_code(proc_exit::Code::FAILURE)?
+                    .expect("staged changes are committed");
+            graph.insert(git_stack::graph::Node::new(fixup_id), head_id);
+            graph.commit_set(fixup_id, git_stack::graph::Fixup);
+            if !self.dry_run {
+                auto_fixup = Some((graph.branches.clone(), head_id, fixup_id));
+                graph.branches.move_local(head_id, fixup_id);
+            }
+        }
+
+        let mut backed_up = false;
+        {
+            let stash_repo =
+                crate::ops::open_repo(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
+            let stash_repo = git_branch_stash::GitRepo::new(stash_repo);
+            let mut snapshots =
+                git_branch_stash::Stack::new(crate::ops::STASH_STACK_NAME, &stash_repo);
+            let snapshot_capacity = repo_config.capacity();
+            snapshots.capacity(snapshot_capacity);
+            let snapshot = git_branch_stash::Snapshot::from_repo(&stash_repo)
+                .with_code(proc_exit::Code::FAILURE)?;
+            if !self.dry_run {
+                snapshots.push(snapshot).to_sysexits()?;
+                backed_up = true;
+            }
+        }
+
+        // Deliberately no `rebase_development_branches`: only fixups and their dependents move
+        match selection.as_deref() {
+            Some(selection) => git_stack::graph::fixup_within(
+                &mut graph,
+                &repo,
+                git_stack::config::Fixup::Squash,
+                selection,
+            ),
+            None => git_stack::graph::fixup(&mut graph, &repo, git_stack::config::Fixup::Squash),
+        }
+
+        let mut summary = crate::ops::RestackSummary::default();
+        let scripts = git_stack::graph::to_scripts(&graph, vec![]);
+        if let Err(err) = crate::ops::ensure_no_merges(
+            repo.raw(),
+            scripts.iter().flat_map(|s| s.stats().rewritten),
+            repo_config.rebase_merges(),
+        ) {
+            return Err(proc_exit::sysexits::USAGE_ERR.with_message(err));
+        }
+        if self.format == crate::ops::PlanFormat::Json {
+            crate::ops::print_plan_json(&mut repo, &scripts, &[])
+                .with_code(proc_exit::Code::FAILURE)?;
+            return Ok(());
+        }
+
+        if let Some((branches, head_id, fixup_id)) = auto_fixup {
+            crate::ops::checkout_fixup(&mut repo, &branches, head_id, fixup_id)
+                .with_code(proc_exit::Code::FAILURE)?;
+        }
+        let mut stash_id = None;
+        if !self.dry_run {
+            stash_id = git_stack::git::stash_push(&mut repo, "fixup");
+        }
+        let head_branch = repo.head_branch();
+        let mut executor = git_stack::rewrite::Executor::new(self.dry_run);
+        for script in scripts {
+            let results = executor.run(&mut repo, &script);
+            let branches = script.stats().branches;
+            summary.record(branches.iter().map(String::as_str), &results);
+        }
+        executor
+            .close(&mut repo, head_branch.as_ref().and_then(|b| b.local_name()))
+            .with_code(proc_exit::Code::FAILURE)?;
+        let success = summary.is_success();
+
+        git_stack::git::stash_pop(&mut repo, stash_id);
+        if backed_up {
+            anstream::eprintln!(
+                "{}: to undo, run {}",
+                stderr_palette.info("note"),
+                stderr_palette.highlight(format_args!(
+                    "`git branch-stash pop {}`",
+                    crate::ops::STASH_STACK_NAME
+                ))
+            );
+        }
+
+        summary.report();
+        if success {
+            Ok(())
+        } else {
+            Err(crate::ops::CONFLICT_ERR.as_exit())
+        }
+    }
+}
+
+/// Paths that differ between `HEAD` and the staged tree
+fn staged_paths(
+    repo: &git_stack::git::GitRepo,
+    tree_id: git2::Oid,
+) -> Result<Vec<std::path::PathBuf>, eyre::Error> {
+    let head_tree = repo.raw().head()?.peel_to_tree()?;
+    let tree = repo.raw().find_tree(tree_id)?;
+    let diff = repo
+        .raw()
+        .diff_tree_to_tree(Some(&head_tree), Some(&tree), None)?;
+    let paths = diff
+        .deltas()
+        .filter_map(|delta| {
+            delta
+                .new_file()
+                .path()
+                .or_else(|| delta.old_file().path())
+                .map(|p| p.to_owned())
+        })
+        .collect();
+    Ok(paths)
+}
+
+/// The one commit in the stack the staged paths belong to
+fn find_fixup_target(
+    repo: &git_stack::git::GitRepo,
+    graph: &git_stack::graph::Graph,
+    head_id: git2::Oid,
+    staged: &[std::path::PathBuf],
+) -> Result<git2::Oid, proc_exit::Exit> {
+    let targets = git_stack::graph::find_fixup_targets(graph, repo, head_id, staged);
+    let candidates = targets
+        .iter()
+        .map(|(_, id)| *id)
+        .unique()
+        .collect::<Vec<_>>();
+    let untouched = staged
+        .iter()
+        .filter(|path| !targets.iter().any(|(target, _)| target == *path))
+        .collect::<Vec<_>>();
+    match candidates.as_slice() {
+        [] => Err(proc_exit::sysexits::USAGE_ERR
+            .with_message("no commit in the stack touched the staged files")),
+        [_] if !untouched.is_empty() => {
+            let mut message = String::from("no commit in the stack touched some staged files:");
+            for path in untouched {
+                message.push_str(&format!("\n  {}", path.display()));
+            }
+            Err(proc_exit::sysexits::USAGE_ERR.with_message(message))
+        }
+        [target_id] => Ok(*target_id),
+        _ => {
+            let mut message = String::from("staged changes belong to multiple commits:");
+            for id in candidates {
+                let commit = repo.find_commit(id).expect("graph commits exist");
+                let paths = targets
+                    .iter()
+                    .filter(|(_, target_id)| *target_id == id)
+                    .map(|(path, _)| path.display())
+                    .join(", ");
+                message.push_str(&format!(
+                    "\n  {} {} ({})",
+                    repo.abbrev_id(id),
+                    commit.summary,
+                    paths
+                ));
+            }
+            Err(proc_exit::sysexits::USAGE_ERR.with_message(message))
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::io::Write;
+
+use proc_exit::prelude::*;
+
+/// Clean up what `git-stack` leaves behind
+///
+/// Scratch worktrees left by an interrupted `git rerere` replay are removed, along with
+/// `stack.picked`, `stack.hidden` and `stack.branch.<name>.pushed` entries for branches that no
+/// longer exist.  Branches are never touched.
+#[derive(clap::Args)]
+pub struct GcArgs {
+    /// Don't actually remove anything
+    #[arg(short = 'n', long)]
+    dry_run: bool,
+}
+
+impl GcArgs {
+    pub fn exec(&self) -> proc_exit::ExitResult {
+        let stderr_palette = crate::ops::Palette::colored();
+
+        let cwd = std::env::current_dir().with_code(proc_exit::sysexits::USAGE_ERR)?;
+        let repo = crate::ops::open_repo(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
+
+        let worktree_names: Vec<_> = repo
+            .worktrees()
+            .with_code(proc_exit::Code::FAILURE)?
+            .iter()
+            .flatten()
+            .filter(|name| name.starts_with(git_stack::git::RERERE_WORKTREE_PREFIX))
+            .map(ToOwned::to_owned)
+            .collect();
+        for name in &worktree_names {
+            log::debug!("removing worktree {}", name);
+            if !self.dry_run {
+                // Like `git worktree remove --force` followed by `git worktree prune`
+                repo.find_worktree(name)
+                    .and_then(|worktree| {
+                        worktree.prune(Some(
+                            git2::WorktreePruneOptions::new()
+                                .valid(true)
+                                .working_tree(true),
+                        ))
+                    })
+                    .with_code(proc_exit::Code::FAILURE)?;
+            }
+        }
+
+        let is_branch = |name: &str| repo.find_branch(name, git2::BranchType::Local).is_ok();
+        let existing = git_stack::config::RepoConfig::from_repo(&repo)
+            .with_code(proc_exit::sysexits::CONFIG_ERR)?;
+        let mut stale_entries = Vec::new();
+        let mut update = git_stack::config::RepoConfig::default();
+        if let Some(picked) = existing.picked {
+            let (kept, stale): (Vec<_>, Vec<_>) = picked.into_iter().partition(|b| is_branch(b));
+            if !stale.is_empty() {
+                stale_entries.extend(stale.into_iter().map(|b| format!("stack.picked={b}")));
+                update.picked = Some(kept);
+            }
+        }
+        if let Some(hidden) = existing.hidden {
+            let (kept, stale): (Vec<_>, Vec<_>) = hidden.into_iter().partition(|b| is_branch(b));
+            if !stale.is_empty() {
+                stale_entries.extend(stale.into_iter().map(|b| format!("stack.hidden={b}")));
+                update.hidden = Some(kept);
+            }
+        }
+        let mut config = repo
+            .config()
+            .and_then(|config| config.open_level(git2::ConfigLevel::Local))
+            .with_code(proc_exit::sysexits::CONFIG_ERR)?;
+        let pushed_keys: Vec<_> = git_stack::git::recorded_pushes(&config)
+            .with_code(proc_exit::sysexits::CONFIG_ERR)?
+            .into_keys()
+            .filter(|name| !is_branch(name))
+            .map(|name| git_stack::git::pushed_key(&name))
+            .collect();
+        stale_entries.extend(pushed_keys.iter().cloned());
+        for entry in &stale_entries {
+            log::debug!("removing {}", entry);
+        }
+        if !self.dry_run {
+            update
+                .to_gitconfig(&mut config)
+                .with_code(proc_exit::Code::FAILURE)?;
+            for key in &pushed_keys {
+                config.remove(key).with_code(proc_exit::Code::FAILURE)?;
+            }
+        }
+
+        let _ = writeln!(
+            anstream::stderr(),
+            "{} {} scratch worktrees and {} stale config entries",
+            stderr_palette.good(if self.dry_run {
+                "Would remove"
+            } else {
+                "Removed"
+            }),
+            worktree_names.len(),
+            stale_entries.len(),
+        );
+
+        Ok(())
+    }
+}
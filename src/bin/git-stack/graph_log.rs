@@ -0,0 +1,131 @@
+/// Show the stacks like `git log --graph`, annotated with how each commit is handled
+///
+/// These are the stacks `git stack` shows, honoring `stack.hidden` and the age and author filters,
+/// with each commit listed with its action, branches and, for branches, whether they are ready to
+/// push.
+#[derive(clap::Args)]
+pub struct LogArgs {
+    /// Show each commit on a single line
+    #[arg(long)]
+    pub oneline: bool,
+
+    /// Show up to `N` commits above the base of each stack, or `all`
+    #[arg(long, value_name = "N", value_parser = crate::args::parse_depth)]
+    pub depth: Option<usize>,
+
+    /// Show the stacks hidden with `git stack hide` too
+    #[arg(long)]
+    pub show_all: bool,
+
+    /// Mark each commit as signed or unsigned
+    #[arg(long)]
+    pub show_signature: bool,
+
+    /// With `--show-signature`, check signatures with `git verify-commit`
+    #[arg(long, requires = "show_signature")]
+    pub verify: bool,
+}
+
+impl LogArgs {
+    pub fn exec(&self, args: &crate::args::Args) -> proc_exit::ExitResult {
+        crate::stack::log(args, self)
+    }
+
+    pub fn style(&self) -> LogStyle {
+        if self.oneline {
+            LogStyle::Oneline
+        } else {
+            LogStyle::Medium
+        }
+    }
+}
+
+/// How much of each commit `git stack log` shows, named after `git log --format`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LogStyle {
+    /// The commit, its author and its message
+    Medium,
+    /// The commit and its summary on one line
+    Oneline,
+}
+
+/// The commits each column of the graph is leading down to, like `git log --graph`
+#[derive(Default)]
+pub struct Lanes {
+    lanes: Vec<git2::Oid>,
+}
+
+impl Lanes {
+    /// The graph for the line `id` is on, adding a column for it if none leads to it
+    pub fn commit_line(&mut self, id: git2::Oid) -> String {
+        let column = self.column_of(id);
+        let mut line = String::new();
+        for i in 0..self.lanes.len() {
+            if i != 0 {
+                line.push(' ');
+            }
+            line.push(if i == column { '*' } else { '|' });
+        }
+        line
+    }
+
+    /// The graph for the lines describing a commit, aligned to where its summary starts
+    pub fn padding(&self) -> String {
+        if self.lanes.is_empty() {
+            return "  ".to_owned();
+        }
+        let mut padding = vec!["|"; self.lanes.len()].join(" ");
+        padding.push(' ');
+        padding
+    }
+
+    /// Lead the column of `id` to its parent instead, ending it if there is none
+    pub fn replace(&mut self, id: git2::Oid, parent_id: Option<git2::Oid>) {
+        let column = self.column_of(id);
+        if let Some(parent_id) = parent_id {
+            self.lanes[column] = parent_id;
+        } else {
+            self.lanes.remove(column);
+        }
+    }
+
+    /// Fold columns leading to the same commit into the leftmost, returning the lines doing so
+    pub fn join(&mut self) -> Vec<String> {
+        let mut connectors = Vec::new();
+        while let Some((into, from)) = self.duplicate() {
+            let mut line = vec![' '; 2 * self.lanes.len()];
+            for i in 0..from {
+                line[2 * i] = '|';
+            }
+            for i in into..from - 1 {
+                line[2 * i + 1] = '_';
+            }
+            line[2 * from - 1] = '/';
+            for i in from + 1..self.lanes.len() {
+                line[2 * i - 1] = '/';
+            }
+            connectors.push(line.into_iter().collect::<String>().trim_end().to_owned());
+            self.lanes.remove(from);
+        }
+
+        connectors
+    }
+
+    fn column_of(&mut self, id: git2::Oid) -> usize {
+        if let Some(column) = self.lanes.iter().position(|lane| *lane == id) {
+            column
+        } else {
+            self.lanes.push(id);
+            self.lanes.len() - 1
+        }
+    }
+
+    fn duplicate(&self) -> Option<(usize, usize)> {
+        for (from, id) in self.lanes.iter().enumerate() {
+            if let Some(into) = self.lanes[..from].iter().position(|lane| lane == id) {
+                return Some((into, from));
+            }
+        }
+        None
+    }
+}
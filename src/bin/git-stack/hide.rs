@@ -0,0 +1,121 @@
+use std::io::Write;
+
+use proc_exit::prelude::*;
+
+/// Leave the stacks of branches out of `git stack`
+///
+/// This only affects what is shown, the stacks are still rebased and pushed like any other.  Hidden
+/// branches are recorded in `.git/config` as `stack.hidden`; see them again with `--show-all` or
+/// `git stack show`.
+#[derive(clap::Args)]
+pub struct HideArgs {
+    /// Branches to hide
+    #[arg(required = true, value_name = "BRANCH")]
+    branches: Vec<String>,
+}
+
+impl HideArgs {
+    pub fn exec(&self) -> proc_exit::ExitResult {
+        let stderr_palette = crate::ops::Palette::colored();
+
+        let repo = open_repo()?;
+        for branch in &self.branches {
+            if repo.find_branch(branch, git2::BranchType::Local).is_err() {
+                return Err(proc_exit::sysexits::USAGE_ERR
+                    .with_message(format!("could not find local branch `{branch}`")));
+            }
+        }
+
+        let mut hidden = load_hidden(&repo)?;
+        for branch in &self.branches {
+            if !hidden.contains(branch) {
+                hidden.push(branch.clone());
+            }
+        }
+        store_hidden(&repo, hidden)?;
+
+        let mut stderr = anstream::stderr().lock();
+        for branch in &self.branches {
+            let _ = writeln!(
+                stderr,
+                "{} {}",
+                stderr_palette.good("Hid"),
+                stderr_palette.highlight(branch),
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Bring back stacks hidden with `git stack hide`
+#[derive(clap::Args)]
+pub struct ShowArgs {
+    /// Branches to show again
+    #[arg(required_unless_present = "all", value_name = "BRANCH")]
+    branches: Vec<String>,
+
+    /// Show every hidden branch again
+    #[arg(long, conflicts_with = "branches")]
+    all: bool,
+}
+
+impl ShowArgs {
+    pub fn exec(&self) -> proc_exit::ExitResult {
+        let stderr_palette = crate::ops::Palette::colored();
+
+        let repo = open_repo()?;
+        let mut hidden = load_hidden(&repo)?;
+        let mut shown = Vec::new();
+        hidden.retain(|branch| {
+            let show = self.all || self.branches.contains(branch);
+            if show {
+                shown.push(branch.clone());
+            }
+            !show
+        });
+        for branch in &self.branches {
+            if !shown.contains(branch) {
+                log::warn!("`{branch}` isn't hidden");
+            }
+        }
+        store_hidden(&repo, hidden)?;
+
+        let mut stderr = anstream::stderr().lock();
+        for branch in &shown {
+            let _ = writeln!(
+                stderr,
+                "{} {}",
+                stderr_palette.good("Showing"),
+                stderr_palette.highlight(branch),
+            );
+        }
+
+        Ok(())
+    }
+}
+
+fn open_repo() -> Result<git2::Repository, proc_exit::Exit> {
+    let cwd = std::env::current_dir().with_code(proc_exit::sysexits::USAGE_ERR)?;
+    crate::ops::open_repo(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)
+}
+
+fn load_hidden(repo: &git2::Repository) -> Result<Vec<String>, proc_exit::Exit> {
+    let existing = git_stack::config::RepoConfig::from_repo(repo)
+        .with_code(proc_exit::sysexits::CONFIG_ERR)?;
+    Ok(existing.hidden.unwrap_or_default())
+}
+
+fn store_hidden(repo: &git2::Repository, hidden: Vec<String>) -> Result<(), proc_exit::Exit> {
+    let update = git_stack::config::RepoConfig {
+        hidden: Some(hidden),
+        ..Default::default()
+    };
+    let mut config = repo
+        .config()
+        .and_then(|config| config.open_level(git2::ConfigLevel::Local))
+        .with_code(proc_exit::sysexits::CONFIG_ERR)?;
+    update
+        .to_gitconfig(&mut config)
+        .with_code(proc_exit::Code::FAILURE)
+}
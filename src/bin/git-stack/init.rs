@@ -0,0 +1,143 @@
+use std::io::Write;
+
+use proc_exit::prelude::*;
+
+/// Set up `git-stack` for this repository
+///
+/// Proposes protected branches and push / pull remotes, based on the repository's default branch
+/// and remotes, and writes them to the config.
+#[derive(clap::Args)]
+pub struct InitArgs {
+    /// Write to `.git/config`, only for this clone (default)
+    #[arg(long, conflicts_with = "shared")]
+    local: bool,
+
+    /// Write to `.gitconfig` in the working tree, to commit and share with everyone
+    #[arg(long)]
+    shared: bool,
+
+    /// Don't ask for confirmation
+    #[arg(short, long)]
+    yes: bool,
+}
+
+impl InitArgs {
+    pub fn exec(&self) -> proc_exit::ExitResult {
+        let stderr_palette = crate::ops::Palette::colored();
+
+        let cwd = std::env::current_dir().with_code(proc_exit::sysexits::USAGE_ERR)?;
+        let repo = crate::ops::open_repo(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
+
+        let (existing, config_path) = if self.shared {
+            let workdir = repo.workdir().ok_or_else(|| {
+                proc_exit::sysexits::USAGE_ERR
+                    .with_message("cannot share config in a bare repository")
+            })?;
+            (
+                git_stack::config::RepoConfig::from_workdir(&repo),
+                workdir.join(".gitconfig"),
+            )
+        } else {
+            (
+                git_stack::config::RepoConfig::from_repo(&repo),
+                repo.path().join("config"),
+            )
+        };
+        let existing = existing.with_code(proc_exit::sysexits::CONFIG_ERR)?;
+
+        let remotes = repo.remotes().with_code(proc_exit::Code::FAILURE)?;
+        let remotes = remotes
+            .iter()
+            .flatten()
+            .map(ToOwned::to_owned)
+            .collect::<Vec<_>>();
+        let push_remote = pick_remote(&remotes, "origin");
+        let pull_remote = pick_remote(&remotes, "upstream").or_else(|| push_remote.clone());
+
+        let mut protected_branches = existing.protected_branches.unwrap_or_default();
+        if let Some(default_branch) = default_branch(&repo, pull_remote.as_deref()) {
+            if !protected_branches.contains(&default_branch) {
+                protected_branches.push(default_branch);
+            }
+        }
+
+        let mut stderr = anstream::stderr().lock();
+        let _ = writeln!(
+            stderr,
+            "{}: {}",
+            stderr_palette.info("protected branches"),
+            if protected_branches.is_empty() {
+                "(none)".to_owned()
+            } else {
+                protected_branches.join(", ")
+            }
+        );
+        let _ = writeln!(
+            stderr,
+            "{}: {}",
+            stderr_palette.info("push remote"),
+            push_remote.as_deref().unwrap_or("(none)")
+        );
+        let _ = writeln!(
+            stderr,
+            "{}: {}",
+            stderr_palette.info("pull remote"),
+            pull_remote.as_deref().unwrap_or("(none)")
+        );
+        drop(stderr);
+
+        let prompt = format!("write to {}?", config_path.display());
+        if !self.yes && !crate::ops::confirm(&prompt).with_code(proc_exit::Code::FAILURE)? {
+            return Err(proc_exit::Code::FAILURE.with_message("aborted"));
+        }
+
+        let repo_config = git_stack::config::RepoConfig {
+            protected_branches: Some(protected_branches),
+            push_remote,
+            pull_remote,
+            ..Default::default()
+        };
+        if self.shared {
+            repo_config
+                .write_workdir(&repo)
+                .with_code(proc_exit::Code::FAILURE)?;
+        } else {
+            repo_config
+                .write_repo(&repo)
+                .with_code(proc_exit::Code::FAILURE)?;
+        }
+        Ok(())
+    }
+}
+
+/// Prefer the conventional name, falling back to the only remote there is
+fn pick_remote(remotes: &[String], preferred: &str) -> Option<String> {
+    if remotes.iter().any(|r| r == preferred) {
+        Some(preferred.to_owned())
+    } else if remotes.len() == 1 {
+        Some(remotes[0].clone())
+    } else {
+        None
+    }
+}
+
+/// The branch the remote's `HEAD` points to, falling back to a local `init.defaultBranch`
+fn default_branch(repo: &git2::Repository, remote: Option<&str>) -> Option<String> {
+    if let Some(remote) = remote {
+        let prefix = format!("refs/remotes/{remote}/");
+        let remote_head = repo.find_reference(&format!("{prefix}HEAD")).ok();
+        if let Some(name) = remote_head
+            .as_ref()
+            .and_then(|r| r.symbolic_target())
+            .and_then(|t| t.strip_prefix(prefix.as_str()))
+        {
+            return Some(name.to_owned());
+        }
+    }
+
+    let config = repo.config().ok()?.snapshot().ok()?;
+    let name = git_stack::config::default_branch(&config);
+    repo.find_branch(name, git2::BranchType::Local)
+        .is_ok()
+        .then(|| name.to_owned())
+}
@@ -6,18 +6,33 @@
 use clap::Parser;
 use proc_exit::WithCodeResultExt;
 
+mod absorb;
 mod alias;
 mod amend;
 mod args;
+mod cherry_pick;
+mod commit;
 mod config;
+mod drop;
+mod duplicate;
+mod explain;
+mod fixup;
+mod gc;
+mod graph_log;
+mod hide;
+mod init;
 mod logger;
 mod next;
 mod ops;
+mod pick;
+mod pr_body;
 mod prev;
 mod reword;
 mod run;
+mod squash_branch;
 mod stack;
 mod sync;
+mod timings;
 
 fn main() {
     human_panic::setup_panic!();
@@ -27,7 +42,15 @@ fn main() {
 
 fn run() -> proc_exit::ExitResult {
     // clap's `get_matches` uses Failure rather than Usage, so bypass it for `get_matches_safe`.
-    let args = match args::Args::try_parse() {
+    let args =
+        args::GitOptions::split_leading(std::env::args_os().collect()).and_then(|(git, argv)| {
+            let mut args = args::Args::try_parse_from(argv)?;
+            let later = std::mem::take(&mut args.git);
+            args.git = git;
+            args.git.extend(later);
+            Ok(args)
+        });
+    let args = match args {
         Ok(args) => args,
         Err(e) if e.use_stderr() => {
             let _ = e.print();
@@ -47,7 +70,7 @@ fn run() -> proc_exit::ExitResult {
 
     logger::init_logging(args.verbose.clone(), colored_stderr);
 
-    if let Some(current_dir) = args.current_dir.as_deref() {
+    if let Some(current_dir) = args.git.current_dir.as_deref() {
         let current_dir = current_dir
             .iter()
             .fold(std::path::PathBuf::new(), |current, next| {
@@ -57,5 +80,30 @@ fn run() -> proc_exit::ExitResult {
         std::env::set_current_dir(current_dir).with_code(proc_exit::sysexits::USAGE_ERR)?;
     }
 
+    // Like with `--committer`, this is where `open_repo` (and any `git` we spawn) looks
+    let cwd = std::env::current_dir().with_code(proc_exit::sysexits::USAGE_ERR)?;
+    if let Some(git_dir) = args.git.git_dir.as_deref() {
+        std::env::set_var("GIT_DIR", cwd.join(git_dir));
+    }
+    if let Some(work_tree) = args.git.work_tree.as_deref() {
+        std::env::set_var("GIT_WORK_TREE", cwd.join(work_tree));
+    }
+
+    if let Some(committer) = args.committer.as_ref() {
+        // Nothing else is running yet and this is where `git2_ext` (and any `git` we spawn) looks
+        std::env::set_var("GIT_COMMITTER_NAME", &committer.name);
+        std::env::set_var("GIT_COMMITTER_EMAIL", &committer.email);
+    }
+
+    // Commands report for themselves when there isn't a repo
+    if let Ok(repo) = crate::ops::open_repo(&cwd) {
+        let repo = git_stack::git::GitRepo::new(repo);
+        if args.needs_worktree() {
+            crate::ops::ensure_worktree(&repo)?;
+        }
+        crate::ops::warn_shallow(&repo);
+        crate::ops::warn_unknown_remotes(&repo);
+    }
+
     args.exec()
 }
@@ -37,38 +37,14 @@ impl NextArgs {
         }
     }
 
-    pub fn exec(&self) -> proc_exit::ExitResult {
+    pub fn exec(&self, global: &git_stack::config::RepoConfig) -> proc_exit::ExitResult {
         let stderr_palette = crate::ops::Palette::colored();
 
-        let cwd = std::env::current_dir().with_code(proc_exit::sysexits::USAGE_ERR)?;
-        let repo = git2::Repository::discover(cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
-        let mut repo = git_stack::git::GitRepo::new(repo);
+        let (mut repo, repo_config) = crate::ops::open_configured_repo(global)?;
 
-        let repo_config = git_stack::config::RepoConfig::from_all(repo.raw())
-            .with_code(proc_exit::sysexits::CONFIG_ERR)?;
-        repo.set_push_remote(repo_config.push_remote());
-        repo.set_pull_remote(repo_config.pull_remote());
+        let branches = crate::ops::load_branches(&repo, &repo_config)?;
 
-        let protected = git_stack::git::ProtectedBranches::new(
-            repo_config.protected_branches().iter().map(|s| s.as_str()),
-        )
-        .with_code(proc_exit::sysexits::CONFIG_ERR)?;
-        let branches = git_stack::graph::BranchSet::from_repo(&repo, &protected)
-            .with_code(proc_exit::Code::FAILURE)?;
-
-        if repo.raw().state() != git2::RepositoryState::Clean {
-            let message = format!("cannot move to next, {:?} in progress", repo.raw().state());
-            if self.dry_run {
-                let _ = writeln!(
-                    anstream::stderr(),
-                    "{}: {}",
-                    stderr_palette.error("error"),
-                    message
-                );
-            } else {
-                return Err(proc_exit::sysexits::USAGE_ERR.with_message(message));
-            }
-        }
+        crate::ops::ensure_clean_state(repo.raw(), "move to next", self.dry_run)?;
 
         if self.stash && !self.dry_run {
             git_stack::git::stash_push(&mut repo, "branch-stash");
@@ -94,15 +70,7 @@ impl NextArgs {
             &branches,
             repo_config.auto_base_commit_count(),
         );
-        let merge_base_oid = repo
-            .merge_base(base.id, head_id)
-            .ok_or_else(|| {
-                git2::Error::new(
-                    git2::ErrorCode::NotFound,
-                    git2::ErrorClass::Reference,
-                    format!("could not find base between {base} and HEAD"),
-                )
-            })
+        let merge_base_oid = crate::ops::resolve_merge_base(&repo, &base, head_id, "HEAD")
             .with_code(proc_exit::sysexits::USAGE_ERR)?;
         let stack_branches = branches.descendants(&repo, merge_base_oid);
         let graph = git_stack::graph::Graph::from_branches(&repo, stack_branches)
@@ -1,5 +1,6 @@
 use bstr::ByteSlice;
 use eyre::WrapErr;
+use proc_exit::prelude::*;
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct AnnotatedOid {
@@ -34,6 +35,17 @@ impl AnnotatedOid {
     }
 }
 
+impl AnnotatedOid {
+    /// Like [`Display`][std::fmt::Display], abbreviating a bare id
+    pub fn display(&self, repo: &dyn git_stack::git::Repo) -> String {
+        if let Some(branch) = &self.branch {
+            branch.to_string()
+        } else {
+            repo.abbrev_id(self.id)
+        }
+    }
+}
+
 impl std::fmt::Display for AnnotatedOid {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if let Some(branch) = &self.branch {
@@ -44,6 +56,67 @@ impl std::fmt::Display for AnnotatedOid {
     }
 }
 
+/// Open the repository for `cwd` like `git` would
+///
+/// `GIT_DIR` (or `--git-dir`) names the repository directly rather than searching up from `cwd`,
+/// and `GIT_WORK_TREE` (or `--work-tree`) overrides its working tree.
+pub fn open_repo(cwd: &std::path::Path) -> Result<git2::Repository, git2::Error> {
+    let Some(work_tree) = std::env::var_os("GIT_WORK_TREE") else {
+        return git2::Repository::open_ext(
+            cwd,
+            git2::RepositoryOpenFlags::FROM_ENV,
+            std::iter::empty::<&std::ffi::OsStr>(),
+        );
+    };
+    // libgit2 refuses `GIT_WORK_TREE` with `FROM_ENV`, so find the repository ourselves
+    let (path, flags) = match std::env::var_os("GIT_DIR") {
+        Some(git_dir) => (cwd.join(git_dir), git2::RepositoryOpenFlags::NO_SEARCH),
+        None => (cwd.to_owned(), git2::RepositoryOpenFlags::empty()),
+    };
+    let repo = git2::Repository::open_ext(path, flags, std::iter::empty::<&std::ffi::OsStr>())?;
+    repo.set_workdir(&cwd.join(work_tree), false)?;
+    Ok(repo)
+}
+
+/// Open the repository for the current directory, configured from `git config` and `global`
+pub fn open_configured_repo(
+    global: &git_stack::config::RepoConfig,
+) -> Result<(git_stack::git::GitRepo, git_stack::config::RepoConfig), proc_exit::Exit> {
+    let cwd = std::env::current_dir().with_code(proc_exit::sysexits::USAGE_ERR)?;
+    let repo = open_repo(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
+    let mut repo = git_stack::git::GitRepo::new(repo);
+
+    let repo_config = git_stack::config::RepoConfig::from_all(repo.raw())
+        .with_code(proc_exit::sysexits::CONFIG_ERR)?
+        .update(global.clone());
+    repo.set_push_remote(repo_config.push_remote());
+    repo.set_pull_remote(repo_config.pull_remote());
+    repo.set_abbrev(repo_config.abbrev());
+    repo.set_first_parent(repo_config.first_parent());
+    repo.set_rebase_strategy(repo_config.rebase_strategy());
+    repo.set_patch_id_skip_binary(repo_config.patch_id_skip_binary());
+
+    Ok((repo, repo_config))
+}
+
+/// Sign rewritten commits if `stack.gpgSign` (or else `commit.gpgSign`) asks for it
+pub fn configure_signing(repo: &mut git_stack::git::GitRepo) -> proc_exit::ExitResult {
+    let sign = repo.config_bool("stack.gpgSign", repo.config_bool("commit.gpgSign", false));
+    repo.set_sign(sign).with_code(proc_exit::Code::FAILURE)
+}
+
+/// The branches of the repository, with `stack.protected-branch` applied
+pub fn load_branches(
+    repo: &git_stack::git::GitRepo,
+    repo_config: &git_stack::config::RepoConfig,
+) -> Result<git_stack::graph::BranchSet, proc_exit::Exit> {
+    let protected = git_stack::git::ProtectedBranches::new(
+        repo_config.protected_branches().iter().map(|s| s.as_str()),
+    )
+    .with_code(proc_exit::sysexits::CONFIG_ERR)?;
+    git_stack::graph::BranchSet::from_repo(repo, &protected).with_code(proc_exit::Code::FAILURE)
+}
+
 pub fn resolve_explicit_base(
     repo: &git_stack::git::GitRepo,
     base: &str,
@@ -79,12 +152,41 @@ pub fn resolve_explicit_base(
     }
 }
 
+/// The base chosen for a stack, see [`resolve_implicit_base_info`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BaseInfo {
+    pub base: AnnotatedOid,
+    /// Remote branch the protected base tracks, like `origin/main` for `main`
+    pub upstream: Option<git_stack::git::Branch>,
+}
+
+impl BaseInfo {
+    fn new(base: AnnotatedOid) -> Self {
+        Self {
+            base,
+            upstream: None,
+        }
+    }
+}
+
 pub fn resolve_implicit_base(
     repo: &dyn git_stack::git::Repo,
     head_oid: git2::Oid,
     branches: &git_stack::graph::BranchSet,
     auto_base_commit_count: Option<usize>,
 ) -> AnnotatedOid {
+    resolve_implicit_base_info(repo, head_oid, branches, auto_base_commit_count).base
+}
+
+/// Like [`resolve_implicit_base`] but also finding the upstream of a protected base
+///
+/// A remote base is its own upstream, otherwise see [`git_stack::git::find_upstream_branch`].
+pub fn resolve_implicit_base_info(
+    repo: &dyn git_stack::git::Repo,
+    head_oid: git2::Oid,
+    branches: &git_stack::graph::BranchSet,
+    auto_base_commit_count: Option<usize>,
+) -> BaseInfo {
     match git_stack::graph::find_protected_base(repo, branches, head_oid) {
         Some(branch) => {
             let merge_base_id = repo
@@ -116,9 +218,9 @@ pub fn resolve_implicit_base(
                         ahead_count,
                         behind_count,
                         branch.display_name(),
-                        assumed_base_oid
+                        repo.abbrev_id(assumed_base_oid)
                     );
-                    return AnnotatedOid::new(assumed_base_oid);
+                    return BaseInfo::new(AnnotatedOid::new(assumed_base_oid));
                 }
             }
 
@@ -137,20 +239,193 @@ pub fn resolve_implicit_base(
                     })
                     .unwrap_or_else(|| "target".to_owned())
             );
-            AnnotatedOid::with_branch(branch.git().to_owned())
+            BaseInfo {
+                base: AnnotatedOid::with_branch(branch.git().to_owned()),
+                upstream: find_upstream(repo, branch.git()),
+            }
         }
         None => {
             let assumed_base_oid = git_stack::graph::infer_base(repo, head_oid).unwrap_or(head_oid);
             log::warn!(
                 "Could not find protected branch for {}, assuming {}",
-                head_oid,
-                assumed_base_oid
+                repo.abbrev_id(head_oid),
+                repo.abbrev_id(assumed_base_oid)
+            );
+            BaseInfo::new(AnnotatedOid::new(assumed_base_oid))
+        }
+    }
+}
+
+fn find_upstream(
+    repo: &dyn git_stack::git::Repo,
+    branch: &git_stack::git::Branch,
+) -> Option<git_stack::git::Branch> {
+    if branch.remote.is_some() {
+        return Some(branch.clone());
+    }
+    git_stack::git::find_upstream_branch(repo, &branch.name)
+}
+
+/// Find where `head_id` forked from `base`
+pub fn resolve_merge_base(
+    repo: &dyn git_stack::git::Repo,
+    base: &AnnotatedOid,
+    head_id: git2::Oid,
+    head_name: &str,
+) -> Result<git2::Oid, git2::Error> {
+    repo.merge_base(base.id, head_id).ok_or_else(|| {
+        no_merge_base(
+            &base.display(repo),
+            head_name,
+            repo.find_commit(base.id).is_some(),
+            repo.is_shallow(),
+        )
+    })
+}
+
+/// Explain why `base` and `head_name` have no merge-base
+///
+/// When `base` exists, the two must come from different root commits (e.g. an orphan branch),
+/// unless a shallow clone cut off the history they share.
+pub fn no_merge_base(
+    base: &dyn std::fmt::Display,
+    head_name: &str,
+    base_exists: bool,
+    shallow: bool,
+) -> git2::Error {
+    if shallow {
+        git2::Error::new(
+            git2::ErrorCode::NotFound,
+            git2::ErrorClass::Merge,
+            format!(
+                "could not find base between {base} and {head_name} in a shallow clone; run `git fetch --unshallow`"
+            ),
+        )
+    } else if base_exists {
+        git2::Error::new(
+            git2::ErrorCode::NotFound,
+            git2::ErrorClass::Merge,
+            format!(
+                "{base} and {head_name} have unrelated histories; choose a `--base` that shares history with {head_name}"
+            ),
+        )
+    } else {
+        git2::Error::new(
+            git2::ErrorCode::NotFound,
+            git2::ErrorClass::Reference,
+            format!("could not find base between {base} and {head_name}"),
+        )
+    }
+}
+
+/// Resolve `--select <rev>..<rev>` to the commits it covers, from the base up
+///
+/// Like `git log <a>..<b>`, `<a>` is excluded and `<b>` included, with either defaulting to
+/// `HEAD`.  The commits must form one unbroken line of development in `graph`.
+pub fn resolve_selection(
+    repo: &git_stack::git::GitRepo,
+    graph: &git_stack::graph::Graph,
+    range: &str,
+) -> eyre::Result<Vec<git2::Oid>> {
+    let (start, end) = range
+        .split_once("..")
+        .filter(|(_, end)| !end.starts_with('.'))
+        .ok_or_else(|| eyre::format_err!("expected `<rev>..<rev>`, got `{}`", range))?;
+    let resolve = |rev: &str| {
+        let rev = if rev.is_empty() { "HEAD" } else { rev };
+        repo.raw()
+            .revparse_single(rev)
+            .and_then(|obj| obj.peel_to_commit())
+            .map(|commit| commit.id())
+            .map_err(|_| eyre::format_err!("unknown commit `{}`", rev))
+    };
+    let start_id = resolve(start)?;
+    let end_id = resolve(end)?;
+    if !graph.contains_id(end_id) {
+        eyre::bail!("commit `{}` is not in the current stack", end);
+    }
+
+    let mut selection = Vec::new();
+    let mut current_id = end_id;
+    while current_id != start_id {
+        selection.push(current_id);
+        current_id = graph.primary_parent_of(current_id).ok_or_else(|| {
+            eyre::format_err!("`{}` is not a contiguous range within one stack", range)
+        })?;
+    }
+    if selection.is_empty() {
+        eyre::bail!("`{}` selects no commits", range);
+    }
+    selection.reverse();
+    Ok(selection)
+}
+
+/// Fail fast in a bare repository, rather than deep in loading the config
+pub fn ensure_worktree(repo: &git_stack::git::GitRepo) -> proc_exit::ExitResult {
+    if repo.is_bare() {
+        return Err(proc_exit::sysexits::USAGE_ERR
+            .with_message("git-stack requires a working tree; this is a bare repository"));
+    }
+    Ok(())
+}
+
+/// Warn that bases resolved in a shallow clone may be wrong
+pub fn warn_shallow(repo: &git_stack::git::GitRepo) {
+    if repo.is_shallow() {
+        let palette = Palette::colored();
+        anstream::eprintln!(
+            "{}: shallow clone, bases may be resolved incorrectly; run {} to fetch the full history",
+            palette.warn("warning"),
+            palette.highlight("`git fetch --unshallow`")
+        );
+    }
+}
+
+/// Warn about a configured push / pull remote that doesn't exist, rather than failing obscurely
+/// when it is first used
+pub fn warn_unknown_remotes(repo: &git_stack::git::GitRepo) {
+    // Commands report for themselves when the config is invalid
+    let Ok(repo_config) = git_stack::config::RepoConfig::from_explicit(repo.raw()) else {
+        return;
+    };
+    let remotes = repo.remotes();
+    let configured = [
+        ("push-remote", repo_config.push_remote.as_deref()),
+        ("pull-remote", repo_config.pull_remote.as_deref()),
+    ];
+    for (field, remote) in configured {
+        let Some(remote) = remote else {
+            continue;
+        };
+        if !remotes.iter().any(|r| r == remote) {
+            let palette = Palette::colored();
+            anstream::eprintln!(
+                "{}: configured {} '{}' not found; available: {}",
+                palette.warn("warning"),
+                field,
+                remote,
+                if remotes.is_empty() {
+                    "(none)".to_owned()
+                } else {
+                    remotes.join(", ")
+                }
             );
-            AnnotatedOid::new(assumed_base_oid)
         }
     }
 }
 
+/// Tell the user there is nothing to stack because HEAD is at `base`
+///
+/// This is not an error: showing the stack still succeeds and re-stacking is a no-op.
+pub fn note_at_base(base: &dyn std::fmt::Display) {
+    let palette = Palette::colored();
+    anstream::eprintln!(
+        "{}: no commits to stack; HEAD is at its base {}",
+        palette.info("note"),
+        palette.highlight(base)
+    );
+}
+
 pub fn resolve_base_from_onto(repo: &git_stack::git::GitRepo, onto: &AnnotatedOid) -> AnnotatedOid {
     // HACK: Assuming the local branch is the current base for all the commits
     onto.branch
@@ -230,6 +505,41 @@ pub fn git_fetch_upstream(remote: &str, branch_name: &str) -> eyre::Result<()> {
     Ok(())
 }
 
+/// What `git verify-commit` made of a signature
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Verification {
+    Good,
+    /// The signature doesn't match the commit
+    Bad,
+    /// The signature couldn't be checked, like without `gpg` or the signer's key
+    Unverifiable,
+}
+
+/// Check the signature of `id` with `git verify-commit`
+pub fn git_verify_commit(id: git2::Oid) -> Verification {
+    log::debug!("git verify-commit --raw {}", id);
+    let output = match std::process::Command::new("git")
+        .arg("verify-commit")
+        .arg("--raw")
+        .arg(id.to_string())
+        .stdin(std::process::Stdio::null())
+        .output()
+    {
+        Ok(output) => output,
+        Err(_) => return Verification::Unverifiable,
+    };
+    if output.status.success() {
+        return Verification::Good;
+    }
+    // `--raw` passes on gpg's status lines or ssh-keygen's messages
+    let status = String::from_utf8_lossy(&output.stderr);
+    if status.contains("[GNUPG:] BADSIG") || status.contains("incorrect signature") {
+        Verification::Bad
+    } else {
+        Verification::Unverifiable
+    }
+}
+
 /// Switch to the best-guess branch
 ///
 /// # Panic
@@ -266,17 +576,12 @@ pub fn switch(
             )?;
         }
     } else {
-        let abbrev_id = repo
-            .raw()
-            .find_object(current_id, None)
-            .unwrap_or_else(|e| panic!("Unexpected git2 error: {e}"))
-            .short_id()
-            .unwrap_or_else(|e| panic!("Unexpected git2 error: {e}"));
+        let abbrev_id = repo.abbrev_id(current_id);
         let _ = writeln!(
             anstream::stderr(),
             "{} to {}: {}",
             stderr_palette.good("Switching"),
-            stderr_palette.highlight(abbrev_id.as_str().unwrap()),
+            stderr_palette.highlight(abbrev_id.as_str()),
             stderr_palette.hint(&current_commit.summary)
         );
         if !dry_run {
@@ -287,6 +592,32 @@ pub fn switch(
     Ok(())
 }
 
+/// Pick the branch to end on when `head_branch`, at `head_id`, is going away
+///
+/// This is the branch closest on top of `head_id`, falling back to `onto`.  `distance` counts the
+/// commits from `head_id` to a branch, returning `None` when the branch isn't built on `head_id`.
+/// Ties go to the first name alphabetically so the pick is stable.
+pub fn fallback_branch<'b>(
+    head_branch: &str,
+    head_id: git2::Oid,
+    branches: impl IntoIterator<Item = (&'b str, git2::Oid)>,
+    distance: impl Fn(git2::Oid, git2::Oid) -> Option<usize>,
+    onto: impl FnOnce() -> Option<&'b str>,
+) -> Option<String> {
+    let fallback = branches
+        .into_iter()
+        .filter_map(|(name, id)| distance(head_id, id).map(|count| (count, name)))
+        .min()
+        .map(|(_, name)| name)
+        .or_else(onto)
+        .map(ToOwned::to_owned);
+    match &fallback {
+        Some(fallback) => log::info!("`{head_branch}` will be deleted, switching to `{fallback}`"),
+        None => log::warn!("`{head_branch}` will be deleted, leaving HEAD detached"),
+    }
+    fallback
+}
+
 pub fn render_id(
     repo: &git_stack::git::GitRepo,
     branches: &git_stack::graph::BranchSet,
@@ -299,20 +630,495 @@ pub fn render_id(
         let name = current_branch.display_name().to_string();
         name
     } else {
-        repo.raw()
-            .find_object(id, None)
-            .unwrap_or_else(|e| panic!("Unexpected git2 error: {e}"))
-            .short_id()
-            .unwrap_or_else(|e| panic!("Unexpected git2 error: {e}"))
-            .as_str()
-            .unwrap()
-            .to_owned()
+        repo.abbrev_id(id)
+    }
+}
+
+/// Commit `tree_id` on top of `HEAD` as a `fixup!` of `target_id`
+///
+/// Unless `dry_run`, the branches at `HEAD` move to the new commit and it is checked out.  Returns
+/// `None` when there is nothing to commit.
+pub fn commit_fixup(
+    repo: &mut git_stack::git::GitRepo,
+    branches: &git_stack::graph::BranchSet,
+    target_id: git2::Oid,
+    tree_id: git2::Oid,
+    dry_run: bool,
+) -> Result<Option<git2::Oid>, eyre::Error> {
+    let ids = commit_fixups(repo, branches, &[(target_id, tree_id)], dry_run)?;
+    Ok(ids.into_iter().next())
+}
+
+/// Commit each `(target_id, tree_id)` in turn on top of `HEAD` as a `fixup!` of `target_id`
+///
+/// Unless `dry_run`, the branches at `HEAD` move to the last new commit and it is checked out.
+/// Trees that match their parent are skipped, so fewer commits than `fixups` may be returned.
+pub fn commit_fixups(
+    repo: &mut git_stack::git::GitRepo,
+    branches: &git_stack::graph::BranchSet,
+    fixups: &[(git2::Oid, git2::Oid)],
+    dry_run: bool,
+) -> Result<Vec<git2::Oid>, eyre::Error> {
+    let head_id = repo.head_commit().id;
+
+    let mut ids = Vec::new();
+    let mut parent_id = head_id;
+    for (target_id, tree_id) in fixups.iter().copied() {
+        let parent_tree_id = repo
+            .raw()
+            .find_commit(parent_id)
+            .expect("head_commit is always valid")
+            .tree_id();
+        if parent_tree_id == tree_id {
+            continue;
+        }
+
+        let target_commit = repo.find_commit(target_id).unwrap();
+
+        let message = format!(
+            "fixup! {}",
+            target_commit
+                .fixup_summary()
+                .unwrap_or_else(|| target_commit.summary.as_ref())
+        );
+        let id = repo.commit_tree(parent_id, tree_id, &message)?;
+        log::debug!("committed {} {}", id, message);
+        ids.push(id);
+        parent_id = id;
+    }
+    let Some(id) = ids.last().copied() else {
+        return Ok(ids);
+    };
+    if !dry_run {
+        checkout_fixup(repo, branches, head_id, id)?;
     }
+    Ok(ids)
+}
+
+/// Move the branches at `head_id` to `id`, a fixup committed on top of it, and check it out
+pub fn checkout_fixup(
+    repo: &mut git_stack::git::GitRepo,
+    branches: &git_stack::graph::BranchSet,
+    head_id: git2::Oid,
+    id: git2::Oid,
+) -> Result<(), eyre::Error> {
+    let mut stash_id = None;
+    if repo.is_dirty() {
+        stash_id = repo.stash_push(None).ok();
+    }
+
+    let head_branch = repo.head_branch();
+    if head_branch.is_some() {
+        repo.detach()?;
+    }
+    for branch in branches.get(head_id).into_iter().flatten() {
+        if let Some(name) = branch.local_name() {
+            repo.branch(name, id)?;
+        }
+    }
+    if let Some(head_branch) = head_branch {
+        log::debug!("switching to {} {}", head_branch, id);
+        repo.switch_branch(
+            head_branch
+                .local_name()
+                .expect("HEAD branch is always local"),
+        )?;
+    } else {
+        log::debug!("switching to {}", id);
+        repo.switch_commit(id)?;
+    }
+
+    if let Some(stash_id) = stash_id {
+        match repo.stash_pop(stash_id) {
+            Ok(()) => {
+                log::debug!("Dropped refs/stash {}", stash_id);
+            }
+            Err(err) => {
+                log::error!("Failed to pop {} from stash: {}", stash_id, err);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Contents of `commit.template`, for commits that don't have a message yet
+pub fn commit_template(repo: &git2::Repository) -> Option<String> {
+    let config = repo.config().ok()?;
+    let path = config.get_path("commit.template").ok()?;
+    let path = if path.is_relative() {
+        // Like git, relative paths are relative to the worktree
+        repo.workdir().unwrap_or_else(|| repo.path()).join(path)
+    } else {
+        path
+    };
+    match std::fs::read_to_string(&path) {
+        Ok(template) => Some(template),
+        Err(err) => {
+            log::warn!(
+                "Could not read `commit.template` {}: {}",
+                path.display(),
+                err
+            );
+            None
+        }
+    }
+}
+
+/// How `--dry-run` reports the planned changes
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum PlanFormat {
+    /// Log what would be done
+    #[default]
+    Text,
+    /// Print the plan as a JSON document, see [`plan_json`]
+    Json,
+}
+
+/// Identifies the [`plan_json`] document, bumped on incompatible changes
+pub const PLAN_SCHEMA: &str = "git-stack-plan";
+pub const PLAN_SCHEMA_VERSION: u32 = 1;
+
+/// Describe `scripts` for tooling to preview, without running them
+///
+/// Each script is a list of batches that replay commits onto `onto`, one command per step:
+/// - `{"action": "pick", "commit": <id>}`
+/// - `{"action": "fixup", "commit": <id>}`, squashing into the prior commit
+/// - `{"action": "reword", "commit": <id>, "message": <message>}`
+/// - `{"action": "reset-author", "commit": <id>}`
+/// - `{"action": "create-branch", "commit": <id>, "branch": <name>}`
+/// - `{"action": "delete-branch", "branch": <name>}`
+///
+/// The summary lists the `dropped` commits and the `conflicts` found by replaying `scripts`
+/// with [`git_stack::rewrite::Executor::verify`], each with the branch that fails and the ones
+/// stacked on it.
+pub fn plan_json(
+    repo: &mut git_stack::git::GitRepo,
+    scripts: &[git_stack::rewrite::Script],
+    dropped: &[git2::Oid],
+) -> serde_json::Value {
+    use git_stack::rewrite::Command;
+
+    let mut stats = git_stack::rewrite::Stats::default();
+    let scripts_json = scripts
+        .iter()
+        .map(|script| {
+            let script_stats = script.stats();
+            stats.rewritten.extend(script_stats.rewritten);
+            stats.branches.extend(script_stats.branches);
+            stats.deleted_branches.extend(script_stats.deleted_branches);
+
+            let batches = script
+                .iter()
+                .map(|batch| {
+                    let commands = batch
+                        .commands()
+                        .filter_map(|(id, command)| {
+                            let id = id.to_string();
+                            let command = match command {
+                                Command::RegisterMark(_) => return None,
+                                Command::CherryPick(cherry_id) => serde_json::json!({
+                                    "action": "pick",
+                                    "commit": cherry_id.to_string(),
+                                }),
+                                Command::Fixup(squash_id) => serde_json::json!({
+                                    "action": "fixup",
+                                    "commit": squash_id.to_string(),
+                                }),
+                                Command::Reword(message) => serde_json::json!({
+                                    "action": "reword",
+                                    "commit": id,
+                                    "message": message,
+                                }),
+                                Command::ResetAuthor => serde_json::json!({
+                                    "action": "reset-author",
+                                    "commit": id,
+                                }),
+                                Command::CreateBranch(name) => serde_json::json!({
+                                    "action": "create-branch",
+                                    "commit": id,
+                                    "branch": name,
+                                }),
+                                Command::DeleteBranch(name) => serde_json::json!({
+                                    "action": "delete-branch",
+                                    "branch": name,
+                                }),
+                            };
+                            Some(command)
+                        })
+                        .collect::<Vec<_>>();
+                    serde_json::json!({
+                        "onto": batch.onto_mark().to_string(),
+                        "branch": batch.branch(),
+                        "commands": commands,
+                    })
+                })
+                .collect::<Vec<_>>();
+            serde_json::json!({ "batches": batches })
+        })
+        .collect::<Vec<_>>();
+
+    let mut verifier = git_stack::rewrite::Executor::new(false);
+    let conflicts = scripts
+        .iter()
+        .flat_map(|script| verifier.verify(repo, script))
+        .map(|(err, name, dependents)| {
+            serde_json::json!({
+                "branch": name,
+                "message": err.message(),
+                "dependents": dependents,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let ids = |ids: &[git2::Oid]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+    serde_json::json!({
+        "schema": PLAN_SCHEMA,
+        "version": PLAN_SCHEMA_VERSION,
+        "scripts": scripts_json,
+        "summary": {
+            "rewritten": ids(&stats.rewritten),
+            "branches": stats.branches,
+            "deleted_branches": stats.deleted_branches,
+            "dropped": ids(dropped),
+            "conflicts": conflicts,
+        },
+    })
+}
+
+pub fn print_plan_json(
+    repo: &mut git_stack::git::GitRepo,
+    scripts: &[git_stack::rewrite::Script],
+    dropped: &[git2::Oid],
+) -> std::io::Result<()> {
+    use std::io::Write as _;
+
+    let plan = plan_json(repo, scripts, dropped);
+    let mut stdout = anstream::stdout().lock();
+    serde_json::to_writer_pretty(&mut stdout, &plan)?;
+    writeln!(stdout)?;
+    Ok(())
+}
+
+/// List what re-stacking will do, for confirming before anything is rewritten
+pub fn print_restack_summary(
+    repo: &git2::Repository,
+    abbrev_id: impl Fn(git2::Oid) -> String,
+    summary: &git_stack::graph::Summary,
+    palette: Palette,
+) {
+    use std::io::Write;
+
+    let mut stderr = anstream::stderr().lock();
+    if !summary.moved.is_empty() {
+        let _ = writeln!(stderr, "{}:", palette.info("moving"));
+        for moved in &summary.moved {
+            let _ = writeln!(
+                stderr,
+                "  {}: {} -> {} commit(s) on {}",
+                palette.highlight(&moved.name),
+                abbrev_id(moved.old_id),
+                moved.commit_count,
+                abbrev_id(moved.onto_id)
+            );
+        }
+    }
+    if !summary.dropped.is_empty() {
+        let _ = writeln!(stderr, "{}:", palette.warn("dropping"));
+        for id in &summary.dropped {
+            let summary = repo
+                .find_commit(*id)
+                .ok()
+                .and_then(|c| c.summary().map(ToOwned::to_owned))
+                .unwrap_or_default();
+            let _ = writeln!(stderr, "  {} {}", abbrev_id(*id), palette.hint(summary));
+        }
+    }
+    if !summary.deleted.is_empty() {
+        let _ = writeln!(stderr, "{}:", palette.warn("deleting"));
+        for name in &summary.deleted {
+            let _ = writeln!(stderr, "  {}", palette.highlight(name));
+        }
+    }
+}
+
+/// Ask a yes/no question on stderr, defaulting to "no"
+pub fn confirm(prompt: &str) -> std::io::Result<bool> {
+    use std::io::Write as _;
+
+    let mut stderr = anstream::stderr();
+    write!(stderr, "{prompt} [y/N] ")?;
+    stderr.flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    let answer = answer.trim();
+    Ok(answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes"))
+}
+
+/// Check before rewriting more than `stack.max-stack-size` commits, which usually means the base
+/// is wrong
+///
+/// This asks when run from a terminal, otherwise it takes `yes` to go ahead.
+pub fn confirm_stack_size(
+    pickable_count: usize,
+    max_stack_size: Option<usize>,
+    yes: bool,
+) -> eyre::Result<()> {
+    use is_terminal::IsTerminal;
+
+    let Some(max_stack_size) = max_stack_size else {
+        return Ok(());
+    };
+    if pickable_count <= max_stack_size {
+        return Ok(());
+    }
+
+    log::warn!(
+        "{} commits would be rewritten, more than `stack.max-stack-size` ({}); the base may be wrong, see `--base`",
+        pickable_count,
+        max_stack_size
+    );
+    if yes {
+        Ok(())
+    } else if !std::io::stdin().is_terminal() {
+        eyre::bail!(
+            "refusing to rewrite {} commits without `--yes`",
+            pickable_count
+        );
+    } else if confirm("rewrite them anyway?")? {
+        Ok(())
+    } else {
+        eyre::bail!("aborted");
+    }
+}
+
+/// Refuse to go on while a merge, rebase, bisect, etc is in progress or the index has unresolved
+/// conflicts, only reporting it when `dry_run`
+///
+/// `doing` is what can't be done, like `walk commits`.
+pub fn ensure_clean_state(
+    repo: &git2::Repository,
+    doing: &str,
+    dry_run: bool,
+) -> proc_exit::ExitResult {
+    use std::io::Write;
+
+    let state = repo.state();
+    let conflicts = git_stack::git::index_conflicts(repo);
+    let (code, message) = if state != git2::RepositoryState::Clean {
+        (
+            proc_exit::sysexits::USAGE_ERR,
+            format!("cannot {doing}, {state:?} in progress"),
+        )
+    } else if !conflicts.is_empty() {
+        let conflicts = conflicts
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect::<Vec<_>>();
+        (
+            CONFLICT_ERR,
+            format!(
+                "cannot {doing} with unresolved conflicts (conflicted files: {})",
+                conflicts.join(", ")
+            ),
+        )
+    } else {
+        return Ok(());
+    };
+    if dry_run {
+        let palette = Palette::colored();
+        let _ = writeln!(
+            anstream::stderr(),
+            "{}: {}",
+            palette.error("error"),
+            message
+        );
+        Ok(())
+    } else {
+        Err(code.with_message(message))
+    }
+}
+
+/// Refuse to rewrite merge commits, unless `stack.rebase-merges` says to recreate them
+///
+/// Otherwise they'd be flattened, losing the merged-in side.  Even then, a merge of a commit that
+/// is itself being rewritten is refused as it would keep merging in the old commit.
+pub fn ensure_no_merges(
+    repo: &git2::Repository,
+    rewritten: impl IntoIterator<Item = git2::Oid>,
+    rebase_merges: bool,
+) -> eyre::Result<()> {
+    let rewritten: Vec<_> = rewritten.into_iter().collect();
+    for id in rewritten.iter().copied() {
+        let commit = repo.find_commit(id)?;
+        if commit.parent_count() <= 1 {
+            continue;
+        }
+        if !rebase_merges {
+            eyre::bail!("merge commit in stack; use --rebase-merges");
+        }
+        if let Some(merged_id) = commit
+            .parent_ids()
+            .skip(1)
+            .find(|parent_id| rewritten.contains(parent_id))
+        {
+            eyre::bail!(
+                "merge commit {} merges in {}, which is also being rewritten",
+                id,
+                merged_id
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Whether the commit's author name or email contains `pattern`, for `--author`
+pub fn is_by_author(repo: &git2::Repository, id: git2::Oid, pattern: &str) -> bool {
+    let Ok(commit) = repo.find_commit(id) else {
+        return false;
+    };
+    let author = commit.author();
+    let matches = |field: Option<&str>| matches!(field, Some(field) if field.contains(pattern));
+    matches(author.name()) || matches(author.email())
+}
+
+/// Pin the commits not by `author` (and what they are built on) in place, for `--author`
+pub fn protect_other_authors(
+    graph: &mut git_stack::graph::Graph,
+    repo: &git_stack::git::GitRepo,
+    author: Option<&str>,
+) {
+    let Some(pattern) = author else {
+        return;
+    };
+    let others: Vec<_> = graph
+        .descendants()
+        .filter(|id| {
+            let action = graph
+                .commit_get::<git_stack::graph::Action>(*id)
+                .copied()
+                .unwrap_or_default();
+            !action.is_protected() && !is_by_author(repo.raw(), *id, pattern)
+        })
+        .collect();
+    git_stack::graph::protect_pinned_commits(graph, &others);
+}
+
+/// Fail before doing any work when a message needs editing but no editor can be launched
+///
+/// Without a configured editor, `vi` is used, which needs a terminal.
+pub fn ensure_editor(repo_config: &git_stack::config::RepoConfig) -> eyre::Result<()> {
+    use is_terminal::IsTerminal;
+
+    if repo_config.editor.is_none() && !std::io::stdin().is_terminal() {
+        eyre::bail!("no editor configured; set core.editor or use --message");
+    }
+    Ok(())
 }
 
 pub fn edit_commit(
     git_path: &std::path::Path,
     editor: &str,
+    cleanup: git_stack::config::CleanupMode,
     initial: &str,
 ) -> eyre::Result<Option<String>> {
     let edit_path = git_path.join("COMMIT_EDITMSG");
@@ -322,10 +1128,17 @@ pub fn edit_commit(
     let mut args = shlex::Shlex::new(editor);
     let cmd = args.next().unwrap_or_else(|| "vi".to_owned());
 
-    let status = std::process::Command::new(cmd)
+    let status = std::process::Command::new(&cmd)
         .args(args)
         .arg(&edit_path)
-        .spawn()?
+        .spawn()
+        .map_err(|err| {
+            eyre::eyre!(
+                "could not launch editor `{}` ({}); set core.editor or use --message",
+                cmd,
+                err
+            )
+        })?
         .wait()?;
     if !status.success() {
         eyre::bail!(
@@ -349,33 +1162,232 @@ pub fn edit_commit(
         return Ok(None);
     }
 
-    let sanitized = sanitize_message(&edited);
-    if sanitized.is_empty() {
+    let sanitized = sanitize_message(&edited, cleanup);
+    if sanitized.trim().is_empty() {
         eyre::bail!("Aborting commit due to empty commit message.")
     }
 
     Ok(Some(sanitized))
 }
 
-pub(crate) fn sanitize_message(message: &str) -> String {
-    let mut lines = LinesWithTerminator::new(message).collect::<Vec<_>>();
-    lines.retain(|l| !l.starts_with('#'));
-    while !lines.is_empty() {
-        if lines.first().unwrap().trim().is_empty() {
-            lines.remove(0);
-        } else {
-            break;
+/// Read a commit message from `path`, or standard input for `-`, like `git commit --file`
+///
+/// The message is cleaned up like an edited one, with an empty message aborting.
+pub fn read_message_file(
+    path: &std::path::Path,
+    cleanup: git_stack::config::CleanupMode,
+) -> eyre::Result<String> {
+    use std::io::Read as _;
+
+    let message = if path == std::path::Path::new("-") {
+        let mut message = String::new();
+        std::io::stdin()
+            .read_to_string(&mut message)
+            .wrap_err("could not read the message from stdin")?;
+        message
+    } else {
+        std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("could not read `{}`", path.display()))?
+    };
+
+    let sanitized = sanitize_message(&message, cleanup);
+    if sanitized.trim().is_empty() {
+        eyre::bail!("Aborting commit due to empty commit message.")
+    }
+    Ok(sanitized)
+}
+
+/// Append `key: value` trailers to `message`, skipping any it already has
+///
+/// Like `git interpret-trailers`, they go in the message's trailer block (its last paragraph,
+/// when that is only `Key: value` lines), or else a new one.
+pub fn add_trailers(message: &str, key: &str, values: &[String]) -> String {
+    fn is_trailer(line: &str) -> bool {
+        line.split_once(':')
+            .map(|(token, _)| {
+                !token.is_empty() && token.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            })
+            .unwrap_or(false)
+            || line.starts_with([' ', '\t'])
+    }
+
+    let message = message.trim_end();
+    let paragraphs = message.split("\n\n").collect::<Vec<_>>();
+    let has_trailer_block = 1 < paragraphs.len()
+        && paragraphs
+            .last()
+            .expect("split is never empty")
+            .lines()
+            .all(is_trailer);
+    let existing = if has_trailer_block {
+        paragraphs
+            .last()
+            .expect("split is never empty")
+            .lines()
+            .collect::<Vec<_>>()
+    } else {
+        Vec::new()
+    };
+
+    let mut trailers: Vec<String> = Vec::new();
+    for value in values {
+        let is_present = existing
+            .iter()
+            .copied()
+            .chain(trailers.iter().map(String::as_str))
+            .any(|line| {
+                line.split_once(':')
+                    .map(|(k, v)| k.trim().eq_ignore_ascii_case(key) && v.trim() == value)
+                    .unwrap_or(false)
+            });
+        if !is_present {
+            trailers.push(format!("{key}: {value}"));
         }
     }
-    while !lines.is_empty() {
-        if lines.last().unwrap().trim().is_empty() {
-            lines.pop();
-        } else {
-            break;
+
+    if trailers.is_empty() {
+        message.to_owned()
+    } else {
+        let separator = if has_trailer_block { "\n" } else { "\n\n" };
+        format!("{message}{separator}{}", trailers.join("\n"))
+    }
+}
+
+/// Pull the `Fixes`, `Closes` and `Refs` lines out of `messages`, merging their issues into one line
+/// per kind, like `Fixes #123, #124`
+///
+/// Returns the messages without those lines, followed by the merged lines in the order their kinds
+/// first appear.  Only lines where every reference has a `#` count, so prose like "Fixes the
+/// parser" is left alone.
+pub fn merge_issue_refs(messages: &[&str]) -> (Vec<String>, Vec<String>) {
+    const KEYS: [&str; 3] = ["fixes", "closes", "refs"];
+
+    fn parse_refs(line: &str) -> Option<(&str, &str, Vec<&str>)> {
+        let line = line.trim();
+        let key_end = line.find([':', ' ', '\t'])?;
+        let key = &line[..key_end];
+        if !KEYS.iter().any(|k| key.eq_ignore_ascii_case(k)) {
+            return None;
+        }
+        let rest = &line[key_end..];
+        let separator = if rest.starts_with(':') { ": " } else { " " };
+        let refs = rest
+            .trim_start_matches(':')
+            .split([',', ' ', '\t'])
+            .filter(|r| !r.is_empty())
+            .collect::<Vec<_>>();
+        (!refs.is_empty() && refs.iter().all(|r| r.contains('#'))).then_some((key, separator, refs))
+    }
+
+    // (key as first written, separator, references)
+    let mut merged: Vec<(&str, &str, Vec<&str>)> = Vec::new();
+    let mut stripped = Vec::new();
+    for message in messages {
+        let mut kept = Vec::new();
+        for line in message.lines() {
+            let Some((key, separator, refs)) = parse_refs(line) else {
+                kept.push(line);
+                continue;
+            };
+            let index = match merged
+                .iter()
+                .position(|(k, _, _)| k.eq_ignore_ascii_case(key))
+            {
+                Some(index) => index,
+                None => {
+                    merged.push((key, separator, Vec::new()));
+                    merged.len() - 1
+                }
+            };
+            for r in refs {
+                if !merged[index].2.contains(&r) {
+                    merged[index].2.push(r);
+                }
+            }
         }
+        stripped.push(kept.join("\n").trim_end().to_owned());
     }
-    let message = lines.join("");
-    message.trim_end().to_owned()
+
+    let merged = merged
+        .into_iter()
+        .map(|(key, separator, refs)| format!("{key}{separator}{}", refs.join(", ")))
+        .collect();
+    (stripped, merged)
+}
+
+/// Everything below this line in a commit message template is ignored, like `git commit --verbose`
+pub(crate) const SCISSORS: &str = "# ------------------------ >8 ------------------------";
+
+/// Append the diff between `parent_id`'s tree and `tree_id` below a [`SCISSORS`] line
+pub(crate) fn append_verbose_diff(
+    template: &mut String,
+    repo: &git2::Repository,
+    parent_id: Option<git2::Oid>,
+    tree_id: git2::Oid,
+) -> Result<(), git2::Error> {
+    let old_tree = parent_id
+        .map(|id| repo.find_commit(id).and_then(|c| c.tree()))
+        .transpose()?;
+    let new_tree = repo.find_tree(tree_id)?;
+    let diff = repo.diff_tree_to_tree(old_tree.as_ref(), Some(&new_tree), None)?;
+
+    template.push_str(SCISSORS);
+    template.push('\n');
+    template.push_str("# Do not modify or remove the line above.\n");
+    template.push_str("# Everything below it will be ignored.\n");
+    diff.print(git2::DiffFormat::Patch, |_delta, _hunk, line| {
+        if matches!(line.origin(), '+' | '-' | ' ') {
+            template.push(line.origin());
+        }
+        template.push_str(&String::from_utf8_lossy(line.content()));
+        true
+    })?;
+    Ok(())
+}
+
+/// Write the hint on how the edited message will be cleaned up, like `git commit`
+pub(crate) fn write_cleanup_hint(template: &mut String, cleanup: git_stack::config::CleanupMode) {
+    template.push_str("# Please enter the commit message for your changes. Lines starting\n");
+    if cleanup == git_stack::config::CleanupMode::Strip {
+        template.push_str("# with '#' will be ignored, and an empty message aborts the commit.\n");
+    } else {
+        template
+            .push_str("# with '#' will be kept; you may remove them yourself if you want to.\n");
+        template.push_str("# An empty message aborts the commit.\n");
+    }
+}
+
+/// Clean up an edited commit message, like `git commit --cleanup=<mode>`
+///
+/// Everything from the [`SCISSORS`] line on is always dropped.  Unless `verbatim`, trailing
+/// whitespace is trimmed from each line, leading and trailing blank lines are dropped, repeated
+/// blank lines are collapsed into one and the message ends in exactly one newline.  `strip` also
+/// drops `#` comment lines.  An empty result means the message was empty.
+pub(crate) fn sanitize_message(message: &str, cleanup: git_stack::config::CleanupMode) -> String {
+    let lines = LinesWithTerminator::new(message).take_while(|l| l.trim_end() != SCISSORS);
+    if cleanup == git_stack::config::CleanupMode::Verbatim {
+        return lines.collect();
+    }
+
+    let mut sanitized = String::new();
+    let mut pending_blank = false;
+    for line in lines {
+        if cleanup == git_stack::config::CleanupMode::Strip && line.starts_with('#') {
+            continue;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            pending_blank = true;
+            continue;
+        }
+        if pending_blank && !sanitized.is_empty() {
+            sanitized.push('\n');
+        }
+        pending_blank = false;
+        sanitized.push_str(line);
+        sanitized.push('\n');
+    }
+    sanitized
 }
 
 #[derive(Clone, Debug)]
@@ -481,3 +1493,85 @@ impl<D: std::fmt::Display> std::fmt::Display for Styled<D> {
 }
 
 pub const STASH_STACK_NAME: &str = "git-stack";
+
+/// Exit code when branches could not be re-stacked, usually due to merge conflicts
+///
+/// Kept apart from `sysexits::USAGE_ERR` so scripts can tell "needs resolution" from "invalid
+/// invocation".
+pub const CONFLICT_ERR: proc_exit::Code = proc_exit::Code::FAILURE;
+
+/// Tally of re-stacking every independent stack, summarized once all of them were attempted
+#[derive(Default, Debug)]
+pub struct RestackSummary {
+    branches: Vec<String>,
+    failures: Vec<(String, String)>,
+}
+
+impl RestackSummary {
+    /// Record the branches a script re-stacks, logging each of its failures as they happen
+    pub fn record<'s>(
+        &mut self,
+        branches: impl IntoIterator<Item = &'s str>,
+        failures: &[(git2::Error, &str, Vec<&str>)],
+    ) {
+        self.branches
+            .extend(branches.into_iter().map(ToOwned::to_owned));
+        for (err, name, dependents) in failures {
+            log::error!("Failed to re-stack branch `{}`: {}", name, err);
+            // Conflicts list one path per line
+            let reason = err
+                .message()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            self.failures.push(((*name).to_owned(), reason));
+            if !dependents.is_empty() {
+                log::error!("  Blocked dependents: {}", dependents.join(", "));
+                for dependent in dependents {
+                    self.failures
+                        .push(((*dependent).to_owned(), format!("blocked by `{name}`")));
+                }
+            }
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// List every failure on stderr, if there were any
+    pub fn report(&self) {
+        if self.is_success() {
+            return;
+        }
+        let restacked = self
+            .branches
+            .iter()
+            .filter(|b| !self.failures.iter().any(|(f, _)| f == *b))
+            .collect::<std::collections::BTreeSet<_>>()
+            .len();
+        let failures = self
+            .failures
+            .iter()
+            .collect::<std::collections::BTreeSet<_>>()
+            .into_iter()
+            .map(|(branch, reason)| format!("{branch} ({reason})"))
+            .collect::<Vec<_>>();
+        let palette = Palette::colored();
+        anstream::eprintln!(
+            "{}: {} re-stacked, {} failed: {}",
+            palette.error("error"),
+            pluralize_branches(restacked),
+            failures.len(),
+            failures.join(", ")
+        );
+    }
+}
+
+fn pluralize_branches(count: usize) -> String {
+    if count == 1 {
+        "1 branch".to_owned()
+    } else {
+        format!("{count} branches")
+    }
+}
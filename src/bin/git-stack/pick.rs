@@ -0,0 +1,126 @@
+use std::io::Write;
+
+use proc_exit::prelude::*;
+
+/// Opt branches in to being rewritten
+///
+/// With `stack.default-action=protect`, commits are protected unless they are yours and not yet
+/// pushed.  Picking a branch lets the stack it is on be re-stacked like any other.  Picks are
+/// recorded by branch name in `.git/config` as `stack.picked`, so they follow the branch as it gets
+/// rewritten.
+#[derive(clap::Args)]
+pub struct PickArgs {
+    /// Branches to pick
+    #[arg(required = true, value_name = "BRANCH")]
+    branches: Vec<String>,
+
+    /// Stop picking the branches
+    #[arg(long)]
+    remove: bool,
+}
+
+impl PickArgs {
+    pub fn exec(&self) -> proc_exit::ExitResult {
+        let stderr_palette = crate::ops::Palette::colored();
+
+        let cwd = std::env::current_dir().with_code(proc_exit::sysexits::USAGE_ERR)?;
+        let repo = crate::ops::open_repo(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
+        let mut repo = git_stack::git::GitRepo::new(repo);
+
+        let repo_config = git_stack::config::RepoConfig::from_all(repo.raw())
+            .with_code(proc_exit::sysexits::CONFIG_ERR)?;
+        repo.set_abbrev(repo_config.abbrev());
+
+        let existing = git_stack::config::RepoConfig::from_repo(repo.raw())
+            .with_code(proc_exit::sysexits::CONFIG_ERR)?;
+        let mut picked = existing.picked.unwrap_or_default();
+        if self.remove {
+            let mut removed = Vec::new();
+            picked.retain(|branch| {
+                let remove = self.branches.contains(branch);
+                if remove {
+                    removed.push(branch.clone());
+                }
+                !remove
+            });
+            for branch in &self.branches {
+                if !removed.contains(branch) {
+                    log::warn!("`{branch}` isn't picked");
+                }
+            }
+            store_picked(&repo, picked)?;
+
+            let mut stderr = anstream::stderr().lock();
+            for branch in &removed {
+                let _ = writeln!(
+                    stderr,
+                    "{} {}",
+                    stderr_palette.good("Unpicked"),
+                    stderr_palette.highlight(branch),
+                );
+            }
+            return Ok(());
+        }
+
+        if repo_config.default_action() != git_stack::config::DefaultAction::Protect {
+            log::warn!(
+                "`stack.default-action` is `{}`, commits are already rewritable unless protected",
+                repo_config.default_action()
+            );
+        }
+        let commits = self
+            .branches
+            .iter()
+            .map(|branch| {
+                let branch_id = repo
+                    .find_local_branch(branch)
+                    .map(|b| b.id)
+                    .ok_or_else(|| {
+                        proc_exit::sysexits::USAGE_ERR
+                            .with_message(format!("could not find local branch `{branch}`"))
+                    })?;
+                Ok(repo
+                    .find_commit(branch_id)
+                    .expect("branches point to commits"))
+            })
+            .collect::<Result<Vec<_>, proc_exit::Exit>>()?;
+        for branch in &self.branches {
+            if !picked.contains(branch) {
+                picked.push(branch.clone());
+            }
+        }
+        store_picked(&repo, picked)?;
+
+        let mut stderr = anstream::stderr().lock();
+        for (branch, commit) in self.branches.iter().zip(&commits) {
+            let _ = writeln!(
+                stderr,
+                "{} {} ({}): {}",
+                stderr_palette.good("Picked"),
+                stderr_palette.highlight(branch),
+                repo.abbrev_id(commit.id),
+                stderr_palette.hint(&commit.summary)
+            );
+        }
+
+        Ok(())
+    }
+}
+
+fn store_picked(
+    repo: &git_stack::git::GitRepo,
+    picked: Vec<String>,
+) -> Result<(), proc_exit::Exit> {
+    let update = git_stack::config::RepoConfig {
+        picked: Some(picked),
+        ..Default::default()
+    };
+    let mut config = repo
+        .raw()
+        .config()
+        .and_then(|config| config.open_level(git2::ConfigLevel::Local))
+        .with_code(proc_exit::sysexits::CONFIG_ERR)?;
+    update
+        .to_gitconfig(&mut config)
+        .with_code(proc_exit::Code::FAILURE)
+}
@@ -0,0 +1,182 @@
+use std::io::Write;
+
+use proc_exit::prelude::*;
+
+/// Render the pull request description for a branch in a stack
+///
+/// The body comes from `stack.pr-body-template`, which can link to the neighbouring pull requests
+/// in the stack with these placeholders:
+/// - `{branch}`: the branch being described
+/// - `{position}` and `{total}`: where the branch is in its stack, counting from the base
+/// - `{prev}` and `{next}`: links to the pull requests for the branches below and above it
+/// - `{commits}`: the branch's commits, as a list
+#[derive(clap::Args)]
+pub struct PrBodyArgs {
+    /// Branch to describe (default: the current branch)
+    #[arg(value_name = "BRANCH")]
+    branch: Option<String>,
+
+    /// Link to the pull request for a branch in the stack
+    #[arg(long = "url", value_name = "BRANCH=URL", value_parser = parse_url)]
+    urls: Vec<(String, String)>,
+}
+
+impl PrBodyArgs {
+    pub fn exec(&self, global: &git_stack::config::RepoConfig) -> proc_exit::ExitResult {
+        let (repo, repo_config) = crate::ops::open_configured_repo(global)?;
+
+        let branches = crate::ops::load_branches(&repo, &repo_config)?;
+
+        let name = match self.branch.as_deref() {
+            Some(name) => name.to_owned(),
+            None => repo
+                .head_branch()
+                .and_then(|b| b.local_name().map(|n| n.to_owned()))
+                .ok_or_else(|| {
+                    proc_exit::sysexits::USAGE_ERR.with_message("HEAD is not on a branch")
+                })?,
+        };
+        let tip_id = repo
+            .find_local_branch(&name)
+            .ok_or_else(|| {
+                proc_exit::sysexits::USAGE_ERR
+                    .with_message(format!("could not find local branch `{name}`"))
+            })?
+            .id;
+        let base = crate::ops::resolve_implicit_base(
+            &repo,
+            tip_id,
+            &branches,
+            repo_config.auto_base_commit_count(),
+        );
+        let merge_base_oid = crate::ops::resolve_merge_base(&repo, &base, tip_id, &name)
+            .with_code(proc_exit::sysexits::USAGE_ERR)?;
+        let stack_branches = branches.dependents(&repo, merge_base_oid, tip_id);
+        let mut graph = git_stack::graph::Graph::from_branches(&repo, stack_branches)
+            .with_code(proc_exit::Code::FAILURE)?;
+        git_stack::graph::protect_branches(&mut graph);
+        git_stack::graph::mark_fixup(&mut graph, &repo);
+
+        let stack = stack_branch_names(&graph, tip_id);
+        let position = stack.iter().position(|b| *b == name).ok_or_else(|| {
+            proc_exit::sysexits::USAGE_ERR.with_message(format!("branch `{name}` is protected"))
+        })?;
+        let stack = stack
+            .into_iter()
+            .map(|branch| {
+                let url = self
+                    .urls
+                    .iter()
+                    .find(|(b, _)| *b == branch)
+                    .map(|(_, url)| url.clone());
+                (branch, url)
+            })
+            .collect::<Vec<_>>();
+        let commits = git_stack::graph::branch_commits(&graph, tip_id)
+            .into_iter()
+            .filter(|id| graph.commit_get::<git_stack::graph::Fixup>(*id).is_none())
+            .map(|id| {
+                repo.find_commit(id)
+                    .expect("graph commits exist")
+                    .summary
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+
+        let body = render_body(repo_config.pr_body_template(), &stack, position, &commits);
+        let _ = writeln!(anstream::stdout(), "{}", body.trim_end());
+
+        Ok(())
+    }
+}
+
+fn parse_url(value: &str) -> Result<(String, String), String> {
+    let (branch, url) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected `BRANCH=URL`, got `{value}`"))?;
+    Ok((branch.to_owned(), url.to_owned()))
+}
+
+/// Branches in the line of development through `tip_id`, from the base up
+///
+/// Above `tip_id`, the line ends where the stack forks since which way it goes is ambiguous.
+fn stack_branch_names(graph: &git_stack::graph::Graph, tip_id: git2::Oid) -> Vec<String> {
+    let branch_name = |id: git2::Oid| {
+        let action = graph
+            .commit_get::<git_stack::graph::Action>(id)
+            .copied()
+            .unwrap_or_default();
+        if action.is_protected() {
+            return None;
+        }
+        graph
+            .branches
+            .get(id)?
+            .iter()
+            .find_map(|b| b.local_name().map(|n| n.to_owned()))
+    };
+
+    let mut names = graph
+        .path_to(tip_id)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(branch_name)
+        .collect::<Vec<_>>();
+    let mut current_id = tip_id;
+    loop {
+        let mut children = graph.primary_children_of(current_id);
+        let (Some(child_id), None) = (children.next(), children.next()) else {
+            break;
+        };
+        names.extend(branch_name(child_id));
+        current_id = child_id;
+    }
+    names
+}
+
+/// Fill in the placeholders of `template` for the PR at `position` in `stack`
+///
+/// `stack` is each branch with the URL of its PR, if known.  Unknown placeholders are left as-is.
+fn render_body(
+    template: &str,
+    stack: &[(String, Option<String>)],
+    position: usize,
+    commits: &[String],
+) -> String {
+    let link = |label: &str, neighbour: Option<&(String, Option<String>)>| match neighbour {
+        Some((_, Some(url))) => format!("[{label}]({url})"),
+        Some((branch, None)) => format!("{label} (`{branch}`)"),
+        None => label.to_owned(),
+    };
+
+    let mut body = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        body.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        match &rest[1..end] {
+            "branch" => body.push_str(&stack[position].0),
+            "position" => body.push_str(&(position + 1).to_string()),
+            "total" => body.push_str(&stack.len().to_string()),
+            "prev" => body.push_str(&link(
+                "← prev",
+                position.checked_sub(1).and_then(|i| stack.get(i)),
+            )),
+            "next" => body.push_str(&link("next →", stack.get(position + 1))),
+            "commits" => {
+                for commit in commits {
+                    body.push_str("- ");
+                    body.push_str(commit);
+                    body.push('\n');
+                }
+            }
+            _ => body.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    body.push_str(rest);
+    body
+}
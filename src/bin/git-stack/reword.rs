@@ -1,6 +1,5 @@
 use std::io::Write;
 
-use itertools::Itertools;
 use proc_exit::prelude::*;
 
 use git_stack::git::Repo;
@@ -19,9 +18,44 @@ pub struct RewordArgs {
     #[arg(short, long)]
     message: Option<String>,
 
+    /// Read the commit message from `PATH`, or standard input with `-`
+    #[arg(short = 'F', long, value_name = "PATH", conflicts_with = "message")]
+    message_file: Option<std::path::PathBuf>,
+
+    /// Open the editor, pre-filled with `--message` or `--message-file` if given
+    #[arg(short, long)]
+    edit: bool,
+
+    /// Reword many commits at once from `<sha> <new summary>` lines
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["message", "message_file", "edit"])]
+    from_file: Option<std::path::PathBuf>,
+
+    /// Reword every commit in a contiguous range, editing their summaries together
+    #[arg(
+        long,
+        value_name = "REV..REV",
+        conflicts_with_all = ["rev", "message", "message_file", "edit"]
+    )]
+    select: Option<String>,
+
+    /// Don't ask for confirmation before rewriting more than `stack.max-stack-size` commits
+    #[arg(short, long)]
+    yes: bool,
+
+    /// Only rewrite commits whose author name or email contains `PATTERN`
+    ///
+    /// Other commits are protected, along with everything they are built on, and what's built on
+    /// them stays on them.
+    #[arg(long, value_name = "PATTERN")]
+    author: Option<String>,
+
     /// Don't actually switch
     #[arg(short = 'n', long)]
     dry_run: bool,
+
+    /// How `--dry-run` reports what it would do
+    #[arg(long, value_enum, default_value_t, requires = "dry_run")]
+    format: crate::ops::PlanFormat,
 }
 
 impl RewordArgs {
@@ -35,35 +69,19 @@ impl RewordArgs {
         }
     }
 
-    pub fn exec(&self) -> proc_exit::ExitResult {
+    pub fn exec(&self, global: &git_stack::config::RepoConfig) -> proc_exit::ExitResult {
         let stderr_palette = crate::ops::Palette::colored();
 
         let cwd = std::env::current_dir().with_code(proc_exit::sysexits::USAGE_ERR)?;
-        let repo = git2::Repository::discover(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
-        let mut repo = git_stack::git::GitRepo::new(repo);
-
-        let repo_config = git_stack::config::RepoConfig::from_all(repo.raw())
-            .with_code(proc_exit::sysexits::CONFIG_ERR)?;
-        repo.set_push_remote(repo_config.push_remote());
-        repo.set_pull_remote(repo_config.pull_remote());
-        let config = repo
-            .raw()
-            .config()
-            .with_code(proc_exit::sysexits::CONFIG_ERR)?;
-        repo.set_sign(
-            config
-                .get_bool("stack.gpgSign")
-                .or_else(|_| config.get_bool("commit.gpgSign"))
-                .unwrap_or_default(),
-        )
-        .with_code(proc_exit::Code::FAILURE)?;
-
-        let protected = git_stack::git::ProtectedBranches::new(
-            repo_config.protected_branches().iter().map(|s| s.as_str()),
-        )
-        .with_code(proc_exit::sysexits::CONFIG_ERR)?;
-        let branches = git_stack::graph::BranchSet::from_repo(&repo, &protected)
-            .with_code(proc_exit::Code::FAILURE)?;
+        let (mut repo, repo_config) = crate::ops::open_configured_repo(global)?;
+        if self.from_file.is_none()
+            && ((self.message.is_none() && self.message_file.is_none()) || self.edit)
+        {
+            crate::ops::ensure_editor(&repo_config).with_code(proc_exit::sysexits::USAGE_ERR)?;
+        }
+        crate::ops::configure_signing(&mut repo)?;
+
+        let branches = crate::ops::load_branches(&repo, &repo_config)?;
 
         let head_ann_id = crate::ops::resolve_explicit_base(&repo, &self.rev)
             .with_code(proc_exit::Code::FAILURE)?;
@@ -76,15 +94,7 @@ impl RewordArgs {
             &branches,
             repo_config.auto_base_commit_count(),
         );
-        let merge_base_oid = repo
-            .merge_base(base.id, head_id)
-            .ok_or_else(|| {
-                git2::Error::new(
-                    git2::ErrorCode::NotFound,
-                    git2::ErrorClass::Reference,
-                    format!("could not find base between {base} and HEAD"),
-                )
-            })
+        let merge_base_oid = crate::ops::resolve_merge_base(&repo, &base, head_id, "HEAD")
             .with_code(proc_exit::sysexits::USAGE_ERR)?;
         let stack_branches = branches.descendants(&repo, merge_base_oid);
         let mut graph = git_stack::graph::Graph::from_branches(&repo, stack_branches)
@@ -92,82 +102,169 @@ impl RewordArgs {
         git_stack::graph::protect_branches(&mut graph);
         git_stack::graph::mark_fixup(&mut graph, &repo);
         git_stack::graph::mark_wip(&mut graph, &repo);
+        crate::ops::protect_other_authors(&mut graph, &repo, self.author.as_deref());
 
-        if repo.raw().state() != git2::RepositoryState::Clean {
-            let message = format!("cannot walk commits, {:?} in progress", repo.raw().state());
-            if self.dry_run {
-                let _ = writeln!(
-                    anstream::stderr(),
-                    "{}: {}",
-                    stderr_palette.error("error"),
-                    message
-                );
-            } else {
-                return Err(proc_exit::sysexits::USAGE_ERR.with_message(message));
-            }
-        }
-        let action = graph
-            .commit_get::<git_stack::graph::Action>(head_id)
-            .copied()
-            .unwrap_or_default();
-        match action {
-            git_stack::graph::Action::Pick => {}
-            git_stack::graph::Action::Fixup => {
-                return Err(proc_exit::Code::FAILURE.with_message("cannot reword fixup commits"));
-            }
-            git_stack::graph::Action::Protected => {
-                return Err(
-                    proc_exit::Code::FAILURE.with_message("cannot reword protected commits")
-                );
-            }
+        if !self.dry_run {
+            crate::ops::confirm_stack_size(
+                git_stack::graph::pickable_count(&graph),
+                repo_config.max_stack_size(),
+                self.yes,
+            )
+            .with_code(proc_exit::sysexits::USAGE_ERR)?;
         }
 
-        let new_message = if let Some(message) = self.message.as_deref() {
-            message.trim().to_owned()
-        } else {
+        crate::ops::ensure_clean_state(repo.raw(), "walk commits", self.dry_run)?;
+        let selection = self
+            .select
+            .as_deref()
+            .map(|range| crate::ops::resolve_selection(&repo, &graph, range))
+            .transpose()
+            .with_code(proc_exit::sysexits::USAGE_ERR)?;
+        let rewords = if let Some(path) = self.from_file.as_deref() {
+            let content = std::fs::read_to_string(path)
+                .map_err(|e| eyre::format_err!("could not read {}: {}", path.display(), e))
+                .with_code(proc_exit::sysexits::USAGE_ERR)?;
+            let mut rewords = parse_rewords(&repo, &graph, &path.display(), &content)
+                .with_code(proc_exit::sysexits::USAGE_ERR)?;
+            if let Some(selection) = &selection {
+                rewords.retain(|(id, _)| {
+                    let selected = selection.contains(id);
+                    if !selected {
+                        let _ = writeln!(
+                            anstream::stderr(),
+                            "{}: skipping {}, it is outside of `--select`",
+                            stderr_palette.warn("warning"),
+                            stderr_palette.highlight(repo.abbrev_id(*id)),
+                        );
+                    }
+                    selected
+                });
+            }
+            rewords
+        } else if let Some(selection) = &selection {
             use std::fmt::Write;
 
-            let raw_commit = repo
-                .raw()
-                .find_commit(head.id)
-                .expect("head_commit is always valid");
-            let existing = String::from_utf8_lossy(raw_commit.message_bytes());
             let mut template = String::new();
-            writeln!(&mut template, "{existing}").unwrap();
+            for id in selection {
+                let action = graph
+                    .commit_get::<git_stack::graph::Action>(*id)
+                    .copied()
+                    .unwrap_or_default();
+                if action != git_stack::graph::Action::Pick {
+                    continue;
+                }
+                let commit = repo.find_commit(*id).expect("graph commits exist");
+                writeln!(&mut template, "{} {}", repo.abbrev_id(*id), commit.summary).unwrap();
+            }
             writeln!(&mut template).unwrap();
             writeln!(
                 &mut template,
-                "# Please enter the commit message for your changes. Lines starting"
+                "# Edit the summaries above; the rest of each message is kept."
             )
             .unwrap();
-            writeln!(
-                &mut template,
-                "# with '#' will be ignored, and an empty message aborts the commit."
-            )
-            .unwrap();
-            if let Some(head_branch) = &head_branch {
-                writeln!(&mut template, "#").unwrap();
-                writeln!(&mut template, "# On branch {head_branch}").unwrap();
-            }
-            let message = crate::ops::edit_commit(
-                repo.path()
-                    .ok_or_else(|| eyre::format_err!("no `.git` path found"))
-                    .with_code(proc_exit::Code::FAILURE)?,
+            writeln!(&mut template, "# An empty list aborts the reword.").unwrap();
+            let edit_path = repo
+                .path()
+                .ok_or_else(|| eyre::format_err!("no `.git` path found"))
+                .with_code(proc_exit::Code::FAILURE)?;
+            let content = crate::ops::edit_commit(
+                edit_path,
                 repo_config.editor(),
+                git_stack::config::CleanupMode::Strip,
                 &template,
             )
             .with_code(proc_exit::Code::FAILURE)?;
-            let message = match message {
-                Some(message) => message,
+            let content = match content {
+                Some(content) => content,
                 None => {
                     return Err(proc_exit::Code::SUCCESS.with_message("Nothing to do."));
                 }
             };
-            message
-        };
+            let mut rewords = parse_rewords(
+                &repo,
+                &graph,
+                &edit_path.join("COMMIT_EDITMSG").display(),
+                &content,
+            )
+            .with_code(proc_exit::sysexits::USAGE_ERR)?;
+            rewords.retain(|(id, new_message)| {
+                let commit = repo.find_commit(*id).expect("graph commits exist");
+                commit.message.trim() != new_message.trim()
+            });
+            if rewords.is_empty() {
+                return Err(proc_exit::Code::SUCCESS.with_message("Nothing to do."));
+            }
+            rewords
+        } else {
+            let action = graph
+                .commit_get::<git_stack::graph::Action>(head_id)
+                .copied()
+                .unwrap_or_default();
+            match action {
+                git_stack::graph::Action::Pick => {}
+                git_stack::graph::Action::Fixup => {
+                    return Err(
+                        proc_exit::sysexits::USAGE_ERR.with_message("cannot reword fixup commits")
+                    );
+                }
+                git_stack::graph::Action::Protected => {
+                    return Err(proc_exit::sysexits::USAGE_ERR
+                        .with_message("cannot reword protected commits"));
+                }
+            }
 
-        git_stack::graph::reword_commit(&mut graph, &repo, head_id, new_message)
-            .with_code(proc_exit::Code::FAILURE)?;
+            let message = match self.message_file.as_deref() {
+                Some(path) => Some(
+                    crate::ops::read_message_file(path, repo_config.cleanup())
+                        .with_code(proc_exit::Code::FAILURE)?,
+                ),
+                None => self.message.clone(),
+            };
+            let new_message = if let Some(message) = message.as_deref().filter(|_| !self.edit) {
+                message.trim().to_owned()
+            } else {
+                use std::fmt::Write;
+
+                let existing =
+                    std::borrow::Cow::Borrowed(message.as_deref().unwrap_or(&head.message));
+                let existing = if existing.trim().is_empty() {
+                    crate::ops::commit_template(repo.raw())
+                        .map(std::borrow::Cow::Owned)
+                        .unwrap_or(existing)
+                } else {
+                    existing
+                };
+                let mut template = String::new();
+                writeln!(&mut template, "{existing}").unwrap();
+                writeln!(&mut template).unwrap();
+                crate::ops::write_cleanup_hint(&mut template, repo_config.cleanup());
+                if let Some(head_branch) = &head_branch {
+                    writeln!(&mut template, "#").unwrap();
+                    writeln!(&mut template, "# On branch {head_branch}").unwrap();
+                }
+                let message = crate::ops::edit_commit(
+                    repo.path()
+                        .ok_or_else(|| eyre::format_err!("no `.git` path found"))
+                        .with_code(proc_exit::Code::FAILURE)?,
+                    repo_config.editor(),
+                    repo_config.cleanup(),
+                    &template,
+                )
+                .with_code(proc_exit::Code::FAILURE)?;
+                let message = match message {
+                    Some(message) => message,
+                    None => {
+                        return Err(proc_exit::Code::SUCCESS.with_message("Nothing to do."));
+                    }
+                };
+                message
+            };
+            vec![(head_id, new_message)]
+        };
+        for (id, new_message) in rewords {
+            git_stack::graph::reword_commit(&mut graph, &repo, id, new_message)
+                .with_code(proc_exit::Code::FAILURE)?;
+        }
 
         let mut stash_id = None;
         if !self.dry_run {
@@ -177,7 +274,7 @@ impl RewordArgs {
         let mut backed_up = false;
         {
             let stash_repo =
-                git2::Repository::discover(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
+                crate::ops::open_repo(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
             let stash_repo = git_branch_stash::GitRepo::new(stash_repo);
             let mut snapshots =
                 git_branch_stash::Stack::new(crate::ops::STASH_STACK_NAME, &stash_repo);
@@ -191,22 +288,31 @@ impl RewordArgs {
             }
         }
 
-        let mut success = true;
+        let mut summary = crate::ops::RestackSummary::default();
         let scripts = git_stack::graph::to_scripts(&graph, vec![]);
+        if let Err(err) = crate::ops::ensure_no_merges(
+            repo.raw(),
+            scripts.iter().flat_map(|s| s.stats().rewritten),
+            repo_config.rebase_merges(),
+        ) {
+            git_stack::git::stash_pop(&mut repo, stash_id);
+            return Err(proc_exit::sysexits::USAGE_ERR.with_message(err));
+        }
+        if self.format == crate::ops::PlanFormat::Json {
+            crate::ops::print_plan_json(&mut repo, &scripts, &[])
+                .with_code(proc_exit::Code::FAILURE)?;
+            return Ok(());
+        }
         let mut executor = git_stack::rewrite::Executor::new(self.dry_run);
         for script in scripts {
             let results = executor.run(&mut repo, &script);
-            for (err, name, dependents) in results.iter() {
-                success = false;
-                log::error!("Failed to re-stack branch `{}`: {}", name, err);
-                if !dependents.is_empty() {
-                    log::error!("  Blocked dependents: {}", dependents.iter().join(", "));
-                }
-            }
+            let branches = script.stats().branches;
+            summary.record(branches.iter().map(String::as_str), &results);
         }
         executor
             .close(&mut repo, head_branch.as_ref().and_then(|b| b.local_name()))
             .with_code(proc_exit::Code::FAILURE)?;
+        let success = summary.is_success();
 
         git_stack::git::stash_pop(&mut repo, stash_id);
         if backed_up {
@@ -220,10 +326,80 @@ impl RewordArgs {
             );
         }
 
+        summary.report();
         if success {
             Ok(())
         } else {
-            Err(proc_exit::Code::FAILURE.as_exit())
+            Err(crate::ops::CONFLICT_ERR.as_exit())
         }
     }
 }
+
+/// Parse `<sha> <new summary>` lines, checking every commit can be reworded before any are
+///
+/// The summary replaces the first line of the message, keeping the rest of it.  Blank lines and
+/// lines starting with `#` are ignored.  Errors point at lines of `path`.
+fn parse_rewords(
+    repo: &git_stack::git::GitRepo,
+    graph: &git_stack::graph::Graph,
+    path: &dyn std::fmt::Display,
+    content: &str,
+) -> Result<Vec<(git2::Oid, String)>, eyre::Error> {
+    let mut rewords = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let lineno = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (rev, summary) = line
+            .split_once(char::is_whitespace)
+            .map(|(rev, summary)| (rev, summary.trim()))
+            .filter(|(_, summary)| !summary.is_empty())
+            .ok_or_else(|| {
+                eyre::format_err!("{}:{}: expected `<sha> <new summary>`", path, lineno)
+            })?;
+        let id = repo
+            .raw()
+            .revparse_single(rev)
+            .and_then(|obj| obj.peel_to_commit())
+            .map(|commit| commit.id())
+            .map_err(|_| eyre::format_err!("{}:{}: unknown commit `{}`", path, lineno, rev))?;
+        if !graph.contains_id(id) {
+            eyre::bail!(
+                "{}:{}: commit `{}` is not in the current stack",
+                path,
+                lineno,
+                rev
+            );
+        }
+        let action = graph
+            .commit_get::<git_stack::graph::Action>(id)
+            .copied()
+            .unwrap_or_default();
+        match action {
+            git_stack::graph::Action::Pick => {}
+            git_stack::graph::Action::Fixup => {
+                eyre::bail!("{}:{}: cannot reword fixup commit `{}`", path, lineno, rev);
+            }
+            git_stack::graph::Action::Protected => {
+                eyre::bail!(
+                    "{}:{}: cannot reword protected commit `{}`",
+                    path,
+                    lineno,
+                    rev
+                );
+            }
+        }
+
+        let commit = repo.find_commit(id).expect("graph commits exist");
+        let message = &commit.message;
+        let new_message = match message.split_once('\n') {
+            Some((_, body)) if !body.trim().is_empty() => format!("{summary}\n{body}"),
+            _ => summary.to_owned(),
+        };
+        rewords.push((id, new_message));
+    }
+    Ok(rewords)
+}
@@ -0,0 +1,251 @@
+use std::io::Write;
+
+use proc_exit::prelude::*;
+
+use git_stack::git::Repo;
+
+/// Collapse a branch's commits into one
+///
+/// Only the commits exclusive to the branch are squashed; the walk stops at protected commits and
+/// at commits shared with other branches.  Any descendants are rebased on top of the squashed
+/// commit.
+#[derive(clap::Args)]
+pub struct SquashBranchArgs {
+    /// Branch to squash (default: current branch)
+    #[arg(short, long)]
+    branch: Option<String>,
+
+    /// Commit message
+    #[arg(short, long)]
+    message: Option<String>,
+
+    /// Merge the `Fixes`, `Closes` and `Refs` lines of the commits into one line per kind
+    #[arg(long, conflicts_with = "message")]
+    merge_issue_refs: bool,
+
+    /// Don't ask for confirmation before rewriting more than `stack.max-stack-size` commits
+    #[arg(short, long)]
+    yes: bool,
+
+    /// Only rewrite commits whose author name or email contains `PATTERN`
+    ///
+    /// Other commits are protected, along with everything they are built on, and what's built on
+    /// them stays on them.
+    #[arg(long, value_name = "PATTERN")]
+    author: Option<String>,
+
+    /// Don't actually switch
+    #[arg(short = 'n', long)]
+    dry_run: bool,
+
+    /// How `--dry-run` reports what it would do
+    #[arg(long, value_enum, default_value_t, requires = "dry_run")]
+    format: crate::ops::PlanFormat,
+}
+
+impl SquashBranchArgs {
+    pub fn exec(&self, global: &git_stack::config::RepoConfig) -> proc_exit::ExitResult {
+        let stderr_palette = crate::ops::Palette::colored();
+
+        let cwd = std::env::current_dir().with_code(proc_exit::sysexits::USAGE_ERR)?;
+        let (mut repo, repo_config) = crate::ops::open_configured_repo(global)?;
+        if self.message.is_none() {
+            crate::ops::ensure_editor(&repo_config).with_code(proc_exit::sysexits::USAGE_ERR)?;
+        }
+        crate::ops::configure_signing(&mut repo)?;
+
+        let branches = crate::ops::load_branches(&repo, &repo_config)?;
+
+        let head_branch = repo.head_branch();
+        let branch_name = match self.branch.as_deref() {
+            Some(branch_name) => branch_name.to_owned(),
+            None => head_branch
+                .as_ref()
+                .and_then(|b| b.local_name())
+                .map(|n| n.to_owned())
+                .ok_or_else(|| {
+                    proc_exit::sysexits::USAGE_ERR
+                        .with_message("HEAD is detached; specify a branch with `--branch`")
+                })?,
+        };
+        let tip_id = repo
+            .find_local_branch(&branch_name)
+            .ok_or_else(|| {
+                proc_exit::sysexits::USAGE_ERR
+                    .with_message(format!("could not find branch `{branch_name}`"))
+            })?
+            .id;
+        let base = crate::ops::resolve_implicit_base(
+            &repo,
+            tip_id,
+            &branches,
+            repo_config.auto_base_commit_count(),
+        );
+        let merge_base_oid = crate::ops::resolve_merge_base(&repo, &base, tip_id, &branch_name)
+            .with_code(proc_exit::sysexits::USAGE_ERR)?;
+        let stack_branches = branches.descendants(&repo, merge_base_oid);
+        let mut graph = git_stack::graph::Graph::from_branches(&repo, stack_branches)
+            .with_code(proc_exit::Code::FAILURE)?;
+        git_stack::graph::protect_branches(&mut graph);
+        git_stack::graph::mark_fixup(&mut graph, &repo);
+        git_stack::graph::mark_wip(&mut graph, &repo);
+        crate::ops::protect_other_authors(&mut graph, &repo, self.author.as_deref());
+
+        if !self.dry_run {
+            crate::ops::confirm_stack_size(
+                git_stack::graph::pickable_count(&graph),
+                repo_config.max_stack_size(),
+                self.yes,
+            )
+            .with_code(proc_exit::sysexits::USAGE_ERR)?;
+        }
+
+        crate::ops::ensure_clean_state(repo.raw(), "walk commits", self.dry_run)?;
+        let action = graph
+            .commit_get::<git_stack::graph::Action>(tip_id)
+            .copied()
+            .unwrap_or_default();
+        if action.is_protected() {
+            return Err(
+                proc_exit::sysexits::USAGE_ERR.with_message("cannot squash protected commits")
+            );
+        }
+
+        let commit_ids = git_stack::graph::branch_commits(&graph, tip_id);
+        if commit_ids.len() < 2 {
+            return Err(proc_exit::Code::SUCCESS.with_message("Nothing to do."));
+        }
+
+        let new_message = if let Some(message) = self.message.as_deref() {
+            message.trim().to_owned()
+        } else {
+            use std::fmt::Write;
+
+            let mut template = String::new();
+            writeln!(
+                &mut template,
+                "# This is a combination of {} commits.",
+                commit_ids.len()
+            )
+            .unwrap();
+            let messages = commit_ids
+                .iter()
+                .map(|id| {
+                    let commit = repo.find_commit(*id).expect("graph commits exist");
+                    commit.message.trim_end().to_owned()
+                })
+                .collect::<Vec<_>>();
+            let (messages, issue_refs) = if self.merge_issue_refs {
+                crate::ops::merge_issue_refs(
+                    &messages.iter().map(String::as_str).collect::<Vec<_>>(),
+                )
+            } else {
+                (messages, Vec::new())
+            };
+            for (i, message) in messages.iter().enumerate() {
+                writeln!(&mut template, "# This is commit message #{}:", i + 1).unwrap();
+                writeln!(&mut template).unwrap();
+                writeln!(&mut template, "{message}").unwrap();
+                writeln!(&mut template).unwrap();
+            }
+            for issue_ref in &issue_refs {
+                writeln!(&mut template, "{issue_ref}").unwrap();
+            }
+            crate::ops::write_cleanup_hint(&mut template, repo_config.cleanup());
+            writeln!(&mut template, "#").unwrap();
+            writeln!(&mut template, "# On branch {branch_name}").unwrap();
+            let message = crate::ops::edit_commit(
+                repo.path()
+                    .ok_or_else(|| eyre::format_err!("no `.git` path found"))
+                    .with_code(proc_exit::Code::FAILURE)?,
+                repo_config.editor(),
+                repo_config.cleanup(),
+                &template,
+            )
+            .with_code(proc_exit::Code::FAILURE)?;
+            // Unlike a reword, leaving the combined message untouched is still a request to squash
+            message
+                .unwrap_or_else(|| crate::ops::sanitize_message(&template, repo_config.cleanup()))
+        };
+
+        git_stack::graph::squash_branch(&mut graph, tip_id, new_message)
+            .with_code(proc_exit::sysexits::USAGE_ERR)?;
+
+        let mut stash_id = None;
+        if !self.dry_run {
+            stash_id = git_stack::git::stash_push(&mut repo, "squash");
+        }
+
+        let mut backed_up = false;
+        {
+            let stash_repo =
+                crate::ops::open_repo(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
+            let stash_repo = git_branch_stash::GitRepo::new(stash_repo);
+            let mut snapshots =
+                git_branch_stash::Stack::new(crate::ops::STASH_STACK_NAME, &stash_repo);
+            let snapshot_capacity = repo_config.capacity();
+            snapshots.capacity(snapshot_capacity);
+            let snapshot = git_branch_stash::Snapshot::from_repo(&stash_repo)
+                .with_code(proc_exit::Code::FAILURE)?;
+            if !self.dry_run {
+                snapshots.push(snapshot).to_sysexits()?;
+                backed_up = true;
+            }
+        }
+
+        let mut summary = crate::ops::RestackSummary::default();
+        let scripts = git_stack::graph::to_scripts(&graph, vec![]);
+        if let Err(err) = crate::ops::ensure_no_merges(
+            repo.raw(),
+            scripts.iter().flat_map(|s| s.stats().rewritten),
+            repo_config.rebase_merges(),
+        ) {
+            git_stack::git::stash_pop(&mut repo, stash_id);
+            return Err(proc_exit::sysexits::USAGE_ERR.with_message(err));
+        }
+        if self.format == crate::ops::PlanFormat::Json {
+            crate::ops::print_plan_json(&mut repo, &scripts, &[])
+                .with_code(proc_exit::Code::FAILURE)?;
+            return Ok(());
+        }
+        let mut executor = git_stack::rewrite::Executor::new(self.dry_run);
+        for script in scripts {
+            let results = executor.run(&mut repo, &script);
+            let branches = script.stats().branches;
+            summary.record(branches.iter().map(String::as_str), &results);
+        }
+        executor
+            .close(&mut repo, head_branch.as_ref().and_then(|b| b.local_name()))
+            .with_code(proc_exit::Code::FAILURE)?;
+        let success = summary.is_success();
+
+        if success {
+            let _ = writeln!(
+                anstream::stderr(),
+                "{} {} commits on {}",
+                stderr_palette.good("Squashed"),
+                commit_ids.len(),
+                stderr_palette.highlight(&branch_name),
+            );
+        }
+
+        git_stack::git::stash_pop(&mut repo, stash_id);
+        if backed_up {
+            anstream::eprintln!(
+                "{}: to undo, run {}",
+                stderr_palette.info("note"),
+                stderr_palette.highlight(format_args!(
+                    "`git branch-stash pop {}`",
+                    crate::ops::STASH_STACK_NAME
+                ))
+            );
+        }
+
+        summary.report();
+        if success {
+            Ok(())
+        } else {
+            Err(crate::ops::CONFLICT_ERR.as_exit())
+        }
+    }
+}
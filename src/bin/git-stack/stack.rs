@@ -3,6 +3,7 @@ use std::io::Write;
 
 use bstr::ByteSlice;
 use eyre::WrapErr;
+use is_terminal::IsTerminal;
 use itertools::Itertools;
 use proc_exit::prelude::*;
 
@@ -17,18 +18,44 @@ struct State {
 
     rebase: bool,
     pull: bool,
+    /// Leave the local base branches behind rather than moving them with their remote
+    onto_remote: bool,
     push: bool,
     fixup: git_stack::config::Fixup,
     repair: bool,
     dry_run: bool,
+    switch: Option<String>,
     snapshot_capacity: Option<usize>,
     protect_commit_count: Option<usize>,
     protect_commit_age: std::time::Duration,
     protect_commit_time: std::time::SystemTime,
+    max_stack_size: Option<usize>,
+    yes: bool,
+    rebase_merges: bool,
+    set_upstream_on_create: bool,
+    /// Commits opted in to being rewritten, when only those are by default
+    picked: Option<std::collections::HashSet<git2::Oid>>,
+    /// Only rewrite commits whose author name or email contains this
+    author: Option<String>,
+    /// Branches whose stacks are left out of the view
+    hidden: Vec<String>,
 
     show_format: git_stack::config::Format,
     show_commits: git_stack::config::ShowCommits,
     show_stacked: bool,
+    show_protected: bool,
+    since_pushed: bool,
+    /// Files to list under each commit, if any
+    files: Option<usize>,
+    show_signature: ShowSignature,
+    /// Commits to show below the base of each stack, if limited
+    depth: Option<usize>,
+    dump_graph: bool,
+    /// Lay the stacks out like `git log --graph` instead
+    graph_log: Option<crate::graph_log::LogStyle>,
+    timings: crate::timings::Timings,
+    log_order: git_stack::config::LogOrder,
+    width: Option<usize>,
 }
 
 impl State {
@@ -46,6 +73,10 @@ impl State {
             log::trace!("`--pull` implies `--rebase`");
             rebase = true;
         }
+        if args.onto_remote {
+            log::trace!("`--onto-remote` implies `--rebase`");
+            rebase = true;
+        }
         let rebase = rebase;
 
         let fixup = match (args.fixup, args.rebase) {
@@ -87,27 +118,34 @@ impl State {
         let protect_commit_count = repo_config.protect_commit_count();
         let protect_commit_age = repo_config.protect_commit_age();
         let protect_commit_time = std::time::SystemTime::now() - protect_commit_age;
+        let picked = (repo_config.default_action() == git_stack::config::DefaultAction::Protect)
+            .then(|| {
+                repo_config
+                    .picked()
+                    .iter()
+                    // Picked branches that were since deleted no longer matter
+                    .filter_map(|rev| repo.resolve(rev).map(|c| c.id))
+                    .collect()
+            });
         let show_format = repo_config.show_format();
         let show_commits = repo_config.show_commits();
         let show_stacked = repo_config.show_stacked();
 
         repo.set_push_remote(repo_config.push_remote());
         repo.set_pull_remote(repo_config.pull_remote());
-        let config = repo
-            .raw()
-            .config()
-            .with_code(proc_exit::sysexits::CONFIG_ERR)?;
-        repo.set_sign(
-            config
-                .get_bool("stack.gpgSign")
-                .or_else(|_| config.get_bool("commit.gpgSign"))
-                .unwrap_or_default(),
-        )
-        .with_code(proc_exit::Code::FAILURE)?;
-
+        repo.set_abbrev(repo_config.abbrev());
+        repo.set_first_parent(repo_config.first_parent());
+        repo.set_rebase_strategy(repo_config.rebase_strategy());
+        repo.set_patch_id_skip_binary(repo_config.patch_id_skip_binary());
+        let sign = repo.config_bool("stack.gpgSign", repo.config_bool("commit.gpgSign", false));
+        repo.set_sign(sign).with_code(proc_exit::Code::FAILURE)?;
+
+        let timings = crate::timings::Timings::new(args.timings);
+        let start = std::time::Instant::now();
         let mut branches = git_stack::legacy::git::Branches::new([]);
         let mut protected_branches = git_stack::legacy::git::Branches::new([]);
-        for branch in repo.local_branches() {
+        for mut branch in repo.local_branches() {
+            repo.resolve_push_id(&mut branch);
             if protected.is_protected(&branch.name) {
                 log::trace!("Branch {} is protected", branch);
                 if let Some(remote) = repo.find_remote_branch(repo.pull_remote(), &branch.name) {
@@ -119,6 +157,16 @@ impl State {
             }
             branches.insert(branch);
         }
+        for branch in repo.remote_branches() {
+            let Some(remote) = branch.remote.as_deref() else {
+                continue;
+            };
+            if branch.name != "HEAD" && protected.is_protected_remote(remote, &branch.name) {
+                log::trace!("Branch {} is protected", branch);
+                protected_branches.insert(branch.clone());
+                branches.insert(branch);
+            }
+        }
         let head_commit = repo.head_commit();
         let base = args
             .base
@@ -133,7 +181,7 @@ impl State {
             .transpose()
             .with_code(proc_exit::sysexits::USAGE_ERR)?;
 
-        let stacks = match (base, onto, repo_config.stack()) {
+        let mut stacks = match (base, onto, repo_config.stack()) {
             (Some(base), Some(onto), git_stack::config::Stack::All) => {
                 vec![StackState::new(base, onto, branches.all())]
             }
@@ -203,10 +251,11 @@ impl State {
                 let merge_base_oid = repo
                     .merge_base(base.id, head_commit.id)
                     .ok_or_else(|| {
-                        git2::Error::new(
-                            git2::ErrorCode::NotFound,
-                            git2::ErrorClass::Reference,
-                            format!("could not find base between {base} and HEAD"),
+                        crate::ops::no_merge_base(
+                            &base.display(&repo),
+                            "HEAD",
+                            repo.find_commit(base.id).is_some(),
+                            repo.is_shallow(),
                         )
                     })
                     .with_code(proc_exit::sysexits::USAGE_ERR)?;
@@ -228,6 +277,13 @@ impl State {
             }
         };
 
+        if args.onto_remote {
+            for stack in &mut stacks {
+                stack.onto = resolve_remote_onto(&repo, &stack.base)
+                    .with_code(proc_exit::sysexits::USAGE_ERR)?;
+            }
+        }
+
         for stack in &stacks {
             if let Some(branch) = stack.base.branch.clone() {
                 protected_branches.insert(branch);
@@ -236,6 +292,7 @@ impl State {
                 protected_branches.insert(branch);
             }
         }
+        timings.record("branches", start.elapsed());
 
         Ok(Self {
             repo,
@@ -246,21 +303,66 @@ impl State {
 
             rebase,
             pull,
+            onto_remote: args.onto_remote,
             push,
             fixup,
             repair,
             dry_run,
+            switch: args.switch.clone(),
             snapshot_capacity,
             protect_commit_count,
             protect_commit_age,
             protect_commit_time,
+            max_stack_size: repo_config.max_stack_size(),
+            yes: args.yes,
+            rebase_merges: repo_config.rebase_merges(),
+            set_upstream_on_create: repo_config.set_upstream_on_create(),
+            picked,
+            author: args.author.clone(),
+            hidden: if args.show_all {
+                Vec::new()
+            } else {
+                repo_config.hidden().to_vec()
+            },
 
             show_format,
             show_commits,
             show_stacked,
+            show_protected: args.show_protected,
+            since_pushed: args.since_pushed,
+            files: match (args.stat_by_file, args.all_files) {
+                (false, _) => None,
+                (true, false) => Some(DEFAULT_FILE_LIMIT),
+                (true, true) => Some(usize::MAX),
+            },
+            show_signature: ShowSignature::new(args.show_signature, args.verify),
+            depth: args.depth.filter(|depth| *depth != usize::MAX),
+            dump_graph: args.dump_graph,
+            graph_log: None,
+            timings,
+            log_order: repo_config.log_order(),
+            // Only fit the terminal when there is one; piped output stays complete
+            width: if args.no_truncate {
+                None
+            } else {
+                args.width
+                    .or_else(|| terminal_size::terminal_size().map(|(w, _)| w.0 as usize))
+            },
         })
     }
 
+    /// The base HEAD is sitting on, when no stack has commits of its own
+    fn at_base(&self) -> Option<&AnnotatedOid> {
+        let head_id = self.head_commit.id;
+        let at_base = !self.stacks.is_empty()
+            && self.stacks.iter().all(|stack| {
+                stack.base.id == head_id
+                    && stack.onto.id == head_id
+                    && stack.branches.iter().all(|(id, _)| id == head_id)
+            });
+        at_base.then(|| &self.stacks[0].base)
+    }
+
     fn update(&mut self) -> eyre::Result<()> {
         self.head_commit = self.repo.head_commit();
         self.branches.update(&self.repo);
@@ -308,10 +410,32 @@ impl StackState {
     }
 }
 
+/// Show the stacks like `git log --graph`, with `log_args` in place of the view's own flags
+pub fn log(
+    args: &crate::args::Args,
+    log_args: &crate::graph_log::LogArgs,
+) -> proc_exit::ExitResult {
+    let cwd = std::env::current_dir().with_code(proc_exit::sysexits::USAGE_ERR)?;
+    let repo = crate::ops::open_repo(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
+    let repo = git_stack::legacy::git::GitRepo::new(repo);
+    let mut state = State::new(repo, args)?;
+    state.graph_log = Some(log_args.style());
+    state.show_signature = ShowSignature::new(log_args.show_signature, log_args.verify);
+    state.depth = log_args.depth.filter(|depth| *depth != usize::MAX);
+    if log_args.show_all {
+        state.hidden.clear();
+    }
+
+    show(&state).with_code(proc_exit::Code::FAILURE)?;
+    state.timings.report();
+
+    Ok(())
+}
+
 pub fn stack(args: &crate::args::Args) -> proc_exit::ExitResult {
     log::trace!("Initializing");
     let cwd = std::env::current_dir().with_code(proc_exit::sysexits::USAGE_ERR)?;
-    let repo = git2::Repository::discover(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
+    let repo = crate::ops::open_repo(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
     let repo = git_stack::legacy::git::GitRepo::new(repo);
     let mut state = State::new(repo, args)?;
 
@@ -323,7 +447,7 @@ pub fn stack(args: &crate::args::Args) -> proc_exit::ExitResult {
             .flat_map(|stack| stack.branches.iter())
             .filter(|(oid, _)| !state.protected_branches.contains_oid(*oid))
             .flat_map(|(_, b)| b.iter())
-            .filter_map(|b| b.push_id.and_then(|_| b.local_name()))
+            .filter_map(|b| b.remote_id.and_then(|_| b.local_name()))
             .collect();
         push_branches.sort_unstable();
         if !push_branches.is_empty() {
@@ -352,10 +476,14 @@ pub fn stack(args: &crate::args::Args) -> proc_exit::ExitResult {
         state.update().with_code(proc_exit::Code::FAILURE)?;
     }
 
-    let mut success = true;
+    let mut summary = crate::ops::RestackSummary::default();
     let mut backed_up = false;
     let mut stash_id = None;
-    if state.rebase || state.fixup != git_stack::config::Fixup::Ignore || state.repair {
+    if let Some(base) = state.at_base() {
+        crate::ops::note_at_base(base);
+    } else if state.rebase || state.fixup != git_stack::config::Fixup::Ignore || state.repair {
+        // A bisect leaves `HEAD` detached on an arbitrary commit, so it's easy to miss
+        crate::ops::ensure_clean_state(state.repo.raw(), "walk commits", state.dry_run)?;
         if stash_id.is_none() && !state.dry_run {
             stash_id = git_stack::legacy::git::stash_push(&mut state.repo, "branch-stash");
         }
@@ -371,7 +499,7 @@ pub fn stack(args: &crate::args::Args) -> proc_exit::ExitResult {
 
         {
             let stash_repo =
-                git2::Repository::discover(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
+                crate::ops::open_repo(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
             let stash_repo = git_branch_stash::GitRepo::new(stash_repo);
             let mut snapshots =
                 git_branch_stash::Stack::new(crate::ops::STASH_STACK_NAME, &stash_repo);
@@ -384,50 +512,145 @@ pub fn stack(args: &crate::args::Args) -> proc_exit::ExitResult {
             }
         }
 
-        let mut head_branch = state
+        let head_branch = state
             .repo
             .head_branch()
             .ok_or_else(|| eyre::eyre!("Must not be in a detached HEAD state."))
-            .with_code(proc_exit::sysexits::USAGE_ERR)?
-            .name;
+            .with_code(proc_exit::sysexits::USAGE_ERR)?;
 
-        let scripts: Result<Vec<_>, proc_exit::Exit> = state
+        let plans: Result<Vec<_>, proc_exit::Exit> = state
             .stacks
             .iter()
-            .map(|stack| {
-                let script = plan_changes(&state, stack).with_code(proc_exit::Code::FAILURE)?;
-                if script.is_branch_deleted(&head_branch) {
-                    // Current branch is deleted, fallback to the local version of the onto branch,
-                    // if possible.
-                    if let Some(local_name) = stack
-                        .onto
-                        .branch
-                        .as_ref()
-                        .map(|b| b.name.as_str())
-                        .filter(|n| state.repo.find_local_branch(n).is_some())
-                    {
-                        head_branch = local_name.to_owned();
-                    }
-                }
-                Ok(script)
-            })
+            .map(|stack| plan_changes(&state, stack).with_code(proc_exit::Code::FAILURE))
             .collect();
-        let scripts = scripts?;
+        let mut scripts = Vec::new();
+        let mut plan_summary = git_stack::graph::Summary::default();
+        let mut pickable_count = 0;
+        for (script, stack_summary, stack_pickable_count) in plans? {
+            scripts.push(script);
+            plan_summary.moved.extend(stack_summary.moved);
+            plan_summary.dropped.extend(stack_summary.dropped);
+            plan_summary.deleted.extend(stack_summary.deleted);
+            pickable_count = pickable_count.max(stack_pickable_count);
+        }
+        if let Err(err) = crate::ops::ensure_no_merges(
+            state.repo.raw(),
+            scripts.iter().flat_map(|s| s.cherry_picks()),
+            state.rebase_merges,
+        ) {
+            git_stack::legacy::git::stash_pop(&mut state.repo, stash_id);
+            return Err(proc_exit::sysexits::USAGE_ERR.with_message(err));
+        }
+        if !state.dry_run && scripts.iter().any(|s| !s.is_empty()) {
+            if let Err(err) =
+                crate::ops::confirm_stack_size(pickable_count, state.max_stack_size, state.yes)
+            {
+                git_stack::legacy::git::stash_pop(&mut state.repo, stash_id);
+                return Err(proc_exit::sysexits::USAGE_ERR.with_message(err));
+            }
+        }
+        if !state.yes
+            && !state.dry_run
+            && plan_summary.is_destructive()
+            && std::io::stdin().is_terminal()
+        {
+            crate::ops::print_restack_summary(
+                state.repo.raw(),
+                |id| state.repo.abbrev_id(id),
+                &plan_summary,
+                crate::ops::Palette::colored(),
+            );
+            if !crate::ops::confirm("re-stack?").with_code(proc_exit::Code::FAILURE)? {
+                git_stack::legacy::git::stash_pop(&mut state.repo, stash_id);
+                return Err(proc_exit::Code::FAILURE.with_message("aborted"));
+            }
+        }
+        let is_deleted = |name: &str| scripts.iter().any(|s| s.is_branch_deleted(name));
+        let switch_branch = if let Some(switch) = state.switch.as_deref() {
+            if state.repo.find_local_branch(switch).is_none() || is_deleted(switch) {
+                git_stack::legacy::git::stash_pop(&mut state.repo, stash_id);
+                return Err(proc_exit::sysexits::USAGE_ERR
+                    .with_message(format!("cannot switch to `{switch}`, it won't exist")));
+            }
+            Some(switch.to_owned())
+        } else if !is_deleted(&head_branch.name) {
+            Some(head_branch.name.clone())
+        } else {
+            let local_branches = state.repo.local_branches().collect::<Vec<_>>();
+            crate::ops::fallback_branch(
+                &head_branch.name,
+                head_branch.id,
+                local_branches
+                    .iter()
+                    .filter(|b| !is_deleted(&b.name))
+                    .map(|b| (b.name.as_str(), b.id)),
+                |base_id, id| state.repo.commit_count(base_id, id),
+                || {
+                    state.stacks.iter().find_map(|stack| {
+                        stack
+                            .onto
+                            .branch
+                            .as_ref()
+                            .map(|b| b.name.as_str())
+                            .filter(|n| state.repo.find_local_branch(n).is_some() && !is_deleted(n))
+                    })
+                },
+            )
+        };
+        // A deleted branch was merged, so detach where its commits landed
+        let detach_id = state
+            .stacks
+            .iter()
+            .zip(scripts.iter())
+            .find(|(_, script)| script.is_branch_deleted(&head_branch.name))
+            .map(|(stack, _)| stack.onto.id)
+            .filter(|_| switch_branch.is_none());
 
+        let start = std::time::Instant::now();
         let mut executor = git_stack::legacy::git::Executor::new(&state.repo, state.dry_run);
         for script in scripts {
             let results = executor.run_script(&mut state.repo, &script);
-            for (err, name, dependents) in results.iter() {
-                success = false;
-                log::error!("Failed to re-stack branch `{}`: {}", name, err);
-                if !dependents.is_empty() {
-                    log::error!("  Blocked dependents: {}", dependents.iter().join(", "));
-                }
+            // Protected branches only come along for the ride
+            let branches = script
+                .branch()
+                .into_iter()
+                .chain(script.dependent_branches())
+                .filter(|b| {
+                    *b != "detached"
+                        && !state
+                            .protected_branches
+                            .iter()
+                            .any(|(_, branches)| branches.iter().any(|p| p.name == *b))
+                });
+            summary.record(branches, &results);
+        }
+        state.timings.record("execute", start.elapsed());
+        state.timings.record_branches(executor.branch_timings());
+        if let Some(switch_branch) = switch_branch.as_deref() {
+            executor
+                .close(&mut state.repo, switch_branch)
+                .with_code(proc_exit::Code::FAILURE)?;
+            // `close` only re-attaches HEAD, it doesn't move to another branch
+            let on_branch =
+                state.repo.head_branch().map(|b| b.name).as_deref() == Some(switch_branch);
+            if !state.dry_run && !on_branch {
+                state
+                    .repo
+                    .switch(switch_branch)
+                    .with_code(proc_exit::Code::FAILURE)?;
             }
+        } else if let Some(detach_id) = detach_id.filter(|_| !state.dry_run) {
+            state
+                .repo
+                .raw()
+                .set_head_detached(detach_id)
+                .and_then(|_| {
+                    let mut builder = git2::build::CheckoutBuilder::new();
+                    builder.force();
+                    state.repo.raw().checkout_head(Some(&mut builder))
+                })
+                .with_code(proc_exit::Code::FAILURE)?;
         }
-        executor
-            .close(&mut state.repo, &head_branch)
-            .with_code(proc_exit::Code::FAILURE)?;
         state.update().with_code(proc_exit::Code::FAILURE)?;
     }
 
@@ -451,15 +674,27 @@ pub fn stack(args: &crate::args::Args) -> proc_exit::ExitResult {
         );
     }
 
-    if !success {
-        return proc_exit::Code::FAILURE.ok();
+    state.timings.report();
+
+    summary.report();
+    if !summary.is_success() {
+        return crate::ops::CONFLICT_ERR.ok();
     }
 
     Ok(())
 }
 
-fn plan_changes(state: &State, stack: &StackState) -> eyre::Result<git_stack::legacy::git::Script> {
+/// Plan re-stacking `stack`, along with how many commits it could rewrite
+fn plan_changes(
+    state: &State,
+    stack: &StackState,
+) -> eyre::Result<(
+    git_stack::legacy::git::Script,
+    git_stack::graph::Summary,
+    usize,
+)> {
     log::trace!("Planning stack changes with base={}", stack.base,);
+    let start = std::time::Instant::now();
     let graphed_branches = stack.branches.clone();
     let mut graph = git_stack::legacy::graph::Graph::from_branches(&state.repo, graphed_branches)?;
     let base_commit = state
@@ -478,6 +713,9 @@ fn plan_changes(state: &State, stack: &StackState) -> eyre::Result<git_stack::le
         &state.repo,
         git_stack::legacy::graph::Node::new(onto_commit),
     )?;
+    state.timings.record("graph", start.elapsed());
+
+    let start = std::time::Instant::now();
     let mut protected_oids: std::collections::HashSet<_> = state
         .protected_branches
         .iter()
@@ -496,7 +734,15 @@ fn plan_changes(state: &State, stack: &StackState) -> eyre::Result<git_stack::le
     if let Some(user) = state.repo.user() {
         git_stack::legacy::graph::protect_foreign_branches(&mut graph, &user, &[]);
     }
+    if let Some(picked) = &state.picked {
+        let user = state.repo.user();
+        git_stack::legacy::graph::protect_unclaimed_branches(&mut graph, user.as_deref(), picked);
+    }
+    protect_other_authors(&mut graph, state);
+    let pickable_count = git_stack::legacy::graph::pickable_count(&graph);
+    state.timings.record("protect", start.elapsed());
 
+    let start = std::time::Instant::now();
     let mut dropped_branches = Vec::new();
     if state.rebase {
         log::trace!("Rebasing onto {}", stack.onto);
@@ -508,7 +754,9 @@ fn plan_changes(state: &State, stack: &StackState) -> eyre::Result<git_stack::le
             .unwrap_or(onto_id);
 
         git_stack::legacy::graph::rebase_development_branches(&mut graph, onto_id);
-        git_stack::legacy::graph::rebase_pulled_branches(&mut graph, pull_start_id, onto_id);
+        if !state.onto_remote {
+            git_stack::legacy::graph::rebase_pulled_branches(&mut graph, pull_start_id, onto_id);
+        }
 
         let pull_range: Vec<_> =
             git_stack::legacy::git::commit_range(&state.repo, onto_id..pull_start_id)?
@@ -524,22 +772,49 @@ fn plan_changes(state: &State, stack: &StackState) -> eyre::Result<git_stack::le
             pull_range.iter().map(|c| c.id),
             &state.protected_branches,
         ));
+        for reintroduced in git_stack::legacy::graph::find_reintroduced_drops(&graph, &state.repo) {
+            let summary = |id| {
+                graph
+                    .get(id)
+                    .expect("reintroduced commits are in the graph")
+                    .commit
+                    .summary
+                    .clone()
+            };
+            log::warn!(
+                "\"{}\" {} \"{}\", which is being dropped as already in {}",
+                summary(reintroduced.id),
+                if reintroduced.reverts {
+                    "reverts"
+                } else {
+                    "re-applies"
+                },
+                summary(reintroduced.dropped_id),
+                stack.onto.display(&state.repo),
+            );
+        }
     }
-    git_stack::legacy::graph::fixup(&mut graph, state.fixup);
+    state.timings.record("rebase", start.elapsed());
+    state.timings.time("fixup", || {
+        git_stack::legacy::graph::fixup(&mut graph, state.fixup);
+    });
     if state.repair {
         log::trace!("Repairing");
         git_stack::legacy::graph::merge_stacks(&mut graph);
         git_stack::legacy::graph::realign_stacks(&mut graph);
     }
 
+    let start = std::time::Instant::now();
     let mut script = git_stack::legacy::graph::to_script(&graph);
     script.commands.extend(
         dropped_branches
             .into_iter()
             .map(git_stack::legacy::git::Command::DeleteBranch),
     );
+    state.timings.record("script", start.elapsed());
+    let summary = git_stack::legacy::graph::summarize(&state.repo, &graph, &script);
 
-    Ok(script)
+    Ok((script, summary, pickable_count))
 }
 
 fn push(state: &mut State) -> eyre::Result<()> {
@@ -566,26 +841,54 @@ fn push(state: &mut State) -> eyre::Result<()> {
     if let Some(user) = state.repo.user() {
         git_stack::legacy::graph::protect_foreign_branches(&mut graph, &user, &[]);
     }
+    if let Some(picked) = &state.picked {
+        let user = state.repo.user();
+        git_stack::legacy::graph::protect_unclaimed_branches(&mut graph, user.as_deref(), picked);
+    }
+    protect_other_authors(&mut graph, state);
 
     git_stack::legacy::graph::pushable(&mut graph);
 
-    git_push(&mut state.repo, &graph, state.dry_run)?;
+    git_push(
+        &mut state.repo,
+        &graph,
+        state.set_upstream_on_create,
+        state.dry_run,
+    )?;
 
     Ok(())
 }
 
+/// Pin commits by other authors, for `--author`
+fn protect_other_authors(graph: &mut git_stack::legacy::graph::Graph, state: &State) {
+    let Some(pattern) = state.author.as_deref() else {
+        return;
+    };
+    let others = graph
+        .breadth_first_iter()
+        .filter(|node| !node.action.is_protected())
+        .map(|node| node.commit.id)
+        .filter(|id| !crate::ops::is_by_author(state.repo.raw(), *id, pattern))
+        .collect();
+    git_stack::legacy::graph::protect_pinned_commits(graph, &state.repo, others);
+}
+
 fn show(state: &State) -> eyre::Result<()> {
     let palette_stderr = crate::ops::Palette::colored();
     let mut empty_stacks = Vec::new();
     let mut old_stacks = Vec::new();
     let mut foreign_stacks = Vec::new();
-
-    let abbrev_graph = match state.show_format {
-        git_stack::config::Format::Silent => false,
-        git_stack::config::Format::List => false,
-        git_stack::config::Format::Graph => true,
-        git_stack::config::Format::Debug => true,
-    };
+    let mut hidden_stacks = Vec::new();
+
+    let abbrev_graph = !state.dump_graph
+        && (state.graph_log.is_some()
+            || match state.show_format {
+                git_stack::config::Format::Silent => false,
+                git_stack::config::Format::List => false,
+                git_stack::config::Format::Porcelain => false,
+                git_stack::config::Format::Graph => true,
+                git_stack::config::Format::Debug => true,
+            });
 
     let mut graphs = Vec::with_capacity(state.stacks.len());
     for stack in state.stacks.iter() {
@@ -599,6 +902,7 @@ fn show(state: &State) -> eyre::Result<()> {
         }
 
         log::trace!("Rendering stack base={}", stack.base,);
+        let start = std::time::Instant::now();
         let mut graph =
             git_stack::legacy::graph::Graph::from_branches(&state.repo, graphed_branches)?;
         let base_commit = state
@@ -617,6 +921,9 @@ fn show(state: &State) -> eyre::Result<()> {
             &state.repo,
             git_stack::legacy::graph::Node::new(onto_commit),
         )?;
+        state.timings.record("graph", start.elapsed());
+
+        let start = std::time::Instant::now();
         let mut protected_oids: std::collections::HashSet<_> = state
             .protected_branches
             .iter()
@@ -658,6 +965,16 @@ fn show(state: &State) -> eyre::Result<()> {
                 git_stack::legacy::graph::protect_foreign_branches(&mut graph, &user, &[]);
             }
         }
+        if let Some(picked) = &state.picked {
+            let user = state.repo.user();
+            git_stack::legacy::graph::protect_unclaimed_branches(
+                &mut graph,
+                user.as_deref(),
+                picked,
+            );
+        }
+        protect_other_authors(&mut graph, state);
+        state.timings.record("protect", start.elapsed());
 
         if state.dry_run {
             // Show as-if we performed all mutations
@@ -692,7 +1009,9 @@ fn show(state: &State) -> eyre::Result<()> {
                     &state.protected_branches,
                 );
             }
-            git_stack::legacy::graph::fixup(&mut graph, state.fixup);
+            state.timings.time("fixup", || {
+                git_stack::legacy::graph::fixup(&mut graph, state.fixup);
+            });
             if state.repair {
                 log::trace!("Repairing");
                 git_stack::legacy::graph::merge_stacks(&mut graph);
@@ -701,6 +1020,20 @@ fn show(state: &State) -> eyre::Result<()> {
         }
 
         git_stack::legacy::graph::pushable(&mut graph);
+        if state.since_pushed {
+            git_stack::legacy::graph::trim_pushed(&mut graph);
+        }
+        if abbrev_graph && !state.hidden.is_empty() {
+            hidden_stacks.extend(
+                git_stack::legacy::graph::trim_hidden_branches(
+                    &mut graph,
+                    &state.hidden,
+                    &[state.head_commit.id],
+                )
+                .into_iter()
+                .map(|b| format!("{}", palette_stderr.info(b))),
+            );
+        }
 
         graphs.push(graph);
     }
@@ -727,7 +1060,17 @@ fn show(state: &State) -> eyre::Result<()> {
         revwalk.count()
     });
 
+    if state.dump_graph {
+        dot(&mut anstream::stdout(), &state.repo, &graphs)?;
+        return Ok(());
+    }
+
     for graph in graphs {
+        if let Some(style) = state.graph_log {
+            let palette = crate::ops::Palette::colored();
+            graph_log(&mut anstream::stdout(), state, &graph, style, &palette)?;
+            continue;
+        }
         match state.show_format {
             git_stack::config::Format::Silent => {}
             git_stack::config::Format::List => {
@@ -740,6 +1083,9 @@ fn show(state: &State) -> eyre::Result<()> {
                     &palette,
                 )?;
             }
+            git_stack::config::Format::Porcelain => {
+                porcelain(&mut anstream::stdout(), &graph)?;
+            }
             git_stack::config::Format::Graph => {
                 write!(
                     anstream::stdout(),
@@ -747,6 +1093,12 @@ fn show(state: &State) -> eyre::Result<()> {
                     DisplayTree::new(&state.repo, &graph)
                         .show(state.show_commits)
                         .stacked(state.show_stacked)
+                        .show_protected(state.show_protected)
+                        .order(state.log_order)
+                        .width(state.width)
+                        .files(state.files)
+                        .show_signature(state.show_signature)
+                        .depth(state.depth)
                         .protected_branches(&state.protected_branches)
                 )?;
             }
@@ -769,6 +1121,12 @@ fn show(state: &State) -> eyre::Result<()> {
     if !foreign_stacks.is_empty() {
         log::info!("Stack from other users: {}", foreign_stacks.join(", "));
     }
+    if !hidden_stacks.is_empty() {
+        log::info!(
+            "Hidden stacks (show with `--show-all`): {}",
+            hidden_stacks.join(", ")
+        );
+    }
 
     Ok(())
 }
@@ -806,6 +1164,17 @@ impl AnnotatedOid {
     }
 }
 
+impl AnnotatedOid {
+    /// Like [`Display`][std::fmt::Display], abbreviating a bare id
+    pub fn display(&self, repo: &dyn git_stack::legacy::git::Repo) -> String {
+        if let Some(branch) = &self.branch {
+            branch.to_string()
+        } else {
+            repo.abbrev_id(self.id)
+        }
+    }
+}
+
 impl std::fmt::Display for AnnotatedOid {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if let Some(branch) = &self.branch {
@@ -882,7 +1251,7 @@ fn resolve_implicit_base(
                         ahead_count,
                         behind_count,
                         branch,
-                        assumed_base_oid
+                        repo.abbrev_id(assumed_base_oid)
                     );
                     return AnnotatedOid::new(assumed_base_oid);
                 }
@@ -910,8 +1279,8 @@ fn resolve_implicit_base(
                 git_stack::legacy::git::infer_base(repo, head_oid).unwrap_or(head_oid);
             log::warn!(
                 "Could not find protected branch for {}, assuming {}",
-                head_oid,
-                assumed_base_oid
+                repo.abbrev_id(head_oid),
+                repo.abbrev_id(assumed_base_oid)
             );
             AnnotatedOid::new(assumed_base_oid)
         }
@@ -944,6 +1313,65 @@ fn resolve_onto_from_base(
         .unwrap_or_else(|| base.clone())
 }
 
+/// How long since the last fetch before `--onto-remote` warns the remote branch may be outdated
+const STALE_FETCH_AGE: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
+/// The remote-tracking branch of `base`, without consulting or touching the local branch
+fn resolve_remote_onto(
+    repo: &git_stack::legacy::git::GitRepo,
+    base: &AnnotatedOid,
+) -> eyre::Result<AnnotatedOid> {
+    let branch = base
+        .branch
+        .as_ref()
+        .ok_or_else(|| eyre::eyre!("`--onto-remote` requires the base to be a branch"))?;
+    let remote_branch = if branch.remote.is_some() {
+        branch.clone()
+    } else {
+        repo.find_remote_branch(repo.pull_remote(), &branch.name)
+            .ok_or_else(|| {
+                eyre::eyre!(
+                    "no remote-tracking branch `{}/{}`, fetch it first",
+                    repo.pull_remote(),
+                    branch.name
+                )
+            })?
+    };
+
+    if let Some(age) = last_fetch_age(repo, &remote_branch) {
+        if STALE_FETCH_AGE < age {
+            log::warn!(
+                "`{}` was last fetched {} days ago, run `git fetch` to rebase onto the latest",
+                remote_branch,
+                age.as_secs() / (24 * 60 * 60)
+            );
+        }
+    }
+
+    Ok(AnnotatedOid::with_branch(remote_branch))
+}
+
+/// Time since the remote-tracking branch was last updated or its remote fetched
+fn last_fetch_age(
+    repo: &git_stack::legacy::git::GitRepo,
+    branch: &git_stack::legacy::git::Branch,
+) -> Option<std::time::Duration> {
+    let remote = branch.remote.as_deref()?;
+    let ref_name = format!("refs/remotes/{}/{}", remote, branch.name);
+    let updated = repo.raw().reflog(&ref_name).ok().and_then(|reflog| {
+        reflog.get(0).map(|entry| {
+            let seconds = entry.committer().when().seconds().max(0) as u64;
+            std::time::UNIX_EPOCH + std::time::Duration::from_secs(seconds)
+        })
+    });
+    // A fetch that finds nothing new doesn't touch the reflog
+    let fetched = std::fs::metadata(repo.raw().path().join("FETCH_HEAD"))
+        .and_then(|m| m.modified())
+        .ok();
+    let last = updated.into_iter().chain(fetched).max()?;
+    std::time::SystemTime::now().duration_since(last).ok()
+}
+
 fn git_prune_development(
     repo: &mut git_stack::legacy::git::GitRepo,
     branches: &[&str],
@@ -1010,6 +1438,7 @@ fn git_fetch_upstream(remote: &str, branch_name: &str) -> eyre::Result<()> {
 fn git_push(
     repo: &mut git_stack::legacy::git::GitRepo,
     graph: &git_stack::legacy::graph::Graph,
+    set_upstream_on_create: bool,
     dry_run: bool,
 ) -> eyre::Result<()> {
     let mut failed = Vec::new();
@@ -1019,7 +1448,12 @@ fn git_push(
     while let Some(current_id) = node_queue.pop_front() {
         let current = graph.get(current_id).expect("all children exist");
 
-        failed.extend(git_push_node(repo, current, dry_run));
+        failed.extend(git_push_node(
+            repo,
+            current,
+            set_upstream_on_create,
+            dry_run,
+        ));
 
         for child_id in current.children.iter().copied() {
             node_queue.push_back(child_id);
@@ -1036,6 +1470,7 @@ fn git_push(
 fn git_push_node(
     repo: &mut git_stack::legacy::git::GitRepo,
     node: &git_stack::legacy::graph::Node,
+    set_upstream_on_create: bool,
     dry_run: bool,
 ) -> Vec<String> {
     let mut failed = Vec::new();
@@ -1055,7 +1490,7 @@ fn git_push_node(
 
             let remote = repo.push_remote();
             let mut args = vec!["push", "--force-with-lease"];
-            if !upstream_set {
+            if !upstream_set && set_upstream_on_create {
                 args.push("--set-upstream");
             }
             args.push(remote);
@@ -1067,6 +1502,8 @@ fn git_push_node(
                     Ok(status) => {
                         if !status.success() {
                             failed.push(local_branch.to_owned());
+                        } else if let Err(err) = repo.record_push(local_branch, branch.id) {
+                            log::debug!("Could not record push of `{}`: {}", local_branch, err);
                         }
                     }
                     Err(err) => {
@@ -1114,12 +1551,246 @@ fn list(
     Ok(())
 }
 
+/// Write one `oid\taction\tbranches\tpushable` line per commit
+///
+/// This is a stable interface for scripts: fields are only ever appended.
+/// - `oid`: full commit id
+/// - `action`: `pick`, `fixup`, `protected`, or `delete`
+/// - `branches`: space-separated branches pointing at the commit (remotes as `remote/name`), or
+///   `-` if none
+/// - `pushable`: `true` or `false`
+fn porcelain(
+    writer: &mut dyn std::io::Write,
+    graph: &git_stack::legacy::graph::Graph,
+) -> Result<(), std::io::Error> {
+    for node in graph.breadth_first_iter() {
+        let action = match node.action {
+            git_stack::legacy::graph::Action::Pick => "pick",
+            git_stack::legacy::graph::Action::Fixup => "fixup",
+            git_stack::legacy::graph::Action::Protected => "protected",
+            git_stack::legacy::graph::Action::Delete => "delete",
+        };
+        let mut branches: Vec<_> = node.branches.iter().map(|b| b.to_string()).collect();
+        branches.sort();
+        let branches = if branches.is_empty() {
+            "-".to_owned()
+        } else {
+            branches.join(" ")
+        };
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}",
+            node.commit.id, action, branches, node.pushable
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Write the stacks as a Graphviz DOT `digraph`
+///
+/// Each stack is its own cluster, so node ids are prefixed with the stack's index to keep a
+/// shared root from being merged across clusters.  Edges point from parent to child.
+fn dot(
+    writer: &mut dyn std::io::Write,
+    repo: &git_stack::legacy::git::GitRepo,
+    graphs: &[git_stack::legacy::graph::Graph],
+) -> Result<(), std::io::Error> {
+    fn escape(s: &str) -> String {
+        s.replace('\\', "\\\\").replace('"', "\\\"")
+    }
+    let abbrev_id = |id: git2::Oid| repo.abbrev_id(id);
+
+    writeln!(writer, "digraph git_stack {{")?;
+    writeln!(
+        writer,
+        "  node [shape=box, style=filled, fontname=monospace];"
+    )?;
+    for (index, graph) in graphs.iter().enumerate() {
+        writeln!(writer, "  subgraph cluster_{index} {{")?;
+        let mut root_branches: Vec<_> = graph
+            .root()
+            .branches
+            .iter()
+            .map(|b| b.to_string())
+            .collect();
+        root_branches.sort();
+        let label = if root_branches.is_empty() {
+            abbrev_id(graph.root_id())
+        } else {
+            root_branches.join(" ")
+        };
+        writeln!(writer, "    label=\"{}\";", escape(&label))?;
+        for node in graph.breadth_first_iter() {
+            let abbrev_id = abbrev_id(node.commit.id);
+            let (action, color) = match node.action {
+                git_stack::legacy::graph::Action::Pick => ("pick", "lightblue"),
+                git_stack::legacy::graph::Action::Fixup => ("fixup", "khaki"),
+                git_stack::legacy::graph::Action::Protected => ("protected", "lightgrey"),
+                git_stack::legacy::graph::Action::Delete => ("delete", "salmon"),
+            };
+            let mut branches: Vec<_> = node.branches.iter().map(|b| b.to_string()).collect();
+            branches.sort();
+            let branches = branches.join(" ");
+            // `\\n` is DOT's line break, so only the pieces are escaped
+            let mut label = format!(
+                "{abbrev_id} {}\\n({action})",
+                escape(&node.commit.summary.to_str_lossy())
+            );
+            if !branches.is_empty() {
+                label.push_str(&format!("\\n[{}]", escape(&branches)));
+            }
+            writeln!(
+                writer,
+                "    \"{index}-{}\" [label=\"{label}\", fillcolor={color}, branches=\"{}\"];",
+                node.commit.id,
+                escape(&branches),
+            )?;
+            for child_id in node.children.iter() {
+                writeln!(
+                    writer,
+                    "    \"{index}-{}\" -> \"{index}-{}\";",
+                    node.commit.id, child_id
+                )?;
+            }
+        }
+        writeln!(writer, "  }}")?;
+    }
+    writeln!(writer, "}}")?;
+
+    Ok(())
+}
+
+/// Write `graph` like `git log --graph`, each stack's tip first
+///
+/// This walks the same tree as [`DisplayTree`], so protected runs and `--depth` are collapsed
+/// into markers the same way.
+fn graph_log(
+    writer: &mut dyn std::io::Write,
+    state: &State,
+    graph: &git_stack::legacy::graph::Graph,
+    style: crate::graph_log::LogStyle,
+    palette: &crate::ops::Palette,
+) -> Result<(), std::io::Error> {
+    let repo = &state.repo;
+    let head_branch = repo.head_branch().unwrap();
+    let mut tree = node_to_tree(repo, &head_branch, graph, graph.root_id(), &|_| true);
+    if !state.show_protected {
+        tree.collapse_protected(head_branch.id);
+    }
+    tree.sort(state.log_order);
+    if let Some(depth) = state.depth {
+        tree.truncate_depth(depth);
+    }
+    let mut order = Vec::new();
+    tree.log_order(None, &mut order);
+
+    let mut lanes = crate::graph_log::Lanes::default();
+    for (i, (tree, parent_id)) in order.iter().enumerate() {
+        let is_last = i + 1 == order.len();
+        let node = tree.root;
+        let id = node.commit.id;
+        let commit_line = lanes.commit_line(id);
+        lanes.replace(id, *parent_id);
+        let marker = if 0 < tree.more {
+            Some(format!("… {} more …", tree.more))
+        } else if 0 < tree.collapsed {
+            Some(format!("… {} protected commits …", tree.collapsed))
+        } else {
+            None
+        };
+        if let Some(marker) = marker {
+            writeln!(writer, "{} {}", commit_line, palette.info(marker))?;
+        } else {
+            let action = match node.action {
+                git_stack::legacy::graph::Action::Pick => "pick",
+                git_stack::legacy::graph::Action::Fixup => "fixup",
+                git_stack::legacy::graph::Action::Protected => "protected",
+                git_stack::legacy::graph::Action::Delete => "delete",
+            };
+            let mut annotations = format!(" {}", palette.info(action));
+            for branch in visible_branches(node, &head_branch) {
+                annotations.push_str(&format!(
+                    " ({}){}",
+                    format_branch_name(
+                        branch,
+                        node,
+                        &head_branch,
+                        &state.protected_branches,
+                        palette
+                    ),
+                    format_branch_status(branch, repo, node, palette),
+                ));
+            }
+            annotations.push_str(&format_commit_status(repo, node, palette));
+            if state.show_signature != ShowSignature::Hide && !node.action.is_protected() {
+                annotations.push_str(&format_signature(repo, node, state.show_signature, palette));
+            }
+
+            match style {
+                crate::graph_log::LogStyle::Oneline => {
+                    writeln!(
+                        writer,
+                        "{} {}{} {}",
+                        commit_line,
+                        palette.highlight(repo.abbrev_id(id)),
+                        annotations,
+                        palette.hint(node.commit.summary.to_str_lossy()),
+                    )?;
+                }
+                crate::graph_log::LogStyle::Medium => {
+                    writeln!(
+                        writer,
+                        "{} commit {}{}",
+                        commit_line,
+                        palette.highlight(id),
+                        annotations,
+                    )?;
+                    let padding = lanes.padding();
+                    let commit = repo
+                        .raw()
+                        .find_commit(id)
+                        .unwrap_or_else(|e| panic!("Unexpected git2 error: {e}"));
+                    let author = commit.author();
+                    writeln!(
+                        writer,
+                        "{}Author: {} <{}>",
+                        padding,
+                        author.name_bytes().to_str_lossy(),
+                        author.email_bytes().to_str_lossy(),
+                    )?;
+                    writeln!(writer, "{}", padding.trim_end())?;
+                    for line in commit.message_bytes().trim_end().lines() {
+                        let line = format!("{padding}    {}", line.to_str_lossy());
+                        writeln!(writer, "{}", line.trim_end())?;
+                    }
+                    if !is_last {
+                        writeln!(writer, "{}", padding.trim_end())?;
+                    }
+                }
+            }
+        }
+
+        for connector in lanes.join() {
+            writeln!(writer, "{connector}")?;
+        }
+    }
+
+    Ok(())
+}
+
 struct DisplayTree<'r> {
     repo: &'r git_stack::legacy::git::GitRepo,
     graph: &'r git_stack::legacy::graph::Graph,
     protected_branches: git_stack::legacy::git::Branches,
     show: git_stack::config::ShowCommits,
     stacked: bool,
+    show_protected: bool,
+    order: git_stack::config::LogOrder,
+    width: Option<usize>,
+    files: Option<usize>,
+    show_signature: ShowSignature,
+    depth: Option<usize>,
 }
 
 impl<'r> DisplayTree<'r> {
@@ -1133,6 +1804,12 @@ impl<'r> DisplayTree<'r> {
             protected_branches: Default::default(),
             show: Default::default(),
             stacked: Default::default(),
+            show_protected: Default::default(),
+            order: Default::default(),
+            width: Default::default(),
+            files: Default::default(),
+            show_signature: Default::default(),
+            depth: Default::default(),
         }
     }
 
@@ -1146,6 +1823,38 @@ impl<'r> DisplayTree<'r> {
         self
     }
 
+    pub fn show_protected(mut self, show_protected: bool) -> Self {
+        self.show_protected = show_protected;
+        self
+    }
+
+    pub fn order(mut self, order: git_stack::config::LogOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn width(mut self, width: Option<usize>) -> Self {
+        self.width = width;
+        self
+    }
+
+    /// List up to `files` paths under each commit
+    pub fn files(mut self, files: Option<usize>) -> Self {
+        self.files = files;
+        self
+    }
+
+    pub fn show_signature(mut self, show_signature: ShowSignature) -> Self {
+        self.show_signature = show_signature;
+        self
+    }
+
+    /// Show up to `depth` commits below the root, marking what is left out
+    pub fn depth(mut self, depth: Option<usize>) -> Self {
+        self.depth = depth;
+        self
+    }
+
     pub fn protected_branches(
         mut self,
         protected_branches: &git_stack::legacy::git::Branches,
@@ -1185,16 +1894,79 @@ impl<'r> std::fmt::Display for DisplayTree<'r> {
             self.graph.root_id(),
             &is_visible,
         );
+        if !self.show_protected {
+            tree.collapse_protected(head_branch.id);
+        }
         if self.stacked {
-            tree.linearize();
+            tree.linearize(self.order);
         } else {
-            tree.sort();
+            tree.sort(self.order);
+        }
+        if let Some(depth) = self.depth {
+            tree.truncate_depth(depth);
+        }
+        let tree = tree.into_display(
+            self.repo,
+            &head_branch,
+            &self.protected_branches,
+            self.files,
+            self.show_signature,
+        );
+        if let Some(width) = self.width {
+            // Summaries come last on each line, so they are what gets cut
+            for line in tree.to_string().split_inclusive('\n') {
+                let (line, newline) = match line.strip_suffix('\n') {
+                    Some(line) => (line, "\n"),
+                    None => (line, ""),
+                };
+                write!(f, "{}{}", truncate_line(line, width), newline)?;
+            }
+            Ok(())
+        } else {
+            tree.fmt(f)
         }
-        let tree = tree.into_display(self.repo, &head_branch, &self.protected_branches);
-        tree.fmt(f)
     }
 }
 
+/// Cut `line` to `width` visible characters, ending in an ellipsis
+///
+/// ANSI escape sequences don't count towards the width and styling is reset after a cut.
+fn truncate_line(line: &str, width: usize) -> std::borrow::Cow<'_, str> {
+    fn visible_chars(line: &str) -> impl Iterator<Item = (usize, bool)> + '_ {
+        let mut in_escape = false;
+        line.char_indices().map(move |(i, c)| {
+            if c == '\x1b' {
+                in_escape = true;
+            }
+            let visible = !in_escape;
+            if in_escape && c.is_ascii_alphabetic() {
+                in_escape = false;
+            }
+            (i, visible)
+        })
+    }
+
+    let visible_count = visible_chars(line).filter(|(_, v)| *v).count();
+    if visible_count <= width {
+        return std::borrow::Cow::Borrowed(line);
+    }
+
+    let keep = width.saturating_sub(1);
+    let end = visible_chars(line)
+        .filter(|(_, v)| *v)
+        .nth(keep)
+        .map(|(i, _)| i)
+        .unwrap_or(line.len());
+    let mut truncated = line[..end].to_owned();
+    if line.contains('\x1b') {
+        truncated.push_str("\x1b[0m");
+    }
+    if 0 < width {
+        truncated.push('…');
+    }
+    std::borrow::Cow::Owned(truncated)
+}
+
 fn node_to_tree<'r>(
     repo: &'r git_stack::legacy::git::GitRepo,
     head_branch: &'r git_stack::legacy::git::Branch,
@@ -1214,6 +1986,8 @@ fn node_to_tree<'r>(
             root: node,
             weight: default_weight(node, head_branch),
             stacks: Default::default(),
+            collapsed: 0,
+            more: 0,
         };
 
         append_children(&mut tree, repo, head_branch, graph, node, is_visible);
@@ -1246,6 +2020,8 @@ fn append_children<'r>(
                             root: node,
                             weight: default_weight(node, head_branch),
                             stacks: Default::default(),
+                            collapsed: 0,
+                            more: 0,
                         };
                         tree.weight = tree.weight.max(child_tree.weight + linear_count);
                         if tree.stacks.is_empty() {
@@ -1260,6 +2036,8 @@ fn append_children<'r>(
                                 root: node,
                                 weight: default_weight(node, head_branch),
                                 stacks: Default::default(),
+                                collapsed: 0,
+                                more: 0,
                             };
                             // `tree.weight`: rely on a terminating case for updating
                             if tree.stacks.is_empty() {
@@ -1306,28 +2084,121 @@ fn default_weight(
     }
 }
 
+/// Runs of more protected commits than this are collapsed into a single line
+const PROTECTED_COLLAPSE_THRESHOLD: usize = 5;
+
 #[derive(Debug)]
 struct Tree<'r> {
     root: &'r git_stack::legacy::graph::Node,
     stacks: Vec<Vec<Self>>,
     weight: Weight,
+    /// Number of protected commits `root` stands in for
+    collapsed: usize,
+    /// Number of commits cut off by `--depth` that `root` stands in for
+    more: usize,
 }
 
 impl<'r> Tree<'r> {
-    fn sort(&mut self) {
-        self.stacks.sort_by_key(|s| s[0].weight);
+    fn collapse_protected(&mut self, head_id: git2::Oid) {
+        for stack in self.stacks.iter_mut() {
+            for child in stack.iter_mut() {
+                child.collapse_protected(head_id);
+            }
+
+            let mut collapsed = Vec::with_capacity(stack.len());
+            let mut run = Vec::new();
+            for child in stack.drain(..) {
+                let boring = child.stacks.is_empty()
+                    && child.root.action.is_protected()
+                    && child.root.branches.is_empty()
+                    && child.root.children.len() == 1
+                    && child.root.commit.id != head_id;
+                if boring {
+                    run.push(child);
+                } else {
+                    Self::flush_protected_run(&mut run, &mut collapsed);
+                    collapsed.push(child);
+                }
+            }
+            Self::flush_protected_run(&mut run, &mut collapsed);
+            *stack = collapsed;
+        }
+    }
+
+    fn flush_protected_run(run: &mut Vec<Self>, stack: &mut Vec<Self>) {
+        if PROTECTED_COLLAPSE_THRESHOLD < run.len() {
+            let count = run.len();
+            let mut first = run.drain(..).next().expect("run is non-empty");
+            first.collapsed = count;
+            stack.push(first);
+        } else {
+            stack.append(run);
+        }
+    }
+
+    /// Cut each stack off `depth` commits below `root`, leaving a marker in place of the rest
+    fn truncate_depth(&mut self, depth: usize) {
+        for stack in self.stacks.iter_mut() {
+            if depth < stack.len() {
+                let more = stack[depth..].iter().map(Self::commit_count).sum();
+                let mut marker = stack.drain(depth..).next().expect("cut is non-empty");
+                marker.stacks.clear();
+                marker.collapsed = 0;
+                marker.more = more;
+                stack.push(marker);
+            }
+            for (i, child) in stack.iter_mut().enumerate() {
+                if child.more == 0 {
+                    child.truncate_depth(depth - i - 1);
+                }
+            }
+        }
+    }
+
+    /// Flatten into the order `git log --graph` lists commits in, each with the one below it
+    fn log_order<'t>(
+        &'t self,
+        parent_id: Option<git2::Oid>,
+        order: &mut Vec<(&'t Self, Option<git2::Oid>)>,
+    ) {
+        let id = self.root.commit.id;
+        for stack in self.stacks.iter() {
+            for (i, child) in stack.iter().enumerate().rev() {
+                let parent_id = if i == 0 {
+                    id
+                } else {
+                    stack[i - 1].root.commit.id
+                };
+                child.log_order(Some(parent_id), order);
+            }
+        }
+        order.push((self, parent_id));
+    }
+
+    fn commit_count(&self) -> usize {
+        self.collapsed.max(1)
+            + self
+                .stacks
+                .iter()
+                .flatten()
+                .map(Self::commit_count)
+                .sum::<usize>()
+    }
+
+    fn sort(&mut self, order: git_stack::config::LogOrder) {
+        self.sort_stacks(order);
         for stack in self.stacks.iter_mut() {
             for child in stack.iter_mut() {
-                child.sort();
+                child.sort(order);
             }
         }
     }
 
-    fn linearize(&mut self) {
-        self.stacks.sort_by_key(|s| s[0].weight);
+    fn linearize(&mut self, order: git_stack::config::LogOrder) {
+        self.sort_stacks(order);
         for stack in self.stacks.iter_mut() {
             for child in stack.iter_mut() {
-                child.linearize();
+                child.linearize(order);
             }
             let append = {
                 let last = stack.last_mut().expect("stack always has at least 1");
@@ -1341,31 +2212,96 @@ impl<'r> Tree<'r> {
         }
     }
 
+    /// Order sibling stacks for display, leaving ties in their structural order
+    fn sort_stacks(&mut self, order: git_stack::config::LogOrder) {
+        match order {
+            git_stack::config::LogOrder::Topo => {
+                self.stacks.sort_by_key(|s| s[0].weight);
+            }
+            git_stack::config::LogOrder::Date => {
+                self.stacks
+                    .sort_by_key(|s| (std::cmp::Reverse(Self::stack_time(s)), s[0].weight));
+            }
+            git_stack::config::LogOrder::Name => {
+                self.stacks.sort_by_key(|s| {
+                    let name = Self::stack_name(s);
+                    (name.is_none(), name, s[0].weight)
+                });
+            }
+        }
+    }
+
+    /// Most recent commit time in `stack`, including anything stacked on top of it
+    fn stack_time(stack: &[Self]) -> std::time::SystemTime {
+        stack
+            .iter()
+            .map(|tree| {
+                tree.stacks
+                    .iter()
+                    .map(|s| Self::stack_time(s))
+                    .fold(tree.root.commit.time, std::cmp::max)
+            })
+            .max()
+            .unwrap_or(std::time::UNIX_EPOCH)
+    }
+
+    /// First branch name in `stack`, including anything stacked on top of it
+    fn stack_name(stack: &[Self]) -> Option<&'r str> {
+        stack
+            .iter()
+            .flat_map(|tree| {
+                tree.root
+                    .branches
+                    .iter()
+                    .map(|b| b.name.as_str())
+                    .chain(tree.stacks.iter().filter_map(|s| Self::stack_name(s)))
+            })
+            .min()
+    }
+
     fn into_display(
         self,
         repo: &'r git_stack::legacy::git::GitRepo,
         head_branch: &'r git_stack::legacy::git::Branch,
         protected_branches: &'r git_stack::legacy::git::Branches,
+        files: Option<usize>,
+        show_signature: ShowSignature,
     ) -> termtree::Tree<RenderNode<'r>> {
         let root = RenderNode {
             repo,
             head_branch,
             protected_branches,
             node: Some(self.root),
+            collapsed: self.collapsed,
+            more: self.more,
+            files,
+            show_signature,
         };
-        let mut tree = termtree::Tree::new(root).with_glyphs(GLYPHS);
+        let mut tree = termtree::Tree::new(root)
+            .with_glyphs(GLYPHS)
+            .with_multiline(files.is_some());
         let joint = RenderNode {
             repo,
             head_branch,
             protected_branches,
             node: None,
+            collapsed: 0,
+            more: 0,
+            files: None,
+            show_signature: ShowSignature::Hide,
         };
         let stacks_len = self.stacks.len();
         for (i, stack) in self.stacks.into_iter().enumerate() {
             if i < stacks_len - 1 {
                 let mut stack_tree = termtree::Tree::new(joint).with_glyphs(JOINT_GLYPHS);
                 for child_tree in stack.into_iter() {
-                    stack_tree.push(child_tree.into_display(repo, head_branch, protected_branches));
+                    stack_tree.push(child_tree.into_display(
+                        repo,
+                        head_branch,
+                        protected_branches,
+                        files,
+                        show_signature,
+                    ));
                 }
                 tree.push(stack_tree);
             } else {
@@ -1379,8 +2315,16 @@ impl<'r> Tree<'r> {
                         head_branch,
                         protected_branches,
                         node: Some(child_tree.root),
+                        collapsed: child_tree.collapsed,
+                        more: child_tree.more,
+                        files,
+                        show_signature,
                     };
-                    tree.push(termtree::Tree::new(child).with_glyphs(GLYPHS));
+                    tree.push(
+                        termtree::Tree::new(child)
+                            .with_glyphs(GLYPHS)
+                            .with_multiline(files.is_some()),
+                    );
                     if !child_tree.stacks.is_empty() {
                         for child_stack in child_tree.stacks.into_iter() {
                             let mut stack_tree =
@@ -1390,6 +2334,8 @@ impl<'r> Tree<'r> {
                                     repo,
                                     head_branch,
                                     protected_branches,
+                                    files,
+                                    show_signature,
                                 ));
                             }
                             tree.push(stack_tree);
@@ -1450,6 +2396,34 @@ struct RenderNode<'r> {
     head_branch: &'r git_stack::legacy::git::Branch,
     protected_branches: &'r git_stack::legacy::git::Branches,
     node: Option<&'r git_stack::legacy::graph::Node>,
+    collapsed: usize,
+    more: usize,
+    /// Files to list under the commit, if any
+    files: Option<usize>,
+    show_signature: ShowSignature,
+}
+
+/// Files `--stat-by-file` lists under a commit, unless `--all-files`
+const DEFAULT_FILE_LIMIT: usize = 10;
+
+/// How `--show-signature` marks commits
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+enum ShowSignature {
+    #[default]
+    Hide,
+    Show,
+    /// Also check signatures with `git verify-commit`
+    Verify,
+}
+
+impl ShowSignature {
+    fn new(show_signature: bool, verify: bool) -> Self {
+        match (show_signature, verify) {
+            (false, _) => Self::Hide,
+            (true, false) => Self::Show,
+            (true, true) => Self::Verify,
+        }
+    }
 }
 
 const GLYPHS: termtree::GlyphPalette = termtree::GlyphPalette {
@@ -1479,15 +2453,21 @@ const JOINT_GLYPHS: termtree::GlyphPalette = termtree::GlyphPalette {
 impl<'r> std::fmt::Display for RenderNode<'r> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
         let palette = crate::ops::Palette::colored();
-        if let Some(node) = self.node.as_ref() {
+        if 0 < self.more {
+            write!(
+                f,
+                "{}",
+                palette.info(format_args!("… {} more below …", self.more))
+            )?;
+        } else if 0 < self.collapsed {
+            write!(
+                f,
+                "{}",
+                palette.info(format_args!("… {} protected commits …", self.collapsed))
+            )?;
+        } else if let Some(node) = self.node.as_ref() {
             if node.branches.is_empty() {
-                let abbrev_id = self
-                    .repo
-                    .raw()
-                    .find_object(node.commit.id, None)
-                    .unwrap_or_else(|e| panic!("Unexpected git2 error: {e}"))
-                    .short_id()
-                    .unwrap_or_else(|e| panic!("Unexpected git2 error: {e}"));
+                let abbrev_id = self.repo.abbrev_id(node.commit.id);
                 let style = if self.head_branch.id == node.commit.id {
                     palette.highlight
                 } else if node.action.is_protected() {
@@ -1498,31 +2478,13 @@ impl<'r> std::fmt::Display for RenderNode<'r> {
                 } else {
                     palette.hint
                 };
-                write!(f, "{}", Styled::new(abbrev_id.as_str().unwrap(), style))?;
+                write!(f, "{}", Styled::new(abbrev_id.as_str(), style))?;
             } else {
-                let mut branches: Vec<_> = node.branches.iter().collect();
-                branches.sort_by_key(|b| {
-                    let is_head = self.head_branch.id == b.id
-                        && self.head_branch.remote == b.remote
-                        && self.head_branch.name == b.name;
-                    let head_first = !is_head;
-                    (head_first, &b.remote, &b.name)
-                });
                 write!(
                     f,
                     "{}",
-                    branches
-                        .iter()
-                        .filter(|b| {
-                            if b.remote.is_some() {
-                                let local_present = branches
-                                    .iter()
-                                    .any(|b| b.local_name() == Some(b.name.as_str()));
-                                !local_present
-                            } else {
-                                true
-                            }
-                        })
+                    visible_branches(node, self.head_branch)
+                        .into_iter()
                         .map(|b| {
                             format!(
                                 "{}{}",
@@ -1540,7 +2502,15 @@ impl<'r> std::fmt::Display for RenderNode<'r> {
                 )?;
             }
 
-            write!(f, "{} ", format_commit_status(self.repo, node, &palette))?;
+            write!(f, "{}", format_commit_status(self.repo, node, &palette))?;
+            if self.show_signature != ShowSignature::Hide && !node.action.is_protected() {
+                write!(
+                    f,
+                    "{}",
+                    format_signature(self.repo, node, self.show_signature, &palette)
+                )?;
+            }
+            write!(f, " ")?;
 
             let summary = String::from_utf8_lossy(&node.commit.summary);
             if node.action.is_protected() {
@@ -1554,11 +2524,55 @@ impl<'r> std::fmt::Display for RenderNode<'r> {
             } else {
                 write!(f, "{summary}")?;
             }
+
+            if let Some(limit) = self.files.filter(|_| !node.action.is_protected()) {
+                let paths = self.repo.changed_paths(node.commit.id);
+                for path in paths.iter().take(limit) {
+                    write!(f, "\n  {}", palette.hint(path.display()))?;
+                }
+                if limit < paths.len() {
+                    write!(
+                        f,
+                        "\n  {}",
+                        palette.hint(format_args!("… and {} more", paths.len() - limit))
+                    )?;
+                }
+            }
         }
         Ok(())
     }
 }
 
+/// The branches to label `node` with, the HEAD branch first
+///
+/// Remote branches are left out when their local branch is there too.
+fn visible_branches<'n>(
+    node: &'n git_stack::legacy::graph::Node,
+    head_branch: &git_stack::legacy::git::Branch,
+) -> Vec<&'n git_stack::legacy::git::Branch> {
+    let mut branches: Vec<_> = node.branches.iter().collect();
+    branches.sort_by_key(|b| {
+        let is_head =
+            head_branch.id == b.id && head_branch.remote == b.remote && head_branch.name == b.name;
+        let head_first = !is_head;
+        (head_first, &b.remote, &b.name)
+    });
+    branches
+        .iter()
+        .copied()
+        .filter(|b| {
+            if b.remote.is_some() {
+                let local_present = branches
+                    .iter()
+                    .any(|b| b.local_name() == Some(b.name.as_str()));
+                !local_present
+            } else {
+                true
+            }
+        })
+        .collect()
+}
+
 fn format_branch_name<'d>(
     branch: &'d git_stack::legacy::git::Branch,
     node: &'d git_stack::legacy::graph::Node,
@@ -1612,7 +2626,15 @@ fn format_branch_status<'d>(
         if node.branches.is_empty() {
             String::new()
         } else {
-            match commit_relation(repo, branch.id, branch.push_id) {
+            let diverged = if branch.is_remote_diverged() {
+                format!(" {}", palette.error("(remote diverged)"))
+            } else {
+                String::new()
+            };
+            let relation = match branch
+                .remote_id
+                .and_then(|remote_id| repo.ahead_behind(branch.id, remote_id))
+            {
                 Some((0, 0)) => {
                     format!(" {}", palette.good("(pushed)"))
                 }
@@ -1635,7 +2657,29 @@ fn format_branch_status<'d>(
                         String::new()
                     }
                 }
-            }
+            };
+            format!("{relation}{diverged}")
+        }
+    }
+}
+
+fn format_signature(
+    repo: &git_stack::legacy::git::GitRepo,
+    node: &git_stack::legacy::graph::Node,
+    show_signature: ShowSignature,
+    palette: &crate::ops::Palette,
+) -> String {
+    if !repo.is_signed(node.commit.id) {
+        return format!(" {}", palette.warn("(unsigned)"));
+    }
+    if show_signature != ShowSignature::Verify {
+        return format!(" {}", palette.good("(signed)"));
+    }
+    match crate::ops::git_verify_commit(node.commit.id) {
+        crate::ops::Verification::Good => format!(" {}", palette.good("(signed)")),
+        crate::ops::Verification::Bad => format!(" {}", palette.error("(bad sig)")),
+        crate::ops::Verification::Unverifiable => {
+            format!(" {}", palette.warn("(unverified)"))
         }
     }
 }
@@ -1661,19 +2705,3 @@ fn format_commit_status<'d>(
         String::new()
     }
 }
-
-fn commit_relation(
-    repo: &git_stack::legacy::git::GitRepo,
-    local: git2::Oid,
-    remote: Option<git2::Oid>,
-) -> Option<(usize, usize)> {
-    let remote = remote?;
-    if local == remote {
-        return Some((0, 0));
-    }
-
-    let base = repo.merge_base(local, remote)?;
-    let local_count = repo.commit_count(base, local)?;
-    let remote_count = repo.commit_count(base, remote)?;
-    Some((local_count, remote_count))
-}
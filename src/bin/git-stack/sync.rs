@@ -1,4 +1,4 @@
-use itertools::Itertools;
+use is_terminal::IsTerminal;
 use proc_exit::prelude::*;
 
 /// Rebase local branches on top of pull remotes
@@ -7,6 +7,35 @@ pub struct SyncArgs {
     /// Don't actually switch
     #[arg(short = 'n', long)]
     dry_run: bool,
+
+    /// How `--dry-run` reports what it would do
+    #[arg(long, value_enum, default_value_t, requires = "dry_run")]
+    format: crate::ops::PlanFormat,
+
+    /// Branch to end on (default: the current branch or, if it gets deleted, the closest branch
+    /// on top of it)
+    #[arg(long, value_name = "BRANCH")]
+    switch: Option<String>,
+
+    /// Re-stack up to N independent stacks at once
+    #[arg(short = 'j', long, value_name = "N", default_value_t = 1)]
+    jobs: usize,
+
+    /// Delete branches whose commits were all merged, even as re-created commits like with a
+    /// rebase-merge
+    #[arg(long)]
+    prune_merged: bool,
+
+    /// Don't ask for confirmation before moving several branches or dropping commits
+    #[arg(short, long)]
+    yes: bool,
+
+    /// Only rewrite commits whose author name or email contains `PATTERN`
+    ///
+    /// Other commits are protected, along with everything they are built on, and what's built on
+    /// them stays on them.
+    #[arg(long, value_name = "PATTERN")]
+    author: Option<String>,
 }
 
 impl SyncArgs {
@@ -20,58 +49,32 @@ impl SyncArgs {
         }
     }
 
-    pub fn exec(&self) -> proc_exit::ExitResult {
+    pub fn exec(&self, global: &git_stack::config::RepoConfig) -> proc_exit::ExitResult {
         let stderr_palette = crate::ops::Palette::colored();
 
         let cwd = std::env::current_dir().with_code(proc_exit::sysexits::USAGE_ERR)?;
-        let repo = git2::Repository::discover(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
-        let mut repo = git_stack::git::GitRepo::new(repo);
-
-        let repo_config = git_stack::config::RepoConfig::from_all(repo.raw())
-            .with_code(proc_exit::sysexits::CONFIG_ERR)?;
-        repo.set_push_remote(repo_config.push_remote());
-        repo.set_pull_remote(repo_config.pull_remote());
-        let config = repo
-            .raw()
-            .config()
-            .with_code(proc_exit::sysexits::CONFIG_ERR)?;
-        repo.set_sign(
-            config
-                .get_bool("stack.gpgSign")
-                .or_else(|_| config.get_bool("commit.gpgSign"))
-                .unwrap_or_default(),
-        )
-        .with_code(proc_exit::Code::FAILURE)?;
+        let (mut repo, repo_config) = crate::ops::open_configured_repo(global)?;
+        crate::ops::configure_signing(&mut repo)?;
 
-        let protected = git_stack::git::ProtectedBranches::new(
-            repo_config.protected_branches().iter().map(|s| s.as_str()),
-        )
-        .with_code(proc_exit::sysexits::CONFIG_ERR)?;
-        let branches = git_stack::graph::BranchSet::from_repo(&repo, &protected)
-            .with_code(proc_exit::Code::FAILURE)?;
+        let branches = crate::ops::load_branches(&repo, &repo_config)?;
 
         let head = repo.head_commit();
         let head_id = head.id;
-        let mut head_branch = repo.head_branch();
-        let mut onto = crate::ops::resolve_implicit_base(
+        let head_branch = repo.head_branch();
+        let base_info = crate::ops::resolve_implicit_base_info(
             &repo,
             head_id,
             &branches,
             repo_config.auto_base_commit_count(),
         );
+        let mut onto = base_info.base;
         let mut base = crate::ops::resolve_base_from_onto(&repo, &onto);
-        let merge_base_oid = repo
-            .merge_base(base.id, head_id)
-            .ok_or_else(|| {
-                git2::Error::new(
-                    git2::ErrorCode::NotFound,
-                    git2::ErrorClass::Reference,
-                    format!("could not find base between {base} and HEAD"),
-                )
-            })
+        let merge_base_oid = crate::ops::resolve_merge_base(&repo, &base, head_id, "HEAD")
             .with_code(proc_exit::sysexits::USAGE_ERR)?;
         let mut branches = branches.descendants(&repo, merge_base_oid);
 
+        crate::ops::ensure_clean_state(repo.raw(), "walk commits", self.dry_run)?;
+
         let mut stash_id = None;
         if !self.dry_run {
             stash_id = git_stack::git::stash_push(&mut repo, "reword");
@@ -80,7 +83,7 @@ impl SyncArgs {
         let mut backed_up = false;
         {
             let stash_repo =
-                git2::Repository::discover(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
+                crate::ops::open_repo(&cwd).with_code(proc_exit::sysexits::USAGE_ERR)?;
             let stash_repo = git_branch_stash::GitRepo::new(stash_repo);
             let mut snapshots =
                 git_branch_stash::Stack::new(crate::ops::STASH_STACK_NAME, &stash_repo);
@@ -116,13 +119,15 @@ impl SyncArgs {
                 }
             }
         }
-        if let Some(branch) = &onto.branch {
-            if let Some(remote) = &branch.remote {
-                match crate::ops::git_fetch_upstream(remote, branch.name.as_str()) {
-                    Ok(_) => update_branches = true,
-                    Err(err) => {
-                        log::warn!("Skipping pull of `{}`, {}", branch, err);
-                    }
+        if let Some(upstream) = &base_info.upstream {
+            let remote = upstream
+                .remote
+                .as_deref()
+                .expect("upstreams are remote branches");
+            match crate::ops::git_fetch_upstream(remote, upstream.name.as_str()) {
+                Ok(_) => update_branches = true,
+                Err(err) => {
+                    log::warn!("Skipping pull of `{}`, {}", upstream, err);
                 }
             }
         }
@@ -131,52 +136,117 @@ impl SyncArgs {
             base.update(&repo).with_code(proc_exit::Code::FAILURE)?;
             onto.update(&repo).with_code(proc_exit::Code::FAILURE)?;
         }
+        if base.id == head_id && onto.id == head_id && branches.oids().all(|id| id == head_id) {
+            crate::ops::note_at_base(&base);
+            git_stack::git::stash_pop(&mut repo, stash_id);
+            return Ok(());
+        }
 
-        let protect_commit_count = repo_config.protect_commit_count();
-        let protect_commit_age = repo_config.protect_commit_age();
-        let protect_commit_time = std::time::SystemTime::now() - protect_commit_age;
-        let scripts = plan_changes(
+        let (scripts, summary, pickable_count) = plan_changes(
             &repo,
             &base,
             &onto,
             &branches,
-            protect_commit_count,
-            protect_commit_time,
+            &repo_config,
+            self.prune_merged,
+            self.author.as_deref(),
         )
         .with_code(proc_exit::Code::FAILURE)?;
-        let head_local_branch = head_branch.clone();
-        if let Some(head_local_branch) = head_local_branch.as_ref().and_then(|b| b.local_name()) {
-            for script in &scripts {
-                if script.is_branch_deleted(head_local_branch) {
-                    // Current branch is deleted, fallback to the local version of the onto branch,
-                    // if possible.
-                    if let Some(local_branch) = base
-                        .branch
-                        .as_ref()
-                        .map(|b| b.name.as_str())
-                        .and_then(|n| repo.find_local_branch(n))
-                    {
-                        head_branch = Some(local_branch);
-                    }
-                }
+        if let Err(err) = crate::ops::ensure_no_merges(
+            repo.raw(),
+            scripts.iter().flat_map(|s| s.stats().rewritten),
+            repo_config.rebase_merges(),
+        ) {
+            git_stack::git::stash_pop(&mut repo, stash_id);
+            return Err(proc_exit::sysexits::USAGE_ERR.with_message(err));
+        }
+        if self.format == crate::ops::PlanFormat::Json {
+            crate::ops::print_plan_json(&mut repo, &scripts, &summary.dropped)
+                .with_code(proc_exit::Code::FAILURE)?;
+            return Ok(());
+        }
+        if !self.dry_run && !summary.is_empty() {
+            if let Err(err) = crate::ops::confirm_stack_size(
+                pickable_count,
+                repo_config.max_stack_size(),
+                self.yes,
+            ) {
+                git_stack::git::stash_pop(&mut repo, stash_id);
+                return Err(proc_exit::sysexits::USAGE_ERR.with_message(err));
             }
         }
-
-        let mut success = true;
-        let mut executor = git_stack::rewrite::Executor::new(self.dry_run);
-        for script in scripts {
-            let results = executor.run(&mut repo, &script);
-            for (err, name, dependents) in results.iter() {
-                success = false;
-                log::error!("Failed to re-stack branch `{}`: {}", name, err);
-                if !dependents.is_empty() {
-                    log::error!("  Blocked dependents: {}", dependents.iter().join(", "));
-                }
+        if !self.yes && !self.dry_run && summary.is_destructive() && std::io::stdin().is_terminal()
+        {
+            crate::ops::print_restack_summary(
+                repo.raw(),
+                |id| repo.abbrev_id(id),
+                &summary,
+                stderr_palette,
+            );
+            if !crate::ops::confirm("re-stack?").with_code(proc_exit::Code::FAILURE)? {
+                git_stack::git::stash_pop(&mut repo, stash_id);
+                return Err(proc_exit::Code::FAILURE.with_message("aborted"));
             }
         }
+        let is_deleted = |name: &str| scripts.iter().any(|s| s.is_branch_deleted(name));
+        let head_local_branch = head_branch
+            .as_ref()
+            .and_then(|b| b.local_name())
+            .map(ToOwned::to_owned);
+        let switch_branch = if let Some(switch) = self.switch.as_deref() {
+            if repo.find_local_branch(switch).is_none() || is_deleted(switch) {
+                git_stack::git::stash_pop(&mut repo, stash_id);
+                return Err(proc_exit::sysexits::USAGE_ERR
+                    .with_message(format!("cannot switch to `{switch}`, it won't exist")));
+            }
+            Some(switch.to_owned())
+        } else if let Some(head_local_branch) = head_local_branch {
+            if !is_deleted(&head_local_branch) {
+                Some(head_local_branch)
+            } else {
+                let local_branches = repo.local_branches().collect::<Vec<_>>();
+                crate::ops::fallback_branch(
+                    &head_local_branch,
+                    head_id,
+                    local_branches
+                        .iter()
+                        .filter(|b| !is_deleted(&b.name))
+                        .map(|b| (b.name.as_str(), b.id)),
+                    |base_id, id| repo.commit_count(base_id, id),
+                    || {
+                        base.branch
+                            .as_ref()
+                            .map(|b| b.name.as_str())
+                            .filter(|n| repo.find_local_branch(n).is_some() && !is_deleted(n))
+                    },
+                )
+            }
+        } else {
+            None
+        };
+
+        let mut executor = git_stack::rewrite::Executor::new(self.dry_run);
+        executor.note_dropped(summary.dropped.iter().copied());
+        let open = repo.opener();
+        let open = || open().map(|repo| Box::new(repo) as Box<dyn git_stack::git::Repo>);
+        let results = executor.run_parallel(&mut repo, &open, &scripts, self.jobs);
+        let branches = scripts
+            .iter()
+            .flat_map(|script| script.stats().branches)
+            .collect::<Vec<_>>();
+        let mut restack_summary = crate::ops::RestackSummary::default();
+        restack_summary.record(branches.iter().map(String::as_str), &results);
         executor
-            .close(&mut repo, head_branch.as_ref().and_then(|b| b.local_name()))
+            .close(&mut repo, switch_branch.as_deref())
             .with_code(proc_exit::Code::FAILURE)?;
+        // `close` only re-attaches HEAD, it doesn't move to another branch
+        if let Some(switch_branch) = switch_branch.as_deref() {
+            let on_branch = repo.head_branch().map(|b| b.name).as_deref() == Some(switch_branch);
+            if !self.dry_run && !on_branch {
+                repo.switch_branch(switch_branch)
+                    .with_code(proc_exit::Code::FAILURE)?;
+            }
+        }
 
         git_stack::git::stash_pop(&mut repo, stash_id);
         if backed_up {
@@ -190,35 +260,54 @@ impl SyncArgs {
             );
         }
 
-        if success {
+        restack_summary.report();
+        if restack_summary.is_success() {
             Ok(())
         } else {
-            Err(proc_exit::Code::FAILURE.as_exit())
+            Err(crate::ops::CONFLICT_ERR.as_exit())
         }
     }
 }
 
 fn plan_changes(
-    repo: &dyn git_stack::git::Repo,
+    repo: &git_stack::git::GitRepo,
     base: &crate::ops::AnnotatedOid,
     onto: &crate::ops::AnnotatedOid,
     branches: &git_stack::graph::BranchSet,
-    protect_commit_count: Option<usize>,
-    protect_commit_time: std::time::SystemTime,
-) -> eyre::Result<Vec<git_stack::rewrite::Script>> {
+    repo_config: &git_stack::config::RepoConfig,
+    prune_merged: bool,
+    author: Option<&str>,
+) -> eyre::Result<(
+    Vec<git_stack::rewrite::Script>,
+    git_stack::graph::Summary,
+    usize,
+)> {
     log::trace!("Planning stack changes with base={}, onto={}", base, onto);
     let graphed_branches = branches.clone();
     let mut graph = git_stack::graph::Graph::from_branches(repo, graphed_branches)?;
     git_stack::graph::protect_branches(&mut graph);
-    if let Some(protect_commit_count) = protect_commit_count {
+    if let Some(protect_commit_count) = repo_config.protect_commit_count() {
         git_stack::graph::protect_large_branches(&mut graph, protect_commit_count);
     }
     let head_id = repo.head_commit().id;
+    let protect_commit_time = std::time::SystemTime::now() - repo_config.protect_commit_age();
     git_stack::graph::protect_stale_branches(&mut graph, repo, protect_commit_time, &[head_id]);
     if let Some(user) = repo.user() {
         git_stack::graph::protect_foreign_branches(&mut graph, repo, &user, &[]);
     }
+    if repo_config.default_action() == git_stack::config::DefaultAction::Protect {
+        let picked = repo_config
+            .picked()
+            .iter()
+            .filter_map(|rev| repo.resolve(rev).map(|c| c.id))
+            .collect::<Vec<_>>();
+        let user = repo.user();
+        git_stack::graph::protect_unclaimed_branches(&mut graph, repo, user.as_deref(), &picked);
+    }
+    crate::ops::protect_other_authors(&mut graph, repo, author);
 
+    let original = graph.clone();
+    let pickable_count = git_stack::graph::pickable_count(&original);
     let mut dropped_branches = Vec::new();
 
     let onto_id = onto.id;
@@ -241,8 +330,26 @@ fn plan_changes(
         &mut graph,
         pull_range.iter().map(|c| c.id),
     ));
+    let merged_branches =
+        git_stack::graph::find_merged_branches(&graph, repo, pull_range.iter().map(|c| c.id));
+    for branch in merged_branches {
+        let name = branch.local_name().expect("only local branches are found");
+        if prune_merged {
+            log::info!("Pruning `{}`, its commits were all merged", name);
+            dropped_branches.extend(git_stack::graph::delete_merged_branch(
+                &mut graph,
+                branch.id(),
+            ));
+        } else {
+            log::info!("`{}` looks merged, delete it with `--prune-merged`", name);
+        }
+    }
 
     log::trace!("Generating script");
     let scripts = git_stack::graph::to_scripts(&graph, dropped_branches);
-    Ok(scripts)
+    for script in &scripts {
+        script.validate()?;
+    }
+    let summary = git_stack::graph::summarize(repo, &original, &graph, &scripts);
+    Ok((scripts, summary, pickable_count))
 }
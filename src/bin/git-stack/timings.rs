@@ -0,0 +1,94 @@
+use std::time::Duration;
+use std::time::Instant;
+
+/// Phases of `git stack`, in the order they are reported
+pub const PHASES: [&str; 7] = [
+    "branches", "graph", "protect", "rebase", "fixup", "script", "execute",
+];
+
+/// Wall-clock time spent in each phase, for `--timings`
+///
+/// Phases run once per stack, so their times add up.  When disabled, nothing is recorded.
+#[derive(Default, Debug)]
+pub struct Timings {
+    enabled: bool,
+    phases: std::cell::RefCell<Vec<(&'static str, Duration)>>,
+    branches: std::cell::RefCell<Vec<(String, Duration)>>,
+}
+
+impl Timings {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            ..Default::default()
+        }
+    }
+
+    pub fn record(&self, phase: &'static str, elapsed: Duration) {
+        debug_assert!(PHASES.contains(&phase), "unknown phase {phase}");
+        if !self.enabled {
+            return;
+        }
+        let mut phases = self.phases.borrow_mut();
+        match phases.iter_mut().find(|(p, _)| *p == phase) {
+            Some((_, total)) => *total += elapsed,
+            None => phases.push((phase, elapsed)),
+        }
+    }
+
+    /// Time `f` as part of `phase`
+    pub fn time<T>(&self, phase: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.record(phase, start.elapsed());
+        result
+    }
+
+    /// Break `execute` down by the branch being re-stacked
+    pub fn record_branches<'b>(&self, timings: impl IntoIterator<Item = &'b (String, Duration)>) {
+        if !self.enabled {
+            return;
+        }
+        self.branches
+            .borrow_mut()
+            .extend(timings.into_iter().cloned());
+    }
+
+    /// Write the report to stderr, if enabled
+    pub fn report(&self) {
+        if !self.enabled {
+            return;
+        }
+        let palette = crate::ops::Palette::colored();
+        let phases = self.phases.borrow();
+        let branches = self.branches.borrow();
+        let width = PHASES
+            .iter()
+            .map(|p| p.len())
+            .chain(branches.iter().map(|(b, _)| b.len() + 2))
+            .max()
+            .unwrap_or(0);
+
+        let mut total = Duration::ZERO;
+        anstream::eprintln!("{}:", palette.info("timings"));
+        for phase in PHASES {
+            match phases.iter().find(|(p, _)| *p == phase) {
+                Some((_, elapsed)) => {
+                    total += *elapsed;
+                    anstream::eprintln!("  {phase:<width$}  {elapsed:.1?}");
+                }
+                None => anstream::eprintln!("  {phase:<width$}  {}", palette.hint("-")),
+            }
+            if phase == "execute" {
+                for (branch, elapsed) in branches.iter() {
+                    let branch = format!("  {branch}");
+                    anstream::eprintln!(
+                        "  {branch:<width$}  {}",
+                        palette.hint(format_args!("{elapsed:.1?}"))
+                    );
+                }
+            }
+        }
+        anstream::eprintln!("  {:<width$}  {total:.1?}", "total");
+    }
+}
@@ -3,36 +3,68 @@ use std::str::FromStr;
 #[derive(Default, Clone, Debug)]
 pub struct RepoConfig {
     pub editor: Option<String>,
+    pub sequence_editor: Option<String>,
+    pub cleanup: Option<CleanupMode>,
 
     pub protected_branches: Option<Vec<String>>,
     pub protect_commit_count: Option<usize>,
     pub protect_commit_age: Option<std::time::Duration>,
     pub auto_base_commit_count: Option<usize>,
+    pub max_stack_size: Option<usize>,
     pub stack: Option<Stack>,
     pub push_remote: Option<String>,
     pub pull_remote: Option<String>,
     pub show_format: Option<Format>,
     pub show_commits: Option<ShowCommits>,
     pub show_stacked: Option<bool>,
+    pub log_order: Option<LogOrder>,
     pub auto_fixup: Option<Fixup>,
     pub auto_repair: Option<bool>,
+    pub rebase_strategy: Option<RebaseStrategy>,
+    pub amend_stage: Option<AmendStage>,
+    pub backup: Option<bool>,
+    pub patch_id_skip_binary: Option<bool>,
+    pub abbrev: Option<usize>,
+    pub first_parent: Option<bool>,
+    pub rebase_merges: Option<bool>,
+    pub set_upstream_on_create: Option<bool>,
+    pub default_action: Option<DefaultAction>,
+    pub picked: Option<Vec<String>>,
+    pub hidden: Option<Vec<String>>,
+    pub pr_body_template: Option<String>,
 
     pub capacity: Option<usize>,
 }
 
 static CORE_EDITOR: &str = "core.editor";
+static SEQUENCE_EDITOR: &str = "sequence.editor";
+static COMMIT_CLEANUP: &str = "commit.cleanup";
 static PROTECTED_STACK_FIELD: &str = "stack.protected-branch";
 static PROTECT_COMMIT_COUNT: &str = "stack.protect-commit-count";
 static PROTECT_COMMIT_AGE: &str = "stack.protect-commit-age";
 static AUTO_BASE_COMMIT_COUNT: &str = "stack.auto-base-commit-count";
+static MAX_STACK_SIZE_FIELD: &str = "stack.max-stack-size";
 static STACK_FIELD: &str = "stack.stack";
 static PUSH_REMOTE_FIELD: &str = "stack.push-remote";
 static PULL_REMOTE_FIELD: &str = "stack.pull-remote";
 static FORMAT_FIELD: &str = "stack.show-format";
 static SHOW_COMMITS_FIELD: &str = "stack.show-commits";
 static STACKED_FIELD: &str = "stack.show-stacked";
+static LOG_ORDER_FIELD: &str = "stack.log.order";
 static AUTO_FIXUP_FIELD: &str = "stack.auto-fixup";
 static AUTO_REPAIR_FIELD: &str = "stack.auto-repair";
+static REBASE_STRATEGY_FIELD: &str = "stack.rebase.strategy";
+static AMEND_STAGE_FIELD: &str = "stack.amend.stage";
+static BACKUP_FIELD: &str = "stack.backup";
+static PATCH_ID_SKIP_BINARY_FIELD: &str = "stack.patch-id-skip-binary";
+static ABBREV_FIELD: &str = "stack.abbrev";
+static FIRST_PARENT_FIELD: &str = "stack.first-parent";
+static REBASE_MERGES_FIELD: &str = "stack.rebase-merges";
+static SET_UPSTREAM_ON_CREATE_FIELD: &str = "stack.push.set-upstream-on-create";
+static DEFAULT_ACTION_FIELD: &str = "stack.default-action";
+static PICKED_FIELD: &str = "stack.picked";
+static HIDDEN_FIELD: &str = "stack.hidden";
+static PR_BODY_TEMPLATE_FIELD: &str = "stack.pr-body-template";
 static BACKUP_CAPACITY_FIELD: &str = "branch-stash.capacity";
 
 #[cfg(windows)]
@@ -44,6 +76,8 @@ static DEFAULT_PROTECT_COMMIT_COUNT: usize = 50;
 static DEFAULT_PROTECT_COMMIT_AGE: std::time::Duration =
     std::time::Duration::from_secs(60 * 60 * 24 * 14);
 static DEFAULT_AUTO_BASE_COMMIT_COUNT: usize = 500;
+static DEFAULT_MAX_STACK_SIZE: usize = 100;
+static DEFAULT_PR_BODY_TEMPLATE: &str = "Part {position} of {total}: {prev} | {next}\n\n{commits}";
 const DEFAULT_CAPACITY: usize = 30;
 
 impl RepoConfig {
@@ -57,22 +91,63 @@ impl RepoConfig {
             }
         };
         let config = Self::from_defaults_internal(default_config.as_ref());
-        let config = if let Some(default_config) = default_config.as_ref() {
-            config.update(Self::from_gitconfig(default_config))
-        } else {
-            config
-        };
+        let config = config.update(Self::from_explicit_internal(repo, default_config.as_ref())?);
+        Ok(config)
+    }
+
+    /// Only what the user has set, without filling in defaults
+    pub fn from_explicit(repo: &git2::Repository) -> eyre::Result<Self> {
+        let default_config = git2::Config::open_default().ok();
+        Self::from_explicit_internal(repo, default_config.as_ref())
+    }
+
+    fn from_explicit_internal(
+        repo: &git2::Repository,
+        default_config: Option<&git2::Config>,
+    ) -> eyre::Result<Self> {
+        let config = Self::from_user(repo)
+            .or_else(|| default_config.map(Self::from_gitconfig))
+            .unwrap_or_default();
         let config = config.update(Self::from_workdir(repo)?);
         let config = config.update(Self::from_repo(repo)?);
         let config = config.update(Self::from_env());
         Ok(config)
     }
 
+    /// The system, XDG and global config, as seen from `repo`
+    ///
+    /// Unlike [`git2::Config::open_default`], this resolves conditional includes like
+    /// `[includeIf "onbranch:feature/*"]` against `repo`'s `HEAD`.
+    fn from_user(repo: &git2::Repository) -> Option<Self> {
+        let config = repo.config().ok()?;
+        let levels = [
+            git2::ConfigLevel::ProgramData,
+            git2::ConfigLevel::System,
+            git2::ConfigLevel::XDG,
+            git2::ConfigLevel::Global,
+        ];
+        let user = levels
+            .into_iter()
+            .filter_map(|level| config.open_level(level).ok())
+            .fold(Self::default(), |user, level| {
+                user.update(Self::from_gitconfig(&level))
+            });
+        Some(user)
+    }
+
+    /// The repo's own config (`.git/config`)
+    ///
+    /// Conditional includes like `[includeIf "onbranch:feature/*"]` are resolved against `HEAD`.
     pub fn from_repo(repo: &git2::Repository) -> eyre::Result<Self> {
         let config_path = git_dir_config(repo);
         log::trace!("Loading {}", config_path.display());
         if config_path.exists() {
-            match git2::Config::open(&config_path) {
+            // Only a config loaded through the repo knows the branch for `onbranch:`
+            let config = repo
+                .config()
+                .and_then(|config| config.open_level(git2::ConfigLevel::Local))
+                .or_else(|_| git2::Config::open(&config_path));
+            match config {
                 Ok(config) => Ok(Self::from_gitconfig(&config)),
                 Err(err) => {
                     log::debug!("Failed to load git config: {}", err);
@@ -84,10 +159,11 @@ impl RepoConfig {
         }
     }
 
+    /// The `.gitconfig` at the root of the working tree, if there is one
     pub fn from_workdir(repo: &git2::Repository) -> eyre::Result<Self> {
-        let workdir = repo
-            .workdir()
-            .ok_or_else(|| eyre::eyre!("Cannot read config in bare repository."))?;
+        let Some(workdir) = repo.workdir() else {
+            return Ok(Default::default());
+        };
         let config_path = workdir.join(".gitconfig");
         log::trace!("Loading {}", config_path.display());
         if config_path.exists() {
@@ -115,6 +191,7 @@ impl RepoConfig {
         ));
 
         config.editor = std::env::var("GIT_EDITOR").ok();
+        config.sequence_editor = std::env::var("GIT_SEQUENCE_EDITOR").ok();
 
         config
     }
@@ -130,6 +207,14 @@ impl RepoConfig {
                 if let Some(value) = value {
                     config.editor = Some(value.into_owned());
                 }
+            } else if key == SEQUENCE_EDITOR {
+                if let Some(value) = value {
+                    config.sequence_editor = Some(value.into_owned());
+                }
+            } else if key == COMMIT_CLEANUP {
+                if let Some(value) = value.as_ref().and_then(|v| FromStr::from_str(v).ok()) {
+                    config.cleanup = Some(value);
+                }
             } else if key == PROTECTED_STACK_FIELD {
                 if let Some(value) = value {
                     config
@@ -152,6 +237,10 @@ impl RepoConfig {
                 if let Some(value) = value.as_ref().and_then(|v| FromStr::from_str(v).ok()) {
                     config.auto_base_commit_count = Some(value);
                 }
+            } else if key == MAX_STACK_SIZE_FIELD {
+                if let Some(value) = value.as_ref().and_then(|v| FromStr::from_str(v).ok()) {
+                    config.max_stack_size = Some(value);
+                }
             } else if key == STACK_FIELD {
                 if let Some(value) = value.as_ref().and_then(|v| FromStr::from_str(v).ok()) {
                     config.stack = Some(value);
@@ -174,12 +263,58 @@ impl RepoConfig {
                 }
             } else if key == STACKED_FIELD {
                 config.show_stacked = Some(value.as_ref().map(|v| v == "true").unwrap_or(true));
+            } else if key == LOG_ORDER_FIELD {
+                if let Some(value) = value.as_ref().and_then(|v| FromStr::from_str(v).ok()) {
+                    config.log_order = Some(value);
+                }
             } else if key == AUTO_FIXUP_FIELD {
                 if let Some(value) = value.as_ref().and_then(|v| FromStr::from_str(v).ok()) {
                     config.auto_fixup = Some(value);
                 }
             } else if key == AUTO_REPAIR_FIELD {
                 config.auto_repair = Some(value.as_ref().map(|v| v == "true").unwrap_or(true));
+            } else if key == REBASE_STRATEGY_FIELD {
+                if let Some(value) = value.as_ref().and_then(|v| FromStr::from_str(v).ok()) {
+                    config.rebase_strategy = Some(value);
+                }
+            } else if key == AMEND_STAGE_FIELD {
+                if let Some(value) = value.as_ref().and_then(|v| FromStr::from_str(v).ok()) {
+                    config.amend_stage = Some(value);
+                }
+            } else if key == BACKUP_FIELD {
+                config.backup = parse_bool(value.as_deref());
+            } else if key == PATCH_ID_SKIP_BINARY_FIELD {
+                config.patch_id_skip_binary = parse_bool(value.as_deref());
+            } else if key == ABBREV_FIELD {
+                config.abbrev = value.as_deref().and_then(|s| s.parse::<usize>().ok());
+            } else if key == FIRST_PARENT_FIELD {
+                config.first_parent = parse_bool(value.as_deref());
+            } else if key == REBASE_MERGES_FIELD {
+                config.rebase_merges = parse_bool(value.as_deref());
+            } else if key == SET_UPSTREAM_ON_CREATE_FIELD {
+                config.set_upstream_on_create = parse_bool(value.as_deref());
+            } else if key == DEFAULT_ACTION_FIELD {
+                if let Some(value) = value.as_ref().and_then(|v| FromStr::from_str(v).ok()) {
+                    config.default_action = Some(value);
+                }
+            } else if key == PICKED_FIELD {
+                if let Some(value) = value {
+                    config
+                        .picked
+                        .get_or_insert_with(Vec::new)
+                        .push(value.into_owned());
+                }
+            } else if key == HIDDEN_FIELD {
+                if let Some(value) = value {
+                    config
+                        .hidden
+                        .get_or_insert_with(Vec::new)
+                        .push(value.into_owned());
+                }
+            } else if key == PR_BODY_TEMPLATE_FIELD {
+                if let Some(value) = value {
+                    config.pr_body_template = Some(value.into_owned());
+                }
             } else if key == BACKUP_CAPACITY_FIELD {
                 config.capacity = value.as_deref().and_then(|s| s.parse::<usize>().ok());
             } else {
@@ -214,13 +349,17 @@ impl RepoConfig {
         conf.protect_commit_count = Some(conf.protect_commit_count().unwrap_or(0));
         conf.protect_commit_age = Some(conf.protect_commit_age());
         conf.auto_base_commit_count = Some(conf.auto_base_commit_count().unwrap_or(0));
+        conf.max_stack_size = Some(conf.max_stack_size().unwrap_or(0));
         conf.stack = Some(conf.stack());
         conf.push_remote = Some(conf.push_remote().to_owned());
         conf.pull_remote = Some(conf.pull_remote().to_owned());
         conf.show_format = Some(conf.show_format());
         conf.show_commits = Some(conf.show_commits());
         conf.show_stacked = Some(conf.show_stacked());
+        conf.log_order = Some(conf.log_order());
         conf.auto_fixup = Some(conf.auto_fixup());
+        conf.rebase_strategy = Some(conf.rebase_strategy());
+        conf.amend_stage = Some(conf.amend_stage());
         conf.capacity = Some(DEFAULT_CAPACITY);
 
         let mut protected_branches: Vec<String> = Vec::new();
@@ -240,25 +379,13 @@ impl RepoConfig {
 
     pub fn from_gitconfig(config: &git2::Config) -> Self {
         let editor = config.get_string(CORE_EDITOR).ok();
+        let sequence_editor = config.get_string(SEQUENCE_EDITOR).ok();
+        let cleanup = config
+            .get_string(COMMIT_CLEANUP)
+            .ok()
+            .and_then(|s| FromStr::from_str(&s).ok());
 
-        let protected_branches = config
-            .multivar(PROTECTED_STACK_FIELD, None)
-            .map(|entries| {
-                let mut protected_branches = Vec::new();
-                entries
-                    .for_each(|entry| {
-                        if let Some(value) = entry.value() {
-                            protected_branches.push(value.to_owned());
-                        }
-                    })
-                    .unwrap();
-                if protected_branches.is_empty() {
-                    None
-                } else {
-                    Some(protected_branches)
-                }
-            })
-            .unwrap_or(None);
+        let protected_branches = get_multivar(config, PROTECTED_STACK_FIELD);
 
         let protect_commit_count = config
             .get_i64(PROTECT_COMMIT_COUNT)
@@ -273,6 +400,10 @@ impl RepoConfig {
             .get_i64(AUTO_BASE_COMMIT_COUNT)
             .ok()
             .map(|i| i.max(0) as usize);
+        let max_stack_size = config
+            .get_i64(MAX_STACK_SIZE_FIELD)
+            .ok()
+            .map(|i| i.max(0) as usize);
 
         let push_remote = config
             .get_string(PUSH_REMOTE_FIELD)
@@ -297,6 +428,11 @@ impl RepoConfig {
 
         let show_stacked = config.get_bool(STACKED_FIELD).ok();
 
+        let log_order = config
+            .get_string(LOG_ORDER_FIELD)
+            .ok()
+            .and_then(|s| FromStr::from_str(&s).ok());
+
         let auto_fixup = config
             .get_string(AUTO_FIXUP_FIELD)
             .ok()
@@ -304,6 +440,37 @@ impl RepoConfig {
 
         let auto_repair = config.get_bool(AUTO_REPAIR_FIELD).ok();
 
+        let rebase_strategy = config
+            .get_string(REBASE_STRATEGY_FIELD)
+            .ok()
+            .and_then(|s| FromStr::from_str(&s).ok());
+
+        let amend_stage = config
+            .get_string(AMEND_STAGE_FIELD)
+            .ok()
+            .and_then(|s| FromStr::from_str(&s).ok());
+
+        let backup = config.get_bool(BACKUP_FIELD).ok();
+
+        let patch_id_skip_binary = config.get_bool(PATCH_ID_SKIP_BINARY_FIELD).ok();
+
+        let abbrev = config.get_i64(ABBREV_FIELD).map(|i| i as usize).ok();
+
+        let first_parent = config.get_bool(FIRST_PARENT_FIELD).ok();
+        let rebase_merges = config.get_bool(REBASE_MERGES_FIELD).ok();
+        let set_upstream_on_create = config.get_bool(SET_UPSTREAM_ON_CREATE_FIELD).ok();
+
+        let default_action = config
+            .get_string(DEFAULT_ACTION_FIELD)
+            .ok()
+            .and_then(|s| FromStr::from_str(&s).ok());
+
+        let picked = get_multivar(config, PICKED_FIELD);
+
+        let hidden = get_multivar(config, HIDDEN_FIELD);
+
+        let pr_body_template = config.get_string(PR_BODY_TEMPLATE_FIELD).ok();
+
         let capacity = config
             .get_i64(BACKUP_CAPACITY_FIELD)
             .map(|i| i as usize)
@@ -311,18 +478,34 @@ impl RepoConfig {
 
         Self {
             editor,
+            sequence_editor,
+            cleanup,
             protected_branches,
             protect_commit_count,
             protect_commit_age,
             auto_base_commit_count,
+            max_stack_size,
             push_remote,
             pull_remote,
             stack,
             show_format,
             show_commits,
             show_stacked,
+            log_order,
             auto_fixup,
             auto_repair,
+            rebase_strategy,
+            amend_stage,
+            backup,
+            patch_id_skip_binary,
+            abbrev,
+            first_parent,
+            rebase_merges,
+            set_upstream_on_create,
+            default_action,
+            picked,
+            hidden,
+            pr_body_template,
 
             capacity,
         }
@@ -337,6 +520,19 @@ impl RepoConfig {
         Ok(())
     }
 
+    /// Write to the `.gitconfig` at the root of the working tree, shared by committing it
+    pub fn write_workdir(&self, repo: &git2::Repository) -> eyre::Result<()> {
+        let workdir = repo
+            .workdir()
+            .ok_or_else(|| eyre::eyre!("Cannot write config in bare repository."))?;
+        let config_path = workdir.join(".gitconfig");
+        log::trace!("Loading {}", config_path.display());
+        let mut config = git2::Config::open(&config_path)?;
+        log::info!("Writing {}", config_path.display());
+        self.to_gitconfig(&mut config)?;
+        Ok(())
+    }
+
     pub fn to_gitconfig(&self, config: &mut git2::Config) -> eyre::Result<()> {
         if let Some(protected_branches) = self.protected_branches.as_ref() {
             // Ignore errors if there aren't keys to remove
@@ -345,11 +541,36 @@ impl RepoConfig {
                 config.set_multivar(PROTECTED_STACK_FIELD, "^$", branch)?;
             }
         }
+        if let Some(picked) = self.picked.as_ref() {
+            // Ignore errors if there aren't keys to remove
+            let _ = config.remove_multivar(PICKED_FIELD, ".*");
+            for id in picked {
+                config.set_multivar(PICKED_FIELD, "^$", id)?;
+            }
+        }
+        if let Some(hidden) = self.hidden.as_ref() {
+            // Ignore errors if there aren't keys to remove
+            let _ = config.remove_multivar(HIDDEN_FIELD, ".*");
+            for branch in hidden {
+                config.set_multivar(HIDDEN_FIELD, "^$", branch)?;
+            }
+        }
+        if let Some(push_remote) = self.push_remote.as_deref() {
+            config.set_str(PUSH_REMOTE_FIELD, push_remote)?;
+        }
+        if let Some(pull_remote) = self.pull_remote.as_deref() {
+            config.set_str(PULL_REMOTE_FIELD, pull_remote)?;
+        }
+        if let Some(pr_body_template) = self.pr_body_template.as_deref() {
+            config.set_str(PR_BODY_TEMPLATE_FIELD, pr_body_template)?;
+        }
         Ok(())
     }
 
     pub fn update(mut self, other: Self) -> Self {
         self.editor = other.editor.or(self.editor);
+        self.sequence_editor = other.sequence_editor.or(self.sequence_editor);
+        self.cleanup = other.cleanup.or(self.cleanup);
         match (&mut self.protected_branches, other.protected_branches) {
             (Some(lhs), Some(rhs)) => lhs.extend(rhs),
             (None, Some(rhs)) => self.protected_branches = Some(rhs),
@@ -358,14 +579,36 @@ impl RepoConfig {
         self.protect_commit_count = other.protect_commit_count.or(self.protect_commit_count);
         self.protect_commit_age = other.protect_commit_age.or(self.protect_commit_age);
         self.auto_base_commit_count = other.auto_base_commit_count.or(self.auto_base_commit_count);
+        self.max_stack_size = other.max_stack_size.or(self.max_stack_size);
         self.push_remote = other.push_remote.or(self.push_remote);
         self.pull_remote = other.pull_remote.or(self.pull_remote);
         self.stack = other.stack.or(self.stack);
         self.show_format = other.show_format.or(self.show_format);
         self.show_commits = other.show_commits.or(self.show_commits);
         self.show_stacked = other.show_stacked.or(self.show_stacked);
+        self.log_order = other.log_order.or(self.log_order);
         self.auto_fixup = other.auto_fixup.or(self.auto_fixup);
         self.auto_repair = other.auto_repair.or(self.auto_repair);
+        self.rebase_strategy = other.rebase_strategy.or(self.rebase_strategy);
+        self.amend_stage = other.amend_stage.or(self.amend_stage);
+        self.backup = other.backup.or(self.backup);
+        self.patch_id_skip_binary = other.patch_id_skip_binary.or(self.patch_id_skip_binary);
+        self.abbrev = other.abbrev.or(self.abbrev);
+        self.first_parent = other.first_parent.or(self.first_parent);
+        self.rebase_merges = other.rebase_merges.or(self.rebase_merges);
+        self.set_upstream_on_create = other.set_upstream_on_create.or(self.set_upstream_on_create);
+        self.default_action = other.default_action.or(self.default_action);
+        match (&mut self.picked, other.picked) {
+            (Some(lhs), Some(rhs)) => lhs.extend(rhs),
+            (None, Some(rhs)) => self.picked = Some(rhs),
+            (_, _) => (),
+        }
+        match (&mut self.hidden, other.hidden) {
+            (Some(lhs), Some(rhs)) => lhs.extend(rhs),
+            (None, Some(rhs)) => self.hidden = Some(rhs),
+            (_, _) => (),
+        }
+        self.pr_body_template = other.pr_body_template.or(self.pr_body_template);
         self.capacity = other.capacity.or(self.capacity);
 
         self
@@ -375,6 +618,20 @@ impl RepoConfig {
         self.editor.as_deref().unwrap_or(DEFAULT_CORE_EDITOR)
     }
 
+    /// Editor for plans (todo lists) rather than commit messages, like `git rebase -i`
+    ///
+    /// Resolved from `GIT_SEQUENCE_EDITOR`, then `sequence.editor`, then [`RepoConfig::editor`].
+    pub fn sequence_editor(&self) -> &str {
+        self.sequence_editor
+            .as_deref()
+            .unwrap_or_else(|| self.editor())
+    }
+
+    /// How edited commit messages are cleaned up, like `git commit --cleanup`
+    pub fn cleanup(&self) -> CleanupMode {
+        self.cleanup.unwrap_or_default()
+    }
+
     pub fn protected_branches(&self) -> &[String] {
         self.protected_branches.as_deref().unwrap_or(&[])
     }
@@ -398,6 +655,11 @@ impl RepoConfig {
         (auto_base_commit_count != 0).then_some(auto_base_commit_count)
     }
 
+    pub fn max_stack_size(&self) -> Option<usize> {
+        let max_stack_size = self.max_stack_size.unwrap_or(DEFAULT_MAX_STACK_SIZE);
+        (max_stack_size != 0).then_some(max_stack_size)
+    }
+
     pub fn push_remote(&self) -> &str {
         self.push_remote.as_deref().unwrap_or("origin")
     }
@@ -424,6 +686,10 @@ impl RepoConfig {
         self.show_stacked.unwrap_or(true)
     }
 
+    pub fn log_order(&self) -> LogOrder {
+        self.log_order.unwrap_or_default()
+    }
+
     pub fn auto_fixup(&self) -> Fixup {
         self.auto_fixup.unwrap_or_default()
     }
@@ -432,6 +698,68 @@ impl RepoConfig {
         self.auto_repair.unwrap_or(true)
     }
 
+    pub fn rebase_strategy(&self) -> RebaseStrategy {
+        self.rebase_strategy.unwrap_or_default()
+    }
+
+    pub fn amend_stage(&self) -> AmendStage {
+        self.amend_stage.unwrap_or_default()
+    }
+
+    pub fn backup(&self) -> bool {
+        self.backup.unwrap_or(true)
+    }
+
+    /// Leave binary files out of patch-ids, trading exact matches for speed on large blobs
+    pub fn patch_id_skip_binary(&self) -> bool {
+        self.patch_id_skip_binary.unwrap_or(false)
+    }
+
+    /// How many hex digits to show of commit ids, `None` to defer to `core.abbrev`
+    pub fn abbrev(&self) -> Option<usize> {
+        self.abbrev
+    }
+
+    /// Only follow first parents when finding bases and the commits to stack
+    ///
+    /// This is implied by [`RepoConfig::rebase_merges`], keeping the merged-in side out of the
+    /// stack.
+    pub fn first_parent(&self) -> bool {
+        self.first_parent.unwrap_or(false) || self.rebase_merges()
+    }
+
+    /// Recreate merge commits in the stack rather than refusing to rewrite them
+    pub fn rebase_merges(&self) -> bool {
+        self.rebase_merges.unwrap_or(false)
+    }
+
+    /// Have `--push` set the upstream of branches it pushes for the first time
+    pub fn set_upstream_on_create(&self) -> bool {
+        self.set_upstream_on_create.unwrap_or(true)
+    }
+
+    /// What commits are, unless something else protects them
+    pub fn default_action(&self) -> DefaultAction {
+        self.default_action.unwrap_or_default()
+    }
+
+    /// Branches opted in to being rewritten with `git stack pick`
+    pub fn picked(&self) -> &[String] {
+        self.picked.as_deref().unwrap_or(&[])
+    }
+
+    /// Branches whose stacks `git stack hide` left out of the view
+    pub fn hidden(&self) -> &[String] {
+        self.hidden.as_deref().unwrap_or(&[])
+    }
+
+    /// Body for each PR in a stack, see `git stack pr-body`
+    pub fn pr_body_template(&self) -> &str {
+        self.pr_body_template
+            .as_deref()
+            .unwrap_or(DEFAULT_PR_BODY_TEMPLATE)
+    }
+
     pub fn capacity(&self) -> Option<usize> {
         let capacity = self.capacity.unwrap_or(DEFAULT_CAPACITY);
         (capacity != 0).then_some(capacity)
@@ -447,6 +775,20 @@ impl std::fmt::Display for RepoConfig {
             CORE_EDITOR.split_once('.').unwrap().1,
             self.editor()
         )?;
+        writeln!(f, "[{}]", SEQUENCE_EDITOR.split_once('.').unwrap().0)?;
+        writeln!(
+            f,
+            "\t{}={}",
+            SEQUENCE_EDITOR.split_once('.').unwrap().1,
+            self.sequence_editor()
+        )?;
+        writeln!(f, "[{}]", COMMIT_CLEANUP.split_once('.').unwrap().0)?;
+        writeln!(
+            f,
+            "\t{}={}",
+            COMMIT_CLEANUP.split_once('.').unwrap().1,
+            self.cleanup()
+        )?;
         writeln!(f, "[{}]", STACK_FIELD.split_once('.').unwrap().0)?;
         for branch in self.protected_branches() {
             writeln!(
@@ -474,6 +816,12 @@ impl std::fmt::Display for RepoConfig {
             AUTO_BASE_COMMIT_COUNT.split_once('.').unwrap().1,
             self.auto_base_commit_count().unwrap_or(0)
         )?;
+        writeln!(
+            f,
+            "\t{}={}",
+            MAX_STACK_SIZE_FIELD.split_once('.').unwrap().1,
+            self.max_stack_size().unwrap_or(0)
+        )?;
         writeln!(
             f,
             "\t{}={}",
@@ -522,6 +870,87 @@ impl std::fmt::Display for RepoConfig {
             AUTO_REPAIR_FIELD.split_once('.').unwrap().1,
             self.auto_repair()
         )?;
+        writeln!(
+            f,
+            "\t{}={}",
+            BACKUP_FIELD.split_once('.').unwrap().1,
+            self.backup()
+        )?;
+        writeln!(
+            f,
+            "\t{}={}",
+            PATCH_ID_SKIP_BINARY_FIELD.split_once('.').unwrap().1,
+            self.patch_id_skip_binary()
+        )?;
+        if let Some(abbrev) = self.abbrev() {
+            writeln!(
+                f,
+                "\t{}={}",
+                ABBREV_FIELD.split_once('.').unwrap().1,
+                abbrev
+            )?;
+        }
+        if self.first_parent() && !self.first_parent.unwrap_or(false) {
+            writeln!(f, "\t# implied by {}", REBASE_MERGES_FIELD)?;
+        }
+        writeln!(
+            f,
+            "\t{}={}",
+            FIRST_PARENT_FIELD.split_once('.').unwrap().1,
+            self.first_parent()
+        )?;
+        writeln!(
+            f,
+            "\t{}={}",
+            REBASE_MERGES_FIELD.split_once('.').unwrap().1,
+            self.rebase_merges()
+        )?;
+        writeln!(
+            f,
+            "\t{}={}",
+            DEFAULT_ACTION_FIELD.split_once('.').unwrap().1,
+            self.default_action()
+        )?;
+        for picked in self.picked() {
+            writeln!(
+                f,
+                "\t{}={}",
+                PICKED_FIELD.split_once('.').unwrap().1,
+                picked
+            )?;
+        }
+        for hidden in self.hidden() {
+            writeln!(
+                f,
+                "\t{}={}",
+                HIDDEN_FIELD.split_once('.').unwrap().1,
+                hidden
+            )?;
+        }
+        if let Some(pr_body_template) = self.pr_body_template.as_deref() {
+            writeln!(
+                f,
+                "\t{}={:?}",
+                PR_BODY_TEMPLATE_FIELD.split_once('.').unwrap().1,
+                pr_body_template
+            )?;
+        }
+        let (rebase_section, rebase_key) = REBASE_STRATEGY_FIELD.rsplit_once('.').unwrap();
+        let (section, subsection) = rebase_section.split_once('.').unwrap();
+        writeln!(f, "[{section} \"{subsection}\"]")?;
+        writeln!(f, "\t{}={}", rebase_key, self.rebase_strategy())?;
+        let (amend_section, amend_key) = AMEND_STAGE_FIELD.rsplit_once('.').unwrap();
+        let (section, subsection) = amend_section.split_once('.').unwrap();
+        writeln!(f, "[{section} \"{subsection}\"]")?;
+        writeln!(f, "\t{}={}", amend_key, self.amend_stage())?;
+        let (log_section, log_key) = LOG_ORDER_FIELD.rsplit_once('.').unwrap();
+        let (section, subsection) = log_section.split_once('.').unwrap();
+        writeln!(f, "[{section} \"{subsection}\"]")?;
+        writeln!(f, "\t{}={}", log_key, self.log_order())?;
+        let (push_section, push_key) = SET_UPSTREAM_ON_CREATE_FIELD.rsplit_once('.').unwrap();
+        let (section, subsection) = push_section.split_once('.').unwrap();
+        writeln!(f, "[{section} \"{subsection}\"]")?;
+        writeln!(f, "\t{}={}", push_key, self.set_upstream_on_create())?;
         writeln!(f, "[{}]", BACKUP_CAPACITY_FIELD.split_once('.').unwrap().0)?;
         writeln!(
             f,
@@ -533,11 +962,64 @@ impl std::fmt::Display for RepoConfig {
     }
 }
 
+/// Parse a git boolean, like `git config --type=bool`
+///
+/// `None` is a key without `= value`, which git reads as `true`.  Returns `None` for anything git
+/// would reject.
+pub fn parse_bool(value: Option<&str>) -> Option<bool> {
+    let Some(value) = value else {
+        return Some(true);
+    };
+    let value = value.trim();
+    if ["true", "yes", "on"]
+        .iter()
+        .any(|t| value.eq_ignore_ascii_case(t))
+    {
+        Some(true)
+    } else if value.is_empty()
+        || ["false", "no", "off"]
+            .iter()
+            .any(|f| value.eq_ignore_ascii_case(f))
+    {
+        Some(false)
+    } else {
+        parse_int(value).map(|i| i != 0)
+    }
+}
+
+/// Parse a git integer, like `git config --type=int`, including `k` / `m` / `g` suffixes
+pub fn parse_int(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (digits, scale) = match value.chars().last()?.to_ascii_lowercase() {
+        'k' => (&value[..value.len() - 1], 1024),
+        'm' => (&value[..value.len() - 1], 1024 * 1024),
+        'g' => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+        _ => (value, 1),
+    };
+    digits.parse::<i64>().ok()?.checked_mul(scale)
+}
+
+/// All values of a multivar, `None` when it isn't set
+fn get_multivar(config: &git2::Config, key: &str) -> Option<Vec<String>> {
+    let mut values = Vec::new();
+    config
+        .multivar(key, None)
+        .ok()?
+        .for_each(|entry| {
+            if let Some(value) = entry.value() {
+                values.push(value.to_owned());
+            }
+        })
+        .ok()?;
+    (!values.is_empty()).then_some(values)
+}
+
 fn git_dir_config(repo: &git2::Repository) -> std::path::PathBuf {
     repo.path().join("config")
 }
 
-fn default_branch(config: &git2::Config) -> &str {
+/// Branch name `git init` starts with, from `init.defaultBranch`
+pub fn default_branch(config: &git2::Config) -> &str {
     config.get_str("init.defaultBranch").ok().unwrap_or("main")
 }
 
@@ -549,6 +1031,8 @@ pub enum Format {
     List,
     /// Render a branch branch
     Graph,
+    /// Tab-separated `oid action branches pushable` for each commit, stable for scripts
+    Porcelain,
     /// Internal data for debugging
     Debug,
 }
@@ -662,6 +1146,46 @@ impl Default for Stack {
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogOrder {
+    /// Order sibling stacks by their structure
+    Topo,
+    /// Show the most recently committed stack first
+    Date,
+    /// Order sibling stacks by branch name
+    Name,
+}
+
+impl std::fmt::Display for LogOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use clap::ValueEnum;
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+impl std::str::FromStr for LogOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use clap::ValueEnum;
+        for variant in Self::value_variants() {
+            if variant.to_possible_value().unwrap().matches(s, false) {
+                return Ok(*variant);
+            }
+        }
+        Err(format!("Invalid variant: {s}"))
+    }
+}
+
+impl Default for LogOrder {
+    fn default() -> Self {
+        Self::Topo
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
 pub enum Fixup {
     /// No special processing
@@ -707,3 +1231,141 @@ impl Default for Fixup {
         Self::Move
     }
 }
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum CleanupMode {
+    /// Drop `#` comment lines, trailing whitespace and surrounding or repeated blank lines
+    #[default]
+    #[value(alias = "default")]
+    Strip,
+    /// Like `strip` but keep `#` comment lines
+    #[value(alias = "scissors")]
+    Whitespace,
+    /// Keep the message exactly as edited
+    Verbatim,
+}
+
+impl std::fmt::Display for CleanupMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use clap::ValueEnum;
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+impl std::str::FromStr for CleanupMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use clap::ValueEnum;
+        for variant in Self::value_variants() {
+            if variant.to_possible_value().unwrap().matches(s, false) {
+                return Ok(*variant);
+            }
+        }
+        Err(format!("Invalid variant: {s}"))
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum RebaseStrategy {
+    /// Replay each commit with a cherry-pick
+    #[default]
+    CherryPick,
+    /// Replay each commit by merging trees, without touching the index
+    MergeTree,
+}
+
+impl std::fmt::Display for RebaseStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use clap::ValueEnum;
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+impl std::str::FromStr for RebaseStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use clap::ValueEnum;
+        for variant in Self::value_variants() {
+            if variant.to_possible_value().unwrap().matches(s, false) {
+                return Ok(*variant);
+            }
+        }
+        Err(format!("Invalid variant: {s}"))
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum AmendStage {
+    /// Only amend what is already staged
+    #[default]
+    Index,
+    /// Stage changes to all tracked files, like `git stack amend --all`
+    #[value(alias = "tracked")]
+    All,
+}
+
+impl std::fmt::Display for AmendStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use clap::ValueEnum;
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+impl std::str::FromStr for AmendStage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use clap::ValueEnum;
+        for variant in Self::value_variants() {
+            if variant.to_possible_value().unwrap().matches(s, false) {
+                return Ok(*variant);
+            }
+        }
+        Err(format!("Invalid variant: {s}"))
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum DefaultAction {
+    /// Rewrite commits unless something protects them
+    #[default]
+    Pick,
+    /// Protect commits unless they are yours and unpushed, or their branch was picked with
+    /// `git stack pick`
+    Protect,
+}
+
+impl std::fmt::Display for DefaultAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use clap::ValueEnum;
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+impl std::str::FromStr for DefaultAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use clap::ValueEnum;
+        for variant in Self::value_variants() {
+            if variant.to_possible_value().unwrap().matches(s, false) {
+                return Ok(*variant);
+            }
+        }
+        Err(format!("Invalid variant: {s}"))
+    }
+}
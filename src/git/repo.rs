@@ -11,6 +11,18 @@ pub trait Repo {
     fn pull_remote(&self) -> &str;
 
     fn is_dirty(&self) -> bool;
+    fn is_shallow(&self) -> bool;
+    /// Whether there is no working tree, like with `git clone --bare`
+    fn is_bare(&self) -> bool;
+    /// Whether paths differing only in case are the same file, like with `core.ignorecase`
+    fn ignore_case(&self) -> bool;
+    fn index_conflicts(&self) -> Vec<std::path::PathBuf>;
+    fn lfs_paths(&self, id: git2::Oid) -> Vec<std::path::PathBuf>;
+    fn changed_paths(&self, id: git2::Oid) -> Vec<std::path::PathBuf>;
+    /// Untracked files in the worktree that checking out `id` would overwrite
+    fn untracked_collisions(&self, id: git2::Oid) -> Result<Vec<std::path::PathBuf>>;
+    /// Whether `id` carries a GPG or SSH signature, valid or not
+    fn is_signed(&self, id: git2::Oid) -> bool;
     fn merge_base(&self, one: git2::Oid, two: git2::Oid) -> Option<git2::Oid>;
 
     fn find_commit(&self, id: git2::Oid) -> Option<std::rc::Rc<Commit>>;
@@ -25,8 +37,18 @@ pub trait Repo {
         head_bound: std::ops::Bound<&git2::Oid>,
     ) -> Result<Vec<git2::Oid>>;
     fn contains_commit(&self, haystack_id: git2::Oid, needle_id: git2::Oid) -> Result<bool>;
+    /// Like `git patch-id`, identifying a commit's change independent of where it is applied
+    fn patch_id(&self, id: git2::Oid) -> Option<git2::Oid>;
     fn cherry_pick(&mut self, head_id: git2::Oid, cherry_id: git2::Oid) -> Result<git2::Oid>;
+    /// Like [`Repo::cherry_pick`] but resolving conflicts from `git rerere`'s recorded
+    /// resolutions, returning the resolved paths
+    fn rerere_pick(
+        &mut self,
+        head_id: git2::Oid,
+        cherry_id: git2::Oid,
+    ) -> Result<(git2::Oid, Vec<std::path::PathBuf>)>;
     fn reword(&mut self, head_oid: git2::Oid, msg: &str) -> Result<git2::Oid>;
+    fn reset_author(&mut self, head_oid: git2::Oid) -> Result<git2::Oid>;
     fn squash(&mut self, head_id: git2::Oid, into_id: git2::Oid) -> Result<git2::Oid>;
 
     fn stash_push(&mut self, message: Option<&str>) -> Result<git2::Oid>;
@@ -41,6 +63,17 @@ pub trait Repo {
     fn detach(&mut self) -> Result<()>;
     fn switch_branch(&mut self, name: &str) -> Result<()>;
     fn switch_commit(&mut self, id: git2::Oid) -> Result<()>;
+    fn worktrees(&self) -> Vec<Worktree>;
+    /// Names of the configured remotes
+    fn remotes(&self) -> Vec<String>;
+    /// Shorten `id` for showing to the user, see [`abbrev_id`]
+    fn abbrev_id(&self, id: git2::Oid) -> String;
+
+    /// Read a boolean config value with git's parsing, falling back to `default` when unset or
+    /// invalid
+    fn config_bool(&self, key: &str, default: bool) -> bool;
+    fn config_string(&self, key: &str) -> Option<String>;
+    fn config_int(&self, key: &str) -> Option<i64>;
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -66,11 +99,333 @@ impl std::fmt::Display for Branch {
     }
 }
 
+/// A working tree other than the one git-stack is running in
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Worktree {
+    pub path: std::path::PathBuf,
+    /// Local branch checked out in it, if any
+    pub branch: Option<String>,
+}
+
+/// A copy of the index to stage into, leaving the user's staged changes alone
+///
+/// Nothing reaches the real index until [`ScratchIndex::persist`], so an operation that fails
+/// part-way, or is only a dry run, can just drop it.
+pub struct ScratchIndex {
+    index: git2::Index,
+    // `index` stages from this handle's worktree, so it must outlive it
+    _repo: git2::Repository,
+}
+
+impl ScratchIndex {
+    pub fn index_mut(&mut self) -> &mut git2::Index {
+        &mut self.index
+    }
+
+    pub fn write_tree(&mut self) -> Result<git2::Oid> {
+        self.index.write_tree()
+    }
+
+    /// Replace the user's index with this one
+    pub fn persist(mut self) -> Result<()> {
+        self.index.write()
+    }
+}
+
+/// Commits whose parents were cut off by a shallow clone, from `$GIT_DIR/shallow`
+pub(crate) fn shallow_ids(repo: &git2::Repository) -> Vec<git2::Oid> {
+    if !repo.is_shallow() {
+        return Vec::new();
+    }
+    // Linked worktrees share the main repository's `shallow`
+    let common_dir = std::fs::read_to_string(repo.path().join("commondir"))
+        .map(|dir| repo.path().join(dir.trim()))
+        .unwrap_or_else(|_| repo.path().to_owned());
+    let shallow_path = common_dir.join("shallow");
+    std::fs::read_to_string(shallow_path)
+        .map(|ids| {
+            ids.lines()
+                .filter_map(|id| git2::Oid::from_str(id.trim()).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Refuse to replay a commit whose parents are missing from a shallow clone
+///
+/// The commit would be treated as a root commit, re-adding every file it has.  `shallow_ids` is
+/// looked up with [`shallow_ids`] on first use and kept for later commits.
+pub(crate) fn check_shallow_boundary(
+    repo: &git2::Repository,
+    shallow_ids: &std::cell::RefCell<Option<Vec<git2::Oid>>>,
+    id: git2::Oid,
+) -> Result<(), git2::Error> {
+    let mut shallow_ids = shallow_ids.borrow_mut();
+    let shallow_ids = shallow_ids.get_or_insert_with(|| self::shallow_ids(repo));
+    if shallow_ids.contains(&id) {
+        return Err(git2::Error::new(
+            git2::ErrorCode::Invalid,
+            git2::ErrorClass::Repository,
+            format!(
+                "cannot rewrite {id}, its parents are beyond the shallow clone; run `git fetch --unshallow`"
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Abbreviate `id` to at least `len` hex digits, growing it until it is unambiguous
+///
+/// Without `len`, this defers to `core.abbrev` like `git log --abbrev-commit`.
+pub fn abbrev_id(repo: &git2::Repository, id: git2::Oid, len: Option<usize>) -> String {
+    let hex = id.to_string();
+    let Some(len) = len else {
+        return repo
+            .find_object(id, None)
+            .and_then(|o| o.short_id())
+            .ok()
+            .and_then(|short| short.as_str().map(ToOwned::to_owned))
+            .unwrap_or(hex);
+    };
+    let Ok(odb) = repo.odb() else {
+        return hex;
+    };
+    // git won't go below 4 digits either
+    let mut len = len.clamp(4, hex.len());
+    while len < hex.len() && odb.exists_prefix(id, len).ok() != Some(id) {
+        len += 1;
+    }
+    hex[..len].to_owned()
+}
+
+/// Where `--push` records the id it last pushed local branch `name` as
+pub fn pushed_key(name: &str) -> String {
+    format!("stack.branch.{name}.pushed")
+}
+
+/// Every push recorded under [`pushed_key`], by branch name
+pub fn recorded_pushes(
+    config: &git2::Config,
+) -> Result<std::collections::HashMap<String, git2::Oid>> {
+    let mut pushes = std::collections::HashMap::new();
+    let mut entries = config.entries(Some(r"^stack\.branch\..*\.pushed$"))?;
+    while let Some(entry) = entries.next() {
+        let entry = entry?;
+        let name = entry
+            .name()
+            .and_then(|key| key.strip_prefix("stack.branch."))
+            .and_then(|rest| rest.strip_suffix(".pushed"));
+        let id = entry.value().and_then(|id| git2::Oid::from_str(id).ok());
+        if let (Some(name), Some(id)) = (name, id) {
+            pushes.insert(name.to_owned(), id);
+        }
+    }
+    Ok(pushes)
+}
+
+/// Drop what was recorded under [`pushed_key`] for local branch `name`, now that it's gone
+pub(crate) fn forget_push(repo: &git2::Repository, name: &str) -> Result<()> {
+    let mut config = repo.config()?.open_level(git2::ConfigLevel::Local)?;
+    match config.remove(&pushed_key(name)) {
+        Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Paths with unresolved conflicts in the index, sorted
+pub fn index_conflicts(repo: &git2::Repository) -> Vec<std::path::PathBuf> {
+    let index = repo
+        .index()
+        .unwrap_or_else(|e| panic!("Unexpected git2 error: {e}"));
+    if !index.has_conflicts() {
+        return Vec::new();
+    }
+    let mut paths = index
+        .conflicts()
+        .unwrap_or_else(|e| panic!("Unexpected git2 error: {e}"))
+        .filter_map(|conflict| {
+            let conflict = conflict.ok()?;
+            let entry = conflict.our.or(conflict.their).or(conflict.ancestor)?;
+            let path = entry.path.to_path().ok()?;
+            Some(path.to_owned())
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// Whether `id` carries a GPG or SSH signature, valid or not
+pub fn is_signed(repo: &git2::Repository, id: git2::Oid) -> bool {
+    repo.extract_signature(&id, None).is_ok()
+}
+
+/// Whether `one` and `two` are the same path, folding case when `ignore_case`
+///
+/// See [`Repo::ignore_case`].
+pub fn same_path(one: &std::path::Path, two: &std::path::Path, ignore_case: bool) -> bool {
+    if ignore_case {
+        one.to_string_lossy().to_lowercase() == two.to_string_lossy().to_lowercase()
+    } else {
+        one == two
+    }
+}
+
+/// Find the remote branch the local branch `name` pulls from
+///
+/// This is its configured `branch.<name>.remote` and `branch.<name>.merge`, falling back to
+/// `<pull_remote>/<name>`.
+pub fn find_upstream_branch(repo: &dyn Repo, name: &str) -> Option<Branch> {
+    let configured = repo
+        .config_string(&format!("branch.{name}.remote"))
+        .filter(|remote| remote != ".")
+        .and_then(|remote| {
+            let merge = repo.config_string(&format!("branch.{name}.merge"))?;
+            let merge = merge.strip_prefix("refs/heads/").unwrap_or(&merge);
+            repo.find_remote_branch(&remote, merge)
+        });
+    configured.or_else(|| repo.find_remote_branch(repo.pull_remote(), name))
+}
+
+/// Open the repository at `git_dir` again, checked out at `workdir`
+///
+/// Unlike [`git2::Repository::open`], this keeps a `--work-tree` / `GIT_WORK_TREE` override.
+pub(crate) fn open_at(
+    git_dir: &std::path::Path,
+    workdir: Option<&std::path::Path>,
+) -> Result<git2::Repository> {
+    let repo = git2::Repository::open_ext(
+        git_dir,
+        git2::RepositoryOpenFlags::NO_SEARCH,
+        std::iter::empty::<&std::ffi::OsStr>(),
+    )?;
+    if let Some(workdir) = workdir.filter(|w| repo.workdir() != Some(*w)) {
+        repo.set_workdir(workdir, false)?;
+    }
+    Ok(repo)
+}
+
+/// Untracked files in the worktree that checking out `id` would overwrite
+///
+/// Only paths that `id` adds relative to `HEAD` can collide, so just those are looked at.
+pub(crate) fn untracked_collisions(
+    repo: &git2::Repository,
+    id: git2::Oid,
+) -> Result<Vec<std::path::PathBuf>> {
+    let Some(workdir) = repo.workdir() else {
+        return Ok(Vec::new());
+    };
+    let head_tree = repo.head()?.peel_to_tree()?;
+    let tree = repo.find_commit(id)?.tree()?;
+    let diff = repo.diff_tree_to_tree(Some(&head_tree), Some(&tree), None)?;
+    let index = repo.index()?;
+    let is_untracked = |path: &std::path::Path| -> Result<bool> {
+        Ok(index.get_path(path, 0).is_none() && !repo.status_should_ignore(path)?)
+    };
+    let mut collisions = Vec::new();
+    for delta in diff.deltas() {
+        if delta.status() != git2::Delta::Added {
+            continue;
+        }
+        let Some(path) = delta.new_file().path() else {
+            continue;
+        };
+        if workdir.join(path).symlink_metadata().is_ok() && is_untracked(path)? {
+            collisions.push(path.to_owned());
+            continue;
+        }
+        // A file where one of its directories goes
+        for dir in path.ancestors().skip(1) {
+            if dir.as_os_str().is_empty() {
+                break;
+            }
+            let is_file = workdir
+                .join(dir)
+                .symlink_metadata()
+                .map(|m| !m.is_dir())
+                .unwrap_or(false);
+            if is_file && is_untracked(dir)? {
+                collisions.push(dir.to_owned());
+                break;
+            }
+        }
+    }
+    collisions.sort();
+    collisions.dedup();
+    Ok(collisions)
+}
+
+/// Refuse a checkout that [`untracked_collisions`] found would clobber untracked files
+pub(crate) fn untracked_collisions_error(collisions: &[std::path::PathBuf]) -> Error {
+    Error::new(
+        git2::ErrorCode::Conflict,
+        git2::ErrorClass::Checkout,
+        format!(
+            "the following untracked files would be overwritten: {}",
+            collisions.iter().map(|p| p.display()).join(", ")
+        ),
+    )
+}
+
+/// List the working trees sharing `repo`'s branches, besides `repo`'s own
+pub(crate) fn other_worktrees(repo: &git2::Repository) -> Vec<Worktree> {
+    let current = repo.workdir().and_then(|p| p.canonicalize().ok());
+    let mut repos = Vec::new();
+    if repo.is_worktree() {
+        // Linked worktrees record where the shared `.git` lives
+        let common_dir = std::fs::read_to_string(repo.path().join("commondir"))
+            .map(|dir| repo.path().join(dir.trim()));
+        match common_dir {
+            Ok(common_dir) => match git2::Repository::open(common_dir) {
+                Ok(main) => repos.push(main),
+                Err(err) => log::debug!("Could not open main worktree: {}", err),
+            },
+            Err(err) => log::debug!("Could not find main worktree: {}", err),
+        }
+    }
+    let names = repo.worktrees().ok();
+    for name in names.iter().flat_map(|n| n.iter()).flatten() {
+        let worktree = match repo.find_worktree(name) {
+            Ok(worktree) => worktree,
+            Err(err) => {
+                log::debug!("Could not find worktree `{}`: {}", name, err);
+                continue;
+            }
+        };
+        if worktree.validate().is_err() {
+            // Pruneable, nothing is checked out there anymore
+            continue;
+        }
+        match git2::Repository::open_from_worktree(&worktree) {
+            Ok(worktree_repo) => repos.push(worktree_repo),
+            Err(err) => log::debug!("Could not open worktree `{}`: {}", name, err),
+        }
+    }
+
+    repos
+        .into_iter()
+        .filter_map(|worktree_repo| {
+            let path = worktree_repo.workdir()?.canonicalize().ok()?;
+            if Some(&path) == current.as_ref() {
+                return None;
+            }
+            let branch = worktree_repo
+                .head()
+                .ok()
+                .filter(|head| head.is_branch())
+                .and_then(|head| head.shorthand().map(ToOwned::to_owned));
+            Some(Worktree { path, branch })
+        })
+        .collect()
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Commit {
     pub id: git2::Oid,
     pub tree_id: git2::Oid,
     pub summary: bstr::BString,
+    /// The full message, decoded lossily
+    pub message: String,
     pub time: std::time::SystemTime,
     pub author: Option<std::rc::Rc<str>>,
     pub committer: Option<std::rc::Rc<str>>,
@@ -114,15 +469,631 @@ impl Commit {
     }
 }
 
+/// Whether merges should follow renames, like `git rebase`
+///
+/// `merge.renames` falls back to `diff.renames`, both defaulting to on.  libgit2 reads
+/// `merge.renameLimit` / `diff.renameLimit` itself.
+pub(crate) fn find_renames(repo: &git2::Repository) -> bool {
+    ["merge.renames", "diff.renames"]
+        .into_iter()
+        .find_map(|key| {
+            let value = config_value(repo, key)?;
+            // `copies` implies renames
+            Some(
+                crate::config::parse_bool(value.as_deref())
+                    .unwrap_or_else(|| value.unwrap_or_default().starts_with("cop")),
+            )
+        })
+        .unwrap_or(true)
+}
+
+/// `key` as a boolean, like `git config --type=bool`, warning about and ignoring invalid values
+pub fn config_bool(repo: &git2::Repository, key: &str, default: bool) -> bool {
+    match config_value(repo, key) {
+        Some(value) => crate::config::parse_bool(value.as_deref()).unwrap_or_else(|| {
+            log::warn!(
+                "Ignoring invalid boolean for `{}`: {}",
+                key,
+                value.as_deref().unwrap_or_default()
+            );
+            default
+        }),
+        None => default,
+    }
+}
+
+/// The highest-precedence value for `key`, `Some(None)` when set without a value
+fn config_value(repo: &git2::Repository, key: &str) -> Option<Option<String>> {
+    let config = match repo.config() {
+        Ok(config) => config,
+        Err(err) => {
+            log::debug!("Could not read config: {}", err);
+            return None;
+        }
+    };
+    let entry = config.get_entry(key).ok()?;
+    if entry.has_value() {
+        Some(Some(
+            String::from_utf8_lossy(entry.value_bytes()).into_owned(),
+        ))
+    } else {
+        Some(None)
+    }
+}
+
+/// Like `git patch-id`, identifying a commit's change independent of where it is applied
+///
+/// With `skip_binary`, binary files are left out when the commit also changes text.  Which files
+/// are binary is left to the diff, so `.gitattributes` (`binary`, `-diff`) are honored the same
+/// way as `git diff`.
+pub(crate) fn patch_id(
+    repo: &git2::Repository,
+    id: git2::Oid,
+    reverse: bool,
+    skip_binary: bool,
+) -> Result<Option<git2::Oid>, git2::Error> {
+    let commit = repo.find_commit(id)?;
+    if commit.parent_count() != 1 {
+        // Root and merge commits don't have a single change to compare
+        return Ok(None);
+    }
+    let tree = commit.tree()?;
+    let parent_tree = commit.parent(0)?.tree()?;
+    let (old_tree, new_tree) = if reverse {
+        (&tree, &parent_tree)
+    } else {
+        (&parent_tree, &tree)
+    };
+    let mut diff = repo.diff_tree_to_tree(Some(old_tree), Some(new_tree), None)?;
+    if diff.deltas().len() == 0 {
+        return Ok(None);
+    }
+    if skip_binary {
+        let mut binary_paths = Vec::new();
+        diff.foreach(
+            &mut |_, _| true,
+            Some(&mut |delta, _| {
+                if let Some(path) = delta.new_file().path().or_else(|| delta.old_file().path()) {
+                    binary_paths.push(path.to_owned());
+                }
+                true
+            }),
+            None,
+            None,
+        )?;
+        let text_paths = diff
+            .deltas()
+            .filter_map(|delta| delta.new_file().path().or_else(|| delta.old_file().path()))
+            .filter(|path| !binary_paths.iter().any(|binary| binary == path))
+            .map(|path| path.to_owned())
+            .collect::<Vec<_>>();
+        if !text_paths.is_empty() && !binary_paths.is_empty() {
+            let mut opts = git2::DiffOptions::new();
+            opts.disable_pathspec_match(true);
+            for path in &text_paths {
+                opts.pathspec(path);
+            }
+            diff = repo.diff_tree_to_tree(Some(old_tree), Some(new_tree), Some(&mut opts))?;
+        }
+    }
+    diff.patchid(None).map(Some)
+}
+
+/// Like [`git2::Repository::merge_base`] but only following first parents
+///
+/// This is the first commit shared by both first-parent chains, so it is never inside a branch
+/// that was merged into either side.  Once the chains meet they stay together, so walking them in
+/// lock-step finds it without visiting the rest of history.
+pub(crate) fn first_parent_merge_base(
+    repo: &git2::Repository,
+    one: git2::Oid,
+    two: git2::Oid,
+) -> Option<git2::Oid> {
+    let mut seen = [
+        std::collections::HashSet::new(),
+        std::collections::HashSet::new(),
+    ];
+    let mut next = [Some(one), Some(two)];
+    while next.iter().any(Option::is_some) {
+        for side in 0..2 {
+            let Some(id) = next[side] else {
+                continue;
+            };
+            if seen[1 - side].contains(&id) {
+                return Some(id);
+            }
+            seen[side].insert(id);
+            next[side] = repo.find_commit(id).ok()?.parent_id(0).ok();
+        }
+    }
+    None
+}
+
+/// Replay `cherry_id` on top of `head_id` using `strategy`
+///
+/// Merge commits are recreated with [`merge_pick`]; commands refuse to rewrite them unless
+/// `stack.rebase-merges` is set.
+pub(crate) fn replay(
+    repo: &git2::Repository,
+    head_id: git2::Oid,
+    cherry_id: git2::Oid,
+    strategy: crate::config::RebaseStrategy,
+    sign: Option<&dyn git2_ext::ops::Sign>,
+) -> Result<git2::Oid> {
+    if 1 < repo.find_commit(cherry_id)?.parent_count() {
+        return merge_pick(repo, head_id, cherry_id, sign);
+    }
+    match strategy {
+        // `git2_ext` can't be given merge options, so fall back to merging the trees directly
+        // when renames should be ignored
+        crate::config::RebaseStrategy::CherryPick if !find_renames(repo) => {
+            merge_tree_pick(repo, head_id, cherry_id, sign)
+        }
+        crate::config::RebaseStrategy::CherryPick => {
+            git2_ext::ops::cherry_pick(repo, head_id, cherry_id, sign)
+        }
+        crate::config::RebaseStrategy::MergeTree => merge_tree_pick(repo, head_id, cherry_id, sign),
+    }
+}
+
+/// Replay `cherry_id` onto `head_id` with a three-way tree merge
+///
+/// Unlike [`git2_ext::ops::cherry_pick`], this never goes through a rebase and produces the
+/// same tree and conflicts for the same inputs.
+pub(crate) fn merge_tree_pick(
+    repo: &git2::Repository,
+    head_id: git2::Oid,
+    cherry_id: git2::Oid,
+    sign: Option<&dyn git2_ext::ops::Sign>,
+) -> Result<git2::Oid> {
+    let cherry_commit = repo.find_commit(cherry_id)?;
+    let base_commit = cherry_commit.parent(0)?;
+    if base_commit.id() == head_id {
+        return Ok(cherry_id);
+    }
+    let head_commit = repo.find_commit(head_id)?;
+
+    let mut options = git2::MergeOptions::new();
+    options.find_renames(find_renames(repo));
+    let mut index = repo.merge_trees(
+        &base_commit.tree()?,
+        &head_commit.tree()?,
+        &cherry_commit.tree()?,
+        Some(&options),
+    )?;
+    if index.has_conflicts() {
+        let conflicts = index
+            .conflicts()?
+            .filter_map(|conflict| {
+                let conflict = conflict.ok()?;
+                let entry = conflict.our.or(conflict.their).or(conflict.ancestor)?;
+                Some(entry.path.to_path_lossy().display().to_string())
+            })
+            .join("\n  ");
+        return Err(git2::Error::new(
+            git2::ErrorCode::Unmerged,
+            git2::ErrorClass::Index,
+            format!("cherry-pick conflicts:\n  {conflicts}\n"),
+        ));
+    }
+    let tree_id = index.write_tree_to(repo)?;
+    commit_pick(repo, &head_commit, &cherry_commit, tree_id, sign)
+}
+
+/// Recreate merge commit `merge_id` on top of `head_id`
+///
+/// The first parent becomes `head_id` while the merged-in parents are kept, so only the changes
+/// between the old and new first parent get merged into its tree.
+pub(crate) fn merge_pick(
+    repo: &git2::Repository,
+    head_id: git2::Oid,
+    merge_id: git2::Oid,
+    sign: Option<&dyn git2_ext::ops::Sign>,
+) -> Result<git2::Oid> {
+    let merge_commit = repo.find_commit(merge_id)?;
+    let base_commit = merge_commit.parent(0)?;
+    if base_commit.id() == head_id {
+        return Ok(merge_id);
+    }
+    let head_commit = repo.find_commit(head_id)?;
+
+    let mut options = git2::MergeOptions::new();
+    options.find_renames(find_renames(repo));
+    let mut index = repo.merge_trees(
+        &base_commit.tree()?,
+        &head_commit.tree()?,
+        &merge_commit.tree()?,
+        Some(&options),
+    )?;
+    if index.has_conflicts() {
+        let conflicts = index
+            .conflicts()?
+            .filter_map(|conflict| {
+                let conflict = conflict.ok()?;
+                let entry = conflict.our.or(conflict.their).or(conflict.ancestor)?;
+                Some(entry.path.to_path_lossy().display().to_string())
+            })
+            .join("\n  ");
+        return Err(git2::Error::new(
+            git2::ErrorCode::Unmerged,
+            git2::ErrorClass::Index,
+            format!("merge conflicts:\n  {conflicts}\n"),
+        ));
+    }
+    let tree_id = index.write_tree_to(repo)?;
+    let tree = repo.find_tree(tree_id)?;
+
+    let mut parents = vec![head_commit];
+    parents.extend(merge_commit.parents().skip(1));
+    let parents = parents.iter().collect::<Vec<_>>();
+    let committer = replay_committer(repo, &merge_commit)?;
+    let author = merge_commit.author();
+    let message = String::from_utf8_lossy(merge_commit.message_bytes());
+    git2_ext::ops::commit(repo, &author, &committer, &message, &tree, &parents, sign)
+}
+
+/// The committer for replaying `cherry_commit`, keeping its commit time like `cherry_pick` does
+fn replay_committer(
+    repo: &git2::Repository,
+    cherry_commit: &git2::Commit<'_>,
+) -> Result<git2::Signature<'static>> {
+    let mut committer = git2_ext::ops::commit_signature(repo)?.to_owned();
+    if let (Some(name), Some(email)) = (committer.name(), committer.email()) {
+        committer = git2::Signature::new(name, email, &cherry_commit.time())?.to_owned();
+    }
+    Ok(committer)
+}
+
+/// Record `tree_id` as `cherry_commit` replayed onto `head_commit`
+fn commit_pick(
+    repo: &git2::Repository,
+    head_commit: &git2::Commit<'_>,
+    cherry_commit: &git2::Commit<'_>,
+    tree_id: git2::Oid,
+    sign: Option<&dyn git2_ext::ops::Sign>,
+) -> Result<git2::Oid> {
+    if tree_id == head_commit.tree_id() {
+        log::trace!(
+            "Skipping {}, already applied to {}",
+            cherry_commit.id(),
+            head_commit.id()
+        );
+        return Ok(head_commit.id());
+    }
+    let tree = repo.find_tree(tree_id)?;
+
+    let committer = replay_committer(repo, cherry_commit)?;
+    let author = cherry_commit.author();
+    let message = String::from_utf8_lossy(cherry_commit.message_bytes());
+    git2_ext::ops::commit(
+        repo,
+        &author,
+        &committer,
+        &message,
+        &tree,
+        &[head_commit],
+        sign,
+    )
+}
+
+/// Name prefix of the throwaway worktrees for `git rerere`, left behind if `git-stack` is killed
+pub const RERERE_WORKTREE_PREFIX: &str = "git-stack-rerere-";
+
+/// Replay `cherry_id` onto `head_id` with `git cherry-pick`, letting `git rerere` resolve
+/// conflicts it has seen before
+///
+/// git2 has no support for rerere, so the pick is redone in a throwaway worktree.  Returns the
+/// new commit and the paths that were resolved, failing if any conflict is left.
+pub(crate) fn rerere_pick(
+    repo: &git2::Repository,
+    head_id: git2::Oid,
+    cherry_id: git2::Oid,
+    sign: Option<&dyn git2_ext::ops::Sign>,
+) -> Result<(git2::Oid, Vec<std::path::PathBuf>)> {
+    let head_commit = repo.find_commit(head_id)?;
+    let cherry_commit = repo.find_commit(cherry_id)?;
+
+    let worktree_path = std::env::temp_dir().join(format!(
+        "{RERERE_WORKTREE_PREFIX}{}-{}",
+        std::process::id(),
+        cherry_id
+    ));
+    // Hooks are for the user's checkouts, not ours, and the repository environment variables
+    // would point `git` away from the scratch worktree
+    let git = |dir: &std::path::Path| {
+        let mut cmd = std::process::Command::new("git");
+        cmd.arg("-C")
+            .arg(dir)
+            .env_remove("GIT_DIR")
+            .env_remove("GIT_WORK_TREE")
+            .env_remove("GIT_INDEX_FILE")
+            .env_remove("GIT_OBJECT_DIRECTORY")
+            .env_remove("GIT_ALTERNATE_OBJECT_DIRECTORIES")
+            .args(["-c", "core.hooksPath=/dev/null"])
+            .stdin(std::process::Stdio::null())
+            .stderr(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped());
+        cmd
+    };
+    let run = |cmd: &mut std::process::Command| -> Result<std::process::Output> {
+        cmd.output().map_err(|err| {
+            Error::new(
+                git2::ErrorCode::GenericError,
+                git2::ErrorClass::Os,
+                format!("could not run `git`: {err}"),
+            )
+        })
+    };
+    let failed = |what: &str, output: &std::process::Output| {
+        Error::new(
+            git2::ErrorCode::GenericError,
+            git2::ErrorClass::CherryPick,
+            format!(
+                "`git {what}` failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        )
+    };
+    let paths = |output: &std::process::Output| {
+        output
+            .stdout
+            .lines()
+            .map(|l| l.to_path_lossy().into_owned())
+            .collect::<Vec<_>>()
+    };
+
+    let output = run(git(repo.path())
+        .args(["worktree", "add", "--detach", "--quiet"])
+        .arg(&worktree_path)
+        .arg(head_id.to_string()))?;
+    if !output.status.success() {
+        return Err(failed("worktree add", &output));
+    }
+    let result = (|| {
+        let picked = run(git(&worktree_path)
+            .args(["-c", "rerere.enabled=true", "-c", "rerere.autoUpdate=false"])
+            .args(["cherry-pick", "--no-commit"])
+            .arg(cherry_id.to_string()))?;
+        let conflicted = run(git(&worktree_path).args(["diff", "--name-only", "--diff-filter=U"]))?;
+        let conflicted = paths(&conflicted);
+        if !picked.status.success() && conflicted.is_empty() {
+            return Err(failed("cherry-pick", &picked));
+        }
+        let remaining = run(git(&worktree_path).args(["rerere", "remaining"]))?;
+        let remaining = paths(&remaining);
+        if !remaining.is_empty() {
+            return Err(Error::new(
+                git2::ErrorCode::Unmerged,
+                git2::ErrorClass::Index,
+                format!(
+                    "cherry-pick conflicts:\n  {}\n",
+                    remaining.iter().map(|p| p.display()).join("\n  ")
+                ),
+            ));
+        }
+        if !conflicted.is_empty() {
+            let output = run(git(&worktree_path).args(["add", "--"]).args(&conflicted))?;
+            if !output.status.success() {
+                return Err(failed("add", &output));
+            }
+        }
+        let output = run(git(&worktree_path).arg("write-tree"))?;
+        if !output.status.success() {
+            return Err(failed("write-tree", &output));
+        }
+        let tree_id = git2::Oid::from_str(output.stdout.trim().to_str_lossy().as_ref())?;
+        let new_id = commit_pick(repo, &head_commit, &cherry_commit, tree_id, sign)?;
+        Ok((new_id, conflicted))
+    })();
+    let output = run(git(repo.path())
+        .args(["worktree", "remove", "--force"])
+        .arg(&worktree_path));
+    if !output.map(|o| o.status.success()).unwrap_or(false) {
+        // Don't leave the worktree registered under `$GIT_DIR/worktrees`
+        if let Err(err) = std::fs::remove_dir_all(&worktree_path) {
+            log::debug!("Could not remove {}: {}", worktree_path.display(), err);
+        }
+        let output = run(git(repo.path()).args(["worktree", "prune"]));
+        if !output.map(|o| o.status.success()).unwrap_or(false) {
+            log::debug!("Could not prune {}", worktree_path.display());
+        }
+    }
+
+    result
+}
+
+/// The committer for recreating `commit`
+///
+/// Like `git rebase`, this is the one from `GIT_COMMITTER_NAME` / `GIT_COMMITTER_EMAIL` when set,
+/// see [`git2_ext::ops::commit_signature`].  Otherwise the commit keeps its committer.  The
+/// original commit time is preserved either way.
+fn rewrite_committer(
+    repo: &git2::Repository,
+    commit: &git2::Commit<'_>,
+) -> Result<git2::Signature<'static>> {
+    let old_committer = commit.committer();
+    if std::env::var_os("GIT_COMMITTER_NAME").is_none()
+        && std::env::var_os("GIT_COMMITTER_EMAIL").is_none()
+    {
+        return Ok(old_committer.to_owned());
+    }
+    let identity = git2_ext::ops::commit_signature(repo)?;
+    match (identity.name(), identity.email()) {
+        (Some(name), Some(email)) => git2::Signature::new(name, email, &old_committer.when()),
+        _ => Ok(old_committer.to_owned()),
+    }
+}
+
+/// Like [`git2_ext::ops::reword`] but with the committer from [`rewrite_committer`]
+pub(crate) fn reword(
+    repo: &git2::Repository,
+    head_id: git2::Oid,
+    msg: &str,
+    sign: Option<&dyn git2_ext::ops::Sign>,
+) -> Result<git2::Oid> {
+    let old_commit = repo.find_commit(head_id)?;
+    let parents = old_commit.parents().collect::<Vec<_>>();
+    let parents = parents.iter().collect::<Vec<_>>();
+    let tree = old_commit.tree()?;
+    let author = old_commit.author();
+    let committer = rewrite_committer(repo, &old_commit)?;
+    git2_ext::ops::commit(repo, &author, &committer, msg, &tree, &parents, sign)
+}
+
+/// Like [`git2_ext::ops::squash`] but with the committer from [`rewrite_committer`]
+pub(crate) fn squash(
+    repo: &git2::Repository,
+    head_id: git2::Oid,
+    into_id: git2::Oid,
+    sign: Option<&dyn git2_ext::ops::Sign>,
+) -> Result<git2::Oid> {
+    let head_commit = repo.find_commit(head_id)?;
+    let head_tree = head_commit.tree()?;
+    let base_commit = if 0 < head_commit.parent_count() {
+        head_commit.parent(0)?
+    } else {
+        head_commit.clone()
+    };
+    let base_tree = base_commit.tree()?;
+
+    let into_commit = repo.find_commit(into_id)?;
+    let into_tree = into_commit.tree()?;
+    let onto_commits = into_commit.parents().take(1).collect::<Vec<_>>();
+    let onto_commits = onto_commits.iter().collect::<Vec<_>>();
+
+    let mut result_index = repo.merge_trees(&base_tree, &into_tree, &head_tree, None)?;
+    if result_index.has_conflicts() {
+        let conflicts = result_index
+            .conflicts()?
+            .filter_map(|conflict| {
+                let conflict = conflict.ok()?;
+                let path = conflict.our.or(conflict.their).or(conflict.ancestor)?.path;
+                Some(path.to_str_lossy().into_owned())
+            })
+            .join("\n  ");
+        return Err(Error::new(
+            git2::ErrorCode::Unmerged,
+            git2::ErrorClass::Index,
+            format!("squash conflicts:\n  {conflicts}\n"),
+        ));
+    }
+    let result_id = result_index.write_tree_to(repo)?;
+    let result_tree = repo.find_tree(result_id)?;
+    let message = into_commit.message().ok_or_else(|| {
+        Error::new(
+            git2::ErrorCode::GenericError,
+            git2::ErrorClass::Invalid,
+            format!("commit {into_id} has a message that isn't valid UTF-8"),
+        )
+    })?;
+    let author = into_commit.author();
+    let committer = rewrite_committer(repo, &into_commit)?;
+    git2_ext::ops::commit(
+        repo,
+        &author,
+        &committer,
+        message,
+        &result_tree,
+        &onto_commits,
+        sign,
+    )
+}
+
+/// Parsed commits, keyed by id, that [`GitRepo`]s can share
+///
+/// Each `GitRepo` has its own by default.  Share one with [`GitRepo::with_commit_cache`] so
+/// repeated graph builds within a process reuse parsed commits.  Commits are immutable, so
+/// entries never go stale.
+#[derive(Default)]
+pub struct CommitCache {
+    commits: std::cell::RefCell<std::collections::HashMap<git2::Oid, std::rc::Rc<Commit>>>,
+    parent_ids: std::cell::RefCell<std::collections::HashMap<git2::Oid, Vec<git2::Oid>>>,
+    interned_strings: std::cell::RefCell<std::collections::HashSet<std::rc::Rc<str>>>,
+    reads: std::cell::Cell<usize>,
+}
+
+impl CommitCache {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// How many commits have been read from the object database to fill the cache
+    pub fn reads(&self) -> usize {
+        self.reads.get()
+    }
+
+    fn get(&self, id: git2::Oid) -> Option<std::rc::Rc<Commit>> {
+        self.commits.borrow().get(&id).cloned()
+    }
+
+    fn get_parent_ids(&self, id: git2::Oid) -> Option<Vec<git2::Oid>> {
+        self.parent_ids.borrow().get(&id).cloned()
+    }
+
+    fn load(&self, repo: &git2::Repository, id: git2::Oid) -> Result<std::rc::Rc<Commit>> {
+        let commit = repo.find_commit(id)?;
+        self.reads.set(self.reads.get() + 1);
+
+        let summary: bstr::BString = commit.summary_bytes().unwrap().into();
+        let message = String::from_utf8_lossy(commit.message_bytes()).into_owned();
+        let time = std::time::SystemTime::UNIX_EPOCH
+            + std::time::Duration::from_secs(commit.time().seconds().max(0) as u64);
+
+        let author = commit.author().name().map(|n| self.intern_string(n));
+        let committer = commit.author().name().map(|n| self.intern_string(n));
+        let parsed = std::rc::Rc::new(Commit {
+            id: commit.id(),
+            tree_id: commit.tree_id(),
+            summary,
+            message,
+            time,
+            author,
+            committer,
+        });
+        self.commits
+            .borrow_mut()
+            .insert(id, std::rc::Rc::clone(&parsed));
+        self.parent_ids
+            .borrow_mut()
+            .insert(id, commit.parent_ids().collect());
+        Ok(parsed)
+    }
+
+    fn intern_string(&self, data: &str) -> std::rc::Rc<str> {
+        let mut interned_strings = self.interned_strings.borrow_mut();
+        if let Some(interned) = interned_strings.get(data) {
+            std::rc::Rc::clone(interned)
+        } else {
+            let interned = std::rc::Rc::from(data);
+            interned_strings.insert(std::rc::Rc::clone(&interned));
+            interned
+        }
+    }
+}
+
+impl std::fmt::Debug for CommitCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        f.debug_struct("CommitCache")
+            .field("commits", &self.commits.borrow().len())
+            .field("reads", &self.reads())
+            .finish()
+    }
+}
+
 pub struct GitRepo {
     repo: git2::Repository,
     sign: Option<git2_ext::ops::UserSign>,
+    rebase_strategy: crate::config::RebaseStrategy,
+    patch_id_skip_binary: bool,
     push_remote: Option<String>,
     pull_remote: Option<String>,
-    commits: std::cell::RefCell<std::collections::HashMap<git2::Oid, std::rc::Rc<Commit>>>,
-    interned_strings: std::cell::RefCell<std::collections::HashSet<std::rc::Rc<str>>>,
+    abbrev: Option<usize>,
+    first_parent: bool,
+    commits: std::rc::Rc<CommitCache>,
     bases: std::cell::RefCell<std::collections::HashMap<(git2::Oid, git2::Oid), Option<git2::Oid>>>,
     counts: std::cell::RefCell<std::collections::HashMap<(git2::Oid, git2::Oid), Option<usize>>>,
+    uses_lfs: std::cell::Cell<Option<bool>>,
+    shallow_ids: std::cell::RefCell<Option<Vec<git2::Oid>>>,
 }
 
 impl GitRepo {
@@ -130,15 +1101,31 @@ impl GitRepo {
         Self {
             repo,
             sign: None,
+            rebase_strategy: Default::default(),
+            patch_id_skip_binary: false,
             push_remote: None,
             pull_remote: None,
+            abbrev: None,
+            first_parent: false,
             commits: Default::default(),
-            interned_strings: Default::default(),
             bases: Default::default(),
             counts: Default::default(),
+            uses_lfs: Default::default(),
+            shallow_ids: Default::default(),
         }
     }
 
+    /// Like [`GitRepo::new`] but reading commits through `cache`, shared with other `GitRepo`s
+    pub fn with_commit_cache(repo: git2::Repository, cache: std::rc::Rc<CommitCache>) -> Self {
+        let mut repo = Self::new(repo);
+        repo.commits = cache;
+        repo
+    }
+
+    pub fn commit_cache(&self) -> &std::rc::Rc<CommitCache> {
+        &self.commits
+    }
+
     pub fn set_sign(&mut self, yes: bool) -> Result<(), git2::Error> {
         if yes {
             let config = self.repo.config()?;
@@ -150,6 +1137,15 @@ impl GitRepo {
         Ok(())
     }
 
+    pub fn set_rebase_strategy(&mut self, strategy: crate::config::RebaseStrategy) {
+        self.rebase_strategy = strategy;
+    }
+
+    /// Leave binary files out of patch-ids when a commit also changes text, see [`patch_id`]
+    pub fn set_patch_id_skip_binary(&mut self, yes: bool) {
+        self.patch_id_skip_binary = yes;
+    }
+
     pub fn set_push_remote(&mut self, remote: &str) {
         self.push_remote = Some(remote.to_owned());
     }
@@ -166,10 +1162,54 @@ impl GitRepo {
         self.pull_remote.as_deref().unwrap_or("origin")
     }
 
+    /// Hex digits to show of commit ids, see [`abbrev_id`]
+    pub fn set_abbrev(&mut self, len: Option<usize>) {
+        self.abbrev = len;
+    }
+
+    pub fn abbrev_id(&self, id: git2::Oid) -> String {
+        abbrev_id(&self.repo, id, self.abbrev)
+    }
+
+    /// Only follow first parents, like `git log --first-parent`
+    ///
+    /// Merged-in branches are then not part of the history, for finding bases or commits to
+    /// stack.  Set this before querying the repo as results are cached.
+    pub fn set_first_parent(&mut self, yes: bool) {
+        self.first_parent = yes;
+    }
+
     pub fn raw(&self) -> &git2::Repository {
         &self.repo
     }
 
+    /// Re-open this repository with the same settings
+    ///
+    /// `GitRepo` can't be shared across threads, so this captures what is needed to create another
+    /// handle on a different thread.
+    pub fn opener(&self) -> impl Fn() -> Result<GitRepo> + Send + Sync {
+        let path = self.repo.path().to_owned();
+        let workdir = self.repo.workdir().map(|p| p.to_owned());
+        let sign = self.sign.is_some();
+        let rebase_strategy = self.rebase_strategy;
+        let patch_id_skip_binary = self.patch_id_skip_binary;
+        let push_remote = self.push_remote.clone();
+        let pull_remote = self.pull_remote.clone();
+        let abbrev = self.abbrev;
+        let first_parent = self.first_parent;
+        move || {
+            let mut repo = GitRepo::new(open_at(&path, workdir.as_deref())?);
+            repo.set_sign(sign)?;
+            repo.set_rebase_strategy(rebase_strategy);
+            repo.patch_id_skip_binary = patch_id_skip_binary;
+            repo.push_remote = push_remote.clone();
+            repo.pull_remote = pull_remote.clone();
+            repo.abbrev = abbrev;
+            repo.first_parent = first_parent;
+            Ok(repo)
+        }
+    }
+
     pub fn user(&self) -> Option<std::rc::Rc<str>> {
         self.repo
             .signature()
@@ -196,6 +1236,125 @@ impl GitRepo {
         }
     }
 
+    /// Whether history was truncated, like with `git clone --depth`
+    pub fn is_shallow(&self) -> bool {
+        self.repo.is_shallow()
+    }
+
+    pub fn is_bare(&self) -> bool {
+        self.repo.is_bare()
+    }
+
+    /// Whether the worktree is on a case-insensitive filesystem, per `core.ignorecase`
+    pub fn ignore_case(&self) -> bool {
+        self.config_bool("core.ignorecase", false)
+    }
+
+    /// Stage into a copy of the index, see [`ScratchIndex`]
+    pub fn scratch_index(&self) -> Result<ScratchIndex> {
+        // A separate handle gets its own in-memory index, rather than sharing ours
+        let repo = open_at(self.repo.path(), self.repo.workdir())?;
+        let index = repo.index()?;
+        Ok(ScratchIndex { index, _repo: repo })
+    }
+
+    pub fn index_conflicts(&self) -> Vec<std::path::PathBuf> {
+        index_conflicts(&self.repo)
+    }
+
+    /// Paths changed by `id` (relative to its first parent) that `.gitattributes` routes through
+    /// the Git LFS filter
+    pub fn lfs_paths(&self, id: git2::Oid) -> Vec<std::path::PathBuf> {
+        if !self.uses_lfs() {
+            return Vec::new();
+        }
+        self.changed_paths(id)
+            .into_iter()
+            .filter(|path| {
+                self.repo
+                    .get_attr(path, "filter", git2::AttrCheckFlags::FILE_THEN_INDEX)
+                    .ok()
+                    .flatten()
+                    == Some("lfs")
+            })
+            .collect()
+    }
+
+    /// Whether there is a `filter=lfs` attribute or any `filter.lfs.*` config, cached
+    ///
+    /// Only the top-level `.gitattributes`, where `git lfs track` writes, and `info/attributes` are
+    /// looked at for the attribute.
+    fn uses_lfs(&self) -> bool {
+        if let Some(uses_lfs) = self.uses_lfs.get() {
+            return uses_lfs;
+        }
+        let configured = self
+            .repo
+            .config()
+            .and_then(|config| Ok(config.entries(Some("filter.lfs.*"))?.next().is_some()))
+            .unwrap_or(false);
+        let attributed = || {
+            let top_level = match self.repo.workdir() {
+                Some(workdir) => std::fs::read(workdir.join(".gitattributes")).ok(),
+                None => self
+                    .repo
+                    .head()
+                    .and_then(|head| head.peel_to_tree())
+                    .and_then(|tree| tree.get_path(std::path::Path::new(".gitattributes")))
+                    .and_then(|entry| entry.to_object(&self.repo))
+                    .and_then(|object| object.peel_to_blob())
+                    .map(|blob| blob.content().to_owned())
+                    .ok(),
+            };
+            let info = std::fs::read(self.repo.path().join("info/attributes")).ok();
+            top_level
+                .into_iter()
+                .chain(info)
+                .any(|attributes| attributes.find(b"filter=lfs").is_some())
+        };
+        let uses_lfs = configured || attributed();
+        self.uses_lfs.set(Some(uses_lfs));
+        uses_lfs
+    }
+
+    pub fn is_signed(&self, id: git2::Oid) -> bool {
+        is_signed(&self.repo, id)
+    }
+
+    /// Paths added, modified, or removed by a commit, relative to its first parent
+    pub fn changed_paths(&self, id: git2::Oid) -> Vec<std::path::PathBuf> {
+        let commit = match self.repo.find_commit(id) {
+            Ok(commit) => commit,
+            Err(_) => return Vec::new(),
+        };
+        let tree = commit.tree().ok();
+        let parent_tree = commit.parent(0).ok().and_then(|p| p.tree().ok());
+        let diff = match self
+            .repo
+            .diff_tree_to_tree(parent_tree.as_ref(), tree.as_ref(), None)
+        {
+            Ok(diff) => diff,
+            Err(_) => return Vec::new(),
+        };
+        let mut paths = diff
+            .deltas()
+            .filter_map(|delta| {
+                let path = delta
+                    .new_file()
+                    .path()
+                    .or_else(|| delta.old_file().path())?;
+                Some(path.to_owned())
+            })
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+
+    pub fn untracked_collisions(&self, id: git2::Oid) -> Result<Vec<std::path::PathBuf>> {
+        untracked_collisions(&self.repo, id)
+    }
+
     pub fn merge_base(&self, one: git2::Oid, two: git2::Oid) -> Option<git2::Oid> {
         if one == two {
             return Some(one);
@@ -210,32 +1369,17 @@ impl GitRepo {
     }
 
     fn merge_base_raw(&self, one: git2::Oid, two: git2::Oid) -> Option<git2::Oid> {
-        self.repo.merge_base(one, two).ok()
+        if self.first_parent {
+            first_parent_merge_base(&self.repo, one, two)
+        } else {
+            self.repo.merge_base(one, two).ok()
+        }
     }
 
     pub fn find_commit(&self, id: git2::Oid) -> Option<std::rc::Rc<Commit>> {
-        let mut commits = self.commits.borrow_mut();
-        if let Some(commit) = commits.get(&id) {
-            Some(std::rc::Rc::clone(commit))
-        } else {
-            let commit = self.repo.find_commit(id).ok()?;
-            let summary: bstr::BString = commit.summary_bytes().unwrap().into();
-            let time = std::time::SystemTime::UNIX_EPOCH
-                + std::time::Duration::from_secs(commit.time().seconds().max(0) as u64);
-
-            let author = commit.author().name().map(|n| self.intern_string(n));
-            let committer = commit.author().name().map(|n| self.intern_string(n));
-            let commit = std::rc::Rc::new(Commit {
-                id: commit.id(),
-                tree_id: commit.tree_id(),
-                summary,
-                time,
-                author,
-                committer,
-            });
-            commits.insert(id, std::rc::Rc::clone(&commit));
-            Some(commit)
-        }
+        self.commits
+            .get(id)
+            .or_else(|| self.commits.load(&self.repo, id).ok())
     }
 
     pub fn head_commit(&self) -> std::rc::Rc<Commit> {
@@ -261,7 +1405,8 @@ impl GitRepo {
             .unwrap_or_else(|e| panic!("Unexpected git2 error: {e}"))
             .resolve()
             .unwrap_or_else(|e| panic!("Unexpected git2 error: {e}"));
-        let name = resolved.shorthand()?;
+        // `shorthand` can be ambiguous (e.g. a local branch named like a remote one)
+        let name = resolved.name()?.strip_prefix("refs/heads/")?;
         let id = resolved.target()?;
 
         Some(Branch {
@@ -277,8 +1422,19 @@ impl GitRepo {
     }
 
     pub fn parent_ids(&self, head_id: git2::Oid) -> Result<Vec<git2::Oid>> {
-        let commit = self.repo.find_commit(head_id)?;
-        Ok(commit.parent_ids().collect())
+        let mut parent_ids = match self.commits.get_parent_ids(head_id) {
+            Some(parent_ids) => parent_ids,
+            None => {
+                self.commits.load(&self.repo, head_id)?;
+                self.commits
+                    .get_parent_ids(head_id)
+                    .expect("`load` caches parent ids")
+            }
+        };
+        if self.first_parent {
+            parent_ids.truncate(1);
+        }
+        Ok(parent_ids)
     }
 
     pub fn commit_count(&self, base_id: git2::Oid, head_id: git2::Oid) -> Option<usize> {
@@ -308,6 +1464,11 @@ impl GitRepo {
         revwalk
             .hide(base_id)
             .unwrap_or_else(|e| panic!("Unexpected git2 error: {e}"));
+        if self.first_parent {
+            revwalk
+                .simplify_first_parent()
+                .unwrap_or_else(|e| panic!("Unexpected git2 error: {e}"));
+        }
         Some(revwalk.count())
     }
 
@@ -340,6 +1501,9 @@ impl GitRepo {
             revwalk.hide(base_id)?;
         }
         revwalk.set_sorting(git2::Sort::TOPOLOGICAL)?;
+        if self.first_parent {
+            revwalk.simplify_first_parent()?;
+        }
         let mut result = revwalk
             .filter_map(Result::ok)
             .skip(skip)
@@ -351,6 +1515,12 @@ impl GitRepo {
         Ok(result)
     }
 
+    pub fn patch_id(&self, id: git2::Oid) -> Option<git2::Oid> {
+        patch_id(&self.repo, id, false, self.patch_id_skip_binary)
+            .ok()
+            .flatten()
+    }
+
     pub fn contains_commit(&self, haystack_id: git2::Oid, needle_id: git2::Oid) -> Result<bool> {
         let needle_commit = self.repo.find_commit(needle_id)?;
         let needle_ann_commit = self.repo.find_annotated_commit(needle_id)?;
@@ -409,7 +1579,23 @@ impl GitRepo {
     }
 
     pub fn cherry_pick(&mut self, head_id: git2::Oid, cherry_id: git2::Oid) -> Result<git2::Oid> {
-        git2_ext::ops::cherry_pick(
+        check_shallow_boundary(&self.repo, &self.shallow_ids, cherry_id)?;
+        replay(
+            &self.repo,
+            head_id,
+            cherry_id,
+            self.rebase_strategy,
+            self.sign.as_ref().map(|s| s as &dyn git2_ext::ops::Sign),
+        )
+    }
+
+    pub fn rerere_pick(
+        &mut self,
+        head_id: git2::Oid,
+        cherry_id: git2::Oid,
+    ) -> Result<(git2::Oid, Vec<std::path::PathBuf>)> {
+        check_shallow_boundary(&self.repo, &self.shallow_ids, cherry_id)?;
+        rerere_pick(
             &self.repo,
             head_id,
             cherry_id,
@@ -418,23 +1604,61 @@ impl GitRepo {
     }
 
     pub fn reword(&mut self, head_oid: git2::Oid, msg: &str) -> Result<git2::Oid> {
-        git2_ext::ops::reword(
+        check_shallow_boundary(&self.repo, &self.shallow_ids, head_oid)?;
+        let sign = self.sign.as_ref().map(|s| s as &dyn git2_ext::ops::Sign);
+        reword(&self.repo, head_oid, msg, sign)
+    }
+
+    /// Commit `tree_id` on top of `parent_id` as the configured user, like `git commit`
+    pub fn commit_tree(
+        &mut self,
+        parent_id: git2::Oid,
+        tree_id: git2::Oid,
+        message: &str,
+    ) -> Result<git2::Oid> {
+        let parent = self.repo.find_commit(parent_id)?;
+        let tree = self.repo.find_tree(tree_id)?;
+        let author = git2_ext::ops::author_signature(&self.repo)?;
+        let committer = git2_ext::ops::commit_signature(&self.repo)?;
+        git2_ext::ops::commit(
             &self.repo,
-            head_oid,
-            msg,
+            &author,
+            &committer,
+            message,
+            &tree,
+            &[&parent],
             self.sign.as_ref().map(|s| s as &dyn git2_ext::ops::Sign),
         )
     }
 
-    pub fn squash(&mut self, head_id: git2::Oid, into_id: git2::Oid) -> Result<git2::Oid> {
-        git2_ext::ops::squash(
+    /// Recreate `head_oid` with the configured user as its author, like
+    /// `git commit --amend --reset-author`
+    pub fn reset_author(&mut self, head_oid: git2::Oid) -> Result<git2::Oid> {
+        check_shallow_boundary(&self.repo, &self.shallow_ids, head_oid)?;
+        let head_commit = self.repo.find_commit(head_oid)?;
+        let author = git2_ext::ops::author_signature(&self.repo)?;
+        let committer = git2_ext::ops::commit_signature(&self.repo)?;
+        let tree = head_commit.tree()?;
+        let parents = head_commit.parents().collect::<Vec<_>>();
+        let parents = parents.iter().collect::<Vec<_>>();
+        let message = String::from_utf8_lossy(head_commit.message_bytes());
+        git2_ext::ops::commit(
             &self.repo,
-            head_id,
-            into_id,
+            &author,
+            &committer,
+            &message,
+            &tree,
+            &parents,
             self.sign.as_ref().map(|s| s as &dyn git2_ext::ops::Sign),
         )
     }
 
+    pub fn squash(&mut self, head_id: git2::Oid, into_id: git2::Oid) -> Result<git2::Oid> {
+        check_shallow_boundary(&self.repo, &self.shallow_ids, into_id)?;
+        let sign = self.sign.as_ref().map(|s| s as &dyn git2_ext::ops::Sign);
+        squash(&self.repo, head_id, into_id, sign)
+    }
+
     pub fn stash_push(&mut self, message: Option<&str>) -> Result<git2::Oid> {
         let signature = self.repo.signature()?;
         self.repo.stash_save2(&signature, message, None)
@@ -469,7 +1693,8 @@ impl GitRepo {
     pub fn delete_branch(&mut self, name: &str) -> Result<()> {
         // HACK: We shouldn't limit ourselves to `Local`
         let mut branch = self.repo.find_branch(name, git2::BranchType::Local)?;
-        branch.delete()
+        branch.delete()?;
+        forget_push(&self.repo, name)
     }
 
     pub fn find_local_branch(&self, name: &str) -> Option<Branch> {
@@ -524,6 +1749,10 @@ impl GitRepo {
                     );
                     return None;
                 };
+                if branch.get().symbolic_target().is_some() {
+                    // Like `origin/HEAD`, an alias for another remote branch
+                    return None;
+                }
                 let (remote, name) = name.split_once('/').unwrap();
                 self.load_remote_branch(&branch, remote, name).ok()
             })
@@ -604,15 +1833,36 @@ impl GitRepo {
         Ok(())
     }
 
-    fn intern_string(&self, data: &str) -> std::rc::Rc<str> {
-        let mut interned_strings = self.interned_strings.borrow_mut();
-        if let Some(interned) = interned_strings.get(data) {
-            std::rc::Rc::clone(interned)
-        } else {
-            let interned = std::rc::Rc::from(data);
-            interned_strings.insert(std::rc::Rc::clone(&interned));
-            interned
+    pub fn worktrees(&self) -> Vec<Worktree> {
+        other_worktrees(&self.repo)
+    }
+
+    pub fn remotes(&self) -> Vec<String> {
+        self.repo
+            .remotes()
+            .map(|remotes| remotes.iter().flatten().map(ToOwned::to_owned).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn config_bool(&self, key: &str, default: bool) -> bool {
+        config_bool(&self.repo, key, default)
+    }
+
+    pub fn config_string(&self, key: &str) -> Option<String> {
+        config_value(&self.repo, key).flatten()
+    }
+
+    pub fn config_int(&self, key: &str) -> Option<i64> {
+        let value = config_value(&self.repo, key)?.unwrap_or_default();
+        let parsed = crate::config::parse_int(&value);
+        if parsed.is_none() {
+            log::warn!("Ignoring invalid integer for `{}`: {}", key, value);
         }
+        parsed
+    }
+
+    fn intern_string(&self, data: &str) -> std::rc::Rc<str> {
+        self.commits.intern_string(data)
     }
 }
 
@@ -644,6 +1894,38 @@ impl Repo for GitRepo {
         self.is_dirty()
     }
 
+    fn is_shallow(&self) -> bool {
+        self.is_shallow()
+    }
+
+    fn is_bare(&self) -> bool {
+        self.is_bare()
+    }
+
+    fn ignore_case(&self) -> bool {
+        self.ignore_case()
+    }
+
+    fn index_conflicts(&self) -> Vec<std::path::PathBuf> {
+        self.index_conflicts()
+    }
+
+    fn lfs_paths(&self, id: git2::Oid) -> Vec<std::path::PathBuf> {
+        self.lfs_paths(id)
+    }
+
+    fn changed_paths(&self, id: git2::Oid) -> Vec<std::path::PathBuf> {
+        self.changed_paths(id)
+    }
+
+    fn untracked_collisions(&self, id: git2::Oid) -> Result<Vec<std::path::PathBuf>> {
+        self.untracked_collisions(id)
+    }
+
+    fn is_signed(&self, id: git2::Oid) -> bool {
+        self.is_signed(id)
+    }
+
     fn merge_base(&self, one: git2::Oid, two: git2::Oid) -> Option<git2::Oid> {
         self.merge_base(one, two)
     }
@@ -684,14 +1966,30 @@ impl Repo for GitRepo {
         self.contains_commit(haystack_id, needle_id)
     }
 
+    fn patch_id(&self, id: git2::Oid) -> Option<git2::Oid> {
+        self.patch_id(id)
+    }
+
     fn cherry_pick(&mut self, head_id: git2::Oid, cherry_id: git2::Oid) -> Result<git2::Oid> {
         self.cherry_pick(head_id, cherry_id)
     }
 
+    fn rerere_pick(
+        &mut self,
+        head_id: git2::Oid,
+        cherry_id: git2::Oid,
+    ) -> Result<(git2::Oid, Vec<std::path::PathBuf>)> {
+        self.rerere_pick(head_id, cherry_id)
+    }
+
     fn reword(&mut self, head_oid: git2::Oid, msg: &str) -> Result<git2::Oid> {
         self.reword(head_oid, msg)
     }
 
+    fn reset_author(&mut self, head_oid: git2::Oid) -> Result<git2::Oid> {
+        self.reset_author(head_oid)
+    }
+
     fn squash(&mut self, head_id: git2::Oid, into_id: git2::Oid) -> Result<git2::Oid> {
         self.squash(head_id, into_id)
     }
@@ -739,6 +2037,30 @@ impl Repo for GitRepo {
     fn switch_commit(&mut self, id: git2::Oid) -> Result<()> {
         self.switch_commit(id)
     }
+
+    fn worktrees(&self) -> Vec<Worktree> {
+        self.worktrees()
+    }
+
+    fn remotes(&self) -> Vec<String> {
+        self.remotes()
+    }
+
+    fn abbrev_id(&self, id: git2::Oid) -> String {
+        self.abbrev_id(id)
+    }
+
+    fn config_bool(&self, key: &str, default: bool) -> bool {
+        self.config_bool(key, default)
+    }
+
+    fn config_string(&self, key: &str) -> Option<String> {
+        self.config_string(key)
+    }
+
+    fn config_int(&self, key: &str) -> Option<i64> {
+        self.config_int(key)
+    }
 }
 
 #[derive(Debug)]
@@ -746,6 +2068,7 @@ pub struct InMemoryRepo {
     commits: std::collections::HashMap<git2::Oid, (Option<git2::Oid>, std::rc::Rc<Commit>)>,
     branches: std::collections::HashMap<String, Branch>,
     head_id: Option<git2::Oid>,
+    config: std::collections::HashMap<String, Option<String>>,
 
     last_id: std::sync::atomic::AtomicUsize,
 }
@@ -756,6 +2079,7 @@ impl InMemoryRepo {
             commits: Default::default(),
             branches: Default::default(),
             head_id: Default::default(),
+            config: Default::default(),
             last_id: std::sync::atomic::AtomicUsize::new(1),
         }
     }
@@ -811,6 +2135,38 @@ impl InMemoryRepo {
         false
     }
 
+    pub fn is_shallow(&self) -> bool {
+        false
+    }
+
+    pub fn is_bare(&self) -> bool {
+        false
+    }
+
+    pub fn ignore_case(&self) -> bool {
+        false
+    }
+
+    pub fn index_conflicts(&self) -> Vec<std::path::PathBuf> {
+        Vec::new()
+    }
+
+    pub fn lfs_paths(&self, _id: git2::Oid) -> Vec<std::path::PathBuf> {
+        Vec::new()
+    }
+
+    pub fn changed_paths(&self, _id: git2::Oid) -> Vec<std::path::PathBuf> {
+        Vec::new()
+    }
+
+    pub fn untracked_collisions(&self, _id: git2::Oid) -> Result<Vec<std::path::PathBuf>> {
+        Ok(Vec::new())
+    }
+
+    pub fn is_signed(&self, _id: git2::Oid) -> bool {
+        false
+    }
+
     pub fn merge_base(&self, one: git2::Oid, two: git2::Oid) -> Option<git2::Oid> {
         let one_ancestors: Vec<_> = self.commits_from(one).collect();
         self.commits_from(two)
@@ -899,6 +2255,11 @@ impl InMemoryRepo {
         Ok(result)
     }
 
+    pub fn patch_id(&self, _id: git2::Oid) -> Option<git2::Oid> {
+        // Commits don't carry their changes
+        None
+    }
+
     pub fn contains_commit(&self, haystack_id: git2::Oid, needle_id: git2::Oid) -> Result<bool> {
         // Because we don't have the information for likeness matches, just checking for Oid
         let mut next = Some(haystack_id);
@@ -927,6 +2288,18 @@ impl InMemoryRepo {
         Ok(new_id)
     }
 
+    pub fn rerere_pick(
+        &mut self,
+        _head_id: git2::Oid,
+        _cherry_id: git2::Oid,
+    ) -> Result<(git2::Oid, Vec<std::path::PathBuf>)> {
+        Err(Error::new(
+            git2::ErrorCode::GenericError,
+            git2::ErrorClass::CherryPick,
+            "rerere is not supported in-memory",
+        ))
+    }
+
     pub fn reword(&mut self, head_id: git2::Oid, msg: &str) -> Result<git2::Oid> {
         let (head_parent, head_commit) = self.commits.get(&head_id).cloned().ok_or_else(|| {
             git2::Error::new(
@@ -945,6 +2318,24 @@ impl InMemoryRepo {
         Ok(new_id)
     }
 
+    pub fn reset_author(&mut self, head_id: git2::Oid) -> Result<git2::Oid> {
+        let (head_parent, head_commit) = self.commits.get(&head_id).cloned().ok_or_else(|| {
+            git2::Error::new(
+                git2::ErrorCode::NotFound,
+                git2::ErrorClass::Reference,
+                format!("could not find commit {head_id:?}"),
+            )
+        })?;
+
+        let mut reset_commit = Commit::clone(&head_commit);
+        let new_id = self.gen_id();
+        reset_commit.id = new_id;
+        reset_commit.author = self.user();
+        self.commits
+            .insert(new_id, (head_parent, std::rc::Rc::new(reset_commit)));
+        Ok(new_id)
+    }
+
     pub fn squash(&mut self, head_id: git2::Oid, into_id: git2::Oid) -> Result<git2::Oid> {
         self.commits.get(&head_id).cloned().ok_or_else(|| {
             Error::new(
@@ -1043,6 +2434,39 @@ impl InMemoryRepo {
         self.head_id = Some(id);
         Ok(())
     }
+
+    pub fn worktrees(&self) -> Vec<Worktree> {
+        Vec::new()
+    }
+
+    pub fn remotes(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    pub fn abbrev_id(&self, id: git2::Oid) -> String {
+        id.to_string()
+    }
+
+    /// Set `key`, with `None` for a key without `= value`
+    pub fn set_config(&mut self, key: &str, value: Option<&str>) {
+        self.config
+            .insert(key.to_owned(), value.map(ToOwned::to_owned));
+    }
+
+    pub fn config_bool(&self, key: &str, default: bool) -> bool {
+        self.config
+            .get(key)
+            .and_then(|value| crate::config::parse_bool(value.as_deref()))
+            .unwrap_or(default)
+    }
+
+    pub fn config_string(&self, key: &str) -> Option<String> {
+        self.config.get(key).cloned().flatten()
+    }
+
+    pub fn config_int(&self, key: &str) -> Option<i64> {
+        crate::config::parse_int(self.config.get(key)?.as_deref()?)
+    }
 }
 
 impl Default for InMemoryRepo {
@@ -1088,6 +2512,38 @@ impl Repo for InMemoryRepo {
         self.is_dirty()
     }
 
+    fn is_shallow(&self) -> bool {
+        self.is_shallow()
+    }
+
+    fn is_bare(&self) -> bool {
+        self.is_bare()
+    }
+
+    fn ignore_case(&self) -> bool {
+        self.ignore_case()
+    }
+
+    fn index_conflicts(&self) -> Vec<std::path::PathBuf> {
+        self.index_conflicts()
+    }
+
+    fn lfs_paths(&self, id: git2::Oid) -> Vec<std::path::PathBuf> {
+        self.lfs_paths(id)
+    }
+
+    fn changed_paths(&self, id: git2::Oid) -> Vec<std::path::PathBuf> {
+        self.changed_paths(id)
+    }
+
+    fn untracked_collisions(&self, id: git2::Oid) -> Result<Vec<std::path::PathBuf>> {
+        self.untracked_collisions(id)
+    }
+
+    fn is_signed(&self, id: git2::Oid) -> bool {
+        self.is_signed(id)
+    }
+
     fn merge_base(&self, one: git2::Oid, two: git2::Oid) -> Option<git2::Oid> {
         self.merge_base(one, two)
     }
@@ -1124,14 +2580,30 @@ impl Repo for InMemoryRepo {
         self.contains_commit(haystack_id, needle_id)
     }
 
+    fn patch_id(&self, id: git2::Oid) -> Option<git2::Oid> {
+        self.patch_id(id)
+    }
+
     fn cherry_pick(&mut self, head_id: git2::Oid, cherry_id: git2::Oid) -> Result<git2::Oid> {
         self.cherry_pick(head_id, cherry_id)
     }
 
+    fn rerere_pick(
+        &mut self,
+        head_id: git2::Oid,
+        cherry_id: git2::Oid,
+    ) -> Result<(git2::Oid, Vec<std::path::PathBuf>)> {
+        self.rerere_pick(head_id, cherry_id)
+    }
+
     fn reword(&mut self, head_oid: git2::Oid, msg: &str) -> Result<git2::Oid> {
         self.reword(head_oid, msg)
     }
 
+    fn reset_author(&mut self, head_oid: git2::Oid) -> Result<git2::Oid> {
+        self.reset_author(head_oid)
+    }
+
     fn squash(&mut self, head_id: git2::Oid, into_id: git2::Oid) -> Result<git2::Oid> {
         self.squash(head_id, into_id)
     }
@@ -1183,6 +2655,30 @@ impl Repo for InMemoryRepo {
     fn switch_commit(&mut self, id: git2::Oid) -> Result<()> {
         self.switch_commit(id)
     }
+
+    fn worktrees(&self) -> Vec<Worktree> {
+        self.worktrees()
+    }
+
+    fn remotes(&self) -> Vec<String> {
+        self.remotes()
+    }
+
+    fn abbrev_id(&self, id: git2::Oid) -> String {
+        self.abbrev_id(id)
+    }
+
+    fn config_bool(&self, key: &str, default: bool) -> bool {
+        self.config_bool(key, default)
+    }
+
+    fn config_string(&self, key: &str) -> Option<String> {
+        self.config_string(key)
+    }
+
+    fn config_int(&self, key: &str) -> Option<i64> {
+        self.config_int(key)
+    }
 }
 
 pub fn stash_push(repo: &mut dyn Repo, context: &str) -> Option<git2::Oid> {
@@ -14,9 +14,7 @@ impl BranchSet {
         for mut branch in repo.local_branches().map(Branch::from) {
             if protected.is_protected(branch.base_name()) {
                 log::trace!("Branch `{}` is protected", branch.display_name());
-                if let Some(remote) =
-                    repo.find_remote_branch(repo.pull_remote(), branch.base_name())
-                {
+                if let Some(remote) = crate::git::find_upstream_branch(repo, branch.base_name()) {
                     branch.set_kind(BranchKind::Mixed);
                     branch.set_pull_id(remote.id);
                     let mut remote: Branch = remote.into();
@@ -35,6 +33,18 @@ impl BranchSet {
             }
             branches.insert(branch);
         }
+        for mut branch in repo.remote_branches().map(Branch::from) {
+            let Some(remote) = branch.remote() else {
+                continue;
+            };
+            if branch.base_name() != "HEAD"
+                && protected.is_protected_remote(remote, branch.base_name())
+            {
+                log::trace!("Branch `{}` is protected", branch.display_name());
+                branch.set_kind(BranchKind::Protected);
+                branches.insert(branch);
+            }
+        }
         Ok(branches)
     }
 
@@ -108,6 +118,18 @@ impl BranchSet {
         }
     }
 
+    /// Point the local branches at `old_id` to `new_id`
+    ///
+    /// A cheap alternative to [`BranchSet::update`] when the caller moved the branches itself.
+    pub fn move_local(&mut self, old_id: git2::Oid, new_id: git2::Oid) {
+        for mut branch in self.branches.remove(&old_id).into_iter().flatten() {
+            if branch.local_name().is_some() {
+                branch.set_id(new_id);
+            }
+            self.insert(branch);
+        }
+    }
+
     pub fn remove(&mut self, oid: git2::Oid) -> Option<Vec<Branch>> {
         self.branches.remove(&oid)
     }
@@ -296,6 +318,7 @@ impl Extend<Branch> for BranchSet {
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Branch {
     core: crate::git::Branch,
+    ref_id: git2::Oid,
     kind: BranchKind,
     pull_id: Option<git2::Oid>,
     push_id: Option<git2::Oid>,
@@ -356,6 +379,11 @@ impl Branch {
         self.core.id
     }
 
+    /// Where the branch points in the repository, regardless of [`Branch::set_id`]
+    pub fn ref_id(&self) -> git2::Oid {
+        self.ref_id
+    }
+
     pub fn pull_id(&self) -> Option<git2::Oid> {
         self.pull_id
     }
@@ -368,6 +396,7 @@ impl Branch {
 impl From<crate::git::Branch> for Branch {
     fn from(core: crate::git::Branch) -> Self {
         Self {
+            ref_id: core.id,
             core,
             kind: BranchKind::Deleted,
             pull_id: None,
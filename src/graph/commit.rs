@@ -26,3 +26,24 @@ impl Default for Action {
 }
 
 impl crate::any::ResourceTag for Action {}
+
+/// The commit's parents changed from what is in the repository, so it has to be re-created
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Rebased;
+
+impl crate::any::ResourceTag for Rebased {}
+
+/// Why a commit is [`Action::Protected`]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ProtectedReason {
+    /// The commit is the tip of this protected branch
+    OnProtectedBranch(String),
+    /// The commit is an ancestor of this protected commit
+    DescendantProtected(git2::Oid),
+    /// A stack-wide policy, like `stack.protect-commit-count`, protected the branch
+    StackProtected,
+    /// Another author's commit, or one it is built on, is pinned in place by `--author`
+    Pinned(git2::Oid),
+}
+
+impl crate::any::ResourceTag for ProtectedReason {}
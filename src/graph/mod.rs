@@ -50,6 +50,17 @@ impl Graph {
             )
         })?;
 
+        Self::from_branches_with_root(repo, root_id, branches)
+    }
+
+    /// Like [`Graph::from_branches`] but rooted at `root_id`
+    ///
+    /// `root_id` must be an ancestor of every branch.
+    pub fn from_branches_with_root(
+        repo: &dyn crate::git::Repo,
+        root_id: git2::Oid,
+        branches: BranchSet,
+    ) -> crate::git::Result<Self> {
         let mut graph = Graph::with_base_id(root_id);
         graph.branches = branches;
         for branch_id in graph.branches.oids() {
@@ -82,6 +93,15 @@ impl Graph {
         }
     }
 
+    /// Insert `node` onto `parent_id` when that isn't its parent in the repository
+    ///
+    /// Like [`Graph::rebase`], this marks the commit [`Rebased`] so it gets re-created.
+    pub fn graft(&mut self, node: Node, parent_id: git2::Oid) {
+        let id = node.id;
+        self.insert(node, parent_id);
+        self.commit_set(id, Rebased);
+    }
+
     pub fn rebase(&mut self, id: git2::Oid, from: git2::Oid, to: git2::Oid) {
         assert!(self.contains_id(id), "expected to contain {id}");
         assert!(self.contains_id(from), "expected to contain {from}");
@@ -93,6 +113,7 @@ impl Graph {
         assert_ne!(id, self.root_id, "Cannot rebase root ({id})");
         let weight = self.graph.remove_edge(id, from).unwrap();
         self.graph.add_edge(id, to, weight);
+        self.commit_set(id, Rebased);
     }
 
     pub fn remove(&mut self, id: git2::Oid) -> Option<Node> {
@@ -104,6 +125,7 @@ impl Graph {
                 for (weight, parent_id) in parents.iter().copied().enumerate() {
                     self.graph.add_edge(child_id, parent_id, weight);
                 }
+                self.commit_set(child_id, Rebased);
             }
         }
         self.graph.remove_node(id).then(|| {
@@ -189,6 +211,27 @@ impl Graph {
         }
     }
 
+    /// The chain of commits from the root to `id`, following primary parents
+    pub fn path_to(&self, id: git2::Oid) -> Option<Vec<git2::Oid>> {
+        if !self.contains_id(id) {
+            return None;
+        }
+        let mut path = vec![id];
+        let mut current_id = id;
+        while current_id != self.root_id {
+            current_id = self.primary_parent_of(current_id)?;
+            path.push(current_id);
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    /// Whether the branches at `id` can be pushed, as `pushable` would mark them, without
+    /// recording anything in the graph
+    pub fn pushable_status(&self, id: git2::Oid) -> PushStatus {
+        ops::push_status(self, id, &mut BTreeMap::new()).unwrap_or_default()
+    }
+
     pub fn commit_get<R: Resource>(&self, id: git2::Oid) -> Option<&R> {
         let commit = self.commits.get(&id)?;
         let boxed_resource = commit.get(&AnyId::of::<R>())?;
@@ -5,19 +5,39 @@ use crate::graph::Graph;
 use crate::graph::Resource;
 
 pub fn protect_branches(graph: &mut Graph) {
-    let protected_oids: Vec<_> = graph
+    let protected_tips: Vec<_> = graph
         .branches
         .iter()
         .filter_map(|(oid, branches)| {
             branches
                 .iter()
                 .find(|b| b.kind() == crate::graph::BranchKind::Protected)
-                .map(|_| oid)
+                .map(|b| (oid, b.name()))
         })
-        .flat_map(|protected_oid| graph.ancestors_of(protected_oid))
         .collect();
-    for protected_oid in protected_oids {
-        graph.commit_set(protected_oid, crate::graph::Action::Protected);
+    for (tip_id, name) in protected_tips {
+        let ancestor_ids: Vec<_> = graph.ancestors_of(tip_id).collect();
+        for protected_oid in ancestor_ids {
+            let reason = if protected_oid == tip_id {
+                crate::graph::ProtectedReason::OnProtectedBranch(name.clone())
+            } else {
+                crate::graph::ProtectedReason::DescendantProtected(tip_id)
+            };
+            protect_commit(graph, protected_oid, reason);
+        }
+    }
+}
+
+fn protect_commit(graph: &mut Graph, id: git2::Oid, reason: crate::graph::ProtectedReason) {
+    graph.commit_set(id, crate::graph::Action::Protected);
+    // Being on a protected branch is the most direct explanation, so it always wins
+    let replace = match graph.commit_get::<crate::graph::ProtectedReason>(id) {
+        Some(crate::graph::ProtectedReason::OnProtectedBranch(_)) => false,
+        Some(_) => matches!(reason, crate::graph::ProtectedReason::OnProtectedBranch(_)),
+        None => true,
+    };
+    if replace {
+        graph.commit_set(id, reason);
     }
 }
 
@@ -73,7 +93,64 @@ pub fn protect_large_branches(graph: &mut Graph, max: usize) -> Vec<String> {
 fn mark_branch_protected(graph: &mut Graph, commit_id: git2::Oid) {
     let protected_oids: Vec<_> = graph.ancestors_of(commit_id).collect();
     for protected_oid in protected_oids {
-        graph.commit_set(protected_oid, crate::graph::Action::Protected);
+        protect_commit(
+            graph,
+            protected_oid,
+            crate::graph::ProtectedReason::StackProtected,
+        );
+    }
+}
+
+/// Pin each of `pinned_ids` in place by protecting it and everything it is built on
+///
+/// Commits built on a newly-protected one stay rewritable but aren't rebased off of it, see
+/// [`rebase_development_branches`].
+pub fn protect_pinned_commits(graph: &mut Graph, pinned_ids: &[git2::Oid]) {
+    for pinned_id in pinned_ids.iter().copied() {
+        let ancestor_ids: Vec<_> = graph.ancestors_of(pinned_id).collect();
+        for ancestor_id in ancestor_ids {
+            let action = graph
+                .commit_get::<crate::graph::Action>(ancestor_id)
+                .copied()
+                .unwrap_or_default();
+            if !action.is_protected() {
+                protect_commit(
+                    graph,
+                    ancestor_id,
+                    crate::graph::ProtectedReason::Pinned(pinned_id),
+                );
+            }
+        }
+    }
+}
+
+fn is_pinned(graph: &Graph, id: git2::Oid) -> bool {
+    matches!(
+        graph.commit_get::<crate::graph::ProtectedReason>(id),
+        Some(crate::graph::ProtectedReason::Pinned(_))
+    )
+}
+
+/// Treat `id` and everything built on it as development commits
+///
+/// For explicitly overriding protection; local protected branches on these commits will be moved
+/// along with them.
+pub fn unprotect_descendants(graph: &mut Graph, id: git2::Oid) {
+    let descendant_ids: Vec<_> = graph.descendants_of(id).collect();
+    for descendant_id in descendant_ids {
+        let action = graph
+            .commit_get::<crate::graph::Action>(descendant_id)
+            .copied()
+            .unwrap_or_default();
+        if action.is_protected() {
+            graph.commit_set(descendant_id, crate::graph::Action::Pick);
+        }
+        for branch in graph.branches.get_mut(descendant_id).into_iter().flatten() {
+            if branch.kind() == crate::graph::BranchKind::Protected && branch.local_name().is_some()
+            {
+                branch.set_kind(crate::graph::BranchKind::Mutable);
+            }
+        }
     }
 }
 
@@ -247,6 +324,51 @@ pub struct ForeignCommit;
 
 impl crate::any::ResourceTag for ForeignCommit {}
 
+/// Tag commits nobody opted in to rewriting, for `stack.default-action=protect`
+///
+/// A commit is claimed if it is in `picked` or is the `user`'s and not yet on the push remote.
+pub fn tag_unclaimed_commits(
+    graph: &mut Graph,
+    repo: &dyn crate::git::Repo,
+    user: Option<&str>,
+    picked: &[git2::Oid],
+) {
+    let push_ids = graph
+        .branches
+        .iter()
+        .flat_map(|(_, branches)| branches.iter().filter_map(|b| b.push_id()))
+        .collect::<Vec<_>>();
+    tag_commits_while(graph, |_graph, id| {
+        if picked.contains(&id) {
+            return None;
+        }
+        let commit = repo.find_commit(id)?;
+        let is_users = user.is_some()
+            && (commit.committer.as_deref() == user || commit.author.as_deref() == user);
+        let is_pushed = || {
+            push_ids
+                .iter()
+                .any(|push_id| repo.merge_base(id, *push_id) == Some(id))
+        };
+        (!is_users || is_pushed()).then(|| UnclaimedCommit.into())
+    })
+}
+
+pub fn protect_unclaimed_branches(
+    graph: &mut Graph,
+    repo: &dyn crate::git::Repo,
+    user: Option<&str>,
+    picked: &[git2::Oid],
+) {
+    tag_unclaimed_commits(graph, repo, user, picked);
+    protect_tagged_branch(graph, UnclaimedCommit);
+}
+
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct UnclaimedCommit;
+
+impl crate::any::ResourceTag for UnclaimedCommit {}
+
 /// Pre-requisites:
 /// - Running protect_branches
 ///
@@ -272,7 +394,7 @@ pub fn rebase_development_branches(graph: &mut Graph, onto_id: git2::Oid) {
                     .commit_get::<crate::graph::Action>(*id)
                     .copied()
                     .unwrap_or_default();
-                action.is_protected()
+                action.is_protected() && !is_pinned(graph, *id)
             })
             .collect();
         for base in bases {
@@ -308,6 +430,20 @@ pub fn rebase_pulled_branches(graph: &mut Graph, pull_start: git2::Oid, pull_end
     graph.branches.extend(end_branches);
 }
 
+/// Count the commits that may be rewritten, for catching a stack that's larger than expected
+pub fn pickable_count(graph: &Graph) -> usize {
+    graph
+        .descendants()
+        .filter(|id| {
+            graph
+                .commit_get::<crate::graph::Action>(*id)
+                .copied()
+                .unwrap_or_default()
+                .is_pick()
+        })
+        .count()
+}
+
 pub fn mark_wip(graph: &mut Graph, repo: &dyn crate::git::Repo) {
     let mut cursor = graph.descendants().into_cursor();
     while let Some(current_id) = cursor.next(graph) {
@@ -366,15 +502,32 @@ pub fn pushable(graph: &mut Graph) {
         .iter()
         .map(|(id, _branches)| id)
         .collect::<Vec<_>>();
+    let mut statuses = BTreeMap::new();
     for branch_id in branches {
-        mark_push_status(graph, branch_id);
+        push_status(graph, branch_id, &mut statuses);
+    }
+    for (branch_id, status) in statuses {
+        if let Some(status) = status {
+            graph.commit_set(branch_id, status);
+        }
     }
 }
 
-fn mark_push_status(graph: &mut Graph, branch_id: git2::Oid) -> Option<PushStatus> {
+/// Whether the branches at `branch_id` can be pushed, `None` if they are protected
+///
+/// This is the logic behind both `pushable` and `Graph::pushable_status`, with `statuses` caching
+/// what was found for the branches below it.
+pub(crate) fn push_status(
+    graph: &Graph,
+    branch_id: git2::Oid,
+    statuses: &mut BTreeMap<git2::Oid, Option<PushStatus>>,
+) -> Option<PushStatus> {
     if let Some(status) = graph.commit_get::<PushStatus>(branch_id) {
         return Some(*status);
     }
+    if let Some(status) = statuses.get(&branch_id) {
+        return *status;
+    }
 
     let mut status = Some(PushStatus::Pushable);
 
@@ -408,7 +561,7 @@ fn mark_push_status(graph: &mut Graph, branch_id: git2::Oid) -> Option<PushStatu
                 status = Some(PushStatus::Blocked("wip"));
                 break;
             } else if branch_id != parent_id && graph.branches.contains_oid(parent_id) {
-                let parent_status = mark_push_status(graph, parent_id);
+                let parent_status = push_status(graph, parent_id, statuses);
                 match parent_status {
                     Some(PushStatus::Blocked(reason)) => {
                         log::debug!(
@@ -446,9 +599,7 @@ fn mark_push_status(graph: &mut Graph, branch_id: git2::Oid) -> Option<PushStatu
         status = Some(PushStatus::Pushed);
     }
 
-    if let Some(status) = status {
-        graph.commit_set(branch_id, status);
-    }
+    statuses.insert(branch_id, status);
     status
 }
 
@@ -603,7 +754,94 @@ pub fn delete_merged_branches(
     removed
 }
 
+/// Development branches whose commits are all among the pulled IDs, by ID or patch-id
+///
+/// This catches branches that were rebase-merged, where the merged commits were re-created rather
+/// than the branch's own commits being pulled.  Each branch is judged on [`branch_commits`], so a
+/// branch stacked on a merged one is only merged if its own commits are too.
+pub fn find_merged_branches(
+    graph: &Graph,
+    repo: &dyn crate::git::Repo,
+    pulled_ids: impl Iterator<Item = git2::Oid>,
+) -> Vec<crate::graph::Branch> {
+    let pulled_ids: HashSet<_> = pulled_ids.collect();
+    let pulled_patch_ids: HashSet<_> = pulled_ids
+        .iter()
+        .filter_map(|id| repo.patch_id(*id))
+        .collect();
+
+    let mut merged = Vec::new();
+    for (tip_id, branches) in graph.branches.iter() {
+        let branches = branches
+            .iter()
+            .filter(|b| b.kind() == crate::graph::BranchKind::Mutable && b.local_name().is_some())
+            .collect::<Vec<_>>();
+        if branches.is_empty() || !graph.contains_id(tip_id) {
+            continue;
+        }
+        let commits = branch_commits(graph, tip_id);
+        let is_merged = !commits.is_empty()
+            && commits.iter().all(|id| {
+                pulled_ids.contains(id)
+                    || repo
+                        .patch_id(*id)
+                        .map(|patch_id| pulled_patch_ids.contains(&patch_id))
+                        .unwrap_or(false)
+            });
+        if is_merged {
+            merged.extend(branches.into_iter().cloned());
+        }
+    }
+    merged
+}
+
+/// Drop the commits of a branch found by [`find_merged_branches`], rebasing whatever is stacked
+/// on top of it
+///
+/// Returns the branches that pointed at it, for deleting.
+pub fn delete_merged_branch(graph: &mut Graph, tip_id: git2::Oid) -> Vec<crate::graph::Branch> {
+    let mut removed = Vec::new();
+    if !graph.contains_id(tip_id) {
+        // Already removed with another branch at the same commit
+        return removed;
+    }
+    for id in branch_commits(graph, tip_id) {
+        if let Some(node) = graph.remove(id) {
+            removed.extend(
+                node.branches
+                    .into_iter()
+                    .flatten()
+                    .filter(|b| b.kind() == crate::graph::BranchKind::Mutable)
+                    .map(|mut b| {
+                        b.set_kind(crate::graph::BranchKind::Deleted);
+                        b
+                    }),
+            );
+        }
+    }
+    removed
+}
+
 pub fn fixup(graph: &mut Graph, repo: &dyn crate::git::Repo, effect: crate::config::Fixup) {
+    fixup_matching(graph, repo, effect, &|_| true);
+}
+
+/// Like [`fixup`] but only for fixups of `targets`, leaving the rest where they are
+pub fn fixup_within(
+    graph: &mut Graph,
+    repo: &dyn crate::git::Repo,
+    effect: crate::config::Fixup,
+    targets: &[git2::Oid],
+) {
+    fixup_matching(graph, repo, effect, &|id| targets.contains(&id));
+}
+
+fn fixup_matching(
+    graph: &mut Graph,
+    repo: &dyn crate::git::Repo,
+    effect: crate::config::Fixup,
+    is_target: &dyn Fn(git2::Oid) -> bool,
+) {
     if effect == crate::config::Fixup::Ignore {
         return;
     }
@@ -646,16 +884,13 @@ pub fn fixup(graph: &mut Graph, repo: &dyn crate::git::Repo, effect: crate::conf
             let anc_commit = repo
                 .find_commit(ancestor_id)
                 .expect("all commits in graph present in git");
-            if let Some(anc_summary) = anc_commit.fixup_summary() {
-                if anc_summary == summary {
+            let matches = anc_commit.fixup_summary() == Some(summary.as_ref())
+                || anc_commit.summary == summary;
+            if matches {
+                if is_target(ancestor_id) {
                     fixup_commit(graph, fixup_id, ancestor_id, effect);
                     fixed = true;
-                    break;
                 }
-            }
-            if anc_commit.summary == summary {
-                fixup_commit(graph, fixup_id, ancestor_id, effect);
-                fixed = true;
                 break;
             }
         }
@@ -688,7 +923,7 @@ fn fixup_commit(
 
     // Move the fixup commit
     let node = graph.remove(fixup_id).expect("fixup is always valid");
-    graph.insert(node, target_id);
+    graph.graft(node, target_id);
 
     // Re-parent all commits to the fixup commit
     for target_child_id in graph.children_of(target_id).collect::<Vec<_>>() {
@@ -860,11 +1095,222 @@ pub fn reword_commit(
     Ok(())
 }
 
+pub fn reset_author(graph: &mut Graph, id: git2::Oid) -> Result<(), eyre::Error> {
+    eyre::ensure!(
+        graph.contains_id(id),
+        "cannot rewrite commit {}, not present",
+        id
+    );
+
+    graph.commit_set(id, ResetAuthor);
+
+    Ok(())
+}
+
+/// Commits that belong only to the branch at `tip_id`, oldest first
+///
+/// The walk stops at protected commits, at commits another branch points to, and at commits that
+/// other lines of development build on.
+pub fn branch_commits(graph: &Graph, tip_id: git2::Oid) -> Vec<git2::Oid> {
+    let mut commits = Vec::new();
+    let mut current_id = tip_id;
+    loop {
+        let action = graph
+            .commit_get::<crate::graph::Action>(current_id)
+            .copied()
+            .unwrap_or_default();
+        if current_id == graph.root_id() || action.is_protected() {
+            break;
+        }
+        if current_id != tip_id {
+            if graph.branches.contains_oid(current_id) {
+                break;
+            }
+            if 1 < graph.children_of(current_id).count() {
+                break;
+            }
+        }
+        commits.push(current_id);
+        match graph.primary_parent_of(current_id) {
+            Some(parent_id) => current_id = parent_id,
+            None => break,
+        }
+    }
+    commits.reverse();
+    commits
+}
+
+pub fn squash_branch(
+    graph: &mut Graph,
+    tip_id: git2::Oid,
+    message: String,
+) -> Result<(), eyre::Error> {
+    eyre::ensure!(
+        graph.contains_id(tip_id),
+        "cannot squash commit {}, not present",
+        tip_id
+    );
+    let action = graph
+        .commit_get::<crate::graph::Action>(tip_id)
+        .copied()
+        .unwrap_or_default();
+    eyre::ensure!(!action.is_protected(), "cannot squash protected commits");
+
+    let commits = branch_commits(graph, tip_id);
+    let (first_id, rest) = commits
+        .split_first()
+        .ok_or_else(|| eyre::format_err!("no commits to squash"))?;
+    for id in &commits {
+        eyre::ensure!(
+            graph.parents_of(*id).count() <= 1,
+            "cannot squash merge commit {}",
+            id
+        );
+    }
+
+    for id in rest {
+        graph.commit_set(*id, crate::graph::Action::Fixup);
+    }
+    graph.commit_set(*first_id, Reword(message));
+
+    Ok(())
+}
+
+/// Remove a commit from the stack, rebasing its children over the gap
+///
+/// Branches pointing at the commit are moved to its parent and returned so the caller can report
+/// them.
+pub fn drop_commit(graph: &mut Graph, id: git2::Oid) -> Result<Vec<super::Branch>, eyre::Error> {
+    eyre::ensure!(
+        graph.contains_id(id),
+        "cannot drop commit {}, not present",
+        id
+    );
+    let action = graph
+        .commit_get::<crate::graph::Action>(id)
+        .copied()
+        .unwrap_or_default();
+    eyre::ensure!(!action.is_protected(), "cannot drop protected commits");
+    eyre::ensure!(
+        graph.parents_of(id).count() <= 1,
+        "cannot drop merge commit {}",
+        id
+    );
+
+    // Re-target all branches from the dropped commit to its parent
+    let parent_id = graph
+        .primary_parent_of(id)
+        .expect("non-root commits have a parent");
+    let mut moved = Vec::new();
+    for mut branch in graph.branches.remove(id).into_iter().flatten() {
+        branch.set_id(parent_id);
+        moved.push(branch.clone());
+        graph.branches.insert(branch);
+    }
+
+    // Children are re-parented onto `parent_id`
+    graph.remove(id).expect("presence checked above");
+
+    Ok(moved)
+}
+
+/// Copy a commit from elsewhere into the stack, right on top of `onto_id`
+///
+/// `onto_id`'s children are rebased onto the copy and branches pointing at `onto_id` move to it.
+pub fn cherry_pick(
+    graph: &mut Graph,
+    id: git2::Oid,
+    onto_id: git2::Oid,
+) -> Result<(), eyre::Error> {
+    eyre::ensure!(
+        graph.contains_id(onto_id),
+        "cannot cherry-pick onto {}, not present",
+        onto_id
+    );
+    eyre::ensure!(
+        !graph.contains_id(id),
+        "cannot cherry-pick {}, already in the stack",
+        id
+    );
+    let action = graph
+        .commit_get::<crate::graph::Action>(onto_id)
+        .copied()
+        .unwrap_or_default();
+    eyre::ensure!(
+        !action.is_protected(),
+        "cannot cherry-pick onto protected commits"
+    );
+
+    let children = graph.children_of(onto_id).collect::<Vec<_>>();
+    graph.graft(crate::graph::Node::new(id), onto_id);
+    for child_id in children {
+        graph.rebase(child_id, onto_id, id);
+    }
+    graph.branches.move_local(onto_id, id);
+
+    Ok(())
+}
+
+/// Find the commit below `head_id` that last touched each of `paths`, like `git blame` for files
+///
+/// Only the unprotected part of the stack is searched and pending fixups are skipped, since they
+/// are not commits of their own.  Paths no commit in the stack touched are left out.  With
+/// [`Repo::ignore_case`][crate::git::Repo::ignore_case], paths that only differ in case match.
+pub fn find_fixup_targets(
+    graph: &Graph,
+    repo: &dyn crate::git::Repo,
+    head_id: git2::Oid,
+    paths: &[std::path::PathBuf],
+) -> Vec<(std::path::PathBuf, git2::Oid)> {
+    let ignore_case = repo.ignore_case();
+    let mut remaining = paths.to_vec();
+    let mut targets = Vec::new();
+    let mut cursor = graph
+        .ancestors_of(head_id)
+        .into_cursor()
+        .primary_parents(true);
+    while let Some(current_id) = cursor.next(graph) {
+        if remaining.is_empty() {
+            break;
+        }
+        let action = graph
+            .commit_get::<crate::graph::Action>(current_id)
+            .copied()
+            .unwrap_or_default();
+        if current_id == graph.root_id() || action.is_protected() {
+            cursor.stop();
+            continue;
+        }
+        if graph.commit_get::<Fixup>(current_id).is_some() {
+            continue;
+        }
+
+        let changed = repo.changed_paths(current_id);
+        remaining.retain(|path| {
+            if changed
+                .iter()
+                .any(|changed| crate::git::same_path(changed, path, ignore_case))
+            {
+                targets.push((path.clone(), current_id));
+                false
+            } else {
+                true
+            }
+        });
+    }
+    targets
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Reword(String);
 
 impl crate::any::ResourceTag for Reword {}
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ResetAuthor;
+
+impl crate::any::ResourceTag for ResetAuthor {}
+
 pub fn to_scripts(
     graph: &Graph,
     dropped_branches: Vec<super::Branch>,
@@ -885,13 +1331,16 @@ pub fn to_scripts(
     let mut descendants = graph.descendants().into_cursor();
     let mut seen = std::collections::HashSet::new();
     while let Some(descendant_id) = descendants.next(graph) {
+        let mut in_place = Vec::new();
         for child_id in graph.children_of(descendant_id) {
             let action = graph
                 .commit_get::<crate::graph::Action>(child_id)
                 .copied()
                 .unwrap_or_default();
             if !seen.insert(child_id) {
-            } else if action.is_protected() {
+                continue;
+            }
+            if action.is_protected() {
                 let mut batch = crate::rewrite::Batch::new(child_id);
                 if let Some(dropped) = dropped_branches.remove(&descendant_id) {
                     batch.push(
@@ -900,7 +1349,7 @@ pub fn to_scripts(
                     );
                 }
                 for branch in graph.branches.get(child_id).into_iter().flatten() {
-                    if branch.kind().has_user_commits() {
+                    if branch.kind().has_user_commits() && branch.ref_id() != child_id {
                         if let Some(local_name) = branch.local_name() {
                             batch.push(
                                 child_id,
@@ -912,6 +1361,9 @@ pub fn to_scripts(
                 if !batch.is_empty() {
                     scripts.push(vec![batch].into());
                 }
+            } else if is_in_place(graph, child_id) {
+                descendants.stop();
+                in_place.push(child_id);
             } else {
                 descendants.stop();
                 let mut script = Vec::new();
@@ -925,6 +1377,28 @@ pub fn to_scripts(
                 scripts.push(script.into());
             }
         }
+
+        // Nothing to rewrite until we find a commit that moved or changed
+        while let Some(in_place_id) = in_place.pop() {
+            for child_id in graph.children_of(in_place_id) {
+                if !seen.insert(child_id) {
+                    continue;
+                }
+                if is_in_place(graph, child_id) {
+                    in_place.push(child_id);
+                } else {
+                    let mut script = Vec::new();
+                    gather_script(
+                        graph,
+                        in_place_id,
+                        child_id,
+                        &mut dropped_branches,
+                        &mut script,
+                    );
+                    scripts.push(script.into());
+                }
+            }
+        }
     }
 
     if !dropped_branches.is_empty() {
@@ -938,6 +1412,32 @@ pub fn to_scripts(
     scripts
 }
 
+/// Whether the commit and its branches can be left as they are in the repository
+fn is_in_place(graph: &Graph, id: git2::Oid) -> bool {
+    let action = graph
+        .commit_get::<crate::graph::Action>(id)
+        .copied()
+        .unwrap_or_default();
+    action.is_pick()
+        && graph.commit_get::<crate::graph::Rebased>(id).is_none()
+        && graph.commit_get::<Reword>(id).is_none()
+        && graph.commit_get::<ResetAuthor>(id).is_none()
+        && graph.children_of(id).all(|child_id| {
+            !graph
+                .commit_get::<crate::graph::Action>(child_id)
+                .copied()
+                .unwrap_or_default()
+                .is_fixup()
+        })
+        && graph
+            .branches
+            .get(id)
+            .into_iter()
+            .flatten()
+            .filter(|b| b.kind().has_user_commits() && b.local_name().is_some())
+            .all(|b| b.ref_id() == id)
+}
+
 fn gather_script(
     graph: &Graph,
     onto_id: git2::Oid,
@@ -962,6 +1462,9 @@ fn gather_script(
                 if let Some(Reword(message)) = graph.commit_get::<Reword>(id) {
                     batch.push(id, crate::rewrite::Command::Reword(message.clone()));
                 }
+                if graph.commit_get::<ResetAuthor>(id).is_some() {
+                    batch.push(id, crate::rewrite::Command::ResetAuthor);
+                }
                 for branch in graph.branches.get(id).into_iter().flatten() {
                     if branch.kind().has_user_commits() {
                         if let Some(local_name) = branch.local_name() {
@@ -1013,3 +1516,135 @@ fn gather_script(
 
     script.push(batch);
 }
+
+/// Create a copy of each development branch, named by `rename`, at the commit it points to
+///
+/// No commits are rewritten; this only emits [`crate::rewrite::Command::CreateBranch`].
+pub fn to_duplicate_script(
+    graph: &Graph,
+    mut rename: impl FnMut(&str) -> String,
+) -> crate::rewrite::Script {
+    let mut batches = Vec::new();
+    for (id, branches) in graph.branches.iter() {
+        let mut batch = crate::rewrite::Batch::new(id);
+        for branch in branches {
+            if branch.kind().has_user_commits() {
+                if let Some(local_name) = branch.local_name() {
+                    batch.push(
+                        id,
+                        crate::rewrite::Command::CreateBranch(rename(local_name)),
+                    );
+                }
+            }
+        }
+        if !batch.is_empty() {
+            batches.push(batch);
+        }
+    }
+    batches.into()
+}
+
+/// What running the scripts from [`to_scripts`] will do, for reviewing before anything is
+/// rewritten
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct Summary {
+    /// Branches that will point to re-created commits
+    pub moved: Vec<MovedBranch>,
+    /// Commits that won't be in the re-created stack
+    pub dropped: Vec<git2::Oid>,
+    /// Branches that will be deleted
+    pub deleted: Vec<String>,
+}
+
+impl Summary {
+    pub fn is_empty(&self) -> bool {
+        self.moved.is_empty() && self.dropped.is_empty() && self.deleted.is_empty()
+    }
+
+    /// Whether more than one branch is affected or anything will be lost
+    pub fn is_destructive(&self) -> bool {
+        1 < self.moved.len() || !self.dropped.is_empty() || !self.deleted.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MovedBranch {
+    pub name: String,
+    /// Where the branch points now
+    pub old_id: git2::Oid,
+    /// The commit the branch's re-created commits will be on top of
+    pub onto_id: git2::Oid,
+    /// How many re-created commits will be on top of `onto_id`, up to the branch
+    pub commit_count: usize,
+}
+
+/// Summarize the change from `before` to `after`, as carried out by `scripts`
+pub fn summarize(
+    repo: &dyn crate::git::Repo,
+    before: &Graph,
+    after: &Graph,
+    scripts: &[crate::rewrite::Script],
+) -> Summary {
+    let mut rewritten = HashSet::new();
+    let mut branches = std::collections::BTreeSet::new();
+    let mut deleted = std::collections::BTreeSet::new();
+    for script in scripts {
+        let stats = script.stats();
+        rewritten.extend(stats.rewritten);
+        branches.extend(stats.branches);
+        deleted.extend(stats.deleted_branches);
+    }
+
+    let mut moved = Vec::new();
+    for name in branches {
+        let Some(old) = repo.find_local_branch(&name) else {
+            continue;
+        };
+        let Some(new_id) = after.branches.iter().find_map(|(id, branches)| {
+            branches
+                .iter()
+                .any(|b| b.local_name() == Some(name.as_str()))
+                .then_some(id)
+        }) else {
+            continue;
+        };
+        let mut onto_id = new_id;
+        let mut commit_count = 0;
+        while rewritten.contains(&onto_id) {
+            let action = after
+                .commit_get::<crate::graph::Action>(onto_id)
+                .copied()
+                .unwrap_or_default();
+            if !action.is_fixup() {
+                commit_count += 1;
+            }
+            match after.primary_parent_of(onto_id) {
+                Some(parent_id) => onto_id = parent_id,
+                None => break,
+            }
+        }
+        moved.push(MovedBranch {
+            name,
+            old_id: old.id,
+            onto_id,
+            commit_count,
+        });
+    }
+
+    let dropped = before
+        .descendants()
+        .filter(|id| {
+            let action = before
+                .commit_get::<crate::graph::Action>(*id)
+                .copied()
+                .unwrap_or_default();
+            !action.is_protected() && !after.contains_id(*id)
+        })
+        .collect();
+
+    Summary {
+        moved,
+        dropped,
+        deleted: deleted.into_iter().collect(),
+    }
+}
@@ -32,6 +32,20 @@ impl Script {
         branches
     }
 
+    /// Commits to be cherry-picked, including by dependents
+    pub fn cherry_picks(&self) -> Vec<git2::Oid> {
+        let mut ids = Vec::new();
+        for command in &self.commands {
+            if let Command::CherryPick(id) = command {
+                ids.push(*id);
+            }
+        }
+        for dependent in &self.dependents {
+            ids.extend(dependent.cherry_picks());
+        }
+        ids
+    }
+
     pub fn is_branch_deleted(&self, branch: &str) -> bool {
         for command in &self.commands {
             if let Command::DeleteBranch(ref current) = command {
@@ -49,6 +63,24 @@ impl Script {
 
         false
     }
+
+    /// Drop `SwitchMark`s to where the executor already is
+    ///
+    /// Sibling dependents each start by switching to the mark their parent registered last.  The
+    /// first of them runs right after that, so its switch is a no-op; the later ones still need
+    /// theirs since the sibling before them moved on.
+    pub fn coalesce_dependents(&mut self) {
+        if let (Some(Command::RegisterMark(mark)), Some(first)) =
+            (self.commands.last(), self.dependents.first_mut())
+        {
+            if first.commands.first() == Some(&Command::SwitchMark(*mark)) {
+                first.commands.remove(0);
+            }
+        }
+        for dependent in self.dependents.iter_mut() {
+            dependent.coalesce_dependents();
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
@@ -75,6 +107,9 @@ pub struct Executor {
     branches: Vec<(git2::Oid, String)>,
     delete_branches: Vec<String>,
     post_rewrite: Vec<(git2::Oid, git2::Oid)>,
+    worktree_branches: std::collections::HashMap<String, std::path::PathBuf>,
+    head_branch: Option<String>,
+    branch_timings: Vec<(String, std::time::Duration)>,
     dry_run: bool,
     detached: bool,
 }
@@ -88,6 +123,13 @@ impl Executor {
             branches: Default::default(),
             delete_branches: Default::default(),
             post_rewrite: Default::default(),
+            worktree_branches: repo
+                .worktrees()
+                .into_iter()
+                .filter_map(|w| Some((w.branch?, w.path)))
+                .collect(),
+            head_branch: repo.head_branch().map(|b| b.name),
+            branch_timings: Default::default(),
             dry_run,
             detached: false,
         }
@@ -103,12 +145,16 @@ impl Executor {
 
         log::trace!("Applying `{}`", branch_name);
         log::trace!("Script: {:#?}", script.commands);
+        let start = std::time::Instant::now();
         #[allow(clippy::disallowed_methods)]
         let res = script
             .commands
             .iter()
-            .try_for_each(|command| self.stage_single(repo, command));
-        match res.and_then(|_| self.commit(repo)) {
+            .try_for_each(|command| self.stage_single(repo, command))
+            .and_then(|_| self.commit(repo));
+        self.branch_timings
+            .push((branch_name.to_owned(), start.elapsed()));
+        match res {
             Ok(()) => {
                 log::trace!("         `{}` succeeded", branch_name);
                 for dependent in script.dependents.iter() {
@@ -128,6 +174,11 @@ impl Executor {
         failures
     }
 
+    /// How long each script took to apply, by branch, not counting its dependents
+    pub fn branch_timings(&self) -> &[(String, std::time::Duration)] {
+        &self.branch_timings
+    }
+
     pub fn stage_single(
         &mut self,
         repo: &mut dyn crate::legacy::git::Repo,
@@ -208,9 +259,13 @@ impl Executor {
             }
             Command::CreateBranch(name) => {
                 let branch_oid = self.head_oid;
+                if repo.find_local_branch(name).map(|b| b.id) != Some(branch_oid) {
+                    self.ensure_not_checked_out(name)?;
+                }
                 self.branches.push((branch_oid, name.to_owned()));
             }
             Command::DeleteBranch(name) => {
+                self.ensure_not_checked_out(name)?;
                 self.delete_branches.push(name.to_owned());
             }
         }
@@ -218,7 +273,40 @@ impl Executor {
         Ok(())
     }
 
+    /// Moving a branch checked out in another worktree would leave that worktree out of sync
+    fn ensure_not_checked_out(&self, name: &str) -> Result<(), git2::Error> {
+        if let Some(path) = self.worktree_branches.get(name) {
+            return Err(git2::Error::new(
+                git2::ErrorCode::Locked,
+                git2::ErrorClass::Worktree,
+                format!(
+                    "`{}` is checked out in the worktree at {}",
+                    name,
+                    path.display()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
     pub fn commit(&mut self, repo: &mut dyn crate::legacy::git::Repo) -> Result<(), git2::Error> {
+        // `close` force-checks-out the branch `HEAD` was on, so refuse before moving it rather
+        // than clobbering untracked files, like `git checkout` does
+        if !self.dry_run {
+            let checkout_id = self.head_branch.as_deref().and_then(|head_branch| {
+                self.branches
+                    .iter()
+                    .find(|(_, name)| name == head_branch)
+                    .map(|(id, _)| *id)
+            });
+            if let Some(checkout_id) = checkout_id.filter(|id| *id != repo.head_commit().id) {
+                let collisions = repo.untracked_collisions(checkout_id)?;
+                if !collisions.is_empty() {
+                    return Err(crate::git::untracked_collisions_error(&collisions));
+                }
+            }
+        }
+
         let hook_repo = repo.path().map(git2::Repository::open).transpose()?;
         let hooks = if self.dry_run {
             None
@@ -1,30 +1,83 @@
 #[derive(Clone, Debug)]
 pub struct ProtectedBranches {
     ignores: ignore::gitignore::Gitignore,
+    /// Entries qualified with a remote, like `origin/main`, by the remote they name
+    remote_ignores: std::collections::BTreeMap<String, ignore::gitignore::Gitignore>,
 }
 
 impl ProtectedBranches {
     pub fn new<'p>(patterns: impl IntoIterator<Item = &'p str>) -> eyre::Result<Self> {
         let mut ignores = ignore::gitignore::GitignoreBuilder::new("");
+        let mut remote_ignores = std::collections::BTreeMap::new();
         for pattern in patterns {
             ignores.add_line(None, pattern)?;
+            if let Some((remote, branch_pattern)) = split_remote(pattern) {
+                remote_ignores
+                    .entry(remote.to_owned())
+                    .or_insert_with(|| ignore::gitignore::GitignoreBuilder::new(""))
+                    .add_line(None, &branch_pattern)?;
+            }
         }
         let ignores = ignores.build()?;
-        Ok(Self { ignores })
+        let remote_ignores = remote_ignores
+            .into_iter()
+            .map(|(remote, ignores)| Ok((remote, ignores.build()?)))
+            .collect::<Result<_, ignore::Error>>()?;
+        Ok(Self {
+            ignores,
+            remote_ignores,
+        })
     }
 
     pub fn is_protected(&self, name: &str) -> bool {
-        let name_match = self.ignores.matched_path_or_any_parents(name, false);
-        match name_match {
-            ignore::Match::None => false,
-            ignore::Match::Ignore(glob) => {
-                log::trace!("{}: ignored {:?}", name, glob.original());
-                true
-            }
-            ignore::Match::Whitelist(glob) => {
-                log::trace!("{}: allowed {:?}", name, glob.original());
-                false
-            }
+        is_match(&self.ignores, name)
+    }
+
+    /// Whether the remote-tracking branch is protected by a remote-qualified entry, like
+    /// `origin/main`
+    ///
+    /// Unqualified entries, like `main`, only protect the remote-tracking branch through its local
+    /// branch.
+    pub fn is_protected_remote(&self, remote: &str, name: &str) -> bool {
+        self.remote_ignores
+            .get(remote)
+            .map(|ignores| is_match(ignores, name))
+            .unwrap_or(false)
+    }
+}
+
+/// Split an entry like `origin/main` into the remote it names and the entry for that remote's
+/// branches
+///
+/// Whether the first component is a remote is only known once a remote by that name is looked up,
+/// so `release/*` only applies to the remote-tracking branches of a remote called `release`.  A
+/// leading `/` anchors the entry to local branches and a trailing `/` only marks a folder.
+fn split_remote(pattern: &str) -> Option<(&str, String)> {
+    let (negation, pattern) = match pattern.strip_prefix('!') {
+        Some(pattern) => ("!", pattern),
+        None => ("", pattern),
+    };
+    if pattern.starts_with('/') {
+        return None;
+    }
+    let (remote, branch) = pattern.split_once('/')?;
+    if branch.trim_end_matches('/').is_empty() {
+        return None;
+    }
+    Some((remote, format!("{negation}/{branch}")))
+}
+
+fn is_match(ignores: &ignore::gitignore::Gitignore, name: &str) -> bool {
+    let name_match = ignores.matched_path_or_any_parents(name, false);
+    match name_match {
+        ignore::Match::None => false,
+        ignore::Match::Ignore(glob) => {
+            log::trace!("{}: ignored {:?}", name, glob.original());
+            true
+        }
+        ignore::Match::Whitelist(glob) => {
+            log::trace!("{}: allowed {:?}", name, glob.original());
+            false
         }
     }
 }
@@ -61,4 +114,32 @@ mod test {
         assert!(protect.is_protected("release/v1.0.0"));
         assert!(!protect.is_protected("feature"));
     }
+
+    #[test]
+    fn remote_qualified() {
+        let protect = ProtectedBranches::new(vec!["origin/main"]).unwrap();
+        assert!(!protect.is_protected("main"));
+        assert!(protect.is_protected_remote("origin", "main"));
+        assert!(!protect.is_protected_remote("upstream", "main"));
+    }
+
+    #[test]
+    fn remote_qualified_by_remote_name() {
+        let protect =
+            ProtectedBranches::new(vec!["release/*", "origin/release/*", "!origin/release/wip"])
+                .unwrap();
+        assert!(protect.is_protected_remote("origin", "release/v1.0.0"));
+        assert!(!protect.is_protected_remote("origin", "release/wip"));
+        assert!(!protect.is_protected_remote("upstream", "release/v1.0.0"));
+        assert!(!protect.is_protected_remote("origin", "v1.0.0"));
+    }
+
+    #[test]
+    fn unqualified_ignores_remotes() {
+        let protect = ProtectedBranches::new(vec!["main", "/dev", "release/"]).unwrap();
+        assert!(protect.is_protected("main"));
+        assert!(!protect.is_protected_remote("origin", "main"));
+        assert!(!protect.is_protected_remote("origin", "dev"));
+        assert!(!protect.is_protected_remote("origin", "release/v1.0.0"));
+    }
 }
@@ -14,6 +14,7 @@ pub trait Repo {
     fn resolve(&self, revspec: &str) -> Option<std::rc::Rc<Commit>>;
     fn parent_ids(&self, head_id: git2::Oid) -> Result<Vec<git2::Oid>, git2::Error>;
     fn commit_count(&self, base_id: git2::Oid, head_id: git2::Oid) -> Option<usize>;
+    fn ahead_behind(&self, local_id: git2::Oid, upstream_id: git2::Oid) -> Option<(usize, usize)>;
     fn commit_range(
         &self,
         base_bound: std::ops::Bound<&git2::Oid>,
@@ -42,6 +43,12 @@ pub trait Repo {
     fn remote_branches(&self) -> Box<dyn Iterator<Item = Branch> + '_>;
     fn detach(&mut self) -> Result<(), git2::Error>;
     fn switch(&mut self, name: &str) -> Result<(), git2::Error>;
+    fn worktrees(&self) -> Vec<crate::git::Worktree>;
+    /// Untracked files in the worktree that checking out `id` would overwrite
+    fn untracked_collisions(&self, id: git2::Oid) -> Result<Vec<std::path::PathBuf>, git2::Error>;
+    fn patch_id(&self, id: git2::Oid) -> Option<git2::Oid>;
+    fn revert_patch_id(&self, id: git2::Oid) -> Option<git2::Oid>;
+    fn abbrev_id(&self, id: git2::Oid) -> String;
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -49,14 +56,23 @@ pub struct Branch {
     pub remote: Option<String>,
     pub name: String,
     pub id: git2::Oid,
+    /// What we last pushed the branch as, see [`GitRepo::resolve_push_id`]
     pub push_id: Option<git2::Oid>,
     pub pull_id: Option<git2::Oid>,
+    /// The push remote's branch, as of the last fetch
+    pub remote_id: Option<git2::Oid>,
 }
 
 impl Branch {
     pub fn local_name(&self) -> Option<&str> {
         self.remote.is_none().then_some(self.name.as_str())
     }
+
+    /// The push remote moved since we last pushed, e.g. someone else pushed or we force-pushed
+    /// from another clone
+    pub fn is_remote_diverged(&self) -> bool {
+        self.push_id.is_some() && self.remote_id.is_some() && self.push_id != self.remote_id
+    }
 }
 
 impl std::fmt::Display for Branch {
@@ -120,12 +136,18 @@ impl Commit {
 pub struct GitRepo {
     repo: git2::Repository,
     sign: Option<git2_ext::ops::UserSign>,
+    rebase_strategy: crate::config::RebaseStrategy,
+    patch_id_skip_binary: bool,
     push_remote: Option<String>,
     pull_remote: Option<String>,
+    abbrev: Option<usize>,
+    first_parent: bool,
     commits: std::cell::RefCell<std::collections::HashMap<git2::Oid, std::rc::Rc<Commit>>>,
     interned_strings: std::cell::RefCell<std::collections::HashSet<std::rc::Rc<str>>>,
     bases: std::cell::RefCell<std::collections::HashMap<(git2::Oid, git2::Oid), Option<git2::Oid>>>,
     counts: std::cell::RefCell<std::collections::HashMap<(git2::Oid, git2::Oid), Option<usize>>>,
+    pushes: std::cell::RefCell<Option<std::collections::HashMap<String, git2::Oid>>>,
+    shallow_ids: std::cell::RefCell<Option<Vec<git2::Oid>>>,
 }
 
 impl GitRepo {
@@ -133,12 +155,18 @@ impl GitRepo {
         Self {
             repo,
             sign: None,
+            rebase_strategy: Default::default(),
+            patch_id_skip_binary: false,
             push_remote: None,
             pull_remote: None,
+            abbrev: None,
+            first_parent: false,
             commits: Default::default(),
             interned_strings: Default::default(),
             bases: Default::default(),
             counts: Default::default(),
+            pushes: Default::default(),
+            shallow_ids: Default::default(),
         }
     }
 
@@ -153,6 +181,20 @@ impl GitRepo {
         Ok(())
     }
 
+    pub fn set_rebase_strategy(&mut self, strategy: crate::config::RebaseStrategy) {
+        self.rebase_strategy = strategy;
+    }
+
+    /// Leave binary files out of patch-ids, see [`GitRepo::patch_id`]
+    pub fn set_patch_id_skip_binary(&mut self, yes: bool) {
+        self.patch_id_skip_binary = yes;
+    }
+
+    /// Only follow first parents, see [`crate::git::GitRepo::set_first_parent`]
+    pub fn set_first_parent(&mut self, yes: bool) {
+        self.first_parent = yes;
+    }
+
     pub fn set_push_remote(&mut self, remote: &str) {
         self.push_remote = Some(remote.to_owned());
     }
@@ -169,10 +211,28 @@ impl GitRepo {
         self.pull_remote.as_deref().unwrap_or("origin")
     }
 
+    /// Hex digits to show of commit ids, see [`crate::git::abbrev_id`]
+    pub fn set_abbrev(&mut self, len: Option<usize>) {
+        self.abbrev = len;
+    }
+
+    pub fn abbrev_id(&self, id: git2::Oid) -> String {
+        crate::git::abbrev_id(&self.repo, id, self.abbrev)
+    }
+
+    pub fn config_bool(&self, key: &str, default: bool) -> bool {
+        crate::git::config_bool(&self.repo, key, default)
+    }
+
     pub fn raw(&self) -> &git2::Repository {
         &self.repo
     }
 
+    /// Whether history was truncated, like with `git clone --depth`
+    pub fn is_shallow(&self) -> bool {
+        self.repo.is_shallow()
+    }
+
     pub fn user(&self) -> Option<std::rc::Rc<str>> {
         self.repo
             .signature()
@@ -218,7 +278,11 @@ impl GitRepo {
     }
 
     fn merge_base_raw(&self, one: git2::Oid, two: git2::Oid) -> Option<git2::Oid> {
-        self.repo.merge_base(one, two).ok()
+        if self.first_parent {
+            crate::git::first_parent_merge_base(&self.repo, one, two)
+        } else {
+            self.repo.merge_base(one, two).ok()
+        }
     }
 
     pub fn find_commit(&self, id: git2::Oid) -> Option<std::rc::Rc<Commit>> {
@@ -268,14 +332,15 @@ impl GitRepo {
         let name = resolved.shorthand()?;
         let id = resolved.target()?;
 
-        let push_id = self
+        let push_branch = self
             .repo
             .find_branch(
                 &format!("{}/{}", self.push_remote(), name),
                 git2::BranchType::Remote,
             )
-            .ok()
-            .and_then(|b| b.get().target());
+            .ok();
+        let remote_id = push_branch.as_ref().and_then(|b| b.get().target());
+        let push_id = self.push_id(name, push_branch.as_ref());
         let pull_id = self
             .repo
             .find_branch(
@@ -291,6 +356,7 @@ impl GitRepo {
             id,
             push_id,
             pull_id,
+            remote_id,
         })
     }
 
@@ -301,7 +367,12 @@ impl GitRepo {
 
     pub fn parent_ids(&self, head_id: git2::Oid) -> Result<Vec<git2::Oid>, git2::Error> {
         let commit = self.repo.find_commit(head_id)?;
-        Ok(commit.parent_ids().collect())
+        let parent_ids = commit.parent_ids();
+        if self.first_parent {
+            Ok(parent_ids.take(1).collect())
+        } else {
+            Ok(parent_ids.collect())
+        }
     }
 
     pub fn commit_count(&self, base_id: git2::Oid, head_id: git2::Oid) -> Option<usize> {
@@ -331,9 +402,33 @@ impl GitRepo {
         revwalk
             .hide(base_id)
             .unwrap_or_else(|e| panic!("Unexpected git2 error: {e}"));
+        if self.first_parent {
+            revwalk
+                .simplify_first_parent()
+                .unwrap_or_else(|e| panic!("Unexpected git2 error: {e}"));
+        }
         Some(revwalk.count())
     }
 
+    /// Commits only in `local_id` and only in `upstream_id`, like `git status`'s "ahead 2, behind 1"
+    pub fn ahead_behind(
+        &self,
+        local_id: git2::Oid,
+        upstream_id: git2::Oid,
+    ) -> Option<(usize, usize)> {
+        if local_id == upstream_id {
+            return Some((0, 0));
+        }
+        if self.first_parent {
+            // `graph_ahead_behind` counts every parent
+            let base_id = self.merge_base(local_id, upstream_id)?;
+            let ahead = self.commit_count(base_id, local_id)?;
+            let behind = self.commit_count(base_id, upstream_id)?;
+            return Some((ahead, behind));
+        }
+        self.repo.graph_ahead_behind(local_id, upstream_id).ok()
+    }
+
     pub fn commit_range(
         &self,
         base_bound: std::ops::Bound<&git2::Oid>,
@@ -363,6 +458,9 @@ impl GitRepo {
             revwalk.hide(base_id)?;
         }
         revwalk.set_sorting(git2::Sort::TOPOLOGICAL)?;
+        if self.first_parent {
+            revwalk.simplify_first_parent()?;
+        }
         let mut result = revwalk
             .filter_map(Result::ok)
             .skip(skip)
@@ -440,10 +538,12 @@ impl GitRepo {
         head_id: git2::Oid,
         cherry_id: git2::Oid,
     ) -> Result<git2::Oid, git2::Error> {
-        git2_ext::ops::cherry_pick(
+        crate::git::check_shallow_boundary(&self.repo, &self.shallow_ids, cherry_id)?;
+        crate::git::replay(
             &self.repo,
             head_id,
             cherry_id,
+            self.rebase_strategy,
             self.sign.as_ref().map(|s| s as &dyn git2_ext::ops::Sign),
         )
     }
@@ -453,12 +553,9 @@ impl GitRepo {
         head_id: git2::Oid,
         into_id: git2::Oid,
     ) -> Result<git2::Oid, git2::Error> {
-        git2_ext::ops::squash(
-            &self.repo,
-            head_id,
-            into_id,
-            self.sign.as_ref().map(|s| s as &dyn git2_ext::ops::Sign),
-        )
+        crate::git::check_shallow_boundary(&self.repo, &self.shallow_ids, into_id)?;
+        let sign = self.sign.as_ref().map(|s| s as &dyn git2_ext::ops::Sign);
+        crate::git::squash(&self.repo, head_id, into_id, sign)
     }
 
     pub fn stash_push(&mut self, message: Option<&str>) -> Result<git2::Oid, git2::Error> {
@@ -495,7 +592,8 @@ impl GitRepo {
     pub fn delete_branch(&mut self, name: &str) -> Result<(), git2::Error> {
         // HACK: We shouldn't limit ourselves to `Local`
         let mut branch = self.repo.find_branch(name, git2::BranchType::Local)?;
-        branch.delete()
+        branch.delete()?;
+        crate::git::forget_push(&self.repo, name)
     }
 
     pub fn find_local_branch(&self, name: &str) -> Option<Branch> {
@@ -558,14 +656,15 @@ impl GitRepo {
     fn load_local_branch(&self, branch: &git2::Branch, name: &str) -> Result<Branch, git2::Error> {
         let id = branch.get().target().unwrap();
 
-        let push_id = self
+        let push_branch = self
             .repo
             .find_branch(
                 &format!("{}/{}", self.push_remote(), name),
                 git2::BranchType::Remote,
             )
-            .ok()
-            .and_then(|b| b.get().target());
+            .ok();
+        let remote_id = push_branch.as_ref().and_then(|b| b.get().target());
+        let push_id = self.push_id(name, push_branch.as_ref());
         let pull_id = self
             .repo
             .find_branch(
@@ -581,9 +680,84 @@ impl GitRepo {
             id,
             push_id,
             pull_id,
+            remote_id,
         })
     }
 
+    /// Remember that `id` was pushed as local branch `name`
+    ///
+    /// Unlike the remote-tracking ref, this is only updated by us and, unlike its reflog, it
+    /// survives `git gc`.
+    pub fn record_push(&self, name: &str, id: git2::Oid) -> Result<(), git2::Error> {
+        self.repo
+            .config()?
+            .open_level(git2::ConfigLevel::Local)?
+            .set_str(&crate::git::pushed_key(name), &id.to_string())?;
+        if let Some(pushes) = self.pushes.borrow_mut().as_mut() {
+            pushes.insert(name.to_owned(), id);
+        }
+        Ok(())
+    }
+
+    /// What local branch `name` was last pushed as
+    ///
+    /// This is what [`GitRepo::record_push`] recorded, if the commit still exists, falling back to
+    /// the remote-tracking ref.  See [`GitRepo::resolve_push_id`] for branches pushed some other
+    /// way.
+    fn push_id(&self, name: &str, push_branch: Option<&git2::Branch<'_>>) -> Option<git2::Oid> {
+        self.recorded_push(name)
+            .or_else(|| push_branch.and_then(|b| b.get().target()))
+    }
+
+    fn recorded_push(&self, name: &str) -> Option<git2::Oid> {
+        self.pushes
+            .borrow_mut()
+            .get_or_insert_with(|| {
+                self.repo
+                    .config()
+                    .and_then(|config| crate::git::recorded_pushes(&config))
+                    .unwrap_or_default()
+            })
+            .get(name)
+            .copied()
+            .filter(|id| self.repo.find_commit(*id).is_ok())
+    }
+
+    /// Find where a local branch pushed some other way than `git stack` was last pushed
+    ///
+    /// Without a recorded push, this is the newest push in the remote-tracking ref's reflog.  This
+    /// is left to the callers looking at push state as loading branches would otherwise read a
+    /// reflog for each of them.  The reflog isn't read when the remote-tracking ref is already at
+    /// the branch, as there is no push left to lose then.
+    pub fn resolve_push_id(&self, branch: &mut Branch) {
+        if branch.remote.is_some()
+            || branch.remote_id.is_none()
+            || branch.remote_id == Some(branch.id)
+            || self.recorded_push(&branch.name).is_some()
+        {
+            return;
+        }
+        let ref_name = format!("refs/remotes/{}/{}", self.push_remote(), branch.name);
+        if let Some(pushed_id) = self.last_pushed_id(&ref_name) {
+            branch.push_id = Some(pushed_id);
+        }
+    }
+
+    /// The newest id `git push` recorded for a remote-tracking ref
+    fn last_pushed_id(&self, ref_name: &str) -> Option<git2::Oid> {
+        let reflog = self.repo.reflog(ref_name).ok()?;
+        let pushed_id = reflog
+            .iter()
+            .find(|entry| {
+                entry
+                    .message()
+                    .map(|m| m.starts_with("update by push"))
+                    .unwrap_or(false)
+            })
+            .map(|entry| entry.id_new());
+        pushed_id
+    }
+
     fn load_remote_branch(
         &self,
         branch: &git2::Branch,
@@ -593,6 +767,7 @@ impl GitRepo {
         let id = branch.get().target().unwrap();
 
         let push_id = (remote == self.push_remote()).then_some(id);
+        let remote_id = push_id;
         let pull_id = (remote == self.pull_remote()).then_some(id);
 
         Ok(Branch {
@@ -601,6 +776,7 @@ impl GitRepo {
             id,
             push_id,
             pull_id,
+            remote_id,
         })
     }
 
@@ -620,6 +796,13 @@ impl GitRepo {
     pub fn switch(&mut self, name: &str) -> Result<(), git2::Error> {
         // HACK: We shouldn't limit ourselves to `Local`
         let branch = self.repo.find_branch(name, git2::BranchType::Local)?;
+        // The checkout is forced, so refuse up front rather than clobbering untracked files
+        if let Some(id) = branch.get().target() {
+            let collisions = self.untracked_collisions(id)?;
+            if !collisions.is_empty() {
+                return Err(crate::git::untracked_collisions_error(&collisions));
+            }
+        }
         self.repo.set_head(branch.get().name().unwrap())?;
         let mut builder = git2::build::CheckoutBuilder::new();
         builder.force();
@@ -627,6 +810,68 @@ impl GitRepo {
         Ok(())
     }
 
+    pub fn worktrees(&self) -> Vec<crate::git::Worktree> {
+        crate::git::other_worktrees(&self.repo)
+    }
+
+    pub fn untracked_collisions(
+        &self,
+        id: git2::Oid,
+    ) -> Result<Vec<std::path::PathBuf>, git2::Error> {
+        crate::git::untracked_collisions(&self.repo, id)
+    }
+
+    /// Like `git patch-id`, identifying a commit's change independent of where it is applied
+    ///
+    /// With [`GitRepo::set_patch_id_skip_binary`], binary files are left out when the commit also
+    /// changes text, see [`crate::git::patch_id`].
+    pub fn patch_id(&self, id: git2::Oid) -> Option<git2::Oid> {
+        self.patch_id_raw(id, false)
+            .unwrap_or_else(|e| panic!("Unexpected git2 error: {e}"))
+    }
+
+    /// The patch-id a commit reverting `id` would have
+    pub fn revert_patch_id(&self, id: git2::Oid) -> Option<git2::Oid> {
+        self.patch_id_raw(id, true)
+            .unwrap_or_else(|e| panic!("Unexpected git2 error: {e}"))
+    }
+
+    fn patch_id_raw(&self, id: git2::Oid, reverse: bool) -> Result<Option<git2::Oid>, git2::Error> {
+        crate::git::patch_id(&self.repo, id, reverse, self.patch_id_skip_binary)
+    }
+
+    /// See [`crate::git::is_signed`]
+    pub fn is_signed(&self, id: git2::Oid) -> bool {
+        crate::git::is_signed(&self.repo, id)
+    }
+
+    /// Paths changed by `id`, relative to its first parent, like `git show --name-only`
+    pub fn changed_paths(&self, id: git2::Oid) -> Vec<std::path::PathBuf> {
+        let commit = match self.repo.find_commit(id) {
+            Ok(commit) => commit,
+            Err(_) => return Vec::new(),
+        };
+        let tree = commit.tree().ok();
+        let parent_tree = commit.parent(0).ok().and_then(|p| p.tree().ok());
+        let diff = match self
+            .repo
+            .diff_tree_to_tree(parent_tree.as_ref(), tree.as_ref(), None)
+        {
+            Ok(diff) => diff,
+            Err(_) => return Vec::new(),
+        };
+        diff.deltas()
+            .filter_map(|delta| {
+                delta
+                    .new_file()
+                    .path()
+                    .or_else(|| delta.old_file().path())
+                    .map(ToOwned::to_owned)
+            })
+            .collect()
+    }
+
+    /// Whether a blob's content looks binary, by git's heuristic
     fn intern_string(&self, data: &str) -> std::rc::Rc<str> {
         let mut interned_strings = self.interned_strings.borrow_mut();
         if let Some(interned) = interned_strings.get(data) {
@@ -689,6 +934,10 @@ impl Repo for GitRepo {
         self.commit_count(base_id, head_id)
     }
 
+    fn ahead_behind(&self, local_id: git2::Oid, upstream_id: git2::Oid) -> Option<(usize, usize)> {
+        self.ahead_behind(local_id, upstream_id)
+    }
+
     fn commit_range(
         &self,
         base_bound: std::ops::Bound<&git2::Oid>,
@@ -756,6 +1005,26 @@ impl Repo for GitRepo {
     fn switch(&mut self, name: &str) -> Result<(), git2::Error> {
         self.switch(name)
     }
+
+    fn worktrees(&self) -> Vec<crate::git::Worktree> {
+        self.worktrees()
+    }
+
+    fn untracked_collisions(&self, id: git2::Oid) -> Result<Vec<std::path::PathBuf>, git2::Error> {
+        self.untracked_collisions(id)
+    }
+
+    fn patch_id(&self, id: git2::Oid) -> Option<git2::Oid> {
+        self.patch_id(id)
+    }
+
+    fn revert_patch_id(&self, id: git2::Oid) -> Option<git2::Oid> {
+        self.revert_patch_id(id)
+    }
+
+    fn abbrev_id(&self, id: git2::Oid) -> String {
+        self.abbrev_id(id)
+    }
 }
 
 #[derive(Debug)]
@@ -873,6 +1142,17 @@ impl InMemoryRepo {
         Some(count)
     }
 
+    pub fn ahead_behind(
+        &self,
+        local_id: git2::Oid,
+        upstream_id: git2::Oid,
+    ) -> Option<(usize, usize)> {
+        let base_id = self.merge_base(local_id, upstream_id)?;
+        let ahead = self.commit_count(base_id, local_id)?;
+        let behind = self.commit_count(base_id, upstream_id)?;
+        Some((ahead, behind))
+    }
+
     pub fn commit_range(
         &self,
         base_bound: std::ops::Bound<&git2::Oid>,
@@ -1000,6 +1280,7 @@ impl InMemoryRepo {
                 id,
                 push_id: None,
                 pull_id: None,
+                remote_id: None,
             },
         );
         Ok(())
@@ -1046,6 +1327,29 @@ impl InMemoryRepo {
         self.head_id = Some(branch.id);
         Ok(())
     }
+
+    pub fn worktrees(&self) -> Vec<crate::git::Worktree> {
+        Vec::new()
+    }
+
+    pub fn untracked_collisions(
+        &self,
+        _id: git2::Oid,
+    ) -> Result<Vec<std::path::PathBuf>, git2::Error> {
+        Ok(Vec::new())
+    }
+
+    pub fn patch_id(&self, _id: git2::Oid) -> Option<git2::Oid> {
+        None
+    }
+
+    pub fn revert_patch_id(&self, _id: git2::Oid) -> Option<git2::Oid> {
+        None
+    }
+
+    pub fn abbrev_id(&self, id: git2::Oid) -> String {
+        id.to_string()
+    }
 }
 
 impl Default for InMemoryRepo {
@@ -1109,6 +1413,10 @@ impl Repo for InMemoryRepo {
         self.commit_count(base_id, head_id)
     }
 
+    fn ahead_behind(&self, local_id: git2::Oid, upstream_id: git2::Oid) -> Option<(usize, usize)> {
+        self.ahead_behind(local_id, upstream_id)
+    }
+
     fn commit_range(
         &self,
         base_bound: std::ops::Bound<&git2::Oid>,
@@ -1180,6 +1488,26 @@ impl Repo for InMemoryRepo {
     fn switch(&mut self, name: &str) -> Result<(), git2::Error> {
         self.switch(name)
     }
+
+    fn worktrees(&self) -> Vec<crate::git::Worktree> {
+        self.worktrees()
+    }
+
+    fn untracked_collisions(&self, id: git2::Oid) -> Result<Vec<std::path::PathBuf>, git2::Error> {
+        self.untracked_collisions(id)
+    }
+
+    fn patch_id(&self, id: git2::Oid) -> Option<git2::Oid> {
+        self.patch_id(id)
+    }
+
+    fn revert_patch_id(&self, id: git2::Oid) -> Option<git2::Oid> {
+        self.revert_patch_id(id)
+    }
+
+    fn abbrev_id(&self, id: git2::Oid) -> String {
+        self.abbrev_id(id)
+    }
 }
 
 pub fn stash_push(repo: &mut dyn Repo, context: &str) -> Option<git2::Oid> {
@@ -6,6 +6,8 @@ pub struct Node {
     pub branches: Vec<crate::legacy::git::Branch>,
     pub action: crate::legacy::graph::Action,
     pub pushable: bool,
+    /// Kept in place, with everything built on it, rather than rebased
+    pub pinned: bool,
     pub children: BTreeSet<git2::Oid>,
 }
 
@@ -18,6 +20,7 @@ impl Node {
             branches,
             action: crate::legacy::graph::Action::Pick,
             pushable: false,
+            pinned: false,
             children,
         }
     }
@@ -42,6 +45,10 @@ impl Node {
             self.pushable = true;
         }
 
+        if other.pinned {
+            self.pinned = true;
+        }
+
         self.children.extend(other.children);
     }
 }
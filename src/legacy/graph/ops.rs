@@ -1,4 +1,5 @@
 use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::collections::HashSet;
 use std::collections::VecDeque;
 
@@ -18,6 +19,10 @@ pub fn protect_branches(
     protect_commits(graph, repo, protected_oids);
 }
 
+/// Protect every commit between `root` and each of `protected_oids`
+///
+/// Merges are followed through all of their parents, so commits that are only reachable through a
+/// merge's second (or later, for octopus merges) parent are protected too.
 pub fn protect_commits(
     graph: &mut Graph,
     repo: &dyn crate::legacy::git::Repo,
@@ -30,21 +35,67 @@ pub fn protect_commits(
             .map(|merge_base_oid| merge_base_oid == root_id)
             .unwrap_or(false)
     }) {
-        for commit_id in crate::legacy::git::commit_range(repo, protected_oid..=root_id)
+        let in_range: HashSet<_> = crate::legacy::git::commit_range(repo, protected_oid..=root_id)
             .expect("IDs already validated")
-        {
-            let commit = repo
-                .find_commit(commit_id)
-                .expect("commit_range returns valid commits");
-            if let Some(node) = graph.get_mut(commit.id) {
+            .into_iter()
+            .collect();
+
+        let mut seen = HashSet::new();
+        let mut queue = VecDeque::from([protected_oid]);
+        while let Some(commit_id) = queue.pop_front() {
+            if !seen.insert(commit_id) {
+                continue;
+            }
+            if let Some(node) = graph.get_mut(commit_id) {
                 if node.action.is_protected() {
-                    break;
+                    // Already walked from another protected commit
+                    continue;
                 }
                 node.action = crate::legacy::graph::Action::Protected;
             }
-            if commit.id == root_id {
-                break;
+            if commit_id == root_id {
+                continue;
             }
+            let parent_ids = repo
+                .parent_ids(commit_id)
+                .expect("commit_range returns valid commits");
+            queue.extend(
+                parent_ids
+                    .into_iter()
+                    .filter(|parent_id| in_range.contains(parent_id)),
+            );
+        }
+    }
+}
+
+/// Count the commits that may be rewritten, for catching a stack that's larger than expected
+pub fn pickable_count(graph: &Graph) -> usize {
+    graph
+        .breadth_first_iter()
+        .filter(|node| node.action.is_pick())
+        .count()
+}
+
+/// Pin each of `pinned_oids` in place by protecting it and everything it is built on
+///
+/// Rewriting a commit below one of them would rewrite it.  Commits built on a newly-protected one
+/// stay rewritable but aren't rebased off of it, see [`rebase_development_branches`].
+pub fn protect_pinned_commits(
+    graph: &mut Graph,
+    repo: &dyn crate::legacy::git::Repo,
+    pinned_oids: HashSet<git2::Oid>,
+) {
+    let unprotected: HashSet<_> = graph
+        .breadth_first_iter()
+        .filter(|node| !node.action.is_protected())
+        .map(|node| node.commit.id)
+        .collect();
+    protect_commits(graph, repo, pinned_oids);
+
+    for id in unprotected {
+        let node = graph.get_mut(id).expect("collected from the graph");
+        if node.action.is_protected() {
+            node.pinned = true;
         }
     }
 }
@@ -308,6 +359,69 @@ pub fn trim_foreign_branches(graph: &mut Graph, user: &str, ignore: &[git2::Oid]
     foreign_branches
 }
 
+/// Remove stacks with a branch in `hidden`, keeping those that contain any of `ignore`
+pub fn trim_hidden_branches(
+    graph: &mut Graph,
+    hidden: &[String],
+    ignore: &[git2::Oid],
+) -> Vec<String> {
+    let mut hidden_branches = Vec::new();
+
+    let mut protected_queue = VecDeque::new();
+    if graph.root().action.is_protected() {
+        protected_queue.push_back(graph.root_id());
+    }
+    while let Some(current_id) = protected_queue.pop_front() {
+        let current_children = graph
+            .get(current_id)
+            .expect("all children exist")
+            .children
+            .clone();
+
+        for child_id in current_children {
+            let child_action = graph.get(child_id).expect("all children exist").action;
+            if child_action.is_protected() {
+                protected_queue.push_back(child_id);
+            } else if is_hidden_branch(graph, child_id, hidden, ignore) == Some(true) {
+                let removed = graph
+                    .remove_child(current_id, child_id)
+                    .expect("all children exist");
+                hidden_branches.extend(removed.breadth_first_iter().flat_map(|n| {
+                    n.branches
+                        .iter()
+                        .filter_map(|b| b.local_name().map(String::from))
+                }));
+            }
+        }
+    }
+
+    hidden_branches
+}
+
+/// Whether the stack at `node_id` has a hidden branch, `None` if it has to be kept
+fn is_hidden_branch(
+    graph: &Graph,
+    node_id: git2::Oid,
+    hidden: &[String],
+    ignore: &[git2::Oid],
+) -> Option<bool> {
+    if ignore.contains(&node_id) {
+        return None;
+    }
+
+    let current = graph.get(node_id).expect("all children exist");
+    let mut is_hidden = current
+        .branches
+        .iter()
+        .filter_map(|b| b.local_name())
+        .any(|name| hidden.iter().any(|h| h == name));
+    for child_id in current.children.iter().copied() {
+        is_hidden |= is_hidden_branch(graph, child_id, hidden, ignore)?;
+    }
+
+    Some(is_hidden)
+}
+
 fn is_personal_branch(graph: &Graph, node_id: git2::Oid, user: &str, ignore: &[git2::Oid]) -> bool {
     if ignore.contains(&node_id) {
         return true;
@@ -330,6 +444,87 @@ fn is_personal_branch(graph: &Graph, node_id: git2::Oid, user: &str, ignore: &[g
     false
 }
 
+/// Protect branches that nobody opted in to rewriting, for `stack.default-action=protect`
+///
+/// A branch stays rewritable if any of its commits is in `picked` or is the `user`'s and not yet
+/// on the push remote.
+pub fn protect_unclaimed_branches(
+    graph: &mut Graph,
+    user: Option<&str>,
+    picked: &HashSet<git2::Oid>,
+) -> Vec<String> {
+    let mut unclaimed_branches = Vec::new();
+    let pushed = pushed_ids(graph);
+
+    let mut protected_queue = VecDeque::new();
+    if graph.root().action.is_protected() {
+        protected_queue.push_back(graph.root_id());
+    }
+    while let Some(current_id) = protected_queue.pop_front() {
+        let current_children = graph
+            .get(current_id)
+            .expect("all children exist")
+            .children
+            .clone();
+
+        for child_id in current_children {
+            let child_action = graph.get(child_id).expect("all children exist").action;
+            if child_action.is_protected() {
+                protected_queue.push_back(child_id);
+            } else if !is_claimed_branch(graph, child_id, user, picked, &pushed) {
+                mark_branch_protected(graph, child_id, &mut unclaimed_branches);
+            }
+        }
+    }
+
+    unclaimed_branches
+}
+
+fn is_claimed_branch(
+    graph: &Graph,
+    node_id: git2::Oid,
+    user: Option<&str>,
+    picked: &HashSet<git2::Oid>,
+    pushed: &HashSet<git2::Oid>,
+) -> bool {
+    if picked.contains(&node_id) {
+        return true;
+    }
+
+    let current = graph.get(node_id).expect("all children exist");
+
+    let is_users = user.is_some()
+        && (current.commit.committer.as_deref() == user
+            || current.commit.author.as_deref() == user);
+    if is_users && !pushed.contains(&node_id) {
+        return true;
+    }
+
+    current
+        .children
+        .iter()
+        .any(|child_id| is_claimed_branch(graph, *child_id, user, picked, pushed))
+}
+
+/// Commits that are, or are an ancestor of, a branch's `remote_id`
+fn pushed_ids(graph: &Graph) -> HashSet<git2::Oid> {
+    let remote_ids: HashSet<_> = graph
+        .breadth_first_iter()
+        .flat_map(|node| node.branches.iter().filter_map(|b| b.remote_id))
+        .collect();
+
+    // Children come after their parents, so walking backwards visits children first
+    let order: Vec<_> = graph.breadth_first_iter().map(|n| n.commit.id).collect();
+    let mut pushed = HashSet::new();
+    for id in order.iter().rev().copied() {
+        let node = graph.get(id).expect("all children exist");
+        if remote_ids.contains(&id) || node.children.iter().any(|c| pushed.contains(c)) {
+            pushed.insert(id);
+        }
+    }
+    pushed
+}
+
 /// Pre-requisites:
 /// - Running protect_branches
 ///
@@ -350,6 +545,7 @@ pub fn rebase_development_branches(graph: &mut Graph, new_base_id: git2::Oid) {
             .children
             .clone();
 
+        let pinned = graph.get(current_id).expect("all children exist").pinned;
         let mut rebaseable = Vec::new();
         for child_id in current_children {
             let child_action = graph.get(child_id).expect("all children exist").action;
@@ -360,7 +556,7 @@ pub fn rebase_development_branches(graph: &mut Graph, new_base_id: git2::Oid) {
             }
         }
 
-        if !rebaseable.is_empty() {
+        if !rebaseable.is_empty() && !pinned {
             let current = graph.get_mut(current_id).expect("all children exist");
             for child_id in rebaseable.iter() {
                 current.children.remove(child_id);
@@ -420,7 +616,10 @@ pub fn pushable(graph: &mut Graph) {
             if cause.is_some() {
                 // Preserve existing cause
             } else if !current.branches.is_empty()
-                && current.branches.iter().all(|b| Some(b.id) == b.push_id)
+                && current.branches.iter().all(|b| {
+                    // Others may have pushed on top, which we'd clobber
+                    b.remote_id.is_some() && (Some(b.id) == b.remote_id || Some(b.id) == b.push_id)
+                })
             {
                 cause = Some("already pushed");
             } else if current.commit.wip_summary().is_some() {
@@ -444,6 +643,45 @@ pub fn pushable(graph: &mut Graph) {
     }
 }
 
+/// Hide what is already on the push remote, leaving the commits still to push
+///
+/// A commit is on the remote when it is, or is an ancestor of, a branch's `remote_id`.  Subtrees
+/// without any commit left to push are removed; pushed commits leading up to unpushed ones are
+/// kept for context.
+pub fn trim_pushed(graph: &mut Graph) {
+    let pushed = pushed_ids(graph);
+
+    // Children come after their parents, so walking backwards visits children first
+    let order: Vec<_> = graph.breadth_first_iter().map(|n| n.commit.id).collect();
+    let mut keep = HashSet::new();
+    for id in order.iter().rev().copied() {
+        let node = graph.get(id).expect("all children exist");
+        let unpushed = !node.action.is_protected() && !pushed.contains(&id);
+        if unpushed || node.children.iter().any(|c| keep.contains(c)) {
+            keep.insert(id);
+        }
+    }
+
+    let mut node_queue = VecDeque::new();
+    node_queue.push_back(graph.root_id());
+    while let Some(current_id) = node_queue.pop_front() {
+        let current_children = graph
+            .get(current_id)
+            .expect("all children exist")
+            .children
+            .clone();
+        for child_id in current_children {
+            if keep.contains(&child_id) {
+                node_queue.push_back(child_id);
+            } else {
+                graph
+                    .remove_child(current_id, child_id)
+                    .expect("all children exist");
+            }
+        }
+    }
+}
+
 /// Quick pass for what is droppable
 ///
 /// We get into this state when a branch is squashed.  The id would be different due to metadata
@@ -538,6 +776,61 @@ fn drop_first_branch_by_tree_id(
     }
 }
 
+/// A surviving commit that undoes or redoes a commit dropped as already upstream
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ReintroducedDrop {
+    pub id: git2::Oid,
+    pub dropped_id: git2::Oid,
+    pub reverts: bool,
+}
+
+/// Find commits that revert or re-apply the changes of dropped commits
+///
+/// A dropped commit's change is already in the new base, so a commit reverting it will undo
+/// upstream work and a commit re-applying it will likely conflict or end up empty.  Matching is by
+/// patch-id, so only exact reverts and re-applications are found.
+pub fn find_reintroduced_drops(
+    graph: &Graph,
+    repo: &dyn crate::legacy::git::Repo,
+) -> Vec<ReintroducedDrop> {
+    let dropped: HashMap<_, _> = graph
+        .breadth_first_iter()
+        .filter(|node| node.action.is_delete())
+        .filter_map(|node| Some((repo.patch_id(node.commit.id)?, node.commit.id)))
+        .collect();
+    if dropped.is_empty() {
+        return Vec::new();
+    }
+
+    let mut reintroduced = Vec::new();
+    for node in graph.breadth_first_iter() {
+        if node.action.is_protected() || node.action.is_delete() {
+            continue;
+        }
+        let id = node.commit.id;
+        if let Some(dropped_id) = repo
+            .revert_patch_id(id)
+            .and_then(|patch_id| dropped.get(&patch_id))
+        {
+            reintroduced.push(ReintroducedDrop {
+                id,
+                dropped_id: *dropped_id,
+                reverts: true,
+            });
+        } else if let Some(dropped_id) = repo
+            .patch_id(id)
+            .and_then(|patch_id| dropped.get(&patch_id))
+        {
+            reintroduced.push(ReintroducedDrop {
+                id,
+                dropped_id: *dropped_id,
+                reverts: false,
+            });
+        }
+    }
+    reintroduced
+}
+
 /// Drop branches merged among the pulled IDs
 ///
 /// The removal in `graph` is purely superficial since nothing can act on it.  The returned branch
@@ -962,9 +1255,77 @@ pub fn to_script(graph: &Graph) -> crate::legacy::git::Script {
         }
     }
 
+    script.coalesce_dependents();
     script
 }
 
+/// What running `script` will do, like [`crate::graph::summarize`]
+pub fn summarize(
+    repo: &dyn crate::legacy::git::Repo,
+    graph: &Graph,
+    script: &crate::legacy::git::Script,
+) -> crate::graph::Summary {
+    let mut summary = crate::graph::Summary {
+        dropped: graph
+            .breadth_first_iter()
+            .filter(|node| node.action.is_delete())
+            .map(|node| node.commit.id)
+            .collect(),
+        ..Default::default()
+    };
+    let mut marks = HashMap::new();
+    summarize_script(
+        repo,
+        script,
+        (git2::Oid::zero(), 0),
+        &mut marks,
+        &mut summary,
+    );
+    summary.deleted.sort();
+    summary.deleted.dedup();
+    summary
+}
+
+/// Follow `script` from `onto`, the commit being built on and how many commits were picked on it
+fn summarize_script(
+    repo: &dyn crate::legacy::git::Repo,
+    script: &crate::legacy::git::Script,
+    mut onto: (git2::Oid, usize),
+    marks: &mut HashMap<git2::Oid, (git2::Oid, usize)>,
+    summary: &mut crate::graph::Summary,
+) {
+    for command in &script.commands {
+        match command {
+            crate::legacy::git::Command::SwitchCommit(id) => onto = (*id, 0),
+            crate::legacy::git::Command::RegisterMark(mark) => {
+                marks.insert(*mark, onto);
+            }
+            crate::legacy::git::Command::SwitchMark(mark) => {
+                onto = marks.get(mark).copied().unwrap_or(onto);
+            }
+            crate::legacy::git::Command::CherryPick(_) => onto.1 += 1,
+            crate::legacy::git::Command::Fixup(_) => {}
+            crate::legacy::git::Command::CreateBranch(name) => {
+                let Some(old) = repo.find_local_branch(name).filter(|_| 0 < onto.1) else {
+                    continue;
+                };
+                summary.moved.push(crate::graph::MovedBranch {
+                    name: name.clone(),
+                    old_id: old.id,
+                    onto_id: onto.0,
+                    commit_count: onto.1,
+                });
+            }
+            crate::legacy::git::Command::DeleteBranch(name) => {
+                summary.deleted.push(name.clone());
+            }
+        }
+    }
+    for dependent in &script.dependents {
+        summarize_script(repo, dependent, onto, marks, summary);
+    }
+}
+
 fn node_to_script(graph: &Graph, node_id: git2::Oid) -> Option<crate::legacy::git::Script> {
     let mut script = crate::legacy::git::Script::new();
 
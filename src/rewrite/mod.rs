@@ -22,10 +22,52 @@ impl Script {
             })
     }
 
+    /// Check the script can be applied before touching the repo
+    ///
+    /// Currently this ensures every branch to be created has a valid ref name.
+    pub fn validate(&self) -> Result<(), git2::Error> {
+        for command in self
+            .batches
+            .iter()
+            .flat_map(|b| b.commands.values())
+            .flatten()
+        {
+            if let Command::CreateBranch(name) = command {
+                if !git2::Reference::is_valid_name(&format!("refs/heads/{name}")) {
+                    return Err(git2::Error::new(
+                        git2::ErrorCode::InvalidSpec,
+                        git2::ErrorClass::Reference,
+                        format!("invalid branch name: {name}"),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = &'_ Batch> {
         self.batches.iter()
     }
 
+    /// What running the script will touch
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats::default();
+        for command in self
+            .batches
+            .iter()
+            .flat_map(|b| b.commands.values())
+            .flatten()
+        {
+            match command {
+                Command::CherryPick(id) | Command::Fixup(id) => stats.rewritten.push(*id),
+                Command::CreateBranch(name) => stats.branches.push(name.clone()),
+                Command::DeleteBranch(name) => stats.deleted_branches.push(name.clone()),
+                Command::RegisterMark(_) | Command::Reword(_) | Command::ResetAuthor => {}
+            }
+        }
+        stats
+    }
+
     pub fn display<'a>(&'a self, labels: &'a dyn Labels) -> impl std::fmt::Display + 'a {
         ScriptDisplay {
             script: self,
@@ -107,6 +149,17 @@ impl<'a> std::fmt::Display for ScriptDisplay<'a> {
     }
 }
 
+/// What a [`Script`] will touch, see [`Script::stats`]
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct Stats {
+    /// Commits that will be re-created
+    pub rewritten: Vec<git2::Oid>,
+    /// Branches that will be pointed at re-created commits
+    pub branches: Vec<String>,
+    /// Branches that will be deleted
+    pub deleted_branches: Vec<String>,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Batch {
     onto_mark: git2::Oid,
@@ -153,6 +206,13 @@ impl Batch {
         }
     }
 
+    /// Each command with the commit it applies to, in order
+    pub fn commands(&self) -> impl Iterator<Item = (git2::Oid, &'_ Command)> + '_ {
+        self.commands
+            .iter()
+            .flat_map(|(id, commands)| commands.iter().map(move |c| (*id, c)))
+    }
+
     pub fn display<'a>(&'a self, labels: &'a dyn Labels) -> impl std::fmt::Display + 'a {
         BatchDisplay {
             batch: self,
@@ -229,6 +289,9 @@ impl<'a> std::fmt::Display for BatchDisplay<'a> {
                     Command::Reword(_msg) => {
                         writeln!(f, "reword")?;
                     }
+                    Command::ResetAuthor => {
+                        writeln!(f, "exec git commit --amend --no-edit --reset-author")?;
+                    }
                     Command::Fixup(squash_oid) => {
                         writeln!(f, "fixup {squash_oid}")?;
                     }
@@ -324,6 +387,8 @@ pub enum Command {
     CherryPick(git2::Oid),
     /// Change the wording of a commit message
     Reword(String),
+    /// Make the configured user the commit's author
+    ResetAuthor,
     /// Squash a commit into prior commit, keeping the parent commits identity
     Fixup(git2::Oid),
     /// Mark a branch for creation at the current commit
@@ -332,12 +397,80 @@ pub enum Command {
     DeleteBranch(String),
 }
 
+/// Report a failure for a whole script
+fn script_failure(script: &Script, err: git2::Error) -> (git2::Error, &str, Vec<&str>) {
+    let mut branches = script.batches.iter().flat_map(|b| b.branch());
+    let branch_name = branches.next().unwrap_or("detached");
+    (err, branch_name, branches.collect())
+}
+
+/// Retry a conflicting cherry-pick with `git rerere`'s recorded resolutions
+///
+/// Reports `err` if any conflict is left unresolved.
+fn rerere_pick(
+    repo: &mut dyn crate::git::Repo,
+    head_id: git2::Oid,
+    cherry_id: git2::Oid,
+    err: git2::Error,
+) -> Result<git2::Oid, git2::Error> {
+    log::trace!("git rerere  # {}", cherry_id);
+    match repo.rerere_pick(head_id, cherry_id) {
+        Ok((new_id, paths)) => {
+            if !paths.is_empty() {
+                log::info!(
+                    "Resolved conflicts in {} for {} using a recorded resolution",
+                    paths
+                        .iter()
+                        .map(|p| p.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    repo.abbrev_id(cherry_id)
+                );
+            }
+            Ok(new_id)
+        }
+        Err(rerere_err) if rerere_err.code() == git2::ErrorCode::Unmerged => Err(rerere_err),
+        Err(rerere_err) => {
+            log::debug!("Could not replay recorded resolutions: {}", rerere_err);
+            Err(err)
+        }
+    }
+}
+
+/// Updates from a script run on a worker thread, for the main [`Executor`] to commit
+#[derive(Default)]
+struct Staged {
+    branches: Vec<(git2::Oid, String)>,
+    delete_branches: Vec<String>,
+    post_rewrite: Vec<(git2::Oid, git2::Oid)>,
+    lfs_paths: std::collections::BTreeSet<std::path::PathBuf>,
+    head_id: Option<git2::Oid>,
+}
+
+/// What became of a commit, see [`Executor::mapping`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Rewritten {
+    /// Re-created as this commit
+    Commit(git2::Oid),
+    /// Left out of the re-stacked branches
+    Dropped,
+}
+
+/// Replay [`Script`]s against a repo
+///
+/// Commits are replayed in the object database without touching the worktree; only
+/// [`Executor::close`] checks out where `HEAD` ends up, so the worktree goes straight from the
+/// old tip to the new one.
 pub struct Executor {
     marks: std::collections::HashMap<git2::Oid, git2::Oid>,
     branches: Vec<(git2::Oid, String)>,
     delete_branches: Vec<String>,
     post_rewrite: Vec<(git2::Oid, git2::Oid)>,
+    mapping: indexmap::IndexMap<git2::Oid, Rewritten>,
+    lfs_paths: std::collections::BTreeSet<std::path::PathBuf>,
+    worktree_branches: std::collections::HashMap<String, std::path::PathBuf>,
     head_id: git2::Oid,
+    head_branch: Option<String>,
     dry_run: bool,
     detached: bool,
 }
@@ -349,7 +482,11 @@ impl Executor {
             branches: Default::default(),
             delete_branches: Default::default(),
             post_rewrite: Default::default(),
+            mapping: Default::default(),
+            lfs_paths: Default::default(),
+            worktree_branches: Default::default(),
             head_id: git2::Oid::zero(),
+            head_branch: None,
             dry_run,
             detached: false,
         }
@@ -359,10 +496,152 @@ impl Executor {
         &mut self,
         repo: &mut dyn crate::git::Repo,
         script: &'s Script,
+    ) -> Vec<(git2::Error, &'s str, Vec<&'s str>)> {
+        self.run_with(repo, script, Self::commit)
+    }
+
+    /// Replay `script` without updating any branches, to find conflicts before running it
+    ///
+    /// Only objects are written, which are left unreachable.
+    pub fn verify<'s>(
+        &mut self,
+        repo: &mut dyn crate::git::Repo,
+        script: &'s Script,
+    ) -> Vec<(git2::Error, &'s str, Vec<&'s str>)> {
+        self.run_with(repo, script, |executor, _| {
+            executor.abandon();
+            Ok(())
+        })
+    }
+
+    /// Run independent scripts, up to `jobs` at a time
+    ///
+    /// Each script from [`crate::graph::to_scripts`] starts from its own protected commit, so
+    /// they can be re-stacked without coordinating with each other.
+    ///
+    /// `git2::Repository` can't be shared across threads, so every worker gets its own handle
+    /// from `open`.  Workers only write objects, which libgit2 supports from separate handles;
+    /// the index and working tree are left alone.  Branch updates are collected and applied
+    /// afterwards in a single [`Executor::commit`], so hooks see one reference transaction.
+    pub fn run_parallel<'s>(
+        &mut self,
+        repo: &mut dyn crate::git::Repo,
+        open: &(dyn Fn() -> Result<Box<dyn crate::git::Repo>, git2::Error> + Sync),
+        scripts: &'s [Script],
+        jobs: usize,
+    ) -> Vec<(git2::Error, &'s str, Vec<&'s str>)> {
+        // Don't rewrite anything if any of the scripts can't be run
+        let invalid = scripts
+            .iter()
+            .filter_map(|script| Some(script_failure(script, script.validate().err()?)))
+            .collect::<Vec<_>>();
+        if !invalid.is_empty() {
+            return invalid;
+        }
+
+        if jobs <= 1 || scripts.len() <= 1 {
+            return scripts
+                .iter()
+                .flat_map(|script| self.run(repo, script))
+                .collect();
+        }
+
+        self.head_id = repo.head_commit().id;
+        if !self.detached && self.head_branch.is_none() {
+            self.head_branch = repo
+                .head_branch()
+                .and_then(|b| b.local_name().map(ToOwned::to_owned));
+        }
+
+        let next = std::sync::atomic::AtomicUsize::new(0);
+        let results = std::sync::Mutex::new(Vec::new());
+        let dry_run = self.dry_run;
+        std::thread::scope(|scope| {
+            for _ in 0..jobs.min(scripts.len()) {
+                scope.spawn(|| {
+                    let mut repo = open();
+                    let mut executor = Executor::new(dry_run);
+                    loop {
+                        let i = next.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                        let Some(script) = scripts.get(i) else {
+                            break;
+                        };
+                        let mut staged = Staged::default();
+                        let failures = match repo.as_mut() {
+                            Ok(repo) => executor.run_with(repo.as_mut(), script, |executor, _| {
+                                staged.branches.append(&mut executor.branches);
+                                staged.delete_branches.append(&mut executor.delete_branches);
+                                staged.post_rewrite.append(&mut executor.post_rewrite);
+                                Ok(())
+                            }),
+                            Err(err) => vec![script_failure(
+                                script,
+                                git2::Error::new(err.code(), err.class(), err.message()),
+                            )],
+                        };
+                        staged.lfs_paths = std::mem::take(&mut executor.lfs_paths);
+                        staged.head_id = Some(executor.head_id);
+                        results.lock().unwrap().push((i, failures, staged));
+                    }
+                });
+            }
+        });
+
+        let mut results = results.into_inner().unwrap();
+        results.sort_by_key(|(i, _, _)| *i);
+        let mut failures = Vec::new();
+        let mut succeeded = Vec::new();
+        for (i, script_failures, mut staged) in results {
+            if script_failures.is_empty() {
+                succeeded.push(i);
+            }
+            failures.extend(script_failures);
+            self.branches.append(&mut staged.branches);
+            self.delete_branches.append(&mut staged.delete_branches);
+            self.post_rewrite.append(&mut staged.post_rewrite);
+            self.lfs_paths.append(&mut staged.lfs_paths);
+            if let Some(head_id) = staged.head_id {
+                self.update_head(self.head_id, head_id);
+            }
+        }
+        if let Err(err) = self.commit(repo) {
+            self.abandon();
+            for i in succeeded {
+                failures.push(script_failure(
+                    &scripts[i],
+                    git2::Error::new(err.code(), err.class(), err.message()),
+                ));
+            }
+        }
+
+        failures
+    }
+
+    fn run_with<'s>(
+        &mut self,
+        repo: &mut dyn crate::git::Repo,
+        script: &'s Script,
+        mut finish: impl FnMut(&mut Self, &mut dyn crate::git::Repo) -> Result<(), git2::Error>,
     ) -> Vec<(git2::Error, &'s str, Vec<&'s str>)> {
         let mut failures = Vec::new();
 
         self.head_id = repo.head_commit().id;
+        if !self.detached && self.head_branch.is_none() {
+            self.head_branch = repo
+                .head_branch()
+                .and_then(|b| b.local_name().map(ToOwned::to_owned));
+        }
+
+        if let Err(err) = script.validate() {
+            failures.push(script_failure(script, err));
+            return failures;
+        }
+
+        self.worktree_branches = repo
+            .worktrees()
+            .into_iter()
+            .filter_map(|w| Some((w.branch?, w.path)))
+            .collect();
 
         let onto_id = script.batches[0].onto_mark();
         let labels = NamedLabels::new();
@@ -378,7 +657,7 @@ impl Executor {
             log::trace!("Applying `{}`", branch_name);
             log::trace!("Script:\n{}", batch.display(&labels));
             let res = self.stage_batch(repo, batch);
-            match res.and_then(|_| self.commit(repo)) {
+            match res.and_then(|_| finish(self, repo)) {
                 Ok(()) => {
                     log::trace!("         `{}` succeeded", branch_name);
                 }
@@ -436,8 +715,17 @@ impl Executor {
                         let updated_oid = if self.dry_run {
                             *cherry_oid
                         } else {
-                            repo.cherry_pick(head_oid, *cherry_oid)?
+                            match repo.cherry_pick(head_oid, *cherry_oid) {
+                                Err(err)
+                                    if err.code() == git2::ErrorCode::Unmerged
+                                        && repo.config_bool("rerere.enabled", false) =>
+                                {
+                                    rerere_pick(repo, head_oid, *cherry_oid, err)?
+                                }
+                                res => res?,
+                            }
                         };
+                        self.lfs_paths.extend(repo.lfs_paths(*cherry_oid));
                         self.update_head(*cherry_oid, updated_oid);
                         self.post_rewrite.push((*cherry_oid, updated_oid));
                         head_oid = updated_oid;
@@ -457,6 +745,21 @@ impl Executor {
                         }
                         head_oid = updated_oid;
                     }
+                    Command::ResetAuthor => {
+                        log::trace!("git commit --amend --no-edit --reset-author");
+                        let updated_oid = if self.dry_run {
+                            head_oid
+                        } else {
+                            repo.reset_author(head_oid)?
+                        };
+                        self.update_head(head_oid, updated_oid);
+                        for (_old_oid, new_oid) in &mut self.post_rewrite {
+                            if *new_oid == head_oid {
+                                *new_oid = updated_oid;
+                            }
+                        }
+                        head_oid = updated_oid;
+                    }
                     Command::Fixup(squash_oid) => {
                         let cherry_commit = repo.find_commit(*squash_oid).ok_or_else(|| {
                             git2::Error::new(
@@ -475,6 +778,7 @@ impl Executor {
                         } else {
                             repo.squash(*squash_oid, head_oid)?
                         };
+                        self.lfs_paths.extend(repo.lfs_paths(*squash_oid));
                         self.update_head(head_oid, updated_oid);
                         self.update_head(*squash_oid, updated_oid);
                         for (_old_oid, new_oid) in &mut self.post_rewrite {
@@ -487,9 +791,15 @@ impl Executor {
                     }
                     Command::CreateBranch(name) => {
                         let branch_oid = head_oid;
-                        self.branches.push((branch_oid, name.to_owned()));
+                        if repo.find_local_branch(name).map(|b| b.id) == Some(branch_oid) {
+                            log::trace!("git branch {name}  # already at {branch_oid}");
+                        } else {
+                            self.ensure_not_checked_out(name)?;
+                            self.branches.push((branch_oid, name.to_owned()));
+                        }
                     }
                     Command::DeleteBranch(name) => {
+                        self.ensure_not_checked_out(name)?;
                         self.delete_branches.push(name.to_owned());
                     }
                 }
@@ -499,6 +809,22 @@ impl Executor {
         Ok(())
     }
 
+    /// Moving a branch checked out in another worktree would leave that worktree out of sync
+    fn ensure_not_checked_out(&self, name: &str) -> Result<(), git2::Error> {
+        if let Some(path) = self.worktree_branches.get(name) {
+            return Err(git2::Error::new(
+                git2::ErrorCode::Locked,
+                git2::ErrorClass::Worktree,
+                format!(
+                    "`{}` is checked out in the worktree at {}",
+                    name,
+                    path.display()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
     pub fn update_head(&mut self, old_id: git2::Oid, new_id: git2::Oid) {
         if self.head_id == old_id && old_id != new_id {
             log::trace!("head changed from {} to {}", old_id, new_id);
@@ -507,6 +833,25 @@ impl Executor {
     }
 
     pub fn commit(&mut self, repo: &mut dyn crate::git::Repo) -> Result<(), git2::Error> {
+        // `close` force-checks-out the new `HEAD`, so refuse before moving anything rather than
+        // clobbering untracked files, like `git checkout` does
+        if !self.dry_run {
+            let checkout_id = match self.head_branch.as_deref() {
+                Some(head_branch) => self
+                    .branches
+                    .iter()
+                    .find(|(_, name)| name == head_branch)
+                    .map(|(id, _)| *id),
+                None => Some(self.head_id),
+            };
+            if let Some(checkout_id) = checkout_id.filter(|id| *id != repo.head_commit().id) {
+                let collisions = repo.untracked_collisions(checkout_id)?;
+                if !collisions.is_empty() {
+                    return Err(crate::git::untracked_collisions_error(&collisions));
+                }
+            }
+        }
+
         let hook_repo = repo.path().map(git2::Repository::open).transpose()?;
         let hooks = if self.dry_run {
             None
@@ -564,6 +909,9 @@ impl Executor {
         self.branches.clear();
 
         for name in self.delete_branches.iter() {
+            if self.head_branch.as_ref() == Some(name) {
+                self.head_branch = None;
+            }
             log::trace!("git branch -D {}", name);
             if !self.dry_run {
                 repo.delete_branch(name)?;
@@ -575,6 +923,11 @@ impl Executor {
             tx.committed()
         }
         self.post_rewrite.retain(|(old, new)| old != new);
+        self.mapping.extend(
+            self.post_rewrite
+                .iter()
+                .map(|(old, new)| (*old, Rewritten::Commit(*new))),
+        );
         if !self.post_rewrite.is_empty() {
             log::trace!("Running post-rewrite hook");
             if let (Some(hook_repo), Some(hooks)) = (hook_repo.as_ref(), hooks.as_ref()) {
@@ -586,6 +939,20 @@ impl Executor {
         Ok(())
     }
 
+    /// Record commits that were left out, e.g. [`crate::graph::Summary::dropped`], in
+    /// [`Executor::mapping`]
+    pub fn note_dropped(&mut self, ids: impl IntoIterator<Item = git2::Oid>) {
+        self.mapping
+            .extend(ids.into_iter().map(|id| (id, Rewritten::Dropped)));
+    }
+
+    /// Each original commit and what it became, for everything committed so far
+    ///
+    /// Commits that were replayed unchanged are left out.
+    pub fn mapping(&self) -> &indexmap::IndexMap<git2::Oid, Rewritten> {
+        &self.mapping
+    }
+
     pub fn abandon(&mut self) {
         self.branches.clear();
         self.delete_branches.clear();
@@ -599,6 +966,8 @@ impl Executor {
     ) -> Result<(), git2::Error> {
         assert_eq!(&self.branches, &[]);
         assert_eq!(self.delete_branches, Vec::<String>::new());
+        // Fallback to the branch HEAD was attached to when we started, wherever it moved to
+        let restore_branch = restore_branch.or(self.head_branch.as_deref());
         if let Some(restore_branch) = restore_branch {
             log::trace!("git switch {}", restore_branch);
             if !self.dry_run && self.detached {
@@ -611,6 +980,19 @@ impl Executor {
             }
         }
 
+        // git2 doesn't run smudge/clean filters, so LFS files may be left as pointer files
+        if !self.dry_run && !self.lfs_paths.is_empty() {
+            log::warn!(
+                "re-stacked commits touching Git LFS files ({}); run `git lfs checkout` if they were left as pointer files",
+                self.lfs_paths
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            self.lfs_paths.clear();
+        }
+
         Ok(())
     }
 }
@@ -51,6 +51,7 @@ pub fn populate_repo(
                     id: current_oid,
                     push_id: None,
                     pull_id: None,
+                    remote_id: None,
                 };
                 repo.mark_branch(branch);
             }
@@ -92,6 +92,60 @@ mod test_rebase {
         dbg!(&feature1_branch.id);
         assert!(ancestors.contains(&feature1_branch.id));
     }
+
+    #[test]
+    fn summary() {
+        let mut repo = git_stack::legacy::git::InMemoryRepo::new();
+        let plan = git_fixture::TodoList::load(std::path::Path::new("tests/fixtures/branches.yml"))
+            .unwrap();
+        fixture::populate_repo(&mut repo, plan);
+
+        let master_branch = repo.find_local_branch("master").unwrap();
+        let feature1_branch = repo.find_local_branch("feature1").unwrap();
+        let feature2_branch = repo.find_local_branch("feature2").unwrap();
+        let dropped_id = repo
+            .parent_ids(repo.parent_ids(feature2_branch.id).unwrap()[0])
+            .unwrap()[0];
+
+        let mut protected_branches = git_stack::legacy::git::Branches::default();
+        protected_branches.insert(master_branch.clone());
+
+        let mut graphed_branches = git_stack::legacy::git::Branches::default();
+        graphed_branches.insert(master_branch.clone());
+        graphed_branches.insert(feature1_branch.clone());
+        graphed_branches.insert(feature2_branch.clone());
+
+        let mut graph = Graph::from_branches(&repo, graphed_branches).unwrap();
+        git_stack::legacy::graph::protect_branches(&mut graph, &repo, &protected_branches);
+        git_stack::legacy::graph::rebase_development_branches(&mut graph, master_branch.id);
+        graph.get_mut(dropped_id).unwrap().action = Action::Delete;
+        let script = git_stack::legacy::graph::to_script(&graph);
+        dbg!(&script);
+
+        let summary = git_stack::legacy::graph::summarize(&repo, &graph, &script);
+        assert_eq!(
+            summary,
+            git_stack::graph::Summary {
+                moved: vec![
+                    git_stack::graph::MovedBranch {
+                        name: "feature1".to_owned(),
+                        old_id: feature1_branch.id,
+                        onto_id: master_branch.id,
+                        commit_count: 1,
+                    },
+                    git_stack::graph::MovedBranch {
+                        name: "feature2".to_owned(),
+                        old_id: feature2_branch.id,
+                        onto_id: master_branch.id,
+                        commit_count: 3,
+                    },
+                ],
+                dropped: vec![dropped_id],
+                deleted: vec![],
+            }
+        );
+        assert!(summary.is_destructive());
+    }
 }
 
 mod test_fixup {
@@ -360,3 +414,57 @@ fn overflow() {
     assert_eq!(result, vec![]);
     executor.close(&mut repo, "master").unwrap();
 }
+
+#[test]
+fn coalesce_dependents_drops_redundant_switch() {
+    let mut repo = git_stack::legacy::git::InMemoryRepo::new();
+    let plan =
+        git_fixture::TodoList::load(std::path::Path::new("tests/fixtures/branches.yml")).unwrap();
+    fixture::populate_repo(&mut repo, plan);
+
+    let master_id = repo.find_local_branch("master").unwrap().id;
+    let feature1_id = repo.find_local_branch("feature1").unwrap().id;
+    let off_master_id = repo.find_local_branch("off_master").unwrap().id;
+
+    let dependent = |cherry_id, name: &str| git_stack::legacy::git::Script {
+        commands: vec![
+            git_stack::legacy::git::Command::SwitchMark(master_id),
+            git_stack::legacy::git::Command::CherryPick(cherry_id),
+            git_stack::legacy::git::Command::CreateBranch(name.to_owned()),
+        ],
+        dependents: vec![],
+    };
+    let mut script = git_stack::legacy::git::Script {
+        commands: vec![
+            git_stack::legacy::git::Command::SwitchCommit(master_id),
+            git_stack::legacy::git::Command::RegisterMark(master_id),
+        ],
+        dependents: vec![
+            dependent(feature1_id, "one"),
+            dependent(off_master_id, "two"),
+        ],
+    };
+    script.coalesce_dependents();
+
+    assert_eq!(
+        script.dependents[0].commands,
+        [
+            git_stack::legacy::git::Command::CherryPick(feature1_id),
+            git_stack::legacy::git::Command::CreateBranch("one".to_owned()),
+        ]
+    );
+    assert_eq!(script.dependents[1], dependent(off_master_id, "two"));
+
+    let mut executor = git_stack::legacy::git::Executor::new(&repo, false);
+    let result = executor.run_script(&mut repo, &script);
+    assert_eq!(result, vec![]);
+    executor.close(&mut repo, "master").unwrap();
+    for name in ["one", "two"] {
+        let branch = repo.find_local_branch(name).unwrap();
+        assert_eq!(
+            repo.parent_ids(branch.id).unwrap(),
+            vec![master_id],
+            "{name}"
+        );
+    }
+}
@@ -413,3 +413,68 @@ fn switch() {
 
     temp.close().unwrap();
 }
+
+#[test]
+fn patch_id_skips_binary() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let repo = git2::Repository::init(temp.path()).unwrap();
+    let sig = git2::Signature::now("Test", "test@example.com").unwrap();
+    let commit = |parent: Option<git2::Oid>, files: &[(&str, &[u8])]| {
+        let parent = parent.map(|id| repo.find_commit(id).unwrap());
+        let mut builder = repo
+            .treebuilder(parent.as_ref().map(|c| c.tree().unwrap()).as_ref())
+            .unwrap();
+        for (path, data) in files {
+            let blob_id = repo.blob(data).unwrap();
+            builder.insert(path, blob_id, 0o100644).unwrap();
+        }
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+        repo.commit(
+            None,
+            &sig,
+            &sig,
+            "commit",
+            &tree,
+            parent.iter().collect::<Vec<_>>().as_slice(),
+        )
+        .unwrap()
+    };
+    let large_binary = (0..4 * 1024 * 1024)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<_>>();
+
+    let base_id = commit(None, &[("file.txt", b"1\n2\n3\n")]);
+    let text_id = commit(Some(base_id), &[("file.txt", b"1\ntwo\n3\n")]);
+    let mixed_id = commit(
+        Some(base_id),
+        &[("file.txt", b"1\ntwo\n3\n"), ("large.bin", &large_binary)],
+    );
+    let binary_id = commit(Some(base_id), &[("large.bin", &large_binary)]);
+    // Marked binary by `.gitattributes` despite being text
+    std::fs::write(temp.path().join(".gitattributes"), "data.txt binary\n").unwrap();
+    let attr_id = commit(
+        Some(base_id),
+        &[("file.txt", b"1\ntwo\n3\n"), ("data.txt", b"data\n")],
+    );
+
+    let mut repo = GitRepo::new(repo);
+
+    {
+        // Binary files are part of the patch-id by default
+        let text = repo.patch_id(text_id).unwrap();
+        assert_ne!(repo.patch_id(mixed_id), Some(text));
+    }
+
+    {
+        repo.set_patch_id_skip_binary(true);
+        let text = repo.patch_id(text_id).unwrap();
+        assert_eq!(repo.patch_id(mixed_id), Some(text));
+        assert_eq!(repo.patch_id(attr_id), Some(text));
+        assert_ne!(repo.revert_patch_id(mixed_id), Some(text));
+        // Binary-only changes still get a patch-id
+        assert!(repo.patch_id(binary_id).is_some());
+        assert_ne!(repo.patch_id(binary_id), Some(text));
+    }
+
+    temp.close().unwrap();
+}
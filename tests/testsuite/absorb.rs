@@ -0,0 +1,221 @@
+use bstr::ByteSlice as _;
+
+fn file_at(repo: &git_stack::git::GitRepo, id: git2::Oid, path: &str) -> String {
+    repo.raw()
+        .find_commit(id)
+        .unwrap()
+        .tree()
+        .unwrap()
+        .get_path(std::path::Path::new(path))
+        .unwrap()
+        .to_object(repo.raw())
+        .unwrap()
+        .peel_to_blob()
+        .unwrap()
+        .content()
+        .to_str_lossy()
+        .into_owned()
+}
+
+#[test]
+fn absorb_splits_hunks_across_commits() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("base", "base\n")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("base", "base\n"), ("b", "one\ntwo\nthree\n")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [
+                    ("base", "base\n"),
+                    ("b", "one\ntwo\nthree\n"),
+                    ("c", "alpha\nbeta\n"),
+                ]
+                .into_iter()
+                .map(|(p, c)| (p.into(), c.into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("C".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+    snapbox::cmd::Command::new("git")
+        .args(["switch", "target"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+    std::fs::write(root_path.join("base"), "BASE\n").unwrap();
+    std::fs::write(root_path.join("b"), "one\nTWO\nthree\n").unwrap();
+    std::fs::write(root_path.join("c"), "alpha\nBETA\n").unwrap();
+    std::fs::write(root_path.join("new"), "new\n").unwrap();
+    snapbox::cmd::Command::new("git")
+        .args(["add", "base", "b", "c", "new"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+    let main_id = repo.find_local_branch("main").unwrap().id;
+    let old_target_id = repo.find_local_branch("target").unwrap().id;
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["absorb", "--dry-run"])
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq("")
+        .stderr_matches(
+            "\
+note: left base:1 staged, no commit in the stack touched it
+note: left new:1 staged, the file is not just modified
+Would absorb 1 hunk into [..]: B
+Would absorb 1 hunk into [..]: C
+",
+        );
+    assert_eq!(repo.find_local_branch("target").unwrap().id, old_target_id);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("absorb")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq("")
+        .stderr_matches(
+            "\
+note: left base:1 staged, no commit in the stack touched it
+note: left new:1 staged, the file is not just modified
+Saved working directory and index state WIP on target (absorb): [..]
+Dropped refs/stash [..]
+Absorbed 1 hunk into [..]: B
+Absorbed 1 hunk into [..]: C
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    assert_eq!(repo.find_local_branch("main").unwrap().id, main_id);
+    let target_id = repo.find_local_branch("target").unwrap().id;
+    let target_commit = repo.find_commit(target_id).unwrap();
+    snapbox::assert_eq(target_commit.summary.to_str_lossy().into_owned(), "C");
+    let b_id = repo.parent_ids(target_id).unwrap()[0];
+    let b_commit = repo.find_commit(b_id).unwrap();
+    snapbox::assert_eq(b_commit.summary.to_str_lossy().into_owned(), "B");
+    assert_eq!(repo.parent_ids(b_id).unwrap(), vec![main_id]);
+
+    snapbox::assert_eq(file_at(&repo, b_id, "b"), "one\nTWO\nthree\n");
+    snapbox::assert_eq(file_at(&repo, target_id, "c"), "alpha\nBETA\n");
+    snapbox::assert_eq(file_at(&repo, target_id, "base"), "base\n");
+
+    let statuses = repo.raw().statuses(None).unwrap();
+    let mut staged = statuses
+        .iter()
+        .filter(|s| {
+            s.status()
+                .intersects(git2::Status::INDEX_NEW | git2::Status::INDEX_MODIFIED)
+        })
+        .map(|s| s.path().unwrap().to_owned())
+        .collect::<Vec<_>>();
+    staged.sort();
+    assert_eq!(staged, ["base", "new"]);
+    snapbox::assert_eq(std::fs::read(root_path.join("base")).unwrap(), "BASE\n");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn absorb_ignorecase_matches_differently_cased_path() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("base", "base\n")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("base", "base\n"), ("b", "one\ntwo\nthree\n")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("base", "base\n"), ("b", "one\ntwo\nthree\n"), ("c", "c\n")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("C".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+    snapbox::cmd::Command::new("git")
+        .args(["switch", "target"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+    snapbox::cmd::Command::new("git")
+        .args(["config", "core.ignorecase", "true"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    // As a case-insensitive filesystem can leave it, the edit is staged as `B`
+    let mut index = repo.index().unwrap();
+    let mut entry = index.get_path(std::path::Path::new("b"), 0).unwrap();
+    index.remove_path(std::path::Path::new("b")).unwrap();
+    entry.path = b"B".to_vec();
+    entry.flags = 1;
+    index.add_frombuffer(&entry, b"one\nTWO\nthree\n").unwrap();
+    index.write().unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+    let main_id = repo.find_local_branch("main").unwrap().id;
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("absorb")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq("")
+        .stderr_matches(
+            "\
+Absorbed 1 hunk into [..]: B
+...
+",
+        );
+
+    let target_id = repo.find_local_branch("target").unwrap().id;
+    let b_id = repo.parent_ids(target_id).unwrap()[0];
+    assert_eq!(repo.parent_ids(b_id).unwrap(), vec![main_id]);
+    snapbox::assert_eq(file_at(&repo, b_id, "B"), "one\nTWO\nthree\n");
+
+    root.close().unwrap();
+}
@@ -99,7 +99,7 @@ fn reword_protected_fails() {
         .arg("--message=hahahaha")
         .current_dir(root_path)
         .assert()
-        .failure()
+        .code(64)
         .stdout_eq(
             "\
 ",
@@ -174,9 +174,7 @@ fn reword() {
         )
         .stderr_matches(
             "\
-Saved working directory and index state WIP on target (amend): [..]
 Amended to [..]: C
-Dropped refs/stash [..]
 note: to undo, run `git branch-stash pop git-stack`
 ",
         );
@@ -334,6 +332,305 @@ note: to undo, run `git branch-stash pop git-stack`
     root.close().unwrap();
 }
 
+#[test]
+fn amend_stage_all_config() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    repo.config()
+        .unwrap()
+        .set_str("stack.amend.stage", "all")
+        .unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    std::fs::write(root_path.join("a"), "new a").unwrap();
+    std::fs::write(root_path.join("b"), "new b").unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("amend")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+Adding a
+Adding b
+Amended to [..]: B
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    let head_tree = repo.raw().head().unwrap().peel_to_tree().unwrap();
+    let blob = |path: &str| {
+        let entry = head_tree.get_path(std::path::Path::new(path)).unwrap();
+        let blob = repo.raw().find_blob(entry.id()).unwrap();
+        String::from_utf8(blob.content().to_owned()).unwrap()
+    };
+    assert_eq!(blob("a"), "new a");
+    assert_eq!(blob("b"), "new b");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn amend_all_with_separate_git_dir() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let work_tree = root_path.join("w");
+    std::fs::create_dir_all(&work_tree).unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(&work_tree).unwrap();
+    // Nothing ties `g` to `w` besides `--work-tree`
+    std::fs::rename(work_tree.join(".git"), root_path.join("g")).unwrap();
+
+    std::fs::write(work_tree.join("b"), "new b").unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["--git-dir=g", "--work-tree=w", "amend", "--all"])
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+Adding b
+Amended to [..]: B
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    let repo = git2::Repository::open(root_path.join("g")).unwrap();
+    let head_tree = repo.head().unwrap().peel_to_tree().unwrap();
+    let entry = head_tree.get_path(std::path::Path::new("b")).unwrap();
+    let blob = repo.find_blob(entry.id()).unwrap();
+    snapbox::assert_eq(blob.content(), "new b");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn amend_exclude_pathspec() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+    // `git_fixture` can't create nested paths
+    std::fs::create_dir(root_path.join("generated")).unwrap();
+    std::fs::write(root_path.join("generated/g"), "g").unwrap();
+    let git = |args: &[&str]| {
+        let status = std::process::Command::new("git")
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(root_path)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    };
+    git(&["add", "generated"]);
+    git(&["commit", "--quiet", "-m", "G"]);
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    std::fs::write(root_path.join("b"), "new b").unwrap();
+    std::fs::write(root_path.join("generated/g"), "new g").unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("amend")
+        .args(["--", ".", ":!generated/"])
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+Adding b
+Amended to [..]: G
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    let branch = repo.find_local_branch("target").unwrap();
+    let tree = repo.raw().find_commit(branch.id).unwrap().tree().unwrap();
+    let blob = |path: &str| {
+        let entry = tree.get_path(std::path::Path::new(path)).unwrap();
+        let blob = repo.raw().find_blob(entry.id()).unwrap();
+        blob.content().to_owned()
+    };
+    snapbox::assert_eq(blob("b"), "new b");
+    snapbox::assert_eq(blob("generated/g"), "g");
+    snapbox::assert_eq(
+        std::fs::read(root_path.join("generated/g")).unwrap(),
+        "new g",
+    );
+
+    root.close().unwrap();
+}
+
+#[test]
+fn amend_exclude_pathspec_ignorecase() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+    // `git_fixture` can't create nested paths
+    std::fs::create_dir(root_path.join("generated")).unwrap();
+    std::fs::write(root_path.join("generated/g"), "g").unwrap();
+    let git = |args: &[&str]| {
+        let status = std::process::Command::new("git")
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(root_path)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    };
+    git(&["add", "generated"]);
+    git(&["commit", "--quiet", "-m", "G"]);
+    git(&["config", "core.ignorecase", "true"]);
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    std::fs::write(root_path.join("b"), "new b").unwrap();
+    std::fs::write(root_path.join("generated/g"), "new g").unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("amend")
+        .args(["--", ".", ":!GENERATED/"])
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+Adding b
+Amended to [..]: G
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    let branch = repo.find_local_branch("target").unwrap();
+    let tree = repo.raw().find_commit(branch.id).unwrap().tree().unwrap();
+    let blob = |path: &str| {
+        let entry = tree.get_path(std::path::Path::new(path)).unwrap();
+        let blob = repo.raw().find_blob(entry.id()).unwrap();
+        blob.content().to_owned()
+    };
+    snapbox::assert_eq(blob("b"), "new b");
+    snapbox::assert_eq(blob("generated/g"), "g");
+    snapbox::assert_eq(
+        std::fs::read(root_path.join("generated/g")).unwrap(),
+        "new g",
+    );
+
+    root.close().unwrap();
+}
+
 #[test]
 fn amend_staged() {
     let root = snapbox::path::PathFixture::mutable_temp().unwrap();
@@ -396,9 +693,7 @@ fn amend_staged() {
         )
         .stderr_matches(
             "\
-Saved working directory and index state WIP on target (amend): [..]
 Amended to [..]: C
-Dropped refs/stash [..]
 note: to undo, run `git branch-stash pop git-stack`
 ",
         );
@@ -556,9 +851,7 @@ fn amend_explicit_head() {
         )
         .stderr_matches(
             "\
-Saved working directory and index state WIP on target (amend): [..]
 Amended to [..]: C
-Dropped refs/stash [..]
 note: to undo, run `git branch-stash pop git-stack`
 ",
         );
@@ -655,7 +948,7 @@ note: to undo, run `git branch-stash pop git-stack`
 }
 
 #[test]
-fn amend_conflict() {
+fn amend_to_branch() {
     let root = snapbox::path::PathFixture::mutable_temp().unwrap();
     let root_path = root.path().unwrap();
     let plan = git_fixture::TodoList {
@@ -695,48 +988,1186 @@ fn amend_conflict() {
     let repo = git2::Repository::discover(root_path).unwrap();
     let repo = git_stack::git::GitRepo::new(repo);
 
-    let old_head_id = repo.head_commit().id;
-
-    std::fs::write(root_path.join("a"), "unstaged a").unwrap();
+    let old_target_id = repo.find_local_branch("target").unwrap().id;
 
-    std::fs::write(root_path.join("c"), "conflicted c").unwrap();
+    std::fs::write(root_path.join("b"), "new b").unwrap();
     snapbox::cmd::Command::new("git")
         .arg("add")
-        .arg("c")
+        .arg("b")
         .current_dir(root_path)
         .assert()
         .success();
     snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
         .arg("amend")
+        .arg("--to-branch")
         .arg("target")
         .current_dir(root_path)
         .assert()
-        .failure()
+        .success()
         .stdout_eq(
             "\
 ",
         )
         .stderr_matches(
             "\
-Saved working directory and index state WIP on local (amend): [..]
-ERROR: Failed to re-stack branch `local`: squash conflicts:
-  c
-; class=Index (10); code=Unmerged (-10)
-Dropped refs/stash [..]
+Amended to [..]: B
 note: to undo, run `git branch-stash pop git-stack`
 ",
         );
 
-    let new_head_id = repo.head_commit().id;
-    assert_ne!(old_head_id, new_head_id);
+    let target = repo.find_local_branch("target").unwrap();
+    assert_ne!(target.id, old_target_id);
+    let target_commit = repo.find_commit(target.id).unwrap();
+    snapbox::assert_eq(target_commit.summary.to_str_lossy().into_owned(), "B");
+    let target_tree = repo.raw().find_commit(target.id).unwrap().tree().unwrap();
+    let b_blob = target_tree
+        .get_name("b")
+        .unwrap()
+        .to_object(repo.raw())
+        .unwrap()
+        .peel_to_blob()
+        .unwrap();
+    snapbox::assert_eq(b_blob.content(), "new b");
+
+    let local = repo.find_local_branch("local").unwrap();
+    assert_eq!(repo.parent_ids(local.id).unwrap(), vec![target.id]);
+    assert_eq!(repo.head_branch().unwrap().name, "local");
+    assert_eq!(repo.head_commit().id, local.id);
+    assert!(!repo.is_dirty());
 
-    snapbox::assert_eq(repo.head_commit().summary.to_str().unwrap(), "fixup! B");
+    root.close().unwrap();
+}
 
-    let local_branch = repo.find_local_branch("local").unwrap();
-    let local_commit = repo.find_commit(local_branch.id).unwrap();
-    snapbox::assert_eq(local_commit.summary.to_str_lossy().into_owned(), "fixup! B");
+#[test]
+fn amend_to_branch_protected_fails() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    protected_stack_plan().run(root_path).unwrap();
 
-    snapbox::assert_eq(std::fs::read(root_path.join("a")).unwrap(), "unstaged a");
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let old_main_id = repo.find_local_branch("main").unwrap().id;
+
+    std::fs::write(root_path.join("b"), "new b").unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("amend")
+        .arg("--all")
+        .arg("--to-branch")
+        .arg("main")
+        .current_dir(root_path)
+        .assert()
+        .code(64)
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+cannot amend protected commits
+",
+        );
+
+    assert_eq!(repo.find_local_branch("main").unwrap().id, old_main_id);
+
+    root.close().unwrap();
+}
+
+#[test]
+fn amend_conflict() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b"), ("c", "c")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("C".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("local".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let old_head_id = repo.head_commit().id;
+    let target_id = repo.find_local_branch("target").unwrap().id;
+
+    std::fs::write(root_path.join("a"), "unstaged a").unwrap();
+
+    std::fs::write(root_path.join("c"), "conflicted c").unwrap();
+    snapbox::cmd::Command::new("git")
+        .arg("add")
+        .arg("c")
+        .current_dir(root_path)
+        .assert()
+        .success();
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("amend")
+        .arg("target")
+        .current_dir(root_path)
+        .assert()
+        .code(1)
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+Saved working directory and index state WIP on local (amend): [..]
+ERROR: Failed to re-stack branch `local`: squash conflicts:
+  c
+; class=Index (10); code=Unmerged (-10)
+Dropped refs/stash [..]
+note: restored branches, leaving the changes staged
+note: to undo, run `git branch-stash pop git-stack`
+error: 1 branch re-stacked, 1 failed: local (squash conflicts: c)
+",
+        );
+
+    let new_head_id = repo.head_commit().id;
+    assert_eq!(old_head_id, new_head_id);
+    assert_eq!(repo.find_local_branch("local").unwrap().id, old_head_id);
+    assert_eq!(repo.find_local_branch("target").unwrap().id, target_id);
+
+    snapbox::cmd::Command::new("git")
+        .arg("diff")
+        .arg("--cached")
+        .arg("--name-only")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+c
+",
+        );
+    snapbox::assert_eq(std::fs::read(root_path.join("c")).unwrap(), "conflicted c");
+    snapbox::assert_eq(std::fs::read(root_path.join("a")).unwrap(), "unstaged a");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn amend_all_conflict_keeps_index() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b"), ("c", "c")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("C".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("local".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let old_head_id = repo.head_commit().id;
+
+    std::fs::write(root_path.join("a"), "staged a").unwrap();
+    snapbox::cmd::Command::new("git")
+        .arg("add")
+        .arg("a")
+        .current_dir(root_path)
+        .assert()
+        .success();
+    std::fs::write(root_path.join("c"), "conflicted c").unwrap();
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("amend")
+        .arg("--all")
+        .arg("target")
+        .current_dir(root_path)
+        .assert()
+        .code(1)
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+Adding c
+ERROR: Failed to re-stack branch `local`: squash conflicts:
+  c
+; class=Index (10); code=Unmerged (-10)
+note: restored branches and the index
+note: to undo, run `git branch-stash pop git-stack`
+error: 1 branch re-stacked, 1 failed: local (squash conflicts: c)
+",
+        );
+
+    assert_eq!(repo.head_commit().id, old_head_id);
+    assert_eq!(repo.find_local_branch("local").unwrap().id, old_head_id);
+
+    // Only what was staged before, `--all` never touched the real index
+    snapbox::cmd::Command::new("git")
+        .arg("diff")
+        .arg("--cached")
+        .arg("--name-only")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+a
+",
+        );
+    snapbox::assert_eq(std::fs::read(root_path.join("a")).unwrap(), "staged a");
+    snapbox::assert_eq(std::fs::read(root_path.join("c")).unwrap(), "conflicted c");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn amend_unresolved_conflicts_fails() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    // Leave the index with unmerged entries without an operation in progress
+    std::fs::write(root_path.join("b"), "stashed b").unwrap();
+    snapbox::cmd::Command::new("git")
+        .arg("stash")
+        .current_dir(root_path)
+        .assert()
+        .success();
+    std::fs::write(root_path.join("b"), "committed b").unwrap();
+    snapbox::cmd::Command::new("git")
+        .args(["commit", "-am", "C"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+    snapbox::cmd::Command::new("git")
+        .args(["stash", "pop"])
+        .current_dir(root_path)
+        .assert()
+        .failure();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let old_head_id = repo.head_commit().id;
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("amend")
+        .current_dir(root_path)
+        .assert()
+        .failure()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_eq(
+            "\
+cannot walk commits with unresolved conflicts (conflicted files: b)
+",
+        );
+
+    let new_head_id = repo.head_commit().id;
+    assert_eq!(old_head_id, new_head_id);
+
+    root.close().unwrap();
+}
+
+#[test]
+fn amend_no_backup() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b"), ("c", "c")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("C".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let old_head_id = repo.head_commit().id;
+
+    std::fs::write(root_path.join("c"), "new c").unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("amend")
+        .arg("-a")
+        .arg("--no-backup")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+Adding c
+Amended to [..]: C
+",
+        );
+
+    let new_head_id = repo.head_commit().id;
+    assert_ne!(old_head_id, new_head_id);
+    assert!(!root_path.join(".git/branch-stash").exists());
+
+    root.close().unwrap();
+}
+
+#[test]
+fn amend_tip_skips_stash() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b"), ("c", "c")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let old_head_id = repo.head_commit().id;
+
+    std::fs::write(root_path.join("b"), "new b").unwrap();
+    let mut index = repo.raw().index().unwrap();
+    index.add_path(std::path::Path::new("b")).unwrap();
+    index.write().unwrap();
+    std::fs::write(root_path.join("c"), "unstaged c").unwrap();
+    // Any attempt to stash fails while the ref is locked
+    std::fs::write(root_path.join(".git/refs/stash.lock"), "").unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("amend")
+        .arg("--no-backup")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+Amended to [..]: B
+",
+        );
+
+    let new_head = repo.head_commit();
+    assert_ne!(old_head_id, new_head.id);
+    assert_eq!(repo.head_branch().unwrap().name, "target");
+    assert_eq!(repo.raw().head().unwrap().name(), Some("refs/heads/target"));
+    assert_eq!(
+        std::fs::read_to_string(root_path.join("c")).unwrap(),
+        "unstaged c"
+    );
+    assert!(repo.raw().find_reference("refs/stash").is_err());
+
+    let statuses = repo.raw().statuses(None).unwrap();
+    let changed = statuses
+        .iter()
+        .map(|s| (s.path().unwrap().to_owned(), s.status()))
+        .collect::<Vec<_>>();
+    assert_eq!(changed, vec![("c".to_owned(), git2::Status::WT_MODIFIED)]);
+
+    root.close().unwrap();
+}
+
+#[test]
+fn amend_tip_all_updates_index() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let old_head_id = repo.head_commit().id;
+
+    std::fs::write(root_path.join("b"), "new b").unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("amend")
+        .arg("--all")
+        .arg("--no-backup")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+Adding b
+Amended to [..]: B
+",
+        );
+
+    assert_ne!(old_head_id, repo.head_commit().id);
+    // The index caught up with the amended commit, nothing is left staged or modified
+    let statuses = repo.raw().statuses(None).unwrap();
+    assert!(statuses.is_empty());
+
+    root.close().unwrap();
+}
+
+fn protected_stack_plan() -> git_fixture::TodoList {
+    git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b"), ("c", "c")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("C".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("feature".into()),
+        ],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn amend_protected_force() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    protected_stack_plan().run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    repo.set_head("refs/heads/main").unwrap();
+    repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+        .unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let old_main_id = repo.find_local_branch("main").unwrap().id;
+
+    std::fs::write(root_path.join("b"), "new b").unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("amend")
+        .arg("--all")
+        .arg("--force")
+        .stdin("y\n")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+commit [..] is protected; amend anyway? [y/N] Adding b
+Amended to [..]: B
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    let main = repo.find_local_branch("main").unwrap();
+    assert_ne!(main.id, old_main_id);
+    assert_eq!(repo.head_commit().id, main.id);
+    let main_commit = repo.find_commit(main.id).unwrap();
+    snapbox::assert_eq(main_commit.summary.to_str().unwrap(), "B");
+    assert!(!repo.is_dirty());
+
+    let feature = repo.find_local_branch("feature").unwrap();
+    assert_eq!(repo.parent_ids(feature.id).unwrap(), vec![main.id]);
+
+    root.close().unwrap();
+}
+
+#[test]
+fn amend_protected_force_declined() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    protected_stack_plan().run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    repo.set_head("refs/heads/main").unwrap();
+    repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+        .unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let old_main_id = repo.find_local_branch("main").unwrap().id;
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("amend")
+        .arg("--message=new B")
+        .arg("--force")
+        .stdin("n\n")
+        .current_dir(root_path)
+        .assert()
+        .code(1)
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+commit [..] is protected; amend anyway? [y/N] aborted
+",
+        );
+
+    assert_eq!(repo.find_local_branch("main").unwrap().id, old_main_id);
+
+    root.close().unwrap();
+}
+
+#[test]
+fn amend_reset_author() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b"), ("c", "c")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("C".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("local".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let old_author = repo
+        .head()
+        .unwrap()
+        .peel_to_commit()
+        .unwrap()
+        .author()
+        .to_owned();
+    assert_ne!(old_author.name(), Some("Amender"));
+
+    for (key, value) in [
+        ("user.name", "Amender"),
+        ("user.email", "amender@example.com"),
+    ] {
+        snapbox::cmd::Command::new("git")
+            .arg("config")
+            .arg(key)
+            .arg(value)
+            .current_dir(root_path)
+            .assert()
+            .success();
+    }
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("amend")
+        .arg("--reset-author")
+        .arg("--message=new B")
+        .arg("target")
+        .env_remove("GIT_AUTHOR_NAME")
+        .env_remove("GIT_AUTHOR_EMAIL")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+Amended to [..]: B
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    let target = repo
+        .find_branch("target", git2::BranchType::Local)
+        .unwrap()
+        .get()
+        .peel_to_commit()
+        .unwrap();
+    assert_eq!(target.summary(), Some("new B"));
+    assert_eq!(target.author().name(), Some("Amender"));
+    assert_eq!(target.author().email(), Some("amender@example.com"));
+
+    let local = repo
+        .find_branch("local", git2::BranchType::Local)
+        .unwrap()
+        .get()
+        .peel_to_commit()
+        .unwrap();
+    assert_eq!(local.parent_id(0).unwrap(), target.id());
+    assert_eq!(local.author().name(), old_author.name());
+
+    root.close().unwrap();
+}
+
+fn sibling_message_plan() -> git_fixture::TodoList {
+    git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Label("base".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("s", "s")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("Sibling summary\n\nSibling body".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("sibling".into()),
+            git_fixture::Command::Reset("base".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn amend_reuse_message() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    sibling_message_plan().run(root_path).unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("amend")
+        .arg("--reuse-message=sibling")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+Amended to [..]: B
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(
+        head.message().unwrap().trim(),
+        "Sibling summary\n\nSibling body"
+    );
+    assert_eq!(
+        repo.find_branch("target", git2::BranchType::Local)
+            .unwrap()
+            .get()
+            .target(),
+        Some(head.id())
+    );
+
+    root.close().unwrap();
+}
+
+#[test]
+fn amend_reuse_message_unknown_commit() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    sibling_message_plan().run(root_path).unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("amend")
+        .arg("--reuse-message=missing")
+        .current_dir(root_path)
+        .assert()
+        .code(64)
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+could not find commit `missing`
+",
+        );
+
+    root.close().unwrap();
+}
+
+#[test]
+fn amend_add_normalizes_line_endings() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [(".gitattributes", "* text=auto\n"), ("a", "a\n")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [
+                    (".gitattributes", "* text=auto\n"),
+                    ("a", "a\n"),
+                    ("b", "b\n"),
+                ]
+                .into_iter()
+                .map(|(p, c)| (p.into(), c.into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    std::fs::write(root_path.join("b"), "one\r\ntwo\r\n").unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("amend")
+        .arg("-a")
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    let expected = snapbox::cmd::Command::new("git")
+        .args(["hash-object", "--path=b", "b"])
+        .current_dir(root_path)
+        .output()
+        .unwrap();
+    let expected =
+        git2::Oid::from_str(std::str::from_utf8(&expected.stdout).unwrap().trim()).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let tree = repo.head().unwrap().peel_to_tree().unwrap();
+    let actual = tree.get_name("b").unwrap();
+    assert_eq!(actual.id(), expected);
+    let blob = actual.to_object(&repo).unwrap().peel_to_blob().unwrap();
+    assert_eq!(blob.content(), b"one\ntwo\n");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn amend_co_author() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some(
+                    "B\n\nBody\n\nSigned-off-by: Me <me@example.com>\nCo-authored-by: Bob <bob@example.com>\n"
+                        .to_owned(),
+                ),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("amend")
+        .args(["--co-author", "Alice <alice@example.com>"])
+        .args(["--co-author", "Bob <bob@example.com>"])
+        .args(["--co-author", "Alice <alice@example.com>"])
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+Amended to [..]: B
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(
+        head.message().unwrap(),
+        "\
+B
+
+Body
+
+Signed-off-by: Me <me@example.com>
+Co-authored-by: Bob <bob@example.com>
+Co-authored-by: Alice <alice@example.com>"
+    );
+
+    root.close().unwrap();
+}
+
+#[test]
+fn amend_co_author_malformed() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    sibling_message_plan().run(root_path).unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("amend")
+        .args(["--co-author", "@alice"])
+        .current_dir(root_path)
+        .assert()
+        .code(64)
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+error: invalid value '@alice' for '--co-author <IDENT>': expected `NAME <EMAIL>`, got `@alice`
+...",
+        );
+
+    root.close().unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn amend_edit_verbose() {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let git_dir = repo.path().to_owned();
+    let editor_path = git_dir.join("editor.sh");
+    std::fs::write(
+        &editor_path,
+        "#!/bin/sh\ncp \"$1\" \"$1.captured\"\nsed -i.bak '1s/.*/Edited/' \"$1\"\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&editor_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    repo.config()
+        .unwrap()
+        .set_bool("commit.verbose", true)
+        .unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("amend")
+        .arg("--edit")
+        .env("GIT_EDITOR", &editor_path)
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    let captured = std::fs::read_to_string(git_dir.join("COMMIT_EDITMSG.captured")).unwrap();
+    let (_, diff) = captured
+        .split_once("# ------------------------ >8 ------------------------\n")
+        .unwrap();
+    assert!(diff.contains("+++ b/b\n"), "{captured}");
+    assert!(diff.contains("\n+b\n"), "{captured}");
+
+    let branch = repo.find_local_branch("target").unwrap();
+    let commit = repo.find_commit(branch.id).unwrap();
+    snapbox::assert_eq(commit.message.as_str(), "Edited\n");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn amend_no_edit() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B  \n\n# not a comment\n".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+    let old_message = repo.head_commit().message.clone();
+
+    std::fs::write(root_path.join("b"), "new b").unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("amend")
+        .arg("--all")
+        .arg("--no-edit")
+        .env("GIT_EDITOR", "false")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stderr_matches(
+            "\
+Adding b
+Amended to [..]: B
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    let branch = repo.find_local_branch("target").unwrap();
+    let commit = repo.find_commit(branch.id).unwrap();
+    snapbox::assert_eq(commit.message.as_str(), &old_message);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("amend")
+        .arg("--no-edit")
+        .arg("--edit")
+        .current_dir(root_path)
+        .assert()
+        .code(64)
+        .stderr_matches(
+            "\
+error: the argument '--no-edit' cannot be used with '--edit'
+...
+",
+        );
 
     root.close().unwrap();
 }
@@ -150,6 +150,36 @@ mod test_branches {
             ]
         );
     }
+
+    #[test]
+    fn test_move_local() {
+        let mut repo = git_stack::git::InMemoryRepo::new();
+        let plan = git_fixture::TodoList::load(std::path::Path::new("tests/fixtures/branches.yml"))
+            .unwrap();
+        fixture::populate_repo(&mut repo, plan);
+
+        let protect = protect();
+        let mut branches = BranchSet::from_repo(&repo, &protect).unwrap();
+
+        let old_oid = repo.resolve("feature1").unwrap().id;
+        let new_oid = repo.resolve("feature2").unwrap().id;
+        repo.branch("feature1", new_oid).unwrap();
+
+        let mut expected = branches.clone();
+        expected.update(&repo).unwrap();
+        branches.move_local(old_oid, new_oid);
+
+        assert!(!branches.contains_oid(old_oid));
+        let names = |set: &BranchSet| {
+            let mut names: Vec<_> = set
+                .iter()
+                .flat_map(|(oid, b)| b.iter().map(move |b| (oid, b.name())))
+                .collect();
+            names.sort_unstable();
+            names
+        };
+        assert_eq!(names(&branches), names(&expected));
+    }
 }
 
 mod test_find_protected_base {
@@ -0,0 +1,123 @@
+use bstr::ByteSlice as _;
+
+/// `feature1` / `feature2` are stacked on `main`, `other` is a sibling stack
+fn sibling_stack_plan() -> git_fixture::TodoList {
+    git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Label("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("o", "o")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("O".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("other".into()),
+            git_fixture::Command::Reset("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("feature1".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b"), ("c", "c")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("C".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("feature2".into()),
+        ],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn cherry_pick_onto_tip() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    sibling_stack_plan().run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let feature1_id = repo.find_local_branch("feature1").unwrap().id;
+    let old_feature2_id = repo.find_local_branch("feature2").unwrap().id;
+    let other_id = repo.find_local_branch("other").unwrap().id;
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["cherry-pick", "other"])
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+Cherry-picked [..]: O
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    assert_eq!(repo.find_local_branch("other").unwrap().id, other_id);
+    assert_eq!(repo.find_local_branch("feature1").unwrap().id, feature1_id);
+
+    let feature2 = repo.find_local_branch("feature2").unwrap();
+    let commit = repo.find_commit(feature2.id).unwrap();
+    snapbox::assert_eq(commit.summary.to_str().unwrap(), "O");
+    assert_ne!(commit.id, other_id);
+    assert_eq!(repo.parent_ids(commit.id).unwrap(), vec![old_feature2_id]);
+    assert_eq!(repo.head_branch().unwrap().name, "feature2");
+    assert_eq!(repo.head_commit().id, commit.id);
+    snapbox::assert_eq(std::fs::read(root_path.join("o")).unwrap(), "o");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn cherry_pick_onto_mid_stack() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    sibling_stack_plan().run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let old_feature1_id = repo.find_local_branch("feature1").unwrap().id;
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["cp", "other", "--onto", "feature1"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    let feature1 = repo.find_local_branch("feature1").unwrap();
+    let picked = repo.find_commit(feature1.id).unwrap();
+    snapbox::assert_eq(picked.summary.to_str().unwrap(), "O");
+    assert_eq!(repo.parent_ids(picked.id).unwrap(), vec![old_feature1_id]);
+
+    let feature2 = repo.find_local_branch("feature2").unwrap();
+    let commit = repo.find_commit(feature2.id).unwrap();
+    snapbox::assert_eq(commit.summary.to_str().unwrap(), "C");
+    assert_eq!(repo.parent_ids(commit.id).unwrap(), vec![picked.id]);
+    assert_eq!(repo.head_branch().unwrap().name, "feature2");
+
+    root.close().unwrap();
+}
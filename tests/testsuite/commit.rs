@@ -0,0 +1,174 @@
+#[test]
+fn commit_fixup_targets_mid_stack_commit() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            tree(&[("a", "a")], "A"),
+            git_fixture::Command::Branch("main".into()),
+            tree(&[("a", "a"), ("b", "b")], "B"),
+            tree(&[("a", "a"), ("b", "b"), ("c", "c")], "C"),
+            tree(&[("a", "a"), ("b", "b"), ("c", "c"), ("d", "d")], "D"),
+            git_fixture::Command::Branch("feature".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+    snapbox::cmd::Command::new("git")
+        .args(["switch", "feature"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+    std::fs::write(root_path.join("c"), "fixed c").unwrap();
+    snapbox::cmd::Command::new("git")
+        .args(["add", "c"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["commit", "--fixup", "HEAD~"])
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq("")
+        .stderr_matches(
+            "\
+Committed [..]: fixup! C
+",
+        );
+
+    // The fixup is picked up as one of `C`
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+    let protect = git_stack::git::ProtectedBranches::new(vec!["main"]).unwrap();
+    let branches = git_stack::graph::BranchSet::from_repo(&repo, &protect).unwrap();
+    let mut graph = git_stack::graph::Graph::from_branches(&repo, branches).unwrap();
+    git_stack::graph::protect_branches(&mut graph);
+    git_stack::graph::mark_fixup(&mut graph, &repo);
+    let head_id = repo.head_commit().id;
+    assert!(graph
+        .commit_get::<git_stack::graph::Fixup>(head_id)
+        .is_some());
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("fixup")
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    let log = |rev: &str| {
+        let commit = repo
+            .raw()
+            .revparse_single(rev)
+            .unwrap()
+            .peel_to_commit()
+            .unwrap();
+        let c = commit
+            .tree()
+            .unwrap()
+            .get_path(std::path::Path::new("c"))
+            .ok()
+            .map(|entry| {
+                let blob = repo.raw().find_blob(entry.id()).unwrap();
+                String::from_utf8(blob.content().to_owned()).unwrap()
+            });
+        (commit.summary().unwrap().to_owned(), c)
+    };
+    assert_eq!(log("feature"), ("D".to_owned(), Some("fixed c".to_owned())));
+    assert_eq!(
+        log("feature~"),
+        ("C".to_owned(), Some("fixed c".to_owned()))
+    );
+    assert_eq!(log("feature~2"), ("B".to_owned(), None));
+
+    root.close().unwrap();
+}
+
+#[test]
+fn commit_fixup_is_by_current_user() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+    std::fs::write(root_path.join("b"), "b").unwrap();
+    for args in [
+        &["switch", "-c", "feature"][..],
+        &["add", "b"],
+        &[
+            "-c",
+            "user.name=Other",
+            "-c",
+            "user.email=other@example.com",
+            "commit",
+            "-m",
+            "B",
+        ],
+    ] {
+        snapbox::cmd::Command::new("git")
+            .args(args)
+            .env("GIT_AUTHOR_DATE", "1577836800 +0000")
+            .env("GIT_COMMITTER_DATE", "1577836800 +0000")
+            .current_dir(root_path)
+            .assert()
+            .success();
+    }
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let mut config = repo.config().unwrap();
+    config.set_str("user.name", "Me").unwrap();
+    config.set_str("user.email", "me@example.com").unwrap();
+    std::fs::write(root_path.join("b"), "fixed b").unwrap();
+    snapbox::cmd::Command::new("git")
+        .args(["add", "b"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["commit", "--fixup", "HEAD"])
+        .env_remove("GIT_AUTHOR_NAME")
+        .env_remove("GIT_AUTHOR_EMAIL")
+        .env_remove("GIT_COMMITTER_NAME")
+        .env_remove("GIT_COMMITTER_EMAIL")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq("")
+        .stderr_matches(
+            "\
+Committed [..]: fixup! B
+",
+        );
+
+    let fixup = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(fixup.author().name(), Some("Me"));
+    assert_eq!(fixup.author().email(), Some("me@example.com"));
+    assert_ne!(fixup.author().when().seconds(), 1_577_836_800);
+    assert_eq!(fixup.committer().name(), Some("Me"));
+    assert_ne!(fixup.committer().when().seconds(), 1_577_836_800);
+
+    root.close().unwrap();
+}
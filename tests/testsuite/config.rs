@@ -0,0 +1,116 @@
+#[test]
+fn includeif_onbranch_scopes_protected_branches() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Branch("other".into()),
+            git_fixture::Command::Branch("feature/x".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    std::fs::write(
+        repo.path().join("feature-config"),
+        "[stack]\n\tprotected-branch = release\n",
+    )
+    .unwrap();
+    let mut config = std::fs::read_to_string(repo.path().join("config")).unwrap();
+    config.push_str("[includeIf \"onbranch:feature/*\"]\n\tpath = feature-config\n");
+    std::fs::write(repo.path().join("config"), config).unwrap();
+
+    let protected_branches = |branch: &str| {
+        repo.set_head(&format!("refs/heads/{branch}")).unwrap();
+        let repo = git2::Repository::discover(root_path).unwrap();
+        git_stack::config::RepoConfig::from_repo(&repo)
+            .unwrap()
+            .protected_branches
+            .unwrap_or_default()
+    };
+    assert_eq!(protected_branches("feature/x"), ["release"]);
+    assert_eq!(protected_branches("other"), Vec::<String>::new());
+
+    root.close().unwrap();
+}
+
+#[test]
+fn dump_config_notes_first_parent_implied_by_rebase_merges() {
+    let config = git_stack::config::RepoConfig {
+        rebase_merges: Some(true),
+        ..Default::default()
+    };
+    assert!(config
+        .to_string()
+        .contains("\t# implied by stack.rebase-merges\n\tfirst-parent=true\n"));
+
+    let config = git_stack::config::RepoConfig {
+        first_parent: Some(true),
+        rebase_merges: Some(true),
+        ..Default::default()
+    };
+    assert!(!config.to_string().contains("# implied by"));
+}
+
+#[test]
+fn env_bools_accept_git_spellings() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    git2::Repository::init(root_path).unwrap();
+
+    let output = snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["--dump-config", "-"])
+        .env("GIT_CONFIG_COUNT", "2")
+        .env("GIT_CONFIG_KEY_0", "stack.first-parent")
+        .env("GIT_CONFIG_VALUE_0", "yes")
+        .env("GIT_CONFIG_KEY_1", "stack.backup")
+        .env("GIT_CONFIG_VALUE_1", "off")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("\tfirst-parent=true\n"), "{output}");
+    assert!(output.contains("\tbackup=false\n"), "{output}");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn rebase_strategy_flag_overrides_config() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let repo = git2::Repository::init(root_path).unwrap();
+    repo.config()
+        .unwrap()
+        .set_str("stack.rebase.strategy", "cherry-pick")
+        .unwrap();
+
+    let output = snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["--rebase-strategy", "merge-tree", "--dump-config", "-"])
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+    assert!(
+        output.contains("[stack \"rebase\"]\n\tstrategy=merge-tree\n"),
+        "{output}"
+    );
+
+    root.close().unwrap();
+}
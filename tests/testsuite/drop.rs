@@ -0,0 +1,609 @@
+use bstr::ByteSlice as _;
+
+#[test]
+fn drop_middle_commit_reparents_descendants() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("feature1".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b"), ("c", "c")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("C".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("feature2".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b"), ("c", "c"), ("d", "d")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("D".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("feature3".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let feature1_id = repo.find_local_branch("feature1").unwrap().id;
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("drop")
+        .arg("feature2")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+warning: branch feature2 moves to [..]
+Dropped 1 commits
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    assert_eq!(repo.find_local_branch("feature1").unwrap().id, feature1_id);
+    assert_eq!(repo.find_local_branch("feature2").unwrap().id, feature1_id);
+
+    let feature3 = repo.find_local_branch("feature3").unwrap();
+    let commit = repo.find_commit(feature3.id).unwrap();
+    snapbox::assert_eq(commit.summary.to_str().unwrap(), "D");
+    assert_eq!(repo.parent_ids(commit.id).unwrap(), vec![feature1_id]);
+    assert_eq!(repo.head_commit().id, commit.id);
+
+    assert!(!root_path.join("c").exists());
+    snapbox::assert_eq(std::fs::read(root_path.join("d")).unwrap(), "d");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn drop_first_commit_moves_branch_to_base() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("feature1".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b"), ("c", "c")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("C".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("feature2".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let main_id = repo.find_local_branch("main").unwrap().id;
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("drop")
+        .arg("feature1")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+warning: branch feature1 moves to [..]
+Dropped 1 commits
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    assert_eq!(repo.find_local_branch("feature1").unwrap().id, main_id);
+    let feature2 = repo.find_local_branch("feature2").unwrap();
+    let commit = repo.find_commit(feature2.id).unwrap();
+    snapbox::assert_eq(commit.summary.to_str().unwrap(), "C");
+    assert_eq!(repo.parent_ids(commit.id).unwrap(), vec![main_id]);
+
+    root.close().unwrap();
+}
+
+#[test]
+fn drop_protected_fails() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let old_head_id = repo.head_commit().id;
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("drop")
+        .arg("HEAD")
+        .current_dir(root_path)
+        .assert()
+        .code(64)
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_eq(
+            "\
+cannot drop protected commits
+",
+        );
+
+    let new_head_id = repo.head_commit().id;
+    assert_eq!(old_head_id, new_head_id);
+
+    root.close().unwrap();
+}
+
+#[test]
+fn drop_select_range() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("feature1".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b"), ("c", "c")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("C".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b"), ("c", "c"), ("d", "d")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("D".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("feature2".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b"), ("c", "c"), ("d", "d"), ("e", "e")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("E".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("feature3".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let main_id = repo.find_local_branch("main").unwrap().id;
+    let feature1_id = repo.find_local_branch("feature1").unwrap().id;
+    let feature3_id = repo.find_local_branch("feature3").unwrap().id;
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("drop")
+        .arg("--select=feature3..feature1")
+        .current_dir(root_path)
+        .assert()
+        .code(64)
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_eq(
+            "\
+`feature3..feature1` is not a contiguous range within one stack
+",
+        );
+    assert_eq!(repo.find_local_branch("feature3").unwrap().id, feature3_id);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("drop")
+        .arg("--select=feature1..feature2")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+warning: branch feature2 moves to [..]
+Dropped 2 commits
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    assert_eq!(repo.find_local_branch("main").unwrap().id, main_id);
+    assert_eq!(repo.find_local_branch("feature1").unwrap().id, feature1_id);
+    assert_eq!(repo.find_local_branch("feature2").unwrap().id, feature1_id);
+
+    let feature3 = repo.find_local_branch("feature3").unwrap();
+    let commit = repo.find_commit(feature3.id).unwrap();
+    snapbox::assert_eq(commit.summary.to_str().unwrap(), "E");
+    assert_eq!(repo.parent_ids(commit.id).unwrap(), vec![feature1_id]);
+
+    assert!(!root_path.join("c").exists());
+    assert!(!root_path.join("d").exists());
+    snapbox::assert_eq(std::fs::read(root_path.join("e")).unwrap(), "e");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn drop_only_checks_out_the_final_tip() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            tree(&[("a", "a")], "A"),
+            git_fixture::Command::Branch("main".into()),
+            tree(&[("a", "a"), ("b", "b")], "B"),
+            tree(&[("a", "a"), ("b", "b"), ("c", "c")], "C"),
+            tree(&[("a", "a"), ("b", "b"), ("c", "c"), ("d", "d")], "D"),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+    snapbox::cmd::Command::new("git")
+        .args(["switch", "target"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+    let modified = |path: &str| {
+        std::fs::metadata(root_path.join(path))
+            .unwrap()
+            .modified()
+            .unwrap()
+    };
+    let old_c_modified = modified("c");
+    let old_d_modified = modified("d");
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["drop", "target~2"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    // `C` and `D` were replayed without writing them out, so files they don't change in the end
+    // are left alone
+    assert_eq!(modified("c"), old_c_modified);
+    assert_eq!(modified("d"), old_d_modified);
+    assert!(!root_path.join("b").exists());
+    snapbox::cmd::Command::new("git")
+        .args(["status", "--porcelain"])
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq("");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn drop_refuses_to_overwrite_untracked_files() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("p", "p")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("Add p".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("Remove p".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Label("remove".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("d", "d")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("D".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("feature".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let feature_id = repo.find_local_branch("feature").unwrap().id;
+    let remove_id = repo.parent_ids(feature_id).unwrap()[0];
+    std::fs::write(root_path.join("p"), "untracked p").unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("drop")
+        .arg(remove_id.to_string())
+        .current_dir(root_path)
+        .assert()
+        .failure()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+ERROR: Failed to re-stack branch `feature`: the following untracked files would be overwritten: p; class=Checkout (20); code=Conflict (-13)
+note: to undo, run `git branch-stash pop git-stack`
+error: 0 branches re-stacked, 1 failed: feature (the following untracked files would be overwritten: p)
+",
+        );
+
+    assert_eq!(repo.find_local_branch("feature").unwrap().id, feature_id);
+    assert_eq!(repo.head_commit().id, feature_id);
+    snapbox::assert_eq(std::fs::read(root_path.join("p")).unwrap(), "untracked p");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn drop_max_stack_size_requires_yes() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            tree(&[("a", "a")], "A"),
+            git_fixture::Command::Branch("main".into()),
+            tree(&[("a", "a"), ("b", "b")], "B"),
+            tree(&[("a", "a"), ("b", "b"), ("c", "c")], "C"),
+            git_fixture::Command::Branch("feature1".into()),
+            tree(&[("a", "a"), ("b", "b"), ("c", "c"), ("d", "d")], "D"),
+            git_fixture::Command::Branch("feature2".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    repo.config()
+        .unwrap()
+        .set_i64("stack.max-stack-size", 2)
+        .unwrap();
+    let feature2_id = repo.revparse_single("feature2").unwrap().id();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["drop", "feature1"])
+        .current_dir(root_path)
+        .assert()
+        .code(64)
+        .stderr_matches(
+            "\
+[..]3 commits would be rewritten, more than `stack.max-stack-size` (2); the base may be wrong, see `--base`
+refusing to rewrite 3 commits without `--yes`
+",
+        );
+    assert_eq!(repo.revparse_single("feature2").unwrap().id(), feature2_id);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["drop", "feature1", "--yes"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+    assert_ne!(repo.revparse_single("feature2").unwrap().id(), feature2_id);
+
+    root.close().unwrap();
+}
+
+#[test]
+fn drop_dry_run_json_reports_drops_and_conflicts() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let tree = |content: &str, message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: [("a", content)]
+                .into_iter()
+                .map(|(p, c)| (p.into(), c.into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            tree("a\n", "A"),
+            git_fixture::Command::Branch("main".into()),
+            tree("b\n", "B"),
+            tree("c\n", "C"),
+            git_fixture::Command::Branch("feature".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let id = |rev: &str| repo.revparse_single(rev).unwrap().id();
+    let (a_id, b_id, c_id) = (id("main"), id("feature~1"), id("feature"));
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["drop", "--dry-run", "--format=json", "feature~1"])
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(format!(
+            r#"{{
+  "schema": "git-stack-plan",
+  "scripts": [
+    {{
+      "batches": [
+        {{
+          "branch": "feature",
+          "commands": [
+            {{
+              "action": "pick",
+              "commit": "{c_id}"
+            }},
+            {{
+              "action": "create-branch",
+              "branch": "feature",
+              "commit": "{c_id}"
+            }}
+          ],
+          "onto": "{a_id}"
+        }}
+      ]
+    }}
+  ],
+  "summary": {{
+    "branches": [
+      "feature"
+    ],
+    "conflicts": [
+      {{
+        "branch": "feature",
+        "dependents": [],
+        "message": "cherry-pick conflicts:\n  a\n"
+      }}
+    ],
+    "deleted_branches": [],
+    "dropped": [
+      "{b_id}"
+    ],
+    "rewritten": [
+      "{c_id}"
+    ]
+  }},
+  "version": 1
+}}
+"#,
+        ));
+
+    assert_eq!(id("feature"), c_id);
+
+    root.close().unwrap();
+}
@@ -0,0 +1,190 @@
+#[test]
+fn duplicate_stack_with_suffix() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("feature1".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b"), ("c", "c")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("C".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("feature2".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let old_head_id = repo.head_commit().id;
+    let feature1_id = repo.find_local_branch("feature1").unwrap().id;
+    let feature2_id = repo.find_local_branch("feature2").unwrap().id;
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("duplicate")
+        .arg("--suffix=-backup")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+Created feature1-backup
+Created feature2-backup
+",
+        );
+
+    assert_eq!(
+        repo.find_local_branch("feature1-backup").unwrap().id,
+        feature1_id
+    );
+    assert_eq!(
+        repo.find_local_branch("feature2-backup").unwrap().id,
+        feature2_id
+    );
+    assert_eq!(repo.find_local_branch("feature1").unwrap().id, feature1_id);
+    assert_eq!(repo.find_local_branch("feature2").unwrap().id, feature2_id);
+    assert!(repo.find_local_branch("main-backup").is_none());
+    assert_eq!(repo.head_commit().id, old_head_id);
+    assert_eq!(repo.head_branch().unwrap().local_name(), Some("feature2"));
+
+    root.close().unwrap();
+}
+
+#[test]
+fn duplicate_existing_name_fails() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Branch("backup-feature".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("feature".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let main_id = repo.find_local_branch("main").unwrap().id;
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("duplicate")
+        .arg("--prefix=backup-")
+        .current_dir(root_path)
+        .assert()
+        .failure()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_eq(
+            "\
+branches already exist: backup-feature
+",
+        );
+
+    assert_eq!(
+        repo.find_local_branch("backup-feature").unwrap().id,
+        main_id
+    );
+
+    root.close().unwrap();
+}
+
+#[test]
+fn duplicate_invalid_name_fails() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("feature".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("duplicate")
+        .arg("--suffix=..bad")
+        .current_dir(root_path)
+        .assert()
+        .failure()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+invalid branch name: feature..bad[..]
+",
+        );
+
+    assert!(repo.find_local_branch("feature..bad").is_none());
+
+    root.close().unwrap();
+}
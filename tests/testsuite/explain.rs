@@ -0,0 +1,142 @@
+fn explain_plan() -> git_fixture::TodoList {
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    git_fixture::TodoList {
+        commands: vec![
+            tree(&[("a", "a")], "A"),
+            git_fixture::Command::Label("base".into()),
+            tree(&[("a", "a"), ("b", "b")], "B"),
+            tree(&[("a", "a"), ("b", "b"), ("c", "c")], "C"),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Reset("base".into()),
+            tree(&[("a", "a"), ("d", "d")], "D"),
+            git_fixture::Command::Branch("feature".into()),
+        ],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn explain_development_commit() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    explain_plan().run(root_path).unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("explain")
+        .arg("feature")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+[..] protected A
+[..] pick D
+[..] is a development commit
+",
+        )
+        .stderr_matches(
+            "\
+",
+        );
+
+    root.close().unwrap();
+}
+
+#[test]
+fn explain_protected_branch() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    explain_plan().run(root_path).unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("explain")
+        .arg("main")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+[..] protected C
+[..] is protected because it is on protected branch `main`
+",
+        )
+        .stderr_matches(
+            "\
+",
+        );
+
+    root.close().unwrap();
+}
+
+#[test]
+fn explain_protected_descendant() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    explain_plan().run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let main_id = repo.revparse_single("main").unwrap().id();
+    let main_abbrev = repo
+        .find_object(main_id, None)
+        .unwrap()
+        .short_id()
+        .unwrap()
+        .as_str()
+        .unwrap()
+        .to_owned();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("explain")
+        .arg("main~1")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(format!(
+            "\
+[..] protected B
+[..] is protected because descendant {main_abbrev} is on protected branch `main`
+"
+        ))
+        .stderr_matches(
+            "\
+",
+        );
+
+    root.close().unwrap();
+}
+
+#[test]
+fn explain_configured_abbrev() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    explain_plan().run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    repo.config().unwrap().set_i64("stack.abbrev", 10).unwrap();
+    let main_id = repo.revparse_single("main").unwrap().id();
+    let main_abbrev = &main_id.to_string()[..10];
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("explain")
+        .arg("main~1")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(format!(
+            "\
+[..] protected B
+[..] is protected because descendant {main_abbrev} is on protected branch `main`
+"
+        ));
+
+    root.close().unwrap();
+}
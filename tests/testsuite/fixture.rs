@@ -26,6 +26,7 @@ pub fn populate_repo(repo: &mut git_stack::git::InMemoryRepo, fixture: git_fixtu
                     id: commit_id,
                     tree_id: commit_id,
                     summary: bstr::BString::from(summary),
+                    message: message.to_str_lossy().into_owned(),
                     time: std::time::SystemTime::now(),
                     author: Some(std::rc::Rc::from(
                         tree.author.as_deref().unwrap_or("fixture"),
@@ -59,3 +60,78 @@ pub fn populate_repo(repo: &mut git_stack::git::InMemoryRepo, fixture: git_fixtu
         }
     }
 }
+
+/// `feature` is `B`, a merge of `side`, then `C`, on top of the base of `main`
+pub fn merge_stack_fixture(root_path: &std::path::Path) {
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            tree(&[("a", "a")], "A"),
+            git_fixture::Command::Label("base".into()),
+            tree(&[("a", "a"), ("m", "m")], "M"),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Reset("base".into()),
+            tree(&[("a", "a"), ("s", "s")], "S"),
+            git_fixture::Command::Branch("side".into()),
+            git_fixture::Command::Reset("base".into()),
+            tree(&[("a", "a"), ("b", "b")], "B"),
+            git_fixture::Command::Branch("feature".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    // `git_fixture` can't write the tree of a merge, so merge `side` into `feature` by hand
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let sig = repo.signature().unwrap();
+    let b = repo
+        .revparse_single("feature")
+        .unwrap()
+        .peel_to_commit()
+        .unwrap();
+    let side = repo
+        .revparse_single("side")
+        .unwrap()
+        .peel_to_commit()
+        .unwrap();
+    let merge_tree = b.tree().unwrap();
+    let mut builder = repo.treebuilder(Some(&merge_tree)).unwrap();
+    builder
+        .insert(
+            "s",
+            side.tree().unwrap().get_name("s").unwrap().id(),
+            0o100644,
+        )
+        .unwrap();
+    let merge_tree = repo.find_tree(builder.write().unwrap()).unwrap();
+    let merge_id = repo
+        .commit(None, &sig, &sig, "Merge side", &merge_tree, &[&b, &side])
+        .unwrap();
+    let merge = repo.find_commit(merge_id).unwrap();
+    let mut builder = repo.treebuilder(Some(&merge_tree)).unwrap();
+    builder
+        .insert("c", repo.blob(b"c").unwrap(), 0o100644)
+        .unwrap();
+    let c_tree = repo.find_tree(builder.write().unwrap()).unwrap();
+    let c_id = repo
+        .commit(None, &sig, &sig, "C", &c_tree, &[&merge])
+        .unwrap();
+    repo.reference("refs/heads/feature", c_id, true, "fixture")
+        .unwrap();
+    repo.find_branch("side", git2::BranchType::Local)
+        .unwrap()
+        .delete()
+        .unwrap();
+    repo.set_head("refs/heads/feature").unwrap();
+    repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+        .unwrap();
+}
@@ -0,0 +1,331 @@
+use bstr::ByteSlice as _;
+
+use crate::fixture;
+
+#[test]
+fn fixup_preserves_unrelated_commits() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("base".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b"), ("c", "c")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("C".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b"), ("c", "fixed c")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("fixup! C".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let old_base_id = repo.find_local_branch("base").unwrap().id;
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("fixup")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    let new_base_id = repo.find_local_branch("base").unwrap().id;
+    assert_eq!(old_base_id, new_base_id);
+
+    let target_branch = repo.find_local_branch("target").unwrap();
+    let target_commit = repo.find_commit(target_branch.id).unwrap();
+    snapbox::assert_eq(target_commit.summary.to_str_lossy().into_owned(), "C");
+    assert_eq!(
+        repo.parent_ids(target_commit.id).unwrap(),
+        vec![old_base_id]
+    );
+
+    snapbox::assert_eq(std::fs::read(root_path.join("c")).unwrap(), "fixed c");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn fixup_auto_targets_commit_touching_staged_file() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b"), ("c", "c")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("C".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+    snapbox::cmd::Command::new("git")
+        .args(["switch", "target"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+    std::fs::write(root_path.join("b"), "fixed b").unwrap();
+    snapbox::cmd::Command::new("git")
+        .args(["add", "b"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["fixup", "--auto"])
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq("")
+        .stderr_matches(
+            "\
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+    let target_branch = repo.find_local_branch("target").unwrap();
+    let target_commit = repo.find_commit(target_branch.id).unwrap();
+    snapbox::assert_eq(target_commit.summary.to_str_lossy().into_owned(), "C");
+    let fixed_id = repo.parent_ids(target_commit.id).unwrap()[0];
+    let fixed_commit = repo.find_commit(fixed_id).unwrap();
+    snapbox::assert_eq(fixed_commit.summary.to_str_lossy().into_owned(), "B");
+    let fixed_b = repo
+        .raw()
+        .find_commit(fixed_id)
+        .unwrap()
+        .tree()
+        .unwrap()
+        .get_path(std::path::Path::new("b"))
+        .unwrap()
+        .to_object(repo.raw())
+        .unwrap()
+        .peel_to_blob()
+        .unwrap()
+        .content()
+        .to_owned();
+    snapbox::assert_eq(fixed_b, "fixed b");
+    snapbox::assert_eq(std::fs::read(root_path.join("b")).unwrap(), "fixed b");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn fixup_auto_refuses_merge_before_committing() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    fixture::merge_stack_fixture(root_path);
+    std::fs::write(root_path.join("b"), "fixed b").unwrap();
+    snapbox::cmd::Command::new("git")
+        .args(["add", "b"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let old_id = repo.revparse_single("feature").unwrap().id();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["fixup", "--auto"])
+        .current_dir(root_path)
+        .assert()
+        .code(64)
+        .stderr_matches(
+            "\
+merge commit in stack; use --rebase-merges
+",
+        );
+    assert_eq!(repo.revparse_single("feature").unwrap().id(), old_id);
+    assert_eq!(repo.head().unwrap().target(), Some(old_id));
+    let status = repo.status_file(std::path::Path::new("b")).unwrap();
+    assert_eq!(status, git2::Status::INDEX_MODIFIED);
+
+    root.close().unwrap();
+}
+
+#[test]
+fn fixup_auto_refuses_untouched_staged_file() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+    snapbox::cmd::Command::new("git")
+        .args(["switch", "target"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+    std::fs::write(root_path.join("a"), "fixed a").unwrap();
+    std::fs::write(root_path.join("b"), "fixed b").unwrap();
+    std::fs::write(root_path.join("new"), "new").unwrap();
+    snapbox::cmd::Command::new("git")
+        .args(["add", "a", "b", "new"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let old_head_id = repo.head().unwrap().target().unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["fixup", "--auto"])
+        .current_dir(root_path)
+        .assert()
+        .code(64)
+        .stdout_eq("")
+        .stderr_eq(
+            "\
+no commit in the stack touched some staged files:
+  a
+  new
+",
+        );
+
+    assert_eq!(repo.head().unwrap().target().unwrap(), old_head_id);
+
+    root.close().unwrap();
+}
+
+#[test]
+fn fixup_targets_fold_case_with_ignorecase() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("Foo.rs", "foo")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let targets = |ignore_case: bool| {
+        let repo = git2::Repository::discover(root_path).unwrap();
+        repo.config()
+            .unwrap()
+            .set_bool("core.ignorecase", ignore_case)
+            .unwrap();
+        let repo = git_stack::git::GitRepo::new(repo);
+        let protect = git_stack::git::ProtectedBranches::new(vec!["main"]).unwrap();
+        let branches = git_stack::graph::BranchSet::from_repo(&repo, &protect).unwrap();
+        let mut graph = git_stack::graph::Graph::from_branches(&repo, branches).unwrap();
+        git_stack::graph::protect_branches(&mut graph);
+        let head_id = repo.find_local_branch("target").unwrap().id;
+        git_stack::graph::find_fixup_targets(
+            &graph,
+            &repo,
+            head_id,
+            &[std::path::PathBuf::from("foo.rs")],
+        )
+        .into_iter()
+        .map(|(path, id)| (path, repo.find_commit(id).unwrap().summary.to_string()))
+        .collect::<Vec<_>>()
+    };
+
+    assert_eq!(targets(false), vec![]);
+    assert_eq!(
+        targets(true),
+        vec![(std::path::PathBuf::from("foo.rs"), "B".to_owned())]
+    );
+
+    root.close().unwrap();
+}
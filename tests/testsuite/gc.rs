@@ -0,0 +1,102 @@
+#[test]
+fn gc_removes_scratch_worktrees_and_stale_config() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            tree(&[("a", "a")], "A"),
+            git_fixture::Command::Branch("main".into()),
+            tree(&[("a", "a"), ("b", "b")], "B"),
+            git_fixture::Command::Branch("feature".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let head_id = repo.head().unwrap().peel_to_commit().unwrap().id();
+    let mut config = repo
+        .config()
+        .unwrap()
+        .open_level(git2::ConfigLevel::Local)
+        .unwrap();
+    for (key, value) in [
+        ("stack.picked", "feature"),
+        ("stack.picked", "gone"),
+        ("stack.hidden", "main"),
+        ("stack.hidden", "deleted"),
+    ] {
+        config.set_multivar(key, "^$", value).unwrap();
+    }
+    config
+        .set_str("stack.branch.feature.pushed", &head_id.to_string())
+        .unwrap();
+    config
+        .set_str("stack.branch.gone.pushed", &head_id.to_string())
+        .unwrap();
+    // What an interrupted `git rerere` replay leaves behind
+    let scratch_path = root_path.join("git-stack-rerere-1-scratch");
+    snapbox::cmd::Command::new("git")
+        .args(["worktree", "add", "--detach"])
+        .arg(&scratch_path)
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["gc", "--dry-run"])
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stderr_matches(
+            "\
+Would remove 1 scratch worktrees and 3 stale config entries
+",
+        );
+    assert!(scratch_path.exists());
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("gc")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stderr_matches(
+            "\
+Removed 1 scratch worktrees and 3 stale config entries
+",
+        );
+
+    assert!(!scratch_path.exists());
+    assert!(repo.worktrees().unwrap().is_empty());
+    let config = repo.config().unwrap().snapshot().unwrap();
+    let values = |key: &str| {
+        let mut values = Vec::new();
+        let mut entries = config.multivar(key, None).unwrap();
+        while let Some(entry) = entries.next() {
+            values.push(entry.unwrap().value().unwrap().to_owned());
+        }
+        values
+    };
+    assert_eq!(values("stack.picked"), ["feature"]);
+    assert_eq!(values("stack.hidden"), ["main"]);
+    assert!(config.get_str("stack.branch.feature.pushed").is_ok());
+    assert!(config.get_str("stack.branch.gone.pushed").is_err());
+    for name in ["main", "feature"] {
+        assert!(
+            repo.find_branch(name, git2::BranchType::Local).is_ok(),
+            "{name}"
+        );
+    }
+
+    root.close().unwrap();
+}
@@ -25,6 +25,35 @@ fn from_branches() {
     assert_eq!(graph.root_id(), to_oid(1));
 }
 
+#[test]
+fn from_branches_reuses_commit_cache() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan =
+        git_fixture::TodoList::load(std::path::Path::new("tests/fixtures/branches.yml")).unwrap();
+    plan.run(root_path).unwrap();
+
+    let cache = std::rc::Rc::new(git_stack::git::CommitCache::new());
+    let protect = protect();
+    let build = || {
+        let repo = git2::Repository::discover(root_path).unwrap();
+        let repo = git_stack::git::GitRepo::with_commit_cache(repo, cache.clone());
+        let branches = BranchSet::from_repo(&repo, &protect).unwrap();
+        let graph = Graph::from_branches(&repo, branches).unwrap();
+        graph.descendants().collect::<Vec<_>>()
+    };
+
+    let first = build();
+    let first_reads = cache.reads();
+    assert!(0 < first_reads);
+
+    let second = build();
+    assert_eq!(first, second);
+    assert_eq!(cache.reads(), first_reads);
+
+    root.close().unwrap();
+}
+
 #[test]
 fn descendants() {
     let mut repo = git_stack::git::InMemoryRepo::new();
@@ -346,3 +375,209 @@ fn remove_base() {
         .collect::<std::collections::HashSet<_>>();
     assert_eq!(actual_children, expected_children);
 }
+
+#[test]
+fn protect_branches_with_negation() {
+    let mut repo = git_stack::git::InMemoryRepo::new();
+    let plan =
+        git_fixture::TodoList::load(std::path::Path::new("tests/fixtures/branches.yml")).unwrap();
+    fixture::populate_repo(&mut repo, plan);
+
+    let protect =
+        git_stack::git::ProtectedBranches::new(vec!["master", "feature*", "!feature2"]).unwrap();
+    let branches = BranchSet::from_repo(&repo, &protect).unwrap();
+    let mut graph = Graph::from_branches(&repo, branches).unwrap();
+    protect_branches(&mut graph);
+
+    let feature1_id = repo.find_local_branch("feature1").unwrap().id;
+    let feature2_id = repo.find_local_branch("feature2").unwrap().id;
+    assert_eq!(
+        graph.commit_get::<Action>(feature1_id).copied(),
+        Some(Action::Protected)
+    );
+    assert_eq!(
+        graph
+            .commit_get::<Action>(feature2_id)
+            .copied()
+            .unwrap_or_default(),
+        Action::Pick
+    );
+}
+
+#[test]
+fn path_to() {
+    let mut repo = git_stack::git::InMemoryRepo::new();
+    let plan =
+        git_fixture::TodoList::load(std::path::Path::new("tests/fixtures/branches.yml")).unwrap();
+    fixture::populate_repo(&mut repo, plan);
+
+    let protect = protect();
+    let branches = BranchSet::from_repo(&repo, &protect).unwrap();
+    let graph = Graph::from_branches(&repo, branches).unwrap();
+
+    assert_eq!(graph.path_to(graph.root_id()), Some(vec![to_oid(1)]));
+    assert_eq!(
+        graph.path_to(to_oid(9)),
+        Some(vec![
+            to_oid(1),
+            to_oid(2),
+            to_oid(3),
+            to_oid(7),
+            to_oid(8),
+            to_oid(9)
+        ])
+    );
+    assert_eq!(graph.path_to(to_oid(100)), None);
+}
+
+#[test]
+fn protected_reason_on_protected_branch() {
+    let mut repo = git_stack::git::InMemoryRepo::new();
+    let plan =
+        git_fixture::TodoList::load(std::path::Path::new("tests/fixtures/branches.yml")).unwrap();
+    fixture::populate_repo(&mut repo, plan);
+
+    let protect = protect();
+    let branches = BranchSet::from_repo(&repo, &protect).unwrap();
+    let mut graph = Graph::from_branches(&repo, branches).unwrap();
+    protect_branches(&mut graph);
+
+    assert_eq!(
+        graph.commit_get::<ProtectedReason>(to_oid(5)),
+        Some(&ProtectedReason::OnProtectedBranch("master".to_owned()))
+    );
+    assert!(graph
+        .commit_get::<Action>(to_oid(5))
+        .unwrap()
+        .is_protected());
+}
+
+#[test]
+fn protected_reason_descendant_protected() {
+    let mut repo = git_stack::git::InMemoryRepo::new();
+    let plan =
+        git_fixture::TodoList::load(std::path::Path::new("tests/fixtures/branches.yml")).unwrap();
+    fixture::populate_repo(&mut repo, plan);
+
+    let protect = protect();
+    let branches = BranchSet::from_repo(&repo, &protect).unwrap();
+    let mut graph = Graph::from_branches(&repo, branches).unwrap();
+    protect_branches(&mut graph);
+
+    for id in [to_oid(1), to_oid(3), to_oid(4)] {
+        assert_eq!(
+            graph.commit_get::<ProtectedReason>(id),
+            Some(&ProtectedReason::DescendantProtected(to_oid(5))),
+            "{id}"
+        );
+    }
+    assert_eq!(graph.commit_get::<ProtectedReason>(to_oid(7)), None);
+}
+
+#[test]
+fn protected_reason_stack_protected() {
+    let mut repo = git_stack::git::InMemoryRepo::new();
+    let plan =
+        git_fixture::TodoList::load(std::path::Path::new("tests/fixtures/branches.yml")).unwrap();
+    fixture::populate_repo(&mut repo, plan);
+
+    let protect = protect();
+    let branches = BranchSet::from_repo(&repo, &protect).unwrap();
+    let mut graph = Graph::from_branches(&repo, branches).unwrap();
+    protect_branches(&mut graph);
+    let future = std::time::SystemTime::now() + std::time::Duration::from_secs(60 * 60);
+    protect_stale_branches(&mut graph, &repo, future, &[]);
+
+    assert_eq!(
+        graph.commit_get::<ProtectedReason>(to_oid(10)),
+        Some(&ProtectedReason::StackProtected)
+    );
+    // Already explained by the protected branch
+    assert_eq!(
+        graph.commit_get::<ProtectedReason>(to_oid(5)),
+        Some(&ProtectedReason::OnProtectedBranch("master".to_owned()))
+    );
+}
+
+#[test]
+fn protect_branches_remote_qualified() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("upstream".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b"), ("c", "c")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("C".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let raw = git2::Repository::discover(root_path).unwrap();
+    let upstream_id = raw.revparse_single("upstream").unwrap().id();
+    raw.reference("refs/remotes/origin/main", upstream_id, true, "test")
+        .unwrap();
+    raw.find_branch("upstream", git2::BranchType::Local)
+        .unwrap()
+        .delete()
+        .unwrap();
+    let repo = git_stack::git::GitRepo::new(raw);
+
+    let protect = git_stack::git::ProtectedBranches::new(vec!["origin/main"]).unwrap();
+    let branches = BranchSet::from_repo(&repo, &protect).unwrap();
+    let mut graph = Graph::from_branches(&repo, branches).unwrap();
+    protect_branches(&mut graph);
+
+    let main_id = repo.find_local_branch("main").unwrap().id;
+    assert_eq!(
+        graph.commit_get::<Action>(upstream_id).copied(),
+        Some(Action::Protected)
+    );
+    assert_eq!(
+        graph.commit_get::<ProtectedReason>(upstream_id),
+        Some(&ProtectedReason::OnProtectedBranch(
+            "origin/main".to_owned()
+        ))
+    );
+    assert_eq!(
+        graph
+            .commit_get::<Action>(main_id)
+            .copied()
+            .unwrap_or_default(),
+        Action::Pick
+    );
+    let main_kind = graph
+        .branches
+        .get(main_id)
+        .unwrap()
+        .iter()
+        .find(|b| b.local_name() == Some("main"))
+        .unwrap()
+        .kind();
+    assert_eq!(main_kind, BranchKind::Mutable);
+
+    root.close().unwrap();
+}
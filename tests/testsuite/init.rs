@@ -0,0 +1,97 @@
+fn remotes_fixture(root_path: &std::path::Path) {
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("trunk".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    repo.remote("origin", "https://example.com/fork.git")
+        .unwrap();
+    repo.remote("upstream", "https://example.com/project.git")
+        .unwrap();
+    let head_id = repo.head().unwrap().target().unwrap();
+    repo.reference("refs/remotes/upstream/trunk", head_id, false, "fixture")
+        .unwrap();
+    repo.reference_symbolic(
+        "refs/remotes/upstream/HEAD",
+        "refs/remotes/upstream/trunk",
+        false,
+        "fixture",
+    )
+    .unwrap();
+}
+
+fn assert_config(config: &git2::Config) {
+    let mut protected = Vec::new();
+    config
+        .multivar("stack.protected-branch", None)
+        .unwrap()
+        .for_each(|entry| protected.push(entry.value().unwrap().to_owned()))
+        .unwrap();
+    assert_eq!(protected, ["trunk"]);
+    assert_eq!(config.get_string("stack.push-remote").unwrap(), "origin");
+    assert_eq!(config.get_string("stack.pull-remote").unwrap(), "upstream");
+}
+
+#[test]
+fn init_local() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    remotes_fixture(root_path);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("init")
+        .arg("--yes")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stderr_matches(
+            "\
+protected branches: trunk
+push remote: origin
+pull remote: upstream
+Writing [..]config
+",
+        );
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let config = git2::Config::open(&repo.path().join("config")).unwrap();
+    assert_config(&config);
+    assert!(!root_path.join(".gitconfig").exists());
+
+    root.close().unwrap();
+}
+
+#[test]
+fn init_shared() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    remotes_fixture(root_path);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("init")
+        .arg("--shared")
+        .arg("--yes")
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    let config = git2::Config::open(&root_path.join(".gitconfig")).unwrap();
+    assert_config(&config);
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo_config = git2::Config::open(&repo.path().join("config")).unwrap();
+    assert!(repo_config.get_string("stack.push-remote").is_err());
+
+    root.close().unwrap();
+}
@@ -0,0 +1,219 @@
+/// `main` with `feature` (two commits, with `nested` branching off its first) and `other` on it
+///
+/// Commits are created with fixed signatures so their IDs, and therefore the order they are
+/// listed in, are stable.
+fn branching_fixture(root_path: &std::path::Path) {
+    let repo = git2::Repository::init(root_path).unwrap();
+    let mut config = repo.config().unwrap();
+    config
+        .set_str("stack.protect-commit-age", "100years")
+        .unwrap();
+    let commit =
+        |branch: Option<&str>, name: &str, seconds: i64, parent: Option<&git2::Commit<'_>>| {
+            let signature =
+                git2::Signature::new("Test", "test@example.com", &git2::Time::new(seconds, 0))
+                    .unwrap();
+            let blob = repo.blob(name.as_bytes()).unwrap();
+            let parent_tree = parent.map(|p| p.tree().unwrap());
+            let mut tree = repo.treebuilder(parent_tree.as_ref()).unwrap();
+            tree.insert(name, blob, 0o100644).unwrap();
+            let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+            let id = repo
+                .commit(
+                    branch.map(|b| format!("refs/heads/{b}")).as_deref(),
+                    &signature,
+                    &signature,
+                    name,
+                    &tree,
+                    &parent.into_iter().collect::<Vec<_>>(),
+                )
+                .unwrap();
+            repo.find_commit(id).unwrap()
+        };
+
+    let main = commit(Some("main"), "A", 1_600_000_000, None);
+    let feature_1 = commit(None, "B1", 1_600_001_000, Some(&main));
+    commit(Some("feature"), "B2", 1_600_002_000, Some(&feature_1));
+    commit(Some("other"), "C", 1_600_003_000, Some(&main));
+    commit(Some("nested"), "D", 1_600_004_000, Some(&feature_1));
+
+    repo.set_head("refs/heads/feature").unwrap();
+    repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+        .unwrap();
+}
+
+#[test]
+fn log_oneline() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    branching_fixture(root_path);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("log")
+        .arg("--oneline")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+* [..] pick (other) (ready) C
+| * [..] pick (nested) (ready) D
+| | * [..] pick (feature) (ready) B2
+| |/
+| * [..] pick B1
+|/
+* [..] protected (main) (no remote) A
+",
+        )
+        .stderr_matches("");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn log() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    branching_fixture(root_path);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("log")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+* commit [..] pick (other) (ready)
+| Author: Test <test@example.com>
+|
+|     C
+|
+| * commit [..] pick (nested) (ready)
+| | Author: Test <test@example.com>
+| |
+| |     D
+| |
+| | * commit [..] pick (feature) (ready)
+| | | Author: Test <test@example.com>
+| | |
+| | |     B2
+| | |
+| |/
+| * commit [..] pick
+| | Author: Test <test@example.com>
+| |
+| |     B1
+| |
+|/
+* commit [..] protected (main) (no remote)
+  Author: Test <test@example.com>
+
+      A
+",
+        )
+        .stderr_matches("");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn log_leaves_out_hidden_stacks() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    branching_fixture(root_path);
+    snapbox::cmd::Command::new("git")
+        .args(["config", "stack.hidden", "other"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("log")
+        .arg("--oneline")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+* [..] pick (nested) (ready) D
+| * [..] pick (feature) (ready) B2
+|/
+* [..] pick B1
+* [..] protected (main) (no remote) A
+",
+        );
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("log")
+        .arg("--oneline")
+        .arg("--show-all")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+* [..] pick (other) (ready) C
+...
+",
+        );
+
+    root.close().unwrap();
+}
+
+#[test]
+fn log_depth() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    branching_fixture(root_path);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("log")
+        .arg("--oneline")
+        .args(["--depth", "1"])
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+* [..] pick (other) (ready) C
+| * … 1 more …
+| | * … 1 more …
+| |/
+| * [..] pick B1
+|/
+* [..] protected (main) (no remote) A
+",
+        )
+        .stderr_matches("");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn log_show_signature() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    branching_fixture(root_path);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("log")
+        .arg("--oneline")
+        .arg("--show-signature")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+* [..] pick (other) (ready) (unsigned) C
+| * [..] pick (nested) (ready) (unsigned) D
+| | * [..] pick (feature) (ready) (unsigned) B2
+| |/
+| * [..] pick (unsigned) B1
+|/
+* [..] protected (main) (no remote) A
+",
+        )
+        .stderr_matches("");
+
+    root.close().unwrap();
+}
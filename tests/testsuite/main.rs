@@ -1,8 +1,23 @@
+mod absorb;
 mod alias;
 mod amend;
 mod branches;
+mod cherry_pick;
+mod commit;
+mod config;
+mod drop;
+mod duplicate;
+mod explain;
 mod fixture;
+mod fixup;
+mod gc;
 mod graph;
+mod init;
+mod log;
 mod ops;
+mod pr_body;
 mod repo;
 mod reword;
+mod squash_branch;
+mod stack;
+mod sync;
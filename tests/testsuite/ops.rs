@@ -88,6 +88,161 @@ mod test_rebase {
         dbg!(&feature1_branch.id);
         assert!(ancestors.contains(&feature1_branch.id));
     }
+
+    fn restack(repo: &mut git_stack::git::InMemoryRepo) -> Vec<git_stack::rewrite::Script> {
+        let protect = protect();
+        let branches = git_stack::graph::BranchSet::from_repo(repo, &protect).unwrap();
+
+        let master_branch = repo.find_local_branch("master").unwrap();
+
+        let mut graph = Graph::from_branches(repo, branches).unwrap();
+        git_stack::graph::protect_branches(&mut graph);
+        git_stack::graph::rebase_development_branches(&mut graph, master_branch.id);
+        let scripts = git_stack::graph::to_scripts(&graph, vec![]);
+        dbg!(&scripts);
+
+        let mut executor = git_stack::rewrite::Executor::new(false);
+        for script in scripts.iter() {
+            let result = executor.run(repo, script);
+            assert_eq!(result, vec![]);
+        }
+        executor.close(repo, Some("off_master")).unwrap();
+
+        scripts
+    }
+
+    #[test]
+    fn summary() {
+        let mut repo = git_stack::git::InMemoryRepo::new();
+        let plan = git_fixture::TodoList::load(std::path::Path::new("tests/fixtures/branches.yml"))
+            .unwrap();
+        fixture::populate_repo(&mut repo, plan);
+
+        let protect = protect();
+        let branches = git_stack::graph::BranchSet::from_repo(&repo, &protect).unwrap();
+
+        let master_id = repo.find_local_branch("master").unwrap().id;
+        let feature1_id = repo.find_local_branch("feature1").unwrap().id;
+        let feature2_id = repo.find_local_branch("feature2").unwrap().id;
+        let dropped_id = repo
+            .parent_ids(repo.parent_ids(feature2_id).unwrap()[0])
+            .unwrap()[0];
+
+        let mut graph = Graph::from_branches(&repo, branches).unwrap();
+        git_stack::graph::protect_branches(&mut graph);
+        let original = graph.clone();
+        git_stack::graph::rebase_development_branches(&mut graph, master_id);
+        git_stack::graph::drop_commit(&mut graph, dropped_id).unwrap();
+        let scripts = git_stack::graph::to_scripts(&graph, vec![]);
+        dbg!(&scripts);
+
+        let summary = git_stack::graph::summarize(&repo, &original, &graph, &scripts);
+        assert_eq!(
+            summary,
+            git_stack::graph::Summary {
+                moved: vec![
+                    git_stack::graph::MovedBranch {
+                        name: "feature1".to_owned(),
+                        old_id: feature1_id,
+                        onto_id: master_id,
+                        commit_count: 1,
+                    },
+                    git_stack::graph::MovedBranch {
+                        name: "feature2".to_owned(),
+                        old_id: feature2_id,
+                        onto_id: master_id,
+                        commit_count: 3,
+                    },
+                ],
+                dropped: vec![dropped_id],
+                deleted: vec![],
+            }
+        );
+        assert!(summary.is_destructive());
+    }
+
+    #[test]
+    fn mapping() {
+        let mut repo = git_stack::git::InMemoryRepo::new();
+        let plan = git_fixture::TodoList::load(std::path::Path::new("tests/fixtures/branches.yml"))
+            .unwrap();
+        fixture::populate_repo(&mut repo, plan);
+
+        let protect = protect();
+        let branches = git_stack::graph::BranchSet::from_repo(&repo, &protect).unwrap();
+
+        let master_id = repo.find_local_branch("master").unwrap().id;
+        let feature2_id = repo.find_local_branch("feature2").unwrap().id;
+        let dropped_id = repo
+            .parent_ids(repo.parent_ids(feature2_id).unwrap()[0])
+            .unwrap()[0];
+
+        let mut graph = Graph::from_branches(&repo, branches).unwrap();
+        git_stack::graph::protect_branches(&mut graph);
+        let original = graph.clone();
+        git_stack::graph::rebase_development_branches(&mut graph, master_id);
+        git_stack::graph::drop_commit(&mut graph, dropped_id).unwrap();
+        let scripts = git_stack::graph::to_scripts(&graph, vec![]);
+        let summary = git_stack::graph::summarize(&repo, &original, &graph, &scripts);
+
+        let mut executor = git_stack::rewrite::Executor::new(false);
+        executor.note_dropped(summary.dropped.iter().copied());
+        for script in scripts.iter() {
+            let result = executor.run(&mut repo, script);
+            assert_eq!(result, vec![]);
+        }
+        executor.close(&mut repo, Some("off_master")).unwrap();
+
+        let mapping = executor.mapping();
+        assert_eq!(
+            mapping.get(&dropped_id),
+            Some(&git_stack::rewrite::Rewritten::Dropped)
+        );
+        let rewritten = scripts
+            .iter()
+            .flat_map(|s| s.stats().rewritten)
+            .collect::<Vec<_>>();
+        assert!(!rewritten.is_empty());
+        for old_id in rewritten {
+            match mapping.get(&old_id) {
+                Some(git_stack::rewrite::Rewritten::Commit(new_id)) => {
+                    assert_ne!(*new_id, old_id);
+                    assert!(repo.find_commit(*new_id).is_some());
+                }
+                other => panic!("{old_id} became {other:?}"),
+            }
+        }
+        assert_eq!(
+            repo.find_local_branch("feature2").unwrap().id,
+            match mapping[&feature2_id] {
+                git_stack::rewrite::Rewritten::Commit(id) => id,
+                git_stack::rewrite::Rewritten::Dropped => panic!("feature2 was dropped"),
+            }
+        );
+    }
+
+    #[test]
+    fn rerun_is_no_op() {
+        let mut repo = git_stack::git::InMemoryRepo::new();
+        let plan = git_fixture::TodoList::load(std::path::Path::new("tests/fixtures/branches.yml"))
+            .unwrap();
+        fixture::populate_repo(&mut repo, plan);
+
+        let scripts = restack(&mut repo);
+        assert!(!scripts.is_empty());
+        let restacked = repo
+            .local_branches()
+            .map(|b| (b.name, b.id))
+            .collect::<Vec<_>>();
+
+        let scripts = restack(&mut repo);
+        assert!(scripts.is_empty(), "{scripts:#?}");
+        let rerun = repo
+            .local_branches()
+            .map(|b| (b.name, b.id))
+            .collect::<Vec<_>>();
+        assert_eq!(rerun, restacked);
+    }
 }
 
 mod test_fixup {
@@ -344,3 +499,139 @@ fn overflow() {
     }
     executor.close(&mut repo, Some("master")).unwrap();
 }
+
+#[test]
+fn pushable_status_matches_pushable() {
+    let mut repo = git_stack::git::InMemoryRepo::new();
+    let plan =
+        git_fixture::TodoList::load(std::path::Path::new("tests/fixtures/branches.yml")).unwrap();
+    fixture::populate_repo(&mut repo, plan);
+
+    let protect = protect();
+    let branches = git_stack::graph::BranchSet::from_repo(&repo, &protect).unwrap();
+    let mut graph = Graph::from_branches(&repo, branches).unwrap();
+    git_stack::graph::protect_branches(&mut graph);
+    git_stack::graph::mark_wip(&mut graph, &repo);
+
+    let ids = ["master", "off_master", "feature1", "feature2"]
+        .into_iter()
+        .map(|name| repo.find_local_branch(name).unwrap().id)
+        .collect::<Vec<_>>();
+    let point = ids
+        .iter()
+        .map(|id| graph.pushable_status(*id))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        point,
+        vec![
+            PushStatus::Blocked("protected"),
+            PushStatus::Pushable,
+            PushStatus::Pushable,
+            PushStatus::Blocked("parent branch"),
+        ]
+    );
+    // Asking doesn't mark anything
+    assert!(ids
+        .iter()
+        .all(|id| graph.commit_get::<PushStatus>(*id).is_none()));
+
+    git_stack::graph::pushable(&mut graph);
+    let marked = ids
+        .iter()
+        .map(|id| {
+            graph
+                .commit_get::<PushStatus>(*id)
+                .copied()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(point, marked);
+}
+
+#[test]
+fn invalid_branch_name() {
+    let mut repo = git_stack::git::InMemoryRepo::new();
+    let plan =
+        git_fixture::TodoList::load(std::path::Path::new("tests/fixtures/branches.yml")).unwrap();
+    fixture::populate_repo(&mut repo, plan);
+
+    let protect = protect();
+    let branches = git_stack::graph::BranchSet::from_repo(&repo, &protect).unwrap();
+    let graph = Graph::from_branches(&repo, branches).unwrap();
+
+    let script = git_stack::graph::to_duplicate_script(&graph, |name| format!("{name}..bad"));
+    let err = script.validate().unwrap_err();
+    assert!(
+        err.message().starts_with("invalid branch name: "),
+        "{}",
+        err.message()
+    );
+
+    let mut executor = git_stack::rewrite::Executor::new(false);
+    let result = executor.run(&mut repo, &script);
+    assert_eq!(result.len(), 1);
+    executor.close(&mut repo, Some("master")).unwrap();
+    assert!(repo.local_branches().all(|b| !b.name.ends_with("..bad")));
+}
+
+#[test]
+fn close_reattaches_moved_head_branch() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let plan =
+        git_fixture::TodoList::load(std::path::Path::new("tests/fixtures/branches.yml")).unwrap();
+    plan.run(temp.path()).unwrap();
+
+    let repo = git2::Repository::discover(temp.path()).unwrap();
+    let mut repo = git_stack::git::GitRepo::new(repo);
+    repo.switch_branch("feature1").unwrap();
+    let old_feature1_id = repo.find_local_branch("feature1").unwrap().id;
+
+    let protect = protect();
+    let branches = git_stack::graph::BranchSet::from_repo(&repo, &protect).unwrap();
+    let master_id = repo.find_local_branch("master").unwrap().id;
+    let mut graph = Graph::from_branches(&repo, branches).unwrap();
+    git_stack::graph::protect_branches(&mut graph);
+    git_stack::graph::rebase_development_branches(&mut graph, master_id);
+    let scripts = git_stack::graph::to_scripts(&graph, vec![]);
+
+    let mut executor = git_stack::rewrite::Executor::new(false);
+    for script in scripts {
+        let result = executor.run(&mut repo, &script);
+        assert_eq!(result, vec![]);
+    }
+    executor.close(&mut repo, None).unwrap();
+
+    let feature1 = repo.find_local_branch("feature1").unwrap();
+    assert_ne!(feature1.id, old_feature1_id);
+    assert!(!repo.raw().head_detached().unwrap());
+    assert_eq!(repo.head_branch(), Some(feature1));
+
+    temp.close().unwrap();
+}
+
+#[test]
+fn invalid_branch_name_runs_no_scripts() {
+    let mut repo = git_stack::git::InMemoryRepo::new();
+    let plan =
+        git_fixture::TodoList::load(std::path::Path::new("tests/fixtures/branches.yml")).unwrap();
+    fixture::populate_repo(&mut repo, plan);
+
+    let protect = protect();
+    let branches = git_stack::graph::BranchSet::from_repo(&repo, &protect).unwrap();
+    let graph = Graph::from_branches(&repo, branches).unwrap();
+
+    let scripts = [
+        git_stack::graph::to_duplicate_script(&graph, |name| format!("{name}-copy")),
+        git_stack::graph::to_duplicate_script(&graph, |name| format!("{name}..bad")),
+    ];
+    let open = || -> Result<Box<dyn git_stack::git::Repo>, git2::Error> {
+        unreachable!("scripts are run one at a time")
+    };
+    let mut executor = git_stack::rewrite::Executor::new(false);
+    let result = executor.run_parallel(&mut repo, &open, &scripts, 1);
+    assert_eq!(result.len(), 1);
+    executor.close(&mut repo, Some("master")).unwrap();
+    assert!(repo
+        .local_branches()
+        .all(|b| !b.name.ends_with("-copy") && !b.name.ends_with("..bad")));
+}
@@ -0,0 +1,66 @@
+#[test]
+fn pr_body_links_neighbours() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            tree(&[("a", "a")], "A"),
+            git_fixture::Command::Branch("main".into()),
+            tree(&[("a", "a"), ("b", "b")], "B"),
+            git_fixture::Command::Branch("first".into()),
+            tree(&[("a", "a"), ("b", "b"), ("c", "c")], "C"),
+            tree(&[("a", "a"), ("b", "b"), ("c", "c"), ("d", "d")], "D"),
+            git_fixture::Command::Branch("second".into()),
+            tree(
+                &[("a", "a"), ("b", "b"), ("c", "c"), ("d", "d"), ("e", "e")],
+                "E",
+            ),
+            git_fixture::Command::Branch("third".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    repo.config()
+        .unwrap()
+        .set_str(
+            "stack.pr-body-template",
+            "`{branch}`, part {position} of {total}: {prev} | {next}\n\n{commits}",
+        )
+        .unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args([
+            "pr-body",
+            "second",
+            "--url",
+            "first=https://example.com/pull/1",
+            "--url",
+            "third=https://example.com/pull/3",
+        ])
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+`second`, part 2 of 3: [← prev](https://example.com/pull/1) | [next →](https://example.com/pull/3)
+
+- C
+- D
+",
+        )
+        .stderr_eq("");
+
+    root.close().unwrap();
+}
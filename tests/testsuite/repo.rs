@@ -255,6 +255,162 @@ fn contains_commit_pr_squashed() {
     temp.close().unwrap();
 }
 
+#[test]
+fn find_commit_message() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("Summary\n\nBody with \u{e9}\n".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(temp.path()).unwrap();
+
+    let repo = git2::Repository::discover(temp.path()).unwrap();
+    let repo = GitRepo::new(repo);
+
+    let head = repo.head_commit();
+    assert_eq!(head.summary, "Summary");
+    assert_eq!(head.message, "Summary\n\nBody with \u{e9}\n");
+
+    let cached = repo.find_commit(head.id).unwrap();
+    assert!(std::rc::Rc::ptr_eq(&head, &cached));
+
+    temp.close().unwrap();
+}
+
+#[test]
+fn commit_messages_decoded_once() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let raw = git2::Repository::init(temp.path()).unwrap();
+    let signature = git2::Signature::now("fixture", "fixture@example.com").unwrap();
+    let tree_id = raw.index().unwrap().write_tree().unwrap();
+    let tree = raw.find_tree(tree_id).unwrap();
+    let mut head_id = None;
+    for i in 0..1000 {
+        let parents = head_id
+            .map(|id| raw.find_commit(id).unwrap())
+            .into_iter()
+            .collect::<Vec<_>>();
+        let parents = parents.iter().collect::<Vec<_>>();
+        let message = format!("Commit {i}\n\nBody with \u{e9}\n");
+        head_id = Some(
+            raw.commit(None, &signature, &signature, &message, &tree, &parents)
+                .unwrap(),
+        );
+    }
+    let head_id = head_id.unwrap();
+    drop(tree);
+
+    let cache = std::rc::Rc::new(CommitCache::new());
+    let repo = GitRepo::with_commit_cache(raw, cache.clone());
+    // Like the protect, fixup, WIP and render passes, each walk reads every message.  Decoding on
+    // access would take 4000 decodes rather than one per commit.
+    for _ in 0..4 {
+        let mut walked = 0;
+        let mut current_id = Some(head_id);
+        while let Some(id) = current_id {
+            let commit = repo.find_commit(id).unwrap();
+            assert!(commit.message.ends_with("Body with \u{e9}\n"));
+            current_id = repo.parent_ids(id).unwrap().first().copied();
+            walked += 1;
+        }
+        assert_eq!(walked, 1000);
+    }
+    assert_eq!(cache.reads(), 1000);
+
+    temp.close().unwrap();
+}
+
+#[test]
+fn lfs_paths_from_info_attributes() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a.bin", "a"), ("b.txt", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(temp.path()).unwrap();
+
+    let repo = GitRepo::new(git2::Repository::discover(temp.path()).unwrap());
+    let head_id = repo.head_commit().id;
+    assert_eq!(repo.lfs_paths(head_id), Vec::<std::path::PathBuf>::new());
+
+    let info = temp.path().join(".git/info");
+    std::fs::create_dir_all(&info).unwrap();
+    std::fs::write(info.join("attributes"), "*.bin filter=lfs\n").unwrap();
+    let repo = GitRepo::new(git2::Repository::discover(temp.path()).unwrap());
+    assert_eq!(
+        repo.lfs_paths(head_id),
+        vec![std::path::PathBuf::from("a.bin")]
+    );
+
+    temp.close().unwrap();
+}
+
+#[test]
+fn find_upstream_branch_prefers_configured_upstream() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Branch("develop".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(temp.path()).unwrap();
+
+    let raw = git2::Repository::discover(temp.path()).unwrap();
+    let head_id = raw.head().unwrap().peel_to_commit().unwrap().id();
+    for name in [
+        "refs/remotes/origin/main",
+        "refs/remotes/origin/develop",
+        "refs/remotes/upstream/trunk",
+    ] {
+        raw.reference(name, head_id, false, "test").unwrap();
+    }
+    let mut config = raw.config().unwrap();
+    config.set_str("branch.main.remote", "upstream").unwrap();
+    config
+        .set_str("branch.main.merge", "refs/heads/trunk")
+        .unwrap();
+    let repo = GitRepo::new(raw);
+
+    let upstream = find_upstream_branch(&repo, "main").unwrap();
+    assert_eq!(upstream.to_string(), "upstream/trunk");
+    assert_eq!(upstream.id, head_id);
+    // Without configuration, `<pull_remote>/<name>` is assumed
+    let upstream = find_upstream_branch(&repo, "develop").unwrap();
+    assert_eq!(upstream.to_string(), "origin/develop");
+
+    temp.close().unwrap();
+}
+
 #[test]
 fn cherry_pick_clean() {
     let temp = assert_fs::TempDir::new().unwrap();
@@ -310,6 +466,161 @@ fn cherry_pick_conflict() {
     temp.close().unwrap();
 }
 
+#[test]
+fn cherry_pick_merge_tree_matches_cherry_pick() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let plan =
+        git_fixture::TodoList::load(std::path::Path::new("tests/fixtures/branches.yml")).unwrap();
+    plan.run(temp.path()).unwrap();
+
+    let repo = git2::Repository::discover(temp.path()).unwrap();
+    let mut repo = GitRepo::new(repo);
+
+    {
+        let base = repo.find_local_branch("off_master").unwrap();
+        let source = repo.find_local_branch("feature1").unwrap();
+
+        repo.set_rebase_strategy(git_stack::config::RebaseStrategy::CherryPick);
+        let cherry_pick_id = repo.cherry_pick(base.id, source.id).unwrap();
+        repo.set_rebase_strategy(git_stack::config::RebaseStrategy::MergeTree);
+        let merge_tree_id = repo.cherry_pick(base.id, source.id).unwrap();
+
+        let cherry_pick_commit = repo.raw().find_commit(cherry_pick_id).unwrap();
+        let merge_tree_commit = repo.raw().find_commit(merge_tree_id).unwrap();
+        assert_eq!(cherry_pick_commit.tree_id(), merge_tree_commit.tree_id());
+        assert_eq!(
+            cherry_pick_commit.parent_ids().collect::<Vec<_>>(),
+            [base.id]
+        );
+        assert_eq!(
+            merge_tree_commit.parent_ids().collect::<Vec<_>>(),
+            [base.id]
+        );
+        assert_eq!(
+            cherry_pick_commit.message_bytes(),
+            merge_tree_commit.message_bytes()
+        );
+        assert!(!repo.is_dirty());
+    }
+
+    temp.close().unwrap();
+}
+
+#[test]
+fn cherry_pick_merge_tree_conflict_matches_cherry_pick() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let plan =
+        git_fixture::TodoList::load(std::path::Path::new("tests/fixtures/conflict.yml")).unwrap();
+    plan.run(temp.path()).unwrap();
+
+    let repo = git2::Repository::discover(temp.path()).unwrap();
+    let mut repo = GitRepo::new(repo);
+
+    {
+        let base = repo.find_local_branch("feature1").unwrap();
+        let source = repo.find_local_branch("master").unwrap();
+
+        repo.set_rebase_strategy(git_stack::config::RebaseStrategy::CherryPick);
+        let cherry_pick_err = repo.cherry_pick(base.id, source.id).unwrap_err();
+        repo.set_rebase_strategy(git_stack::config::RebaseStrategy::MergeTree);
+        let merge_tree_err = repo.cherry_pick(base.id, source.id).unwrap_err();
+
+        assert_eq!(cherry_pick_err.code(), merge_tree_err.code());
+        assert_eq!(cherry_pick_err.message(), merge_tree_err.message());
+        assert!(!repo.is_dirty());
+    }
+
+    temp.close().unwrap();
+}
+
+fn rename_plan() -> git_fixture::TodoList {
+    let content = (1..=20).map(|i| format!("line {i}\n")).collect::<String>();
+    let modified = content.replace("line 10\n", "line ten\n");
+    let tree = |path: &str, content: &str, message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: [(path, content)]
+                .into_iter()
+                .map(|(p, c)| (p.into(), c.into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    git_fixture::TodoList {
+        commands: vec![
+            tree("old.txt", &content, "Add"),
+            git_fixture::Command::Label("base".into()),
+            tree("old.txt", &modified, "Modify"),
+            git_fixture::Command::Branch("modified".into()),
+            git_fixture::Command::Reset("base".into()),
+            tree("new.txt", &content, "Rename"),
+            git_fixture::Command::Branch("renamed".into()),
+        ],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn cherry_pick_follows_renames() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    rename_plan().run(temp.path()).unwrap();
+
+    let repo = git2::Repository::discover(temp.path()).unwrap();
+    let mut repo = GitRepo::new(repo);
+
+    for strategy in [
+        git_stack::config::RebaseStrategy::CherryPick,
+        git_stack::config::RebaseStrategy::MergeTree,
+    ] {
+        let base = repo.find_local_branch("modified").unwrap();
+        let source = repo.find_local_branch("renamed").unwrap();
+
+        repo.set_rebase_strategy(strategy);
+        let new_id = repo.cherry_pick(base.id, source.id).unwrap();
+
+        let tree = repo.raw().find_commit(new_id).unwrap().tree().unwrap();
+        assert!(tree.get_name("old.txt").is_none(), "{strategy}");
+        let blob = tree
+            .get_name("new.txt")
+            .unwrap()
+            .to_object(repo.raw())
+            .unwrap()
+            .peel_to_blob()
+            .unwrap();
+        let content = std::str::from_utf8(blob.content()).unwrap();
+        assert!(content.contains("line ten\n"), "{strategy}: {content}");
+    }
+
+    temp.close().unwrap();
+}
+
+#[test]
+fn cherry_pick_ignores_renames_when_disabled() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    rename_plan().run(temp.path()).unwrap();
+
+    let repo = git2::Repository::discover(temp.path()).unwrap();
+    repo.config()
+        .unwrap()
+        .set_bool("diff.renames", false)
+        .unwrap();
+    let mut repo = GitRepo::new(repo);
+
+    for strategy in [
+        git_stack::config::RebaseStrategy::CherryPick,
+        git_stack::config::RebaseStrategy::MergeTree,
+    ] {
+        let base = repo.find_local_branch("modified").unwrap();
+        let source = repo.find_local_branch("renamed").unwrap();
+
+        repo.set_rebase_strategy(strategy);
+        let err = repo.cherry_pick(base.id, source.id).unwrap_err();
+        assert_eq!(err.code(), git2::ErrorCode::Unmerged, "{strategy}");
+    }
+
+    temp.close().unwrap();
+}
+
 #[test]
 fn squash_clean() {
     let temp = assert_fs::TempDir::new().unwrap();
@@ -413,3 +724,92 @@ fn switch() {
 
     temp.close().unwrap();
 }
+
+#[test]
+fn opener_keeps_workdir() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let plan =
+        git_fixture::TodoList::load(std::path::Path::new("tests/fixtures/branches.yml")).unwrap();
+    plan.run(temp.path()).unwrap();
+    let work_tree = temp.path().join("elsewhere");
+    std::fs::create_dir_all(&work_tree).unwrap();
+
+    let repo = git2::Repository::discover(temp.path()).unwrap();
+    repo.set_workdir(&work_tree, false).unwrap();
+    let repo = GitRepo::new(repo);
+
+    let reopened = repo.opener()().unwrap();
+    assert_eq!(reopened.raw().workdir(), repo.raw().workdir());
+
+    temp.close().unwrap();
+}
+
+#[test]
+fn config_accessors() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let plan =
+        git_fixture::TodoList::load(std::path::Path::new("tests/fixtures/branches.yml")).unwrap();
+    plan.run(temp.path()).unwrap();
+
+    let repo = git2::Repository::discover(temp.path()).unwrap();
+    let config_path = repo.path().join("config");
+    let mut config = std::fs::read_to_string(&config_path).unwrap();
+    config.push_str(
+        "\
+[test]
+\tyes = yes
+\ton = On
+\tone = 1
+\tbig = 10
+\tbare
+\tno = no
+\toff = OFF
+\tzero = 0
+\tempty =
+\tinvalid = maybe
+\tname = Value
+\tkibi = 2k
+[test]
+\tname = Override
+",
+    );
+    std::fs::write(&config_path, config).unwrap();
+    let repo = GitRepo::new(repo);
+
+    for key in ["test.yes", "test.on", "test.one", "test.big", "test.bare"] {
+        assert!(repo.config_bool(key, false), "{key}");
+    }
+    for key in ["test.no", "test.off", "test.zero", "test.empty"] {
+        assert!(!repo.config_bool(key, true), "{key}");
+    }
+    assert!(repo.config_bool("test.invalid", true));
+    assert!(!repo.config_bool("test.invalid", false));
+    assert!(repo.config_bool("test.missing", true));
+
+    assert_eq!(repo.config_string("test.name").as_deref(), Some("Override"));
+    assert_eq!(repo.config_string("test.bare"), None);
+    assert_eq!(repo.config_string("test.missing"), None);
+
+    assert_eq!(repo.config_int("test.big"), Some(10));
+    assert_eq!(repo.config_int("test.kibi"), Some(2048));
+    assert_eq!(repo.config_int("test.invalid"), None);
+    assert_eq!(repo.config_int("test.missing"), None);
+
+    temp.close().unwrap();
+}
+
+#[test]
+fn in_memory_config_accessors() {
+    let mut repo = InMemoryRepo::new();
+    repo.set_config("test.bare", None);
+    repo.set_config("test.on", Some("on"));
+    repo.set_config("test.off", Some("off"));
+    repo.set_config("test.int", Some("3"));
+
+    assert!(repo.config_bool("test.bare", false));
+    assert!(repo.config_bool("test.on", false));
+    assert!(!repo.config_bool("test.off", true));
+    assert!(repo.config_bool("test.missing", true));
+    assert_eq!(repo.config_string("test.on").as_deref(), Some("on"));
+    assert_eq!(repo.config_int("test.int"), Some(3));
+}
@@ -30,7 +30,7 @@ fn reword_protected_fails() {
         .arg("--message=hahahaha")
         .current_dir(root_path)
         .assert()
-        .failure()
+        .code(64)
         .stdout_eq(
             "\
 ",
@@ -273,3 +273,903 @@ note: to undo, run `git branch-stash pop git-stack`
 
     root.close().unwrap();
 }
+
+#[test]
+#[cfg(unix)]
+fn reword_empty_message_uses_commit_template() {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    {
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let signature = repo.signature().unwrap();
+        let tree = head.tree().unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "", &tree, &[&head])
+            .unwrap();
+    }
+    let git_dir = repo.path().to_owned();
+    let template_path = git_dir.join("commit-template");
+    std::fs::write(&template_path, "TICKET-: \n\n# Reference the ticket\n").unwrap();
+    let editor_path = git_dir.join("editor.sh");
+    std::fs::write(
+        &editor_path,
+        "#!/bin/sh\ncp \"$1\" \"$1.captured\"\nprintf 'TICKET-1: Filled in\\n' > \"$1\"\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&editor_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let mut config = repo.config().unwrap();
+    config
+        .set_str("commit.template", template_path.to_str().unwrap())
+        .unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("reword")
+        .env("GIT_EDITOR", &editor_path)
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_eq(
+            "\
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    let captured = std::fs::read_to_string(git_dir.join("COMMIT_EDITMSG.captured")).unwrap();
+    assert!(
+        captured.starts_with("TICKET-: \n\n# Reference the ticket\n"),
+        "{captured}"
+    );
+
+    let branch = repo.find_local_branch("target").unwrap();
+    let commit = repo.find_commit(branch.id).unwrap();
+    snapbox::assert_eq(commit.summary.to_str().unwrap(), "TICKET-1: Filled in");
+
+    root.close().unwrap();
+}
+
+fn single_commit_plan() -> git_fixture::TodoList {
+    git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn reword_from_another_directory() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let repo_path = root_path.join("repo");
+    std::fs::create_dir_all(&repo_path).unwrap();
+    single_commit_plan().run(&repo_path).unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("-C")
+        .arg("repo")
+        .arg("reword")
+        .arg("--message=via -C")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--git-dir=repo/.git")
+        .arg("--work-tree=repo")
+        .arg("reword")
+        .arg("--message=via --git-dir")
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    let repo = git2::Repository::discover(&repo_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+    let branch = repo.find_local_branch("target").unwrap();
+    let commit = repo.find_commit(branch.id).unwrap();
+    snapbox::assert_eq(commit.summary.to_str().unwrap(), "via --git-dir");
+    snapbox::assert_eq(
+        repo.find_commit(repo.parent_ids(commit.id).unwrap()[0])
+            .unwrap()
+            .summary
+            .to_str()
+            .unwrap(),
+        "A",
+    );
+
+    root.close().unwrap();
+}
+
+#[test]
+fn reword_editor_launch_fails() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    single_commit_plan().run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let old_head_id = repo.head_commit().id;
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("reword")
+        .env("GIT_EDITOR", "git-stack-missing-editor")
+        .current_dir(root_path)
+        .assert()
+        .failure()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_eq(
+            "\
+could not launch editor `git-stack-missing-editor` (No such file or directory (os error 2)); set core.editor or use --message
+",
+        );
+
+    let new_head_id = repo.head_commit().id;
+    assert_eq!(old_head_id, new_head_id);
+
+    root.close().unwrap();
+}
+
+#[test]
+fn reword_without_editor_fails_early() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    single_commit_plan().run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let old_head_id = repo.head_commit().id;
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("reword")
+        .env_remove("GIT_EDITOR")
+        .env_remove("VISUAL")
+        .env_remove("EDITOR")
+        .stdin("")
+        .current_dir(root_path)
+        .assert()
+        .code(64)
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_eq(
+            "\
+no editor configured; set core.editor or use --message
+",
+        );
+
+    let new_head_id = repo.head_commit().id;
+    assert_eq!(old_head_id, new_head_id);
+
+    root.close().unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn reword_edit_mid_stack() {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b"), ("c", "c")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("C".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("local".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let git_dir = repo.path().to_owned();
+    let editor_path = git_dir.join("editor.sh");
+    std::fs::write(
+        &editor_path,
+        "#!/bin/sh\ncp \"$1\" \"$1.captured\"\nprintf 'new B\\n\\nWith details\\n' > \"$1\"\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&editor_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let old_local = repo.find_local_branch("local").unwrap();
+    let old_local_commit = repo.find_commit(old_local.id).unwrap();
+    let target_id = repo.parent_ids(old_local.id).unwrap()[0];
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("reword")
+        .arg("--message=new B")
+        .arg("--edit")
+        .arg(target_id.to_string())
+        .env("GIT_EDITOR", &editor_path)
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_eq(
+            "\
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    let captured = std::fs::read_to_string(git_dir.join("COMMIT_EDITMSG.captured")).unwrap();
+    assert!(captured.starts_with("new B\n"), "{captured}");
+
+    let local = repo.find_local_branch("local").unwrap();
+    let local_commit = repo.find_commit(local.id).unwrap();
+    snapbox::assert_eq(local_commit.summary.to_str().unwrap(), "C");
+    assert_eq!(local_commit.tree_id, old_local_commit.tree_id);
+
+    let new_target_id = repo.parent_ids(local.id).unwrap()[0];
+    assert_ne!(new_target_id, target_id);
+    let new_target = repo.find_commit(new_target_id).unwrap();
+    snapbox::assert_eq(new_target.message.as_str(), "new B\n\nWith details\n");
+    assert_eq!(repo.head_branch().unwrap().name, "local");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn reword_restack_warns_about_lfs_files() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let attributes = "*.bin filter=lfs diff=lfs merge=lfs -text\n";
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [(".gitattributes", attributes), ("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [(".gitattributes", attributes), ("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [
+                    (".gitattributes", attributes),
+                    ("a", "a"),
+                    ("b", "b"),
+                    ("c.bin", "c"),
+                ]
+                .into_iter()
+                .map(|(p, c)| (p.into(), c.into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("C".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("reword")
+        .arg("--message=new B")
+        .arg("HEAD~")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_eq(
+            "\
+WARN: re-stacked commits touching Git LFS files (c.bin); run `git lfs checkout` if they were left as pointer files
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    root.close().unwrap();
+}
+
+fn three_commit_plan() -> git_fixture::TodoList {
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    git_fixture::TodoList {
+        commands: vec![
+            tree(&[("a", "a")], "A"),
+            git_fixture::Command::Branch("main".into()),
+            tree(&[("a", "a"), ("b", "b")], "B"),
+            tree(&[("a", "a"), ("b", "b"), ("c", "c")], "C"),
+            tree(&[("a", "a"), ("b", "b"), ("c", "c"), ("d", "d")], "D"),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn reword_from_file() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    three_commit_plan().run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    let c = head.parent(0).unwrap();
+    let b = c.parent(0).unwrap();
+    let messages = root_path.join("messages.txt");
+    std::fs::write(
+        &messages,
+        format!(
+            "# new summaries\n{} new B\n{} new C\n\n{} new D\n",
+            b.id(),
+            c.id(),
+            head.id()
+        ),
+    )
+    .unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("reword")
+        .arg("--from-file")
+        .arg(&messages)
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    let d = repo
+        .find_branch("target", git2::BranchType::Local)
+        .unwrap()
+        .get()
+        .peel_to_commit()
+        .unwrap();
+    let c = d.parent(0).unwrap();
+    let b = c.parent(0).unwrap();
+    assert_eq!(d.summary(), Some("new D"));
+    assert_eq!(c.summary(), Some("new C"));
+    assert_eq!(b.summary(), Some("new B"));
+    assert_eq!(b.parent(0).unwrap().summary(), Some("A"));
+
+    root.close().unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn reword_select_range() {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    three_commit_plan().run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let main_id = repo.revparse_single("main").unwrap().id();
+    let git_dir = repo.path().to_owned();
+    let editor_path = git_dir.join("editor.sh");
+    std::fs::write(
+        &editor_path,
+        "#!/bin/sh\ncp \"$1\" \"$1.captured\"\nsed -i 's/ \\([BCD]\\)$/ new \\1/' \"$1\"\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&editor_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("reword")
+        .arg("--select=main..target~1")
+        .env("GIT_EDITOR", &editor_path)
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    let captured = std::fs::read_to_string(git_dir.join("COMMIT_EDITMSG.captured")).unwrap();
+    let listed = captured
+        .lines()
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .count();
+    assert_eq!(listed, 2, "{captured}");
+
+    let d = repo
+        .find_branch("target", git2::BranchType::Local)
+        .unwrap()
+        .get()
+        .peel_to_commit()
+        .unwrap();
+    let c = d.parent(0).unwrap();
+    let b = c.parent(0).unwrap();
+    assert_eq!(d.summary(), Some("D"));
+    assert_eq!(c.summary(), Some("new C"));
+    assert_eq!(b.summary(), Some("new B"));
+    assert_eq!(b.parent(0).unwrap().id(), main_id);
+
+    root.close().unwrap();
+}
+
+#[test]
+fn reword_from_file_malformed() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    three_commit_plan().run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let old_head_id = repo.head().unwrap().target().unwrap();
+    let messages = root_path.join("messages.txt");
+    std::fs::write(&messages, format!("{} new D\nHEAD~\n", old_head_id)).unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("reword")
+        .arg("--from-file=messages.txt")
+        .current_dir(root_path)
+        .assert()
+        .code(64)
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+messages.txt:2: expected `<sha> <new summary>`
+",
+        );
+
+    let new_head_id = repo.head().unwrap().target().unwrap();
+    assert_eq!(old_head_id, new_head_id);
+
+    root.close().unwrap();
+}
+
+#[test]
+fn reword_refuses_branch_in_other_worktree() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let worktree = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let worktree_path = worktree.path().unwrap().join("upper");
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("lower".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b"), ("c", "c")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("C".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("upper".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+    snapbox::cmd::Command::new("git")
+        .arg("switch")
+        .arg("lower")
+        .current_dir(root_path)
+        .assert()
+        .success();
+    snapbox::cmd::Command::new("git")
+        .arg("worktree")
+        .arg("add")
+        .arg(&worktree_path)
+        .arg("upper")
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let old_upper_id = repo.revparse_single("upper").unwrap().id();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("reword")
+        .arg("--message=new B")
+        .current_dir(root_path)
+        .assert()
+        .code(1)
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+ERROR: Failed to re-stack branch `upper`: `upper` is checked out in the worktree at [..]
+note: to undo, run `git branch-stash pop git-stack`
+error: 1 branch re-stacked, 1 failed: upper (`upper` is checked out in the worktree at [..])
+",
+        );
+
+    let new_upper_id = repo.revparse_single("upper").unwrap().id();
+    assert_eq!(old_upper_id, new_upper_id);
+
+    worktree.close().unwrap();
+    root.close().unwrap();
+}
+
+/// Reword `target` with an editor that writes `edited`, under `commit.cleanup=<cleanup>`
+#[cfg(unix)]
+fn reword_with_cleanup(cleanup: &str, edited: &str) -> String {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    three_commit_plan().run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let git_dir = repo.path().to_owned();
+    let message_path = git_dir.join("edited");
+    std::fs::write(&message_path, edited).unwrap();
+    let editor_path = git_dir.join("editor.sh");
+    std::fs::write(
+        &editor_path,
+        format!("#!/bin/sh\ncp \"{}\" \"$1\"\n", message_path.display()),
+    )
+    .unwrap();
+    std::fs::set_permissions(&editor_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    repo.config()
+        .unwrap()
+        .set_str("commit.cleanup", cleanup)
+        .unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("reword")
+        .env("GIT_EDITOR", &editor_path)
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    // `git2::Commit::message` drops leading newlines
+    let commit = repo
+        .raw()
+        .revparse_single("target")
+        .unwrap()
+        .peel_to_commit()
+        .unwrap();
+    let message = commit.message_raw().unwrap().to_owned();
+
+    root.close().unwrap();
+    message
+}
+
+#[cfg(unix)]
+const UNCLEAN_MESSAGE: &str = "\n\nSummary  \n\n\n# comment\nBody\t\n\n\n";
+
+#[test]
+#[cfg(unix)]
+fn reword_cleanup_strip() {
+    let message = reword_with_cleanup("strip", UNCLEAN_MESSAGE);
+    snapbox::assert_eq(message, "Summary\n\nBody\n");
+}
+
+#[test]
+#[cfg(unix)]
+fn reword_cleanup_whitespace() {
+    let message = reword_with_cleanup("whitespace", UNCLEAN_MESSAGE);
+    snapbox::assert_eq(message, "Summary\n\n# comment\nBody\n");
+}
+
+#[test]
+#[cfg(unix)]
+fn reword_cleanup_verbatim() {
+    let message = reword_with_cleanup("verbatim", UNCLEAN_MESSAGE);
+    assert_eq!(message, UNCLEAN_MESSAGE);
+}
+
+#[test]
+fn reword_dry_run_json() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    three_commit_plan().run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let id = |rev: &str| repo.revparse_single(rev).unwrap().id();
+    let (main_id, c_id, d_id) = (id("main"), id("target~1"), id("target"));
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("reword")
+        .arg("--dry-run")
+        .arg("--format=json")
+        .arg("--message=new C")
+        .arg("target~1")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(format!(
+            r#"{{
+  "schema": "git-stack-plan",
+  "scripts": [
+    {{
+      "batches": [
+        {{
+          "branch": "target",
+          "commands": [
+            {{
+              "action": "pick",
+              "commit": "{c_id}"
+            }},
+            {{
+              "action": "reword",
+              "commit": "{c_id}",
+              "message": "new C"
+            }},
+            {{
+              "action": "pick",
+              "commit": "{d_id}"
+            }},
+            {{
+              "action": "create-branch",
+              "branch": "target",
+              "commit": "{d_id}"
+            }}
+          ],
+          "onto": "{b_id}"
+        }}
+      ]
+    }}
+  ],
+  "summary": {{
+    "branches": [
+      "target"
+    ],
+    "conflicts": [],
+    "deleted_branches": [],
+    "dropped": [],
+    "rewritten": [
+      "{c_id}",
+      "{d_id}"
+    ]
+  }},
+  "version": 1
+}}
+"#,
+            b_id = id("target~2"),
+        ))
+        .stderr_eq(
+            "\
+",
+        );
+
+    assert_eq!(id("main"), main_id);
+    assert_eq!(id("target"), d_id);
+
+    root.close().unwrap();
+}
+
+#[test]
+fn reword_message_from_stdin() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    single_commit_plan().run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["reword", "--message-file", "-"])
+        .env_remove("GIT_EDITOR")
+        .env_remove("VISUAL")
+        .env_remove("EDITOR")
+        .stdin("new B\n\n# not part of the message\nDetails\n")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stderr_eq(
+            "\
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    let branch = repo.find_local_branch("target").unwrap();
+    let commit = repo.find_commit(branch.id).unwrap();
+    snapbox::assert_eq(commit.message.as_str(), "new B\n\nDetails");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn reword_empty_stdin_aborts() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    single_commit_plan().run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let old_head_id = repo.head_commit().id;
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["reword", "--message-file", "-"])
+        .stdin("\n# only a comment\n")
+        .current_dir(root_path)
+        .assert()
+        .failure()
+        .stderr_eq(
+            "\
+Aborting commit due to empty commit message.
+",
+        );
+
+    let new_head_id = repo.head_commit().id;
+    assert_eq!(old_head_id, new_head_id);
+
+    root.close().unwrap();
+}
+
+#[test]
+fn reword_with_committer() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let old = repo.head().unwrap().peel_to_commit().unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args([
+            "reword",
+            "--message=B2",
+            "--committer",
+            "Stack Bot <bot@example.com>",
+        ])
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    let new = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(new.message(), Some("B2"));
+    assert_eq!(new.committer().name(), Some("Stack Bot"));
+    assert_eq!(new.committer().email(), Some("bot@example.com"));
+    assert_eq!(new.committer().when(), old.committer().when());
+    assert_eq!(new.author().name(), old.author().name());
+    assert_eq!(
+        new.parent_ids().collect::<Vec<_>>(),
+        [old.parent_id(0).unwrap()]
+    );
+
+    root.close().unwrap();
+}
@@ -0,0 +1,210 @@
+use bstr::ByteSlice as _;
+
+#[test]
+fn squash_branch_collapses_commits() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b"), ("c", "c")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("C".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b"), ("c", "c"), ("d", "d")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("D".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b"), ("c", "c"), ("d", "d"), ("e", "e")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("E".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let main_id = repo.find_local_branch("main").unwrap().id;
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("squash-branch")
+        .arg("--message=BCDE")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_matches(
+            "\
+Squashed 4 commits on target
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    let branch = repo.find_local_branch("target").unwrap();
+    let commit = repo.find_commit(branch.id).unwrap();
+    snapbox::assert_eq(commit.summary.to_str().unwrap(), "BCDE");
+    assert_eq!(repo.parent_ids(commit.id).unwrap(), vec![main_id]);
+    assert_eq!(repo.head_commit().id, commit.id);
+
+    snapbox::assert_eq(std::fs::read(root_path.join("e")).unwrap(), "e");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn squash_branch_protected_fails() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let old_head_id = repo.head_commit().id;
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("squash-branch")
+        .arg("--message=AB")
+        .current_dir(root_path)
+        .assert()
+        .code(64)
+        .stdout_eq(
+            "\
+",
+        )
+        .stderr_eq(
+            "\
+cannot squash protected commits
+",
+        );
+
+    let new_head_id = repo.head_commit().id;
+    assert_eq!(old_head_id, new_head_id);
+
+    root.close().unwrap();
+}
+
+#[test]
+fn squash_branch_merges_issue_refs() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            tree(&[("a", "a")], "A"),
+            git_fixture::Command::Branch("main".into()),
+            tree(&[("a", "a"), ("b", "b")], "B\n\nFixes #123"),
+            tree(
+                &[("a", "a"), ("b", "b"), ("c", "c")],
+                "C\n\nFixes the parser too\n\nFixes #123, #124\nRefs #7",
+            ),
+            tree(
+                &[("a", "a"), ("b", "b"), ("c", "c"), ("d", "d")],
+                "D\n\nfixes: #125",
+            ),
+            git_fixture::Command::Branch("target".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+    snapbox::cmd::Command::new("git")
+        .args(["switch", "target"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["squash-branch", "--merge-issue-refs"])
+        .env("GIT_EDITOR", "true")
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+    let branch = repo.find_local_branch("target").unwrap();
+    let commit = repo.find_commit(branch.id).unwrap();
+    snapbox::assert_eq(
+        "\
+B
+
+C
+
+Fixes the parser too
+
+D
+
+Fixes #123, #124, #125
+Refs #7
+",
+        commit.message.as_str(),
+    );
+
+    root.close().unwrap();
+}
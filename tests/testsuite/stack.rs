@@ -0,0 +1,2308 @@
+fn protected_run_fixture(root_path: &std::path::Path) {
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let mut commands = vec![
+        tree(&[("a", "a")], "A"),
+        git_fixture::Command::Label("base".into()),
+    ];
+    for i in 1..=20 {
+        let content = i.to_string();
+        commands.push(tree(
+            &[("a", "a"), ("main", content.as_str())],
+            &format!("Main {i}"),
+        ));
+    }
+    commands.push(git_fixture::Command::Branch("main".into()));
+    commands.push(git_fixture::Command::Reset("base".into()));
+    commands.push(tree(&[("a", "a"), ("feature", "feature")], "Feature"));
+    commands.push(git_fixture::Command::Branch("feature".into()));
+    let plan = git_fixture::TodoList {
+        commands,
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    repo.set_head("refs/heads/feature").unwrap();
+}
+
+#[test]
+fn show_collapses_protected_run() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    protected_run_fixture(root_path);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--show-commits=all")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+[..] A
+├─┐
+│ ⌽ feature (ready) Feature
+│ 
+⌽ [..] Main 1
+⌽ … 18 protected commits …
+⌽ main (no remote) Main 20
+",
+        )
+        .stderr_matches("");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn show_protected_expands_protected_run() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    protected_run_fixture(root_path);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--show-commits=all")
+        .arg("--show-protected")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+[..] A
+├─┐
+│ ⌽ feature (ready) Feature
+│ 
+⌽ [..] Main 1
+⌽ [..] Main 2
+⌽ [..] Main 3
+⌽ [..] Main 4
+⌽ [..] Main 5
+⌽ [..] Main 6
+⌽ [..] Main 7
+⌽ [..] Main 8
+⌽ [..] Main 9
+⌽ [..] Main 10
+⌽ [..] Main 11
+⌽ [..] Main 12
+⌽ [..] Main 13
+⌽ [..] Main 14
+⌽ [..] Main 15
+⌽ [..] Main 16
+⌽ [..] Main 17
+⌽ [..] Main 18
+⌽ [..] Main 19
+⌽ main (no remote) Main 20
+",
+        )
+        .stderr_matches("");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn depth_collapses_deeper_commits() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let mut commands = vec![
+        tree(&[("a", "a")], "A"),
+        git_fixture::Command::Branch("main".into()),
+    ];
+    for i in 1..=5 {
+        let content = i.to_string();
+        commands.push(tree(
+            &[("a", "a"), ("feature", content.as_str())],
+            &format!("Feature {i}"),
+        ));
+    }
+    commands.push(git_fixture::Command::Branch("feature".into()));
+    let plan = git_fixture::TodoList {
+        commands,
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+    let repo = git2::Repository::discover(root_path).unwrap();
+    repo.set_head("refs/heads/feature").unwrap();
+
+    let git_stack = |args: &[&str]| {
+        snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+            .arg("--show-commits=all")
+            .args(args)
+            .current_dir(root_path)
+            .assert()
+            .success()
+    };
+
+    git_stack(&["--depth", "2"])
+        .stdout_matches(
+            "\
+main (no remote) A
+⌽ [..] Feature 1
+⌽ [..] Feature 2
+⌽ … 3 more below …
+",
+        )
+        .stderr_matches("");
+    git_stack(&["--depth", "all"])
+        .stdout_matches(
+            "\
+main (no remote) A
+⌽ [..] Feature 1
+⌽ [..] Feature 2
+⌽ [..] Feature 3
+⌽ [..] Feature 4
+⌽ feature (ready) Feature 5
+",
+        )
+        .stderr_matches("");
+
+    root.close().unwrap();
+}
+
+/// `main` with three sibling stacks whose tips were committed at different times
+///
+/// Commits are created with fixed signatures so their IDs, and therefore the topological order,
+/// are stable.
+fn sibling_stacks_fixture(root_path: &std::path::Path) {
+    let repo = git2::Repository::init(root_path).unwrap();
+    let mut config = repo.config().unwrap();
+    config
+        .set_str("stack.protect-commit-age", "100years")
+        .unwrap();
+    let commit = |name: &str, seconds: i64, parent: Option<&git2::Commit<'_>>| {
+        let signature =
+            git2::Signature::new("Test", "test@example.com", &git2::Time::new(seconds, 0)).unwrap();
+        let blob = repo.blob(name.as_bytes()).unwrap();
+        let parent_tree = parent.map(|p| p.tree().unwrap());
+        let mut tree = repo.treebuilder(parent_tree.as_ref()).unwrap();
+        tree.insert(name, blob, 0o100644).unwrap();
+        let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+        let id = repo
+            .commit(
+                Some(&format!("refs/heads/{name}")),
+                &signature,
+                &signature,
+                name,
+                &tree,
+                &parent.into_iter().collect::<Vec<_>>(),
+            )
+            .unwrap();
+        repo.find_commit(id).unwrap()
+    };
+
+    let main = commit("main", 1_500_000_004, None);
+    commit("charlie", 1_600_000_000, Some(&main));
+    commit("alpha", 1_600_001_000, Some(&main));
+    commit("bravo", 1_600_002_000, Some(&main));
+
+    repo.set_head("refs/heads/main").unwrap();
+    repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+        .unwrap();
+}
+#[test]
+fn order_topo() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    sibling_stacks_fixture(root_path);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--order=topo")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+main (no remote) main
+├─┐
+│ ⌽ bravo (ready) bravo
+├─┐
+│ ⌽ charlie (ready) charlie
+│ 
+⌽ alpha (ready) alpha
+",
+        )
+        .stderr_matches("");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn order_date() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    sibling_stacks_fixture(root_path);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--order=date")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+main (no remote) main
+├─┐
+│ ⌽ bravo (ready) bravo
+├─┐
+│ ⌽ alpha (ready) alpha
+│ 
+⌽ charlie (ready) charlie
+",
+        )
+        .stderr_matches("");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn order_name() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    sibling_stacks_fixture(root_path);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--order=name")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+main (no remote) main
+├─┐
+│ ⌽ alpha (ready) alpha
+├─┐
+│ ⌽ bravo (ready) bravo
+│ 
+⌽ charlie (ready) charlie
+",
+        )
+        .stderr_matches("");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn base_with_unrelated_history() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("feature".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let signature = repo.signature().unwrap();
+    let tree = repo
+        .find_tree(repo.treebuilder(None).unwrap().write().unwrap())
+        .unwrap();
+    repo.commit(
+        Some("refs/heads/orphan"),
+        &signature,
+        &signature,
+        "Orphan",
+        &tree,
+        &[],
+    )
+    .unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--base=orphan")
+        .arg("--stack=current")
+        .current_dir(root_path)
+        .assert()
+        .code(64)
+        .stdout_matches("")
+        .stderr_matches(
+            "\
+orphan and HEAD have unrelated histories; choose a `--base` that shares history with HEAD[..]
+",
+        );
+
+    root.close().unwrap();
+}
+
+fn long_summary_fixture(root_path: &std::path::Path) {
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("Short".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b"), ("c", "c")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("Explain in great detail why this commit exists".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("feature".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+}
+
+#[test]
+fn width_truncates_summaries() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    long_summary_fixture(root_path);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--width=30")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+main (no remote) A
+⌽ [..] Short
+⌽ feature (ready) Explain in …
+",
+        )
+        .stderr_matches("");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn porcelain() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    long_summary_fixture(root_path);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--porcelain")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+[..]\tprotected\tmain\tfalse
+[..]\tpick\t-\tfalse
+[..]\tpick\tfeature\ttrue
+",
+        )
+        .stderr_matches("");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn dump_graph() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    long_summary_fixture(root_path);
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let feature = repo.revparse_single("feature").unwrap().id();
+    let middle = repo.revparse_single("feature~").unwrap().id();
+    let main = repo.revparse_single("main").unwrap().id();
+
+    let output = snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--dump-graph")
+        .current_dir(root_path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("digraph git_stack {\n"), "{stdout}");
+    assert!(
+        stdout.contains("  subgraph cluster_0 {\n    label=\"main\";\n"),
+        "{stdout}"
+    );
+    for line in [
+        format!(
+            r#""0-{main}" [label="{:.7} A\n(protected)\n[main]", fillcolor=lightgrey, branches="main"];"#,
+            main
+        ),
+        format!(r#""0-{main}" -> "0-{middle}";"#),
+        format!(
+            r#""0-{middle}" [label="{:.7} Short\n(pick)", fillcolor=lightblue, branches=""];"#,
+            middle
+        ),
+        format!(r#""0-{middle}" -> "0-{feature}";"#),
+        format!(
+            r#""0-{feature}" [label="{:.7} Explain in great detail why this commit exists\n(pick)\n[feature]", fillcolor=lightblue, branches="feature"];"#,
+            feature
+        ),
+    ] {
+        assert!(stdout.contains(&line), "missing `{line}` in\n{stdout}");
+    }
+
+    root.close().unwrap();
+}
+
+fn reintroduced_drop_fixture(root_path: &std::path::Path) {
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            tree(&[("a", "a")], "A"),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Label("base".into()),
+            tree(&[("a", "a"), ("b", "b")], "Add b (#1)"),
+            git_fixture::Command::Branch("upstream".into()),
+            git_fixture::Command::Reset("base".into()),
+            tree(&[("a", "a"), ("b", "b")], "Add b"),
+            git_fixture::Command::Branch("feature".into()),
+            tree(&[("a", "a")], "Revert \"Add b\""),
+            tree(&[("a", "a"), ("b", "b")], "Add b again"),
+            git_fixture::Command::Branch("feature2".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+}
+
+#[test]
+fn rebase_warns_about_reintroduced_drop() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    reintroduced_drop_fixture(root_path);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["--rebase", "--base=main", "--onto=upstream", "--dry-run"])
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stderr_matches(
+            "\
+WARN: \"Revert \"Add b\"\" reverts \"Add b\", which is being dropped as already in upstream
+WARN: \"Add b again\" re-applies \"Add b\", which is being dropped as already in upstream
+",
+        );
+
+    root.close().unwrap();
+}
+
+#[test]
+fn at_base_notes_nothing_to_stack() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+main (no remote) A
+",
+        )
+        .stderr_matches(
+            "\
+note: no commits to stack; HEAD is at its base main
+",
+        );
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--rebase")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+main (no remote) A
+",
+        )
+        .stderr_matches(
+            "\
+note: no commits to stack; HEAD is at its base main
+",
+        );
+
+    root.close().unwrap();
+}
+
+#[test]
+fn sequence_editor_is_separate_from_message_editor() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    long_summary_fixture(root_path);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["--dump-config", "-"])
+        .env("GIT_EDITOR", "message-editor")
+        .env("GIT_SEQUENCE_EDITOR", "plan-editor")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+[core]
+	editor=message-editor
+[sequence]
+	editor=plan-editor
+...
+",
+        );
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["--dump-config", "-"])
+        .env("GIT_EDITOR", "message-editor")
+        .env_remove("GIT_SEQUENCE_EDITOR")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+[core]
+	editor=message-editor
+[sequence]
+	editor=message-editor
+...
+",
+        );
+
+    root.close().unwrap();
+}
+
+fn octopus_fixture(root_path: &std::path::Path) {
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            tree(&[("a", "a")], "A"),
+            git_fixture::Command::Label("a".into()),
+            tree(&[("a", "a"), ("p1", "p1")], "P1"),
+            git_fixture::Command::Branch("stable".into()),
+            git_fixture::Command::Reset("a".into()),
+            tree(&[("a", "a"), ("p2", "p2")], "P2"),
+            git_fixture::Command::Tag("p2".into()),
+            git_fixture::Command::Label("p2".into()),
+            git_fixture::Command::Reset("a".into()),
+            tree(&[("a", "a"), ("p3", "p3")], "P3"),
+            git_fixture::Command::Tag("p3".into()),
+            git_fixture::Command::Reset("p2".into()),
+            tree(&[("a", "a"), ("p2", "p2"), ("f", "f")], "F"),
+            git_fixture::Command::Branch("feature".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    // `main` is an octopus merge of `stable` and the two tags
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let parents = ["refs/heads/stable", "refs/tags/p2", "refs/tags/p3"]
+        .iter()
+        .map(|name| {
+            repo.revparse_single(name)
+                .unwrap()
+                .peel_to_commit()
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    let mut builder = repo.treebuilder(None).unwrap();
+    for parent in &parents {
+        for entry in parent.tree().unwrap().iter() {
+            builder
+                .insert(entry.name().unwrap(), entry.id(), entry.filemode())
+                .unwrap();
+        }
+    }
+    let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+    let sig = repo.signature().unwrap();
+    let merge_id = repo
+        .commit(
+            None,
+            &sig,
+            &sig,
+            "Octopus",
+            &tree,
+            &parents.iter().collect::<Vec<_>>(),
+        )
+        .unwrap();
+    repo.branch("main", &repo.find_commit(merge_id).unwrap(), false)
+        .unwrap();
+}
+
+#[test]
+fn octopus_merge_parents_are_protected() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    octopus_fixture(root_path);
+
+    let output = snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["--porcelain", "--stack=all"])
+        .current_dir(root_path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let actions = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let mut fields = line.split('\t');
+            let id = git2::Oid::from_str(fields.next().unwrap()).unwrap();
+            let summary = repo.find_commit(id).unwrap().summary().unwrap().to_owned();
+            (summary, fields.next().unwrap().to_owned())
+        })
+        .collect::<std::collections::BTreeMap<_, _>>();
+    for summary in ["P1", "P2", "P3", "Octopus"] {
+        assert_eq!(actions[summary], "protected", "{summary}");
+    }
+    assert_eq!(actions["F"], "pick");
+
+    root.close().unwrap();
+}
+
+/// `feature` is merged upstream, so re-stacking onto `upstream` deletes it
+fn merged_branch_fixture(root_path: &std::path::Path, with_child: bool) {
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let mut commands = vec![
+        tree(&[("a", "a")], "A"),
+        git_fixture::Command::Tag("old".into()),
+        git_fixture::Command::Label("old".into()),
+        tree(&[("a", "a"), ("o", "o")], "O"),
+        git_fixture::Command::Branch("other".into()),
+        git_fixture::Command::Reset("old".into()),
+        tree(&[("a", "a"), ("f1", "f1")], "F1"),
+        git_fixture::Command::Label("f1".into()),
+        tree(&[("a", "a"), ("f1", "f1"), ("m1", "m1")], "M1"),
+        tree(
+            &[("a", "a"), ("f1", "f1"), ("m1", "m1"), ("m2", "m2")],
+            "M2",
+        ),
+        git_fixture::Command::Tag("upstream".into()),
+        git_fixture::Command::Reset("f1".into()),
+    ];
+    if with_child {
+        commands.push(tree(&[("a", "a"), ("f1", "f1"), ("f2", "f2")], "F2"));
+        commands.push(git_fixture::Command::Branch("feature2".into()));
+        commands.push(git_fixture::Command::Reset("f1".into()));
+    }
+    commands.push(git_fixture::Command::Branch("feature".into()));
+    let plan = git_fixture::TodoList {
+        commands,
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+}
+
+fn rebase_onto_upstream(root_path: &std::path::Path) -> snapbox::cmd::Command {
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let rev = |name: &str| {
+        repo.revparse_single(name)
+            .unwrap()
+            .peel_to_commit()
+            .unwrap()
+            .id()
+            .to_string()
+    };
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--rebase")
+        .arg(format!("--base={}", rev("old")))
+        .arg(format!("--onto={}", rev("upstream")))
+        .current_dir(root_path)
+}
+
+fn head_branch(root_path: &std::path::Path) -> Option<String> {
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+    repo.head_branch()
+        .and_then(|b| b.local_name().map(ToOwned::to_owned))
+}
+
+#[test]
+fn rebase_stays_on_surviving_branch() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    merged_branch_fixture(root_path, true);
+    snapbox::cmd::Command::new("git")
+        .args(["switch", "feature2"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    rebase_onto_upstream(root_path).assert().success();
+
+    assert_eq!(head_branch(root_path).as_deref(), Some("feature2"));
+    assert!(root_path.join("m2").exists());
+
+    root.close().unwrap();
+}
+
+#[test]
+fn rebase_switches_to_descendant_of_deleted_branch() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    merged_branch_fixture(root_path, true);
+
+    rebase_onto_upstream(root_path).assert().success();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    assert!(repo
+        .find_branch("feature", git2::BranchType::Local)
+        .is_err());
+    assert_eq!(head_branch(root_path).as_deref(), Some("feature2"));
+    assert!(root_path.join("f2").exists());
+
+    root.close().unwrap();
+}
+
+#[test]
+fn rebase_detaches_when_branch_deleted() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    merged_branch_fixture(root_path, false);
+
+    rebase_onto_upstream(root_path)
+        .assert()
+        .success()
+        .stderr_matches(
+            "\
+...
+WARN: `feature` will be deleted, leaving HEAD detached
+...
+",
+        );
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    assert!(repo
+        .find_branch("feature", git2::BranchType::Local)
+        .is_err());
+    assert_eq!(head_branch(root_path), None);
+    let upstream_id = repo.revparse_single("upstream^{commit}").unwrap().id();
+    assert_eq!(repo.head().unwrap().target(), Some(upstream_id));
+
+    root.close().unwrap();
+}
+
+#[test]
+fn rebase_switch_to_deleted_branch_fails() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    merged_branch_fixture(root_path, true);
+
+    rebase_onto_upstream(root_path)
+        .arg("--switch=feature")
+        .assert()
+        .code(64)
+        .stderr_eq(
+            "\
+cannot switch to `feature`, it won't exist
+",
+        );
+
+    assert_eq!(head_branch(root_path).as_deref(), Some("feature"));
+
+    root.close().unwrap();
+}
+
+#[test]
+fn rebase_switch_failure_restores_worktree() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    merged_branch_fixture(root_path, true);
+    std::fs::write(root_path.join("a"), "dirty").unwrap();
+
+    rebase_onto_upstream(root_path)
+        .arg("--switch=typo")
+        .assert()
+        .code(64)
+        .stderr_matches(
+            "\
+...
+cannot switch to `typo`, it won't exist
+",
+        );
+
+    snapbox::assert_eq(std::fs::read(root_path.join("a")).unwrap(), "dirty");
+    let mut repo = git2::Repository::discover(root_path).unwrap();
+    let mut stashes = 0;
+    repo.stash_foreach(|_, _, _| {
+        stashes += 1;
+        true
+    })
+    .unwrap();
+    assert_eq!(stashes, 0);
+
+    root.close().unwrap();
+}
+
+fn mark_shallow(root_path: &std::path::Path, rev: &str) -> git2::Oid {
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let id = repo
+        .revparse_single(rev)
+        .unwrap()
+        .peel_to_commit()
+        .unwrap()
+        .id();
+    std::fs::write(repo.path().join("shallow"), format!("{id}\n")).unwrap();
+    id
+}
+
+#[test]
+fn bare_repo_refuses_early() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    git2::Repository::init_bare(root_path).unwrap();
+
+    for args in [&[][..], &["sync"][..], &["gc"][..]] {
+        snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+            .args(args)
+            .current_dir(root_path)
+            .assert()
+            .code(64)
+            .stderr_eq(
+                "\
+git-stack requires a working tree; this is a bare repository
+",
+            );
+    }
+
+    root.close().unwrap();
+}
+
+#[test]
+fn bare_repo_allows_config_commands() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    git2::Repository::init_bare(root_path).unwrap();
+
+    for args in [
+        &["alias"][..],
+        &["--dump-config", "-"][..],
+        &["--protected"][..],
+    ] {
+        snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+            .args(args)
+            .current_dir(root_path)
+            .assert()
+            .success();
+    }
+
+    root.close().unwrap();
+}
+
+#[test]
+fn shallow_clone_warns() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    merged_branch_fixture(root_path, false);
+    mark_shallow(root_path, "old");
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--format=silent")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stderr_matches(
+            "\
+warning: shallow clone, bases may be resolved incorrectly; run `git fetch --unshallow` to fetch the full history
+...
+",
+        );
+
+    root.close().unwrap();
+}
+
+#[test]
+fn rebase_refuses_crossing_shallow_boundary() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    merged_branch_fixture(root_path, true);
+    let boundary_id = mark_shallow(root_path, "feature2");
+
+    rebase_onto_upstream(root_path)
+        .assert()
+        .code(1)
+        .stderr_matches(
+            "\
+warning: shallow clone, bases may be resolved incorrectly; run `git fetch --unshallow` to fetch the full history
+`feature` will be deleted, switching to `feature2`
+ERROR: Failed to re-stack branch `feature2`: cannot rewrite [..], its parents are beyond the shallow clone; run `git fetch --unshallow`; class=Repository (6); code=Invalid (-21)
+...
+",
+        );
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let feature2_id = repo.revparse_single("feature2").unwrap().id();
+    assert_eq!(feature2_id, boundary_id);
+
+    root.close().unwrap();
+}
+
+#[test]
+fn unknown_push_remote_warns() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    merged_branch_fixture(root_path, false);
+    let repo = git2::Repository::discover(root_path).unwrap();
+    repo.remote("origin", "https://example.com/project.git")
+        .unwrap();
+    repo.config()
+        .unwrap()
+        .set_str("stack.push-remote", "orign")
+        .unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--format=silent")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stderr_matches(
+            "\
+warning: configured push-remote 'orign' not found; available: origin
+...
+",
+        );
+
+    root.close().unwrap();
+}
+
+fn pushed_branches_fixture(root_path: &std::path::Path) {
+    let repo = git2::Repository::init(root_path).unwrap();
+    let mut config = repo.config().unwrap();
+    config
+        .set_str("stack.protect-commit-age", "100years")
+        .unwrap();
+    let commit = |name: &str, seconds: i64, parent: Option<&git2::Commit<'_>>| {
+        let signature =
+            git2::Signature::new("Test", "test@example.com", &git2::Time::new(seconds, 0)).unwrap();
+        let blob = repo.blob(name.as_bytes()).unwrap();
+        let parent_tree = parent.map(|p| p.tree().unwrap());
+        let mut tree = repo.treebuilder(parent_tree.as_ref()).unwrap();
+        tree.insert(name, blob, 0o100644).unwrap();
+        let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+        let id = repo
+            .commit(
+                Some(&format!("refs/heads/{name}")),
+                &signature,
+                &signature,
+                name,
+                &tree,
+                &parent.into_iter().collect::<Vec<_>>(),
+            )
+            .unwrap();
+        repo.find_commit(id).unwrap()
+    };
+    let push = |commit: &git2::Commit<'_>| {
+        let name = commit.summary().unwrap();
+        repo.reference(
+            &format!("refs/remotes/origin/{name}"),
+            commit.id(),
+            false,
+            "fixture",
+        )
+        .unwrap();
+    };
+
+    let main = commit("main", 1_500_000_000, None);
+    let pushed = commit("pushed", 1_600_000_000, Some(&main));
+    push(&pushed);
+    commit("unpushed", 1_600_001_000, Some(&pushed));
+    let done = commit("done", 1_600_002_000, Some(&main));
+    push(&done);
+
+    repo.set_head("refs/heads/main").unwrap();
+    repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+        .unwrap();
+}
+
+#[test]
+fn since_pushed_hides_pushed_branches() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    pushed_branches_fixture(root_path);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+main (no remote) main
+├─┐
+│ ⌽ done (pushed) done
+│ 
+⌽ pushed (pushed) pushed
+⌽ unpushed unpushed
+",
+        );
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--since-pushed")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+main (no remote) main
+⌽ pushed (pushed) pushed
+⌽ unpushed unpushed
+",
+        );
+
+    root.close().unwrap();
+}
+
+fn per_commit_files_fixture(root_path: &std::path::Path) {
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let mut big = vec![("a", "a"), ("b", "b2")];
+    let names = (1..=12).map(|i| format!("gen{i:02}")).collect::<Vec<_>>();
+    big.extend(names.iter().map(|n| (n.as_str(), "gen")));
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            tree(&[("a", "a")], "A"),
+            git_fixture::Command::Branch("main".into()),
+            tree(&[("a", "a"), ("b", "b"), ("c", "c")], "Add"),
+            tree(&[("a", "a"), ("b", "b2")], "Edit"),
+            tree(&big, "Generate"),
+            git_fixture::Command::Branch("feature".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+}
+
+#[test]
+fn stat_by_file_lists_changed_paths() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    per_commit_files_fixture(root_path);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--stat-by-file")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+main (no remote) A
+⌽ [..] Add
+│   b
+│   c
+⌽ [..] Edit
+│   b
+│   c
+⌽ feature (ready) Generate
+    gen01
+    gen02
+    gen03
+    gen04
+    gen05
+    gen06
+    gen07
+    gen08
+    gen09
+    gen10
+    … and 2 more
+",
+        )
+        .stderr_matches("");
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--stat-by-file")
+        .arg("--all-files")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+main (no remote) A
+⌽ [..] Add
+│   b
+│   c
+⌽ [..] Edit
+│   b
+│   c
+⌽ feature (ready) Generate
+    gen01
+    gen02
+    gen03
+    gen04
+    gen05
+    gen06
+    gen07
+    gen08
+    gen09
+    gen10
+    gen11
+    gen12
+",
+        )
+        .stderr_matches("");
+
+    root.close().unwrap();
+}
+
+/// `side` was merged into both `main` and `feature`, making its tip their merge-base
+fn merged_side_branch_fixture(root_path: &std::path::Path) {
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            tree(&[("a", "a")], "A"),
+            git_fixture::Command::Label("base".into()),
+            tree(&[("a", "a"), ("s", "s1")], "S1"),
+            tree(&[("a", "a"), ("s", "s2")], "S2"),
+            git_fixture::Command::Branch("side".into()),
+            git_fixture::Command::Reset("base".into()),
+            tree(&[("a", "a"), ("m", "m")], "M"),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Reset("base".into()),
+            tree(&[("a", "a"), ("f", "f1")], "F1"),
+            git_fixture::Command::Branch("feature".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let git = |args: &[&str]| {
+        snapbox::cmd::Command::new("git")
+            .args(args)
+            .current_dir(root_path)
+            .assert()
+            .success();
+    };
+    git(&["checkout", "-q", "-f", "main"]);
+    git(&[
+        "merge",
+        "-q",
+        "--no-ff",
+        "-m",
+        "Merge side into main",
+        "side",
+    ]);
+    git(&["checkout", "-q", "feature"]);
+    git(&[
+        "merge",
+        "-q",
+        "--no-ff",
+        "-m",
+        "Merge side into feature",
+        "side",
+    ]);
+    std::fs::write(root_path.join("f"), "f2").unwrap();
+    git(&["commit", "-q", "-am", "F2"]);
+    git(&["branch", "-q", "-D", "side"]);
+}
+
+#[test]
+fn first_parent_leaves_out_merged_in_commits() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    merged_side_branch_fixture(root_path);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+[..] S2
+├─┐
+│ ⌽ [..] F1
+│ ⌽ [..] (merge commit) Merge side into feature
+│ ⌽ feature (ready) F2
+│ 
+⌽ main (no remote) Merge side into main
+",
+        )
+        .stderr_matches("");
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--first-parent")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+[..] A
+├─┐
+│ ⌽ [..] F1
+│ ⌽ [..] (merge commit) Merge side into feature
+│ ⌽ feature (ready) F2
+│ 
+⌽ main (no remote) Merge side into main
+",
+        )
+        .stderr_matches("");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn first_parent_applies_to_subcommands() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    merged_side_branch_fixture(root_path);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["explain", "HEAD", "--first-parent"])
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+[..] protected A
+[..] pick F1
+[..] pick Merge side into feature
+[..] pick F2
+[..] is a development commit
+",
+        )
+        .stderr_matches("");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn timings_lists_phases() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    long_summary_fixture(root_path);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["--rebase", "--timings"])
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stderr_matches(
+            "\
+...
+timings:
+  branches [..]
+  graph [..]
+  protect [..]
+  rebase [..]
+  fixup [..]
+  script [..]
+  execute [..]
+    detached [..]
+    feature [..]
+  total [..]
+",
+        );
+
+    root.close().unwrap();
+}
+
+#[test]
+fn default_action_protect_waits_for_pick() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    pushed_branches_fixture(root_path);
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let mut config = repo.config().unwrap();
+    config.set_str("user.name", "Test").unwrap();
+    config.set_str("user.email", "test@example.com").unwrap();
+    config.set_str("stack.default-action", "protect").unwrap();
+    snapbox::cmd::Command::new("git")
+        .args(["commit", "--allow-empty", "-m", "main2"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+    let main_id = repo.revparse_single("main").unwrap().id();
+    let parent_of = |name: &str| {
+        repo.revparse_single(name)
+            .unwrap()
+            .peel_to_commit()
+            .unwrap()
+            .parent_id(0)
+            .unwrap()
+    };
+    let old_done_parent = parent_of("done");
+
+    // `done` is already pushed, so nobody claimed it
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--rebase")
+        .current_dir(root_path)
+        .assert()
+        .success();
+    assert_eq!(parent_of("done"), old_done_parent);
+    assert_eq!(parent_of("pushed"), main_id);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["pick", "done"])
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq("")
+        .stderr_matches(
+            "\
+Picked done ([..]): done
+",
+        );
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--rebase")
+        .current_dir(root_path)
+        .assert()
+        .success();
+    assert_eq!(parent_of("done"), main_id);
+
+    // The pick follows the branch after it was rewritten
+    snapbox::cmd::Command::new("git")
+        .args(["commit", "--allow-empty", "-m", "main3"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+    let main_id = repo.revparse_single("main").unwrap().id();
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--rebase")
+        .current_dir(root_path)
+        .assert()
+        .success();
+    assert_eq!(parent_of("done"), main_id);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["pick", "--remove", "done"])
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq("")
+        .stderr_matches(
+            "\
+Unpicked done
+",
+        );
+    let config = repo.config().unwrap().snapshot().unwrap();
+    assert!(config.get_str("stack.picked").is_err());
+
+    root.close().unwrap();
+}
+
+#[test]
+fn remote_diverged_since_push() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            tree(&[("a", "a")], "A"),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Label("main".into()),
+            tree(&[("a", "a"), ("s", "s")], "S"),
+            git_fixture::Command::Branch("synced".into()),
+            git_fixture::Command::Reset("main".into()),
+            tree(&[("a", "a"), ("f", "f")], "F"),
+            git_fixture::Command::Branch("feature".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    repo.config()
+        .unwrap()
+        .set_str("stack.protect-commit-age", "100years")
+        .unwrap();
+    let rev = |name: &str| {
+        repo.revparse_single(name)
+            .unwrap()
+            .peel_to_commit()
+            .unwrap()
+    };
+    let synced = rev("synced");
+    repo.reference(
+        "refs/remotes/origin/synced",
+        synced.id(),
+        false,
+        "update by push",
+    )
+    .unwrap();
+    let feature = rev("feature");
+    repo.reference(
+        "refs/remotes/origin/feature",
+        feature.id(),
+        false,
+        "update by push",
+    )
+    .unwrap();
+    // Someone else pushes on top of ours and we fetch it
+    let signature = feature.author();
+    let theirs = repo
+        .commit(
+            None,
+            &signature,
+            &signature,
+            "theirs",
+            &feature.tree().unwrap(),
+            &[&feature],
+        )
+        .unwrap();
+    repo.reference(
+        "refs/remotes/origin/feature",
+        theirs,
+        true,
+        "fetch: fast-forward",
+    )
+    .unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+main (no remote) A
+├─┐
+│ ⌽ synced (pushed) S
+│ 
+⌽ feature (1 behind) (remote diverged) F
+",
+        )
+        .stderr_matches("");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn onto_remote_leaves_local_base() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            tree(&[("a", "a")], "A"),
+            git_fixture::Command::Branch("main".into()),
+            tree(&[("a", "a"), ("f", "f")], "F"),
+            git_fixture::Command::Branch("feature".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+    snapbox::cmd::Command::new("git")
+        .args(["switch", "feature"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    repo.config()
+        .unwrap()
+        .set_str("stack.protect-commit-age", "100years")
+        .unwrap();
+    let rev = |name: &str| {
+        repo.revparse_single(name)
+            .unwrap()
+            .peel_to_commit()
+            .unwrap()
+    };
+    let main = rev("main");
+    let signature = main.author();
+    let upstream = repo
+        .commit(
+            None,
+            &signature,
+            &signature,
+            "B",
+            &main.tree().unwrap(),
+            &[&main],
+        )
+        .unwrap();
+    repo.reference(
+        "refs/remotes/origin/main",
+        upstream,
+        false,
+        "fetch: storing head",
+    )
+    .unwrap();
+    // Pretend the fetch happened long ago
+    let mut reflog = repo.reflog("refs/remotes/origin/main").unwrap();
+    reflog.remove(0, false).unwrap();
+    let fetched_by =
+        git2::Signature::new("Fetcher", "fetcher@example.com", &git2::Time::new(0, 0)).unwrap();
+    reflog
+        .append(upstream, &fetched_by, Some("fetch: storing head"))
+        .unwrap();
+    reflog.write().unwrap();
+
+    let output = snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--onto-remote")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("`origin/main` was last fetched"),
+        "stale fetch not reported: {stderr}"
+    );
+
+    assert_eq!(rev("main").id(), main.id());
+    assert_eq!(rev("feature").parent_id(0).unwrap(), upstream);
+
+    root.close().unwrap();
+}
+
+#[test]
+fn rebase_summarizes_failed_branches() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            tree(&[("x", "x")], "A"),
+            git_fixture::Command::Label("base".into()),
+            tree(&[("x", "foo")], "FOO"),
+            git_fixture::Command::Branch("foo".into()),
+            git_fixture::Command::Reset("base".into()),
+            tree(&[("x", "bar")], "BAR"),
+            git_fixture::Command::Branch("bar".into()),
+            git_fixture::Command::Reset("base".into()),
+            tree(&[("x", "x"), ("y", "y")], "OK"),
+            git_fixture::Command::Branch("ok".into()),
+            git_fixture::Command::Reset("base".into()),
+            tree(&[("x", "main")], "MAIN"),
+            git_fixture::Command::Branch("main".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+    snapbox::cmd::Command::new("git")
+        .args(["switch", "ok"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+    let repo = git2::Repository::discover(root_path).unwrap();
+    repo.config()
+        .unwrap()
+        .set_str("stack.protect-commit-age", "100years")
+        .unwrap();
+
+    // Independent stacks are re-stacked in commit-id order, so only the summary has a fixed order
+    let output = snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["--rebase", "--format=silent"])
+        .current_dir(root_path)
+        .assert()
+        .code(1)
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.ends_with(
+            "error: 1 branch re-stacked, 2 failed: bar (cherry-pick conflicts: x), foo (cherry-pick conflicts: x)\n"
+        ),
+        "missing summary: {stderr}"
+    );
+
+    let main_id = repo.revparse_single("main").unwrap().id();
+    let ok = repo
+        .revparse_single("ok")
+        .unwrap()
+        .peel_to_commit()
+        .unwrap();
+    assert_eq!(ok.parent_id(0).unwrap(), main_id);
+
+    root.close().unwrap();
+}
+
+#[test]
+fn ahead_and_behind_push_remote() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            tree(&[("a", "a")], "A"),
+            git_fixture::Command::Branch("main".into()),
+            tree(&[("a", "a"), ("f", "f")], "F"),
+            git_fixture::Command::Label("pushed".into()),
+            tree(&[("a", "a"), ("f", "f"), ("g", "g")], "G"),
+            tree(&[("a", "a"), ("f", "f"), ("g", "g"), ("h", "h")], "H"),
+            git_fixture::Command::Branch("feature".into()),
+            git_fixture::Command::Reset("pushed".into()),
+            tree(&[("a", "a"), ("f", "f"), ("r", "r")], "R"),
+            git_fixture::Command::Branch("remote".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+    snapbox::cmd::Command::new("git")
+        .args(["switch", "feature"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    repo.config()
+        .unwrap()
+        .set_str("stack.protect-commit-age", "100years")
+        .unwrap();
+    // Someone else pushed `R` while we committed `G` and `H` locally
+    let remote_id = repo.revparse_single("remote").unwrap().id();
+    repo.reference(
+        "refs/remotes/origin/feature",
+        remote_id,
+        false,
+        "fetch: storing head",
+    )
+    .unwrap();
+    repo.find_branch("remote", git2::BranchType::Local)
+        .unwrap()
+        .delete()
+        .unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+main (no remote) A
+⌽ [..] F
+⌽ [..] G
+⌽ feature (2 ahead, 1 behind) H
+",
+        )
+        .stderr_matches("");
+
+    root.close().unwrap();
+}
+
+#[test]
+fn rebase_refuses_during_bisect() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    merged_branch_fixture(root_path, false);
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let git_dir = repo.path().to_owned();
+    let feature_id = || {
+        repo.revparse_single("feature")
+            .unwrap()
+            .peel_to_commit()
+            .unwrap()
+            .id()
+    };
+    let old_feature_id = feature_id();
+    let orig_head = format!("{}\n", repo.revparse_single("old").unwrap().id());
+    std::fs::write(git_dir.join("ORIG_HEAD"), &orig_head).unwrap();
+    std::fs::write(git_dir.join("BISECT_START"), "feature\n").unwrap();
+    std::fs::write(git_dir.join("BISECT_LOG"), "git bisect start\n").unwrap();
+
+    rebase_onto_upstream(root_path)
+        .assert()
+        .code(64)
+        .stdout_eq("")
+        .stderr_eq(
+            "\
+cannot walk commits, Bisect in progress
+",
+        );
+    assert_eq!(feature_id(), old_feature_id);
+
+    std::fs::remove_file(git_dir.join("BISECT_START")).unwrap();
+    std::fs::remove_file(git_dir.join("BISECT_LOG")).unwrap();
+    rebase_onto_upstream(root_path).assert().success();
+    assert!(repo
+        .find_branch("feature", git2::BranchType::Local)
+        .is_err());
+    snapbox::assert_eq(
+        std::fs::read_to_string(git_dir.join("ORIG_HEAD")).unwrap(),
+        orig_head,
+    );
+
+    root.close().unwrap();
+}
+
+#[test]
+fn rebase_author_pins_other_authors_commits() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let repo = git2::Repository::init(root_path).unwrap();
+    let mut config = repo.config().unwrap();
+    config.set_str("user.name", "alice").unwrap();
+    config.set_str("user.email", "alice@example.com").unwrap();
+    // `git-fixture` can't set authors, so build the history by hand
+    let commit = |parent: Option<git2::Oid>, file: &str, author: &str| {
+        let mut builder = repo
+            .treebuilder(
+                parent
+                    .map(|p| repo.find_commit(p).unwrap().tree().unwrap())
+                    .as_ref(),
+            )
+            .unwrap();
+        let blob = repo.blob(file.as_bytes()).unwrap();
+        builder.insert(file, blob, 0o100644).unwrap();
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+        let sig = git2::Signature::now(author, &format!("{author}@example.com")).unwrap();
+        let parents = parent
+            .map(|p| repo.find_commit(p).unwrap())
+            .into_iter()
+            .collect::<Vec<_>>();
+        repo.commit(
+            None,
+            &sig,
+            &sig,
+            file,
+            &tree,
+            &parents.iter().collect::<Vec<_>>(),
+        )
+        .unwrap()
+    };
+    let old_id = commit(None, "a", "alice");
+    let main_id = commit(Some(old_id), "u", "carol");
+    let mine_id = commit(Some(old_id), "m1", "alice");
+    let shared_id = commit(Some(old_id), "s1", "alice");
+    let shared_id = commit(Some(shared_id), "t1", "bob");
+    let shared_id = commit(Some(shared_id), "s2", "alice");
+    for (name, id) in [("main", main_id), ("mine", mine_id), ("shared", shared_id)] {
+        repo.branch(name, &repo.find_commit(id).unwrap(), true)
+            .unwrap();
+    }
+    repo.set_head("refs/heads/mine").unwrap();
+    repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+        .unwrap();
+
+    let branch_id = |name: &str| {
+        repo.find_branch(name, git2::BranchType::Local)
+            .unwrap()
+            .get()
+            .target()
+            .unwrap()
+    };
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["--rebase", "--onto=main", "--author=alice@"])
+        .arg(format!("--base={old_id}"))
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    let mine = repo.find_commit(branch_id("mine")).unwrap();
+    assert_eq!(mine.parent_id(0).unwrap(), main_id);
+    assert_eq!(branch_id("shared"), shared_id);
+
+    // Only bob's commit and what it's built on are pinned, alice's commit on top stays rewritable
+    let bob_id = repo.find_commit(shared_id).unwrap().parent_id(0).unwrap();
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["reword", "--message=t1 reworded", "--author=alice@"])
+        .arg(bob_id.to_string())
+        .current_dir(root_path)
+        .assert()
+        .failure();
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args([
+            "reword",
+            "shared",
+            "--message=s2 reworded",
+            "--author=alice@",
+        ])
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    let shared = repo.find_commit(branch_id("shared")).unwrap();
+    assert_eq!(shared.summary(), Some("s2 reworded"));
+    assert_eq!(shared.parent_id(0).unwrap(), bob_id);
+
+    root.close().unwrap();
+}
+
+#[test]
+fn hide_persists_across_renders() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            tree(&[("a", "a")], "A"),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Label("base".into()),
+            tree(&[("a", "a"), ("o", "o")], "O"),
+            git_fixture::Command::Branch("other".into()),
+            git_fixture::Command::Reset("base".into()),
+            tree(&[("a", "a"), ("f", "f")], "F"),
+            git_fixture::Command::Branch("feature".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+    let repo = git2::Repository::discover(root_path).unwrap();
+    repo.set_head("refs/heads/feature").unwrap();
+
+    let git_stack = |args: &[&str]| {
+        snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+            .args(args)
+            .current_dir(root_path)
+            .assert()
+            .success()
+    };
+
+    git_stack(&["hide", "other"]).stderr_eq(
+        "\
+Hid other
+",
+    );
+    // Each render reads it back from the config
+    for _ in 0..2 {
+        git_stack(&[])
+            .stdout_eq(
+                "\
+main (no remote) A
+⌽ feature (ready) F
+",
+            )
+            .stderr_matches(
+                "\
+Hidden stacks (show with `--show-all`): [..]other[..]
+",
+            );
+    }
+    git_stack(&["--show-all"]).stdout_eq(
+        "\
+main (no remote) A
+├─┐
+│ ⌽ other (ready) O
+│ 
+⌽ feature (ready) F
+",
+    );
+
+    git_stack(&["show", "other"]).stderr_eq(
+        "\
+Showing other
+",
+    );
+    git_stack(&[])
+        .stdout_eq(
+            "\
+main (no remote) A
+├─┐
+│ ⌽ other (ready) O
+│ 
+⌽ feature (ready) F
+",
+        )
+        .stderr_eq("");
+
+    root.close().unwrap();
+}
+
+fn signed_commit_fixture(root_path: &std::path::Path) {
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    // `git_fixture` can't sign commits and the signature only needs to be present, not valid
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let sig = repo.signature().unwrap();
+    let main = repo
+        .revparse_single("main")
+        .unwrap()
+        .peel_to_commit()
+        .unwrap();
+    let tree = main.tree().unwrap();
+    let buffer = repo
+        .commit_create_buffer(&sig, &sig, "Signed", &tree, &[&main])
+        .unwrap();
+    let signed_id = repo
+        .commit_signed(
+            buffer.as_str().unwrap(),
+            "-----BEGIN PGP SIGNATURE-----\n\nbogus\n-----END PGP SIGNATURE-----",
+            None,
+        )
+        .unwrap();
+    let signed = repo.find_commit(signed_id).unwrap();
+    let unsigned_id = repo
+        .commit(None, &sig, &sig, "Unsigned", &tree, &[&signed])
+        .unwrap();
+    repo.reference("refs/heads/feature", unsigned_id, true, "fixture")
+        .unwrap();
+    repo.set_head("refs/heads/feature").unwrap();
+}
+
+#[test]
+fn show_signature_marks_signed_commits() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    signed_commit_fixture(root_path);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--show-signature")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+main (no remote) A
+⌽ [..] (signed) Signed
+⌽ feature (ready) (unsigned) Unsigned
+",
+        );
+
+    // Stand in for gpg, so the outcome doesn't depend on it or its keyring
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let gpg = root_path.join(".git/bad-gpg");
+        std::fs::write(
+            &gpg,
+            "#!/bin/sh\necho '[GNUPG:] BADSIG 0123456789ABCDEF Test <test@example.com>'\nexit 1\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&gpg, std::fs::Permissions::from_mode(0o755)).unwrap();
+        snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+            .args(["--show-signature", "--verify"])
+            .env("GIT_CONFIG_COUNT", "1")
+            .env("GIT_CONFIG_KEY_0", "gpg.program")
+            .env("GIT_CONFIG_VALUE_0", &gpg)
+            .current_dir(root_path)
+            .assert()
+            .success()
+            .stdout_matches(
+                "\
+main (no remote) A
+⌽ [..] (bad sig) Signed
+⌽ feature (ready) (unsigned) Unsigned
+",
+            );
+    }
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["--show-signature", "--verify"])
+        .env("GIT_CONFIG_COUNT", "1")
+        .env("GIT_CONFIG_KEY_0", "gpg.program")
+        .env("GIT_CONFIG_VALUE_0", root_path.join("no-such-gpg"))
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(
+            "\
+main (no remote) A
+⌽ [..] (unverified) Signed
+⌽ feature (ready) (unsigned) Unsigned
+",
+        );
+
+    root.close().unwrap();
+}
+
+#[test]
+fn push_records_pushed_oid() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let remote = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let remote_path = remote.path().unwrap();
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            tree(&[("a", "a")], "A"),
+            git_fixture::Command::Branch("main".into()),
+            tree(&[("a", "a"), ("f", "f")], "F"),
+            git_fixture::Command::Branch("feature".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    git2::Repository::init_bare(remote_path).unwrap();
+    let repo = git2::Repository::discover(root_path).unwrap();
+    repo.remote("origin", remote_path.to_str().unwrap())
+        .unwrap();
+    repo.config()
+        .unwrap()
+        .set_str("stack.protect-commit-age", "100years")
+        .unwrap();
+    repo.set_head("refs/heads/feature").unwrap();
+    repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+        .unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--push")
+        .current_dir(root_path)
+        .assert()
+        .success();
+    let feature = repo
+        .revparse_single("feature")
+        .unwrap()
+        .peel_to_commit()
+        .unwrap();
+    let config = repo.config().unwrap().snapshot().unwrap();
+    assert_eq!(
+        config.get_str("stack.branch.feature.pushed").unwrap(),
+        feature.id().to_string()
+    );
+    assert_eq!(config.get_str("branch.feature.remote").unwrap(), "origin");
+
+    // Someone else pushes on top of ours, we fetch it, and the reflog of our push expires
+    let signature = feature.author();
+    let theirs = repo
+        .commit(
+            None,
+            &signature,
+            &signature,
+            "theirs",
+            &feature.tree().unwrap(),
+            &[&feature],
+        )
+        .unwrap();
+    repo.reference(
+        "refs/remotes/origin/feature",
+        theirs,
+        true,
+        "fetch: fast-forward",
+    )
+    .unwrap();
+    repo.reflog_delete("refs/remotes/origin/feature").unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--porcelain")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_matches(format!(
+            "\
+[..]\tprotected\tmain\tfalse
+{}\tpick\tfeature\tfalse
+",
+            feature.id()
+        ));
+
+    root.close().unwrap();
+    remote.close().unwrap();
+}
+
+#[test]
+fn rebase_refuses_to_overwrite_untracked_files() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Label("base".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("p", "p")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("Add p".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Reset("base".into()),
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a"), ("b", "b")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("B".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("feat".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+
+    let feat_id = repo.find_local_branch("feat").unwrap().id;
+    std::fs::write(root_path.join("p"), "untracked p").unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--rebase")
+        .current_dir(root_path)
+        .assert()
+        .failure()
+        .stdout_matches(
+            "\
+[..] A
+├─┐
+│ ⌽ feat (ready) B
+│ 
+⌽ main (no remote) Add p
+",
+        )
+        .stderr_matches(
+            "\
+ERROR: Failed to re-stack branch `feat`: the following untracked files would be overwritten: p; class=Checkout (20); code=Conflict (-13)
+[..]To undo, run `git branch-stash pop git-stack`[..]
+error: 0 branches re-stacked, 1 failed: feat (the following untracked files would be overwritten: p)
+",
+        );
+
+    assert_eq!(repo.find_local_branch("feat").unwrap().id, feat_id);
+    assert_eq!(repo.head_commit().id, feat_id);
+    snapbox::assert_eq(std::fs::read(root_path.join("p")).unwrap(), "untracked p");
+
+    root.close().unwrap();
+}
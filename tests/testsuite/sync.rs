@@ -0,0 +1,758 @@
+use crate::fixture;
+
+fn independent_stacks_fixture(root_path: &std::path::Path) {
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let mut commands = vec![
+        tree(&[("a", "a")], "A"),
+        git_fixture::Command::Label("base".into()),
+        tree(&[("a", "a"), ("b", "b")], "B"),
+        git_fixture::Command::Branch("main".into()),
+    ];
+    for name in ["one", "two", "three", "four"] {
+        commands.push(git_fixture::Command::Reset("base".into()));
+        commands.push(tree(&[("a", "a"), (name, name)], name));
+        commands.push(git_fixture::Command::Branch(name.into()));
+    }
+    let plan = git_fixture::TodoList {
+        commands,
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+}
+
+#[test]
+fn sync_jobs_restacks_independent_stacks() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    independent_stacks_fixture(root_path);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["sync", "--jobs=4"])
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stdout_eq("")
+        .stderr_matches(
+            "\
+note: to undo, run `git branch-stash pop git-stack`
+",
+        );
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+    let main_id = repo.find_local_branch("main").unwrap().id;
+    for name in ["one", "two", "three", "four"] {
+        let branch = repo.find_local_branch(name).unwrap();
+        assert_eq!(repo.parent_ids(branch.id).unwrap(), vec![main_id], "{name}");
+    }
+    assert_eq!(
+        repo.head_branch()
+            .and_then(|b| b.local_name().map(ToOwned::to_owned)),
+        Some("four".to_owned())
+    );
+
+    root.close().unwrap();
+}
+
+#[test]
+fn sync_refuses_unresolved_conflicts() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    independent_stacks_fixture(root_path);
+
+    // Leave the index with unmerged entries without an operation in progress
+    std::fs::write(root_path.join("four"), "stashed").unwrap();
+    snapbox::cmd::Command::new("git")
+        .arg("stash")
+        .current_dir(root_path)
+        .assert()
+        .success();
+    std::fs::write(root_path.join("four"), "committed").unwrap();
+    snapbox::cmd::Command::new("git")
+        .args(["commit", "-am", "four again"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+    snapbox::cmd::Command::new("git")
+        .args(["stash", "pop"])
+        .current_dir(root_path)
+        .assert()
+        .failure();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let old_four_id = repo.revparse_single("four").unwrap().id();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("sync")
+        .current_dir(root_path)
+        .assert()
+        .code(1)
+        .stdout_eq("")
+        .stderr_eq(
+            "\
+cannot walk commits with unresolved conflicts (conflicted files: four)
+",
+        );
+    assert_eq!(repo.revparse_single("four").unwrap().id(), old_four_id);
+
+    root.close().unwrap();
+}
+
+#[test]
+fn sync_committer_overrides_rewritten_commits() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    independent_stacks_fixture(root_path);
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let old_authors = ["one", "two", "three", "four"]
+        .iter()
+        .map(|name| {
+            let commit = repo
+                .revparse_single(name)
+                .unwrap()
+                .peel_to_commit()
+                .unwrap();
+            let author = commit.author();
+            (
+                author.name().unwrap().to_owned(),
+                author.email().unwrap().to_owned(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["sync", "--committer", "Stack Bot <bot@example.com>"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    for (name, old_author) in ["one", "two", "three", "four"].iter().zip(old_authors) {
+        let commit = repo
+            .revparse_single(name)
+            .unwrap()
+            .peel_to_commit()
+            .unwrap();
+        let committer = commit.committer();
+        assert_eq!(committer.name(), Some("Stack Bot"), "{name}");
+        assert_eq!(committer.email(), Some("bot@example.com"), "{name}");
+        let author = commit.author();
+        assert_eq!(
+            (
+                author.name().unwrap().to_owned(),
+                author.email().unwrap().to_owned()
+            ),
+            old_author,
+            "{name}"
+        );
+    }
+
+    root.close().unwrap();
+}
+
+#[test]
+fn committer_must_be_an_ident() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    independent_stacks_fixture(root_path);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["sync", "--committer", "bot@example.com"])
+        .current_dir(root_path)
+        .assert()
+        .code(64)
+        .stdout_eq("")
+        .stderr_matches(
+            "\
+error: invalid value 'bot@example.com' for '--committer <IDENT>': expected `NAME <EMAIL>`, got `bot@example.com`
+...",
+        );
+
+    root.close().unwrap();
+}
+
+/// `feature` sits on `main`, and `origin/main` has since taken its change as a re-created commit
+fn rebase_merged_fixture(root_path: &std::path::Path) {
+    let upstream_path = root_path.join("upstream");
+    let local_path = root_path.join("local");
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+        ],
+        ..Default::default()
+    };
+    std::fs::create_dir_all(&upstream_path).unwrap();
+    plan.run(&upstream_path).unwrap();
+    let status = std::process::Command::new("git")
+        .arg("clone")
+        .arg("--quiet")
+        .arg(&upstream_path)
+        .arg(&local_path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let commit_b = |repo: &git2::Repository, update_ref: &str, message: &str| {
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let blob = repo.blob(b"b\n").unwrap();
+        let mut builder = repo.treebuilder(Some(&head.tree().unwrap())).unwrap();
+        builder.insert("b", blob, 0o100644).unwrap();
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+        let signature = git2::Signature::now("Someone", "someone@example.com").unwrap();
+        repo.commit(
+            Some(update_ref),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &[&head],
+        )
+        .unwrap()
+    };
+
+    let local = git2::Repository::open(&local_path).unwrap();
+    let head = local.head().unwrap().peel_to_commit().unwrap();
+    local.branch("feature", &head, false).unwrap();
+    local.set_head("refs/heads/feature").unwrap();
+    commit_b(&local, "HEAD", "B");
+    local
+        .checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+        .unwrap();
+    local
+        .config()
+        .unwrap()
+        .set_str("stack.protected-branch", "main")
+        .unwrap();
+
+    let upstream = git2::Repository::open(&upstream_path).unwrap();
+    commit_b(&upstream, "refs/heads/main", "B (merged)");
+}
+
+#[test]
+fn sync_flags_rebase_merged_branch() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    rebase_merged_fixture(root_path);
+    let local_path = root_path.join("local");
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("sync")
+        .current_dir(&local_path)
+        .assert()
+        .success()
+        .stderr_matches(
+            "\
+...
+`feature` looks merged, delete it with `--prune-merged`
+...
+",
+        );
+
+    let repo = git2::Repository::discover(&local_path).unwrap();
+    assert!(repo.find_branch("feature", git2::BranchType::Local).is_ok());
+
+    root.close().unwrap();
+}
+
+#[test]
+fn sync_prune_merged_deletes_rebase_merged_branch() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    rebase_merged_fixture(root_path);
+    let local_path = root_path.join("local");
+    let repo = git2::Repository::discover(&local_path).unwrap();
+    let feature_id = repo.revparse_single("feature").unwrap().id();
+    repo.config()
+        .unwrap()
+        .set_str("stack.branch.feature.pushed", &feature_id.to_string())
+        .unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["sync", "--prune-merged"])
+        .current_dir(&local_path)
+        .assert()
+        .success()
+        .stderr_matches(
+            "\
+...
+Pruning `feature`, its commits were all merged
+...
+",
+        );
+
+    assert!(repo
+        .find_branch("feature", git2::BranchType::Local)
+        .is_err());
+    let config = repo.config().unwrap().snapshot().unwrap();
+    assert!(config.get_str("stack.branch.feature.pushed").is_err());
+
+    root.close().unwrap();
+}
+
+#[test]
+fn sync_pulls_base_branch_upstream() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let upstream_path = root_path.join("upstream");
+    let local_path = root_path.join("local");
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            git_fixture::Command::Tree(git_fixture::Tree {
+                files: [("a", "a")]
+                    .into_iter()
+                    .map(|(p, c)| (p.into(), c.into()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+                message: Some("A".to_owned()),
+                author: None,
+            }),
+            git_fixture::Command::Branch("main".into()),
+        ],
+        ..Default::default()
+    };
+    std::fs::create_dir_all(&upstream_path).unwrap();
+    plan.run(&upstream_path).unwrap();
+    // Not the default `origin`, so only `branch.main.remote` says where `main` comes from
+    let status = std::process::Command::new("git")
+        .args(["clone", "--quiet", "--origin", "upstream"])
+        .arg(&upstream_path)
+        .arg(&local_path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let commit = |repo: &git2::Repository, update_ref: &str, path: &str, message: &str| {
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let blob = repo.blob(path.as_bytes()).unwrap();
+        let mut builder = repo.treebuilder(Some(&head.tree().unwrap())).unwrap();
+        builder.insert(path, blob, 0o100644).unwrap();
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+        let signature = git2::Signature::now("Someone", "someone@example.com").unwrap();
+        repo.commit(
+            Some(update_ref),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &[&head],
+        )
+        .unwrap()
+    };
+
+    let local = git2::Repository::open(&local_path).unwrap();
+    let head = local.head().unwrap().peel_to_commit().unwrap();
+    local.branch("feature", &head, false).unwrap();
+    local.set_head("refs/heads/feature").unwrap();
+    commit(&local, "HEAD", "b", "B");
+    local
+        .checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+        .unwrap();
+    local
+        .config()
+        .unwrap()
+        .set_str("stack.protected-branch", "main")
+        .unwrap();
+
+    let upstream = git2::Repository::open(&upstream_path).unwrap();
+    let upstream_id = commit(&upstream, "refs/heads/main", "c", "C");
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("sync")
+        .current_dir(&local_path)
+        .assert()
+        .success();
+
+    let repo = git2::Repository::discover(&local_path).unwrap();
+    let repo = git_stack::git::GitRepo::new(repo);
+    assert_eq!(
+        repo.find_remote_branch("upstream", "main").unwrap().id,
+        upstream_id
+    );
+    let feature = repo.find_local_branch("feature").unwrap();
+    assert_eq!(repo.parent_ids(feature.id).unwrap(), vec![upstream_id]);
+
+    root.close().unwrap();
+}
+
+/// `feature` is three commits behind `main`
+fn long_stack_fixture(root_path: &std::path::Path) {
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            tree(&[("a", "a")], "A"),
+            git_fixture::Command::Label("base".into()),
+            tree(&[("a", "a"), ("b", "b")], "B"),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Reset("base".into()),
+            tree(&[("a", "a"), ("c", "1")], "C1"),
+            tree(&[("a", "a"), ("c", "2")], "C2"),
+            tree(&[("a", "a"), ("c", "3")], "C3"),
+            git_fixture::Command::Branch("feature".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    repo.set_head("refs/heads/feature").unwrap();
+    repo.config()
+        .unwrap()
+        .set_i64("stack.max-stack-size", 2)
+        .unwrap();
+}
+
+#[test]
+fn sync_max_stack_size_requires_yes() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    long_stack_fixture(root_path);
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let old_id = repo.revparse_single("feature").unwrap().id();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("sync")
+        .current_dir(root_path)
+        .assert()
+        .code(64)
+        .stderr_matches(
+            "\
+[..]3 commits would be rewritten, more than `stack.max-stack-size` (2); the base may be wrong, see `--base`
+refusing to rewrite 3 commits without `--yes`
+",
+        );
+    assert_eq!(repo.revparse_single("feature").unwrap().id(), old_id);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["sync", "--yes"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+    let main_id = repo.revparse_single("main").unwrap().id();
+    let feature = repo
+        .revparse_single("feature~2")
+        .unwrap()
+        .peel_to_commit()
+        .unwrap();
+    assert_eq!(feature.parent_id(0).unwrap(), main_id);
+
+    root.close().unwrap();
+}
+
+fn conflicting_stack_fixture(root_path: &std::path::Path) {
+    let tree = |content: &str, message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: [("a", content)]
+                .into_iter()
+                .map(|(p, c)| (p.into(), c.into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            tree("a\n", "A"),
+            git_fixture::Command::Label("base".into()),
+            tree("main\n", "Main"),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Reset("base".into()),
+            tree("feature\n", "Feature"),
+            git_fixture::Command::Branch("feature".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    repo.set_head("refs/heads/feature").unwrap();
+    repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+        .unwrap();
+    repo.config()
+        .unwrap()
+        .set_bool("rerere.enabled", true)
+        .unwrap();
+}
+
+#[test]
+fn sync_replays_recorded_resolution() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    conflicting_stack_fixture(root_path);
+    let git = |args: &[&str]| {
+        snapbox::cmd::Command::new("git")
+            .args(args)
+            .env("GIT_EDITOR", "true")
+            .current_dir(root_path)
+            .assert()
+    };
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("sync")
+        .current_dir(root_path)
+        .assert()
+        .failure()
+        .stderr_matches(
+            "\
+ERROR: Failed to re-stack branch `feature`: cherry-pick conflicts:
+  a
+...
+",
+        );
+
+    // Resolve the conflict once by hand, for `git rerere` to record
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let feature_id = repo.revparse_single("feature").unwrap().id();
+    git(&["rebase", "main"]).failure();
+    std::fs::write(root_path.join("a"), "main\nfeature\n").unwrap();
+    git(&["add", "a"]).success();
+    git(&["rebase", "--continue"]).success();
+    git(&["reset", "--hard", &feature_id.to_string()]).success();
+
+    // `git` runs in a scratch worktree where the user's relative index path doesn't resolve
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("sync")
+        .env("GIT_INDEX_FILE", ".git/index")
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .stderr_matches(
+            "\
+Resolved conflicts in a for [..] using a recorded resolution
+...
+",
+        );
+
+    let main_id = repo.revparse_single("main").unwrap().id();
+    let feature = repo
+        .revparse_single("feature")
+        .unwrap()
+        .peel_to_commit()
+        .unwrap();
+    assert_eq!(feature.parent_ids().collect::<Vec<_>>(), [main_id]);
+    let blob = feature
+        .tree()
+        .unwrap()
+        .get_name("a")
+        .unwrap()
+        .to_object(&repo)
+        .unwrap()
+        .peel_to_blob()
+        .unwrap();
+    assert_eq!(blob.content(), b"main\nfeature\n");
+    assert_eq!(repo.worktrees().unwrap().len(), 0);
+    assert!(repo.statuses(None).unwrap().is_empty());
+
+    root.close().unwrap();
+}
+
+#[test]
+fn sync_refuses_merge_in_stack() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    fixture::merge_stack_fixture(root_path);
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let old_id = repo.revparse_single("feature").unwrap().id();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("sync")
+        .current_dir(root_path)
+        .assert()
+        .code(64)
+        .stderr_matches(
+            "\
+merge commit in stack; use --rebase-merges
+",
+        );
+    assert_eq!(repo.revparse_single("feature").unwrap().id(), old_id);
+
+    root.close().unwrap();
+}
+
+#[test]
+fn sync_rebase_merges_recreates_merge() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    fixture::merge_stack_fixture(root_path);
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let side_id = repo
+        .revparse_single("feature~1^2")
+        .unwrap()
+        .peel_to_commit()
+        .unwrap()
+        .id();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["sync", "--rebase-merges"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+
+    let main_id = repo.revparse_single("main").unwrap().id();
+    let merge = repo
+        .revparse_single("feature~1")
+        .unwrap()
+        .peel_to_commit()
+        .unwrap();
+    assert_eq!(merge.summary(), Some("Merge side"));
+    assert_eq!(merge.parent_count(), 2);
+    assert_eq!(merge.parent_id(1).unwrap(), side_id);
+    let b = merge.parent(0).unwrap();
+    assert_eq!(b.summary(), Some("B"));
+    assert_eq!(b.parent_id(0).unwrap(), main_id);
+    let feature = repo
+        .revparse_single("feature")
+        .unwrap()
+        .peel_to_commit()
+        .unwrap();
+    let tree = feature.tree().unwrap();
+    for path in ["a", "b", "c", "m", "s"] {
+        assert!(tree.get_name(path).is_some(), "{path} is missing");
+    }
+
+    root.close().unwrap();
+}
+
+#[test]
+fn sync_rebase_merges_refuses_merging_rewritten_commit() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    let tree = |files: &[(&str, &str)], message: &str| {
+        git_fixture::Command::Tree(git_fixture::Tree {
+            files: files
+                .iter()
+                .map(|(p, c)| ((*p).into(), (*c).into()))
+                .collect::<std::collections::HashMap<_, _>>(),
+            message: Some(message.to_owned()),
+            author: None,
+        })
+    };
+    let plan = git_fixture::TodoList {
+        commands: vec![
+            tree(&[("a", "a")], "A"),
+            git_fixture::Command::Label("base".into()),
+            tree(&[("a", "a"), ("m", "m")], "M"),
+            git_fixture::Command::Branch("main".into()),
+            git_fixture::Command::Reset("base".into()),
+            tree(&[("a", "a"), ("b", "b")], "B"),
+            git_fixture::Command::Label("b".into()),
+            tree(&[("a", "a"), ("b", "b"), ("s", "s")], "S"),
+            git_fixture::Command::Branch("side".into()),
+            git_fixture::Command::Reset("b".into()),
+            tree(&[("a", "a"), ("b", "b"), ("c", "c")], "C"),
+            git_fixture::Command::Branch("feature".into()),
+        ],
+        ..Default::default()
+    };
+    plan.run(root_path).unwrap();
+
+    // Merge `side`, which is in the same stack, into `feature`
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let sig = repo.signature().unwrap();
+    let c = repo
+        .revparse_single("feature")
+        .unwrap()
+        .peel_to_commit()
+        .unwrap();
+    let side = repo
+        .revparse_single("side")
+        .unwrap()
+        .peel_to_commit()
+        .unwrap();
+    let mut builder = repo.treebuilder(Some(&c.tree().unwrap())).unwrap();
+    builder
+        .insert(
+            "s",
+            side.tree().unwrap().get_name("s").unwrap().id(),
+            0o100644,
+        )
+        .unwrap();
+    let merge_tree = repo.find_tree(builder.write().unwrap()).unwrap();
+    let merge_id = repo
+        .commit(None, &sig, &sig, "Merge side", &merge_tree, &[&c, &side])
+        .unwrap();
+    repo.reference("refs/heads/feature", merge_id, true, "fixture")
+        .unwrap();
+    repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+        .unwrap();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["sync", "--rebase-merges"])
+        .current_dir(root_path)
+        .assert()
+        .code(64)
+        .stderr_matches(
+            "\
+merge commit [..] merges in [..], which is also being rewritten
+",
+        );
+    assert_eq!(repo.revparse_single("feature").unwrap().id(), merge_id);
+    assert_eq!(repo.revparse_single("side").unwrap().id(), side.id());
+
+    root.close().unwrap();
+}
+
+#[test]
+fn stack_rebase_refuses_merge_in_stack() {
+    let root = snapbox::path::PathFixture::mutable_temp().unwrap();
+    let root_path = root.path().unwrap();
+    fixture::merge_stack_fixture(root_path);
+
+    let repo = git2::Repository::discover(root_path).unwrap();
+    let old_id = repo.revparse_single("feature").unwrap().id();
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .arg("--rebase")
+        .current_dir(root_path)
+        .assert()
+        .code(64)
+        .stderr_matches(
+            "\
+merge commit in stack; use --rebase-merges
+",
+        );
+    assert_eq!(repo.revparse_single("feature").unwrap().id(), old_id);
+
+    snapbox::cmd::Command::new(snapbox::cmd::cargo_bin!("git-stack"))
+        .args(["--rebase", "--rebase-merges"])
+        .current_dir(root_path)
+        .assert()
+        .success();
+    let main_id = repo.revparse_single("main").unwrap().id();
+    let merge = repo
+        .revparse_single("feature~1")
+        .unwrap()
+        .peel_to_commit()
+        .unwrap();
+    assert_eq!(merge.parent_count(), 2);
+    assert_eq!(merge.parent(0).unwrap().parent_id(0).unwrap(), main_id);
+
+    root.close().unwrap();
+}